@@ -35,6 +35,8 @@
 
           nativeBuildInputs = with pkgs; [
             pkg-config
+            # Builds libopus for the `opus` feature
+            cmake
           ];
 
           PKG_CONFIG_PATH = "${pkgs.alsa-lib.dev}/lib/pkgconfig";
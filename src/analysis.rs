@@ -0,0 +1,221 @@
+use std::fmt;
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use crate::decoder::DecodedAudio;
+use crate::dsp;
+use crate::loudness::{LoudnessMeter, LoudnessReport};
+
+/// Samples at or above this magnitude count as clipped. Slightly below 1.0 so that
+/// integer full scale (32767 / 32768) is caught too.
+const CLIP_LEVEL: f32 = 0.999;
+/// Clipping events listed in the report before the rest are summarized.
+const MAX_LISTED_CLIPS: usize = 10;
+
+const SPECTROGRAM_COLUMNS: usize = 64;
+const SPECTROGRAM_BANDS: usize = 16;
+const SPECTROGRAM_FFT_SIZE: usize = 2048;
+const SPECTROGRAM_MIN_FREQ: f32 = 50.0;
+/// Dynamic range shown in the spectrogram, below its loudest cell.
+const SPECTROGRAM_RANGE_DB: f32 = 60.0;
+
+/// Offline analysis of a whole file or take.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub duration: Duration,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+    pub loudness: LoudnessReport,
+    pub clipping: ClippingReport,
+    pub spectrogram: Spectrogram,
+}
+
+/// Runs of clipped samples found in the audio.
+#[derive(Debug, Clone, Default)]
+pub struct ClippingReport {
+    pub clipped_samples: usize,
+    /// Start time of each run of consecutive clipped frames.
+    pub events: Vec<Duration>,
+}
+
+/// Coarse time/frequency energy map, small enough to print in a terminal.
+#[derive(Debug, Clone, Default)]
+pub struct Spectrogram {
+    /// Lower edge of each band in Hz, lowest first.
+    pub band_edges: Vec<f32>,
+    /// One column per time slice, each holding a dB value per band.
+    pub columns: Vec<Vec<f32>>,
+}
+
+pub fn analyze(audio: &DecodedAudio) -> Result<Analysis> {
+    let mut meter = LoudnessMeter::new(audio.channels, audio.sample_rate)?;
+    meter.add(&audio.samples)?;
+
+    Ok(Analysis {
+        duration: audio.duration(),
+        channels: audio.channels,
+        sample_rate: audio.sample_rate,
+        peak_dbfs: dsp::to_dbfs(dsp::peak(&audio.samples)),
+        rms_dbfs: dsp::to_dbfs(dsp::rms(&audio.samples)),
+        loudness: meter.report(),
+        clipping: find_clipping(audio),
+        spectrogram: Spectrogram::compute(audio),
+    })
+}
+
+fn find_clipping(audio: &DecodedAudio) -> ClippingReport {
+    let channels = audio.channels.max(1) as usize;
+    let mut report = ClippingReport::default();
+    let mut in_run = false;
+
+    for (frame_index, frame) in audio.samples.chunks(channels).enumerate() {
+        let clipped = frame.iter().filter(|x| x.abs() >= CLIP_LEVEL).count();
+        report.clipped_samples += clipped;
+
+        if clipped > 0 && !in_run {
+            report.events.push(Duration::from_secs_f64(
+                frame_index as f64 / audio.sample_rate.max(1) as f64,
+            ));
+        }
+        in_run = clipped > 0;
+    }
+
+    report
+}
+
+impl Spectrogram {
+    fn compute(audio: &DecodedAudio) -> Self {
+        let mono = dsp::mixdown(&audio.samples, audio.channels as usize);
+        let nyquist = audio.sample_rate as f32 / 2.0;
+        if mono.len() < SPECTROGRAM_FFT_SIZE || nyquist <= SPECTROGRAM_MIN_FREQ {
+            return Self::default();
+        }
+
+        // Log-spaced bands from the minimum frequency up to Nyquist
+        let ratio = (nyquist / SPECTROGRAM_MIN_FREQ).powf(1.0 / SPECTROGRAM_BANDS as f32);
+        let mut band_edges: Vec<f32> = (0..=SPECTROGRAM_BANDS)
+            .map(|i| SPECTROGRAM_MIN_FREQ * ratio.powi(i as i32))
+            .collect();
+        let bin_hz = audio.sample_rate as f32 / SPECTROGRAM_FFT_SIZE as f32;
+
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(SPECTROGRAM_FFT_SIZE);
+        let window: Vec<f32> = (0..SPECTROGRAM_FFT_SIZE)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * i as f32 / SPECTROGRAM_FFT_SIZE as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+
+        let last_start = mono.len() - SPECTROGRAM_FFT_SIZE;
+        let columns = (0..SPECTROGRAM_COLUMNS)
+            .map(|column| {
+                let start = last_start * column / (SPECTROGRAM_COLUMNS - 1);
+                let mut spectrum: Vec<Complex<f32>> = mono[start..start + SPECTROGRAM_FFT_SIZE]
+                    .iter()
+                    .zip(&window)
+                    .map(|(&x, &w)| Complex::new(x * w, 0.0))
+                    .collect();
+                fft.process(&mut spectrum);
+
+                band_edges
+                    .windows(2)
+                    .map(|edges| {
+                        let low = (edges[0] / bin_hz) as usize;
+                        let high = ((edges[1] / bin_hz) as usize).max(low + 1);
+                        let magnitude = spectrum[low..high.min(SPECTROGRAM_FFT_SIZE / 2)]
+                            .iter()
+                            .fold(0.0_f32, |acc, bin| acc.max(bin.norm()));
+                        dsp::to_dbfs(magnitude / (SPECTROGRAM_FFT_SIZE as f32 / 4.0))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        band_edges.truncate(SPECTROGRAM_BANDS);
+        Self {
+            band_edges,
+            columns,
+        }
+    }
+
+    /// Text rows, highest band first, shading each cell by its level.
+    pub fn render(&self) -> Vec<String> {
+        const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+        let loudest = self
+            .columns
+            .iter()
+            .flatten()
+            .fold(f32::NEG_INFINITY, |acc, &db| acc.max(db));
+
+        (0..self.band_edges.len())
+            .rev()
+            .map(|band| {
+                let cells: String = self
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        let level = 1.0 - (loudest - column[band]) / SPECTROGRAM_RANGE_DB;
+                        let shade = (level.clamp(0.0, 1.0) * (SHADES.len() - 1) as f32).round();
+                        SHADES[shade as usize]
+                    })
+                    .collect();
+                format!("{:>6.0} Hz │{cells}", self.band_edges[band])
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Duration:    {:.1} s ({} ch, {} Hz)",
+            self.duration.as_secs_f64(),
+            self.channels,
+            self.sample_rate
+        )?;
+        writeln!(f, "Peak:        {:.1} dBFS", self.peak_dbfs)?;
+        writeln!(f, "RMS:         {:.1} dBFS", self.rms_dbfs)?;
+        let loudness = &self.loudness;
+        writeln!(f, "Loudness:    {:.1} LUFS", loudness.integrated_lufs)?;
+        writeln!(f, "Range:       {:.1} LU", loudness.loudness_range_lu)?;
+        writeln!(f, "True peak:   {:.1} dBTP", loudness.true_peak_dbtp)?;
+        writeln!(f, "Noise floor: {:.1} dBFS", loudness.noise_floor_dbfs)?;
+
+        let clipping = &self.clipping;
+        if clipping.events.is_empty() {
+            writeln!(f, "Clipping:    none")?;
+        } else {
+            writeln!(
+                f,
+                "Clipping:    {} samples in {} events",
+                clipping.clipped_samples,
+                clipping.events.len()
+            )?;
+            for at in clipping.events.iter().take(MAX_LISTED_CLIPS) {
+                writeln!(f, "             at {:.3} s", at.as_secs_f64())?;
+            }
+            if clipping.events.len() > MAX_LISTED_CLIPS {
+                writeln!(
+                    f,
+                    "             ... and {} more",
+                    clipping.events.len() - MAX_LISTED_CLIPS
+                )?;
+            }
+        }
+
+        if !self.spectrogram.columns.is_empty() {
+            writeln!(f, "\nSpectrogram:")?;
+            for row in self.spectrogram.render() {
+                writeln!(f, "{row}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,2161 @@
+use std::cell::RefCell;
+use std::fs;
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::{io, time::Duration};
+
+use chrono::{DateTime, Local};
+use cpal::StreamConfig;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::{
+    backend::Backend,
+    buffer::Buffer,
+    layout::{Constraint, Flex, Layout, Position, Rect},
+    style::{Color, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Row, Table, Widget, Wrap},
+    Frame, Terminal,
+};
+
+use crate::browser::{Browser, BrowserAction};
+use crate::config::{Config, Key, KeyBindings, VisualizationScale, VisualizationStyle};
+use crate::control::{ControlMessage, ControlServer};
+use crate::instance::{Instance, Role, TransportCommand};
+use crate::preflight::{Preflight, PreflightAction};
+use crate::speech::Announcer;
+use crate::theme::{ColorSupport, Theme, Zone};
+use micrec::decoder::{self, DecodedAudio};
+use micrec::dsp;
+use micrec::engine::{
+    EngineEvent, Recorder, RecordingOptions, RecordingStats, GAIN_RANGE_DB, NOISE_LEARN_DURATION,
+};
+use micrec::library::format_size;
+use micrec::loudness::LoudnessReport;
+use micrec::meter::{MeterReading, StereoReading};
+use micrec::naming;
+use micrec::permission;
+use micrec::playback::Playback;
+use micrec::probe::{self, ActiveInput};
+use micrec::tags::{self, Tags};
+
+/// Quietest level shown on the level meter.
+const METER_FLOOR_DB: f32 = -60.0;
+/// How much each press of the gain keys changes the input gain.
+const GAIN_STEP_DB: f32 = 1.0;
+/// Samples to a block of [`Overview`], a few milliseconds.
+const OVERVIEW_BLOCK_SAMPLES: usize = 512;
+
+#[derive(Debug)]
+pub struct App {
+    config: Config,
+    bar_values: Arc<Mutex<Vec<f32>>>,
+    exit: bool,
+    recording: bool,
+    /// Where to record to; kept so a failed capture can be retried.
+    options: Option<RecordingOptions>,
+    engine: Option<Recorder>,
+    /// Why capture couldn't start, until the user retries.
+    error: Option<String>,
+    /// Most recent non-fatal problem reported by the audio backend.
+    stream_error: Option<String>,
+    /// Why capture is waiting for microphone access, until it starts.
+    waiting_for_permission: Option<String>,
+    /// When an armed take starts, until it does.
+    scheduled: Option<DateTime<Local>>,
+    /// Length of the pre-roll kept while a take waits for the `record` key.
+    standing_by: Option<Duration>,
+    /// The take was stopped before it began, so the next one can be armed.
+    cancelled: bool,
+    /// Devices being recorded, with their channel counts, when there are several.
+    devices: Vec<(String, u16)>,
+    /// How long the input had been silent when the engine flagged it.
+    silent: Option<Duration>,
+    /// Result of probing the other inputs for sound, while they're being probed.
+    finding_input: Option<Receiver<color_eyre::Result<Option<ActiveInput>>>>,
+    /// The input is nothing but digital zeros, e.g. from a hardware mute switch.
+    muted: bool,
+    /// Free space left on disk once it's running low.
+    low_disk_space: Option<u64>,
+    /// Input device that went away mid-take, until capture resumes.
+    disconnected: Option<String>,
+    instance: Option<Instance>,
+    /// OSC and MIDI controllers, handled like keys.
+    control: Option<ControlServer>,
+    /// Problems worth showing above the meters, e.g. another instance on our device.
+    warnings: Vec<String>,
+    /// One reading per input channel.
+    levels: Vec<MeterReading>,
+    stereo: Option<StereoReading>,
+    /// Show mid/side meters and the stereo width under the L/R ones.
+    show_mid_side: bool,
+    /// Show the key bindings over the current screen.
+    show_help: bool,
+    /// What the mouse can click on the recorder screen, where it was last drawn.
+    click_targets: RefCell<Vec<(Rect, ClickTarget)>>,
+    /// The gain slider was clicked and the button is still held.
+    dragging_gain: bool,
+    theme: Theme,
+    /// Whether the input is being played through the default output.
+    monitoring: bool,
+    noise_reduction: NoiseReduction,
+    /// Positions of the markers placed in this take.
+    markers: Vec<Duration>,
+    /// Latest clip saved from the buffer, when recording into one.
+    last_clip: Option<PathBuf>,
+    /// Reads levels out loud, when enabled in the config.
+    announcer: Option<Announcer>,
+    last_terminal_width: u16,
+    /// Audio so far in the newest bar, with `bar_history_ms`.
+    bar_window: BarWindow,
+    stream_config: Option<StreamConfig>,
+    /// Frames received since the take started.
+    recorded_frames: usize,
+    overview: Overview,
+    /// The take being decoded to play, until it has been.
+    loading: Option<Receiver<color_eyre::Result<DecodedAudio>>>,
+    playback: Option<Playback>,
+    save_result: Option<Result<PathBuf, String>>,
+    /// Files saved next to the take, such as its markers, deleted with it on a
+    /// retake.
+    sidecars: Vec<PathBuf>,
+    /// Tags being typed in for the stopped take.
+    tagging: Option<TagPrompt>,
+    /// Tags last given to a take, to start the next prompt from.
+    tags: Tags,
+    /// How far along the compressed copy of the stopped take is.
+    transcoding: Option<f32>,
+    /// How loud the stopped take came out.
+    loudness: Option<LoudnessReport>,
+    /// File being reviewed, when opened with `micrec play`.
+    loaded_from: Option<PathBuf>,
+    screen: Screen,
+}
+
+/// Progress of [`Recorder::learn_noise`] for the current take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NoiseReduction {
+    Off,
+    Learning,
+    On,
+}
+
+/// Running level of the bar being filled, when bars scroll through time.
+#[derive(Debug, Default)]
+struct BarWindow {
+    sum_of_squares: f32,
+    samples: usize,
+}
+
+impl BarWindow {
+    fn rms(&self) -> f32 {
+        (self.sum_of_squares / self.samples.max(1) as f32).sqrt()
+    }
+}
+
+/// Level of the whole take in short blocks, to draw it once stopped without
+/// keeping hours of audio in memory.
+#[derive(Debug, Default)]
+struct Overview {
+    /// Mean square of each block so far.
+    blocks: Vec<f32>,
+    /// The block being filled.
+    window: BarWindow,
+}
+
+impl Overview {
+    fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.window.sum_of_squares += sample * sample;
+            self.window.samples += 1;
+            if self.window.samples == OVERVIEW_BLOCK_SAMPLES {
+                let window = mem::take(&mut self.window);
+                self.blocks
+                    .push(window.sum_of_squares / window.samples as f32);
+            }
+        }
+    }
+
+    /// RMS of each of `bars` even stretches of the take.
+    fn levels(&self, bars: usize) -> Vec<f32> {
+        let mut blocks = self.blocks.clone();
+        if self.window.samples > 0 {
+            blocks.push(self.window.rms().powi(2));
+        }
+        if blocks.is_empty() {
+            return vec![0.0; bars];
+        }
+        // A short take has blocks shared between neighbouring bars
+        (0..bars)
+            .map(|bar| {
+                let start = (bar * blocks.len() / bars).min(blocks.len() - 1);
+                let end = ((bar + 1) * blocks.len() / bars).max(start + 1);
+                let chunk = &blocks[start..end];
+                (chunk.iter().sum::<f32>() / chunk.len() as f32).sqrt()
+            })
+            .collect()
+    }
+}
+
+/// Something on the recorder screen that does something when clicked.
+#[derive(Debug, Clone, Copy)]
+enum ClickTarget {
+    /// A key hint, which stands in for pressing its key.
+    Key(Key),
+    /// The gain slider's track, spanning [`GAIN_RANGE_DB`].
+    GainSlider,
+    /// The bars, switching to the other visualization style.
+    Bars,
+}
+
+/// The key hints along the bottom, and the columns each one takes up.
+#[derive(Default)]
+struct Hints {
+    line: Line<'static>,
+    keys: Vec<(Range<u16>, Key)>,
+}
+
+impl Hints {
+    fn push(&mut self, action: impl Into<String>, key: Key) {
+        let start = self.line.width() as u16;
+        self.line.push_span(action.into());
+        self.line.push_span(key.label().blue().bold());
+        self.keys.push((start..self.line.width() as u16, key));
+    }
+}
+
+/// Which screen the TUI is showing.
+#[derive(Debug)]
+enum Screen {
+    Recorder,
+    /// Previous takes in the output directory.
+    Browser(Box<Browser>),
+    /// Checks to get through before the next take starts.
+    Preflight(Box<Preflight>),
+}
+
+impl App {
+    pub fn new(
+        config: Config,
+        options: RecordingOptions,
+        instance: Instance,
+        control: Option<ControlServer>,
+        warnings: Vec<String>,
+    ) -> Self {
+        let theme = Theme::new(config.theme, ColorSupport::detect());
+        let tags = options.tags.clone();
+        Self {
+            config,
+            bar_values: Arc::new(Mutex::new(vec![0.0; 50])), // Start with fewer bars
+            exit: false,
+            recording: true,
+            options: Some(options),
+            engine: None,
+            error: None,
+            stream_error: None,
+            waiting_for_permission: None,
+            scheduled: None,
+            standing_by: None,
+            cancelled: false,
+            devices: Vec::new(),
+            silent: None,
+            finding_input: None,
+            muted: false,
+            low_disk_space: None,
+            disconnected: None,
+            instance: Some(instance),
+            control,
+            warnings,
+            levels: Vec::new(),
+            stereo: None,
+            show_mid_side: false,
+            show_help: false,
+            click_targets: RefCell::default(),
+            dragging_gain: false,
+            theme,
+            monitoring: false,
+            noise_reduction: NoiseReduction::Off,
+            markers: Vec::new(),
+            last_clip: None,
+            announcer: None,
+            last_terminal_width: 0,
+            bar_window: BarWindow::default(),
+            stream_config: None,
+            recorded_frames: 0,
+            overview: Overview::default(),
+            loading: None,
+            playback: None,
+            save_result: None,
+            sidecars: Vec::new(),
+            tagging: None,
+            tags,
+            transcoding: None,
+            loudness: None,
+            loaded_from: None,
+            screen: Screen::Recorder,
+        }
+    }
+
+    /// Opens an existing recording for review instead of capturing a new one.
+    pub fn review(config: Config, path: PathBuf, audio: DecodedAudio) -> Self {
+        let theme = Theme::new(config.theme, ColorSupport::detect());
+        // It's decoded again to play, rather than kept
+        let mut overview = Overview::default();
+        overview.push(&audio.samples);
+        Self {
+            config,
+            bar_values: Arc::new(Mutex::new(vec![0.0; 50])),
+            exit: false,
+            recording: false,
+            options: None,
+            engine: None,
+            error: None,
+            stream_error: None,
+            waiting_for_permission: None,
+            scheduled: None,
+            standing_by: None,
+            cancelled: false,
+            devices: Vec::new(),
+            silent: None,
+            finding_input: None,
+            muted: false,
+            low_disk_space: None,
+            disconnected: None,
+            instance: None,
+            control: None,
+            warnings: Vec::new(),
+            levels: Vec::new(),
+            stereo: None,
+            show_mid_side: false,
+            show_help: false,
+            click_targets: RefCell::default(),
+            dragging_gain: false,
+            theme,
+            monitoring: false,
+            noise_reduction: NoiseReduction::Off,
+            markers: Vec::new(),
+            last_clip: None,
+            announcer: None,
+            last_terminal_width: 0,
+            bar_window: BarWindow::default(),
+            stream_config: Some(audio.stream_config()),
+            recorded_frames: audio.samples.len() / audio.channels.max(1) as usize,
+            overview,
+            loading: None,
+            playback: None,
+            save_result: None,
+            sidecars: Vec::new(),
+            tagging: None,
+            tags: Tags::default(),
+            transcoding: None,
+            loudness: None,
+            loaded_from: Some(path),
+            screen: Screen::Recorder,
+        }
+    }
+
+    /// Whether the terminal should report mouse events.
+    pub fn wants_mouse(&self) -> bool {
+        self.config.mouse
+    }
+
+    pub fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        if self.options.is_some() {
+            self.arm();
+        }
+
+        while !self.exit {
+            match &mut self.screen {
+                Screen::Preflight(preflight) => preflight.poll(),
+                Screen::Browser(browser) => browser.poll(),
+                Screen::Recorder => {}
+            }
+            let events: Vec<EngineEvent> = self
+                .engine
+                .as_ref()
+                .map(|engine| engine.events().collect())
+                .unwrap_or_default();
+            for event in events {
+                self.handle_engine_event(event);
+            }
+            self.take_samples();
+            self.levels = self
+                .engine
+                .as_ref()
+                .map(Recorder::levels)
+                .unwrap_or_default();
+            self.stereo = self.engine.as_ref().and_then(Recorder::stereo_levels);
+
+            let commands: Vec<TransportCommand> = self
+                .instance
+                .as_ref()
+                .map(|instance| instance.commands().collect())
+                .unwrap_or_default();
+            for command in commands {
+                match command {
+                    TransportCommand::Stop if self.recording => self.stop_recording(),
+                    TransportCommand::Stop => {}
+                    TransportCommand::Quit => self.exit(),
+                }
+            }
+            let messages: Vec<ControlMessage> = self
+                .control
+                .as_ref()
+                .map(|control| control.messages().collect())
+                .unwrap_or_default();
+            for message in messages {
+                self.handle_control_message(message);
+            }
+            self.poll_active_input();
+            self.poll_playback();
+
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if crossterm::event::poll(Duration::from_millis(16))? {
+                self.handle_events()?;
+            }
+        }
+
+        if let Some(playback) = &self.playback {
+            playback.stop();
+        }
+        // Dropping the engine stops it and waits for the file to be finalized
+        self.engine = None;
+
+        Ok(())
+    }
+
+    /// Starts a new take, after the pre-flight checklist if that's enabled.
+    fn arm(&mut self) {
+        let Some(options) = &self.options else {
+            return;
+        };
+        if !self.config.preflight.enabled {
+            self.start_recording();
+            return;
+        }
+        self.recording = false;
+        self.screen = Screen::Preflight(Box::new(Preflight::start(
+            self.config.preflight.clone(),
+            options.host,
+            options.capture,
+            options.device.clone(),
+            options.output_dir.clone(),
+            self.config.keys.clone(),
+        )));
+    }
+
+    /// Starts (or restarts, after a failure) capturing a new take.
+    fn start_recording(&mut self) {
+        let Some(mut options) = self.options.clone() else {
+            return;
+        };
+        options.standby = options.pre_roll.is_some();
+
+        // Let a previous engine finish before opening the device again
+        self.engine = None;
+        self.error = None;
+        self.stream_error = None;
+        self.waiting_for_permission = None;
+        self.scheduled = None;
+        self.standing_by = None;
+        self.cancelled = false;
+        self.devices.clear();
+        self.silent = None;
+        self.finding_input = None;
+        self.muted = false;
+        self.low_disk_space = None;
+        self.disconnected = None;
+        self.monitoring = false;
+        self.noise_reduction = NoiseReduction::Off;
+        self.markers.clear();
+        self.last_clip = None;
+        self.save_result = None;
+        self.sidecars.clear();
+        self.transcoding = None;
+        self.loudness = None;
+        self.announcer = None;
+        if self.config.speak_interval_secs > 0 {
+            let interval = Duration::from_secs(self.config.speak_interval_secs);
+            match Announcer::new(interval) {
+                Ok(announcer) => self.announcer = Some(announcer),
+                Err(err) => self.warnings.push(format!("{err:#}")),
+            }
+        }
+        self.recorded_frames = 0;
+        self.overview = Overview::default();
+        self.loading = None;
+        self.recording = true;
+        self.bar_window = BarWindow::default();
+        if let Ok(mut bars) = self.bar_values.lock() {
+            bars.fill(0.0);
+        }
+        self.engine = Some(Recorder::start(options));
+    }
+
+    fn handle_engine_event(&mut self, event: EngineEvent) {
+        match event {
+            EngineEvent::Started(config) => {
+                self.waiting_for_permission = None;
+                // Still ahead when starting early for a pre-roll
+                self.scheduled = self.scheduled.filter(|&at| at > Local::now());
+                self.stream_config = Some(config);
+            }
+            EngineEvent::Scheduled(at) => self.scheduled = Some(at),
+            EngineEvent::StandingBy(pre_roll) => self.standing_by = Some(pre_roll),
+            EngineEvent::Rolling => {
+                self.standing_by = None;
+                self.scheduled = None;
+            }
+            EngineEvent::Devices(devices) => self.devices = devices,
+            EngineEvent::WaitingForPermission(message) => {
+                self.waiting_for_permission = Some(message)
+            }
+            EngineEvent::Silent(after) => self.silent = Some(after),
+            EngineEvent::Muted(muted) => self.muted = muted,
+            EngineEvent::StoppedOnSilence(after) => {
+                self.warnings
+                    .push(format!("Stopped after {}s of silence", after.as_secs_f32()));
+                self.stop_recording();
+            }
+            EngineEvent::LowDiskSpace(free) => self.low_disk_space = Some(free),
+            EngineEvent::StoppedOnLowDiskSpace(free) => {
+                self.warnings.push(format!(
+                    "Stopped with only {} left on disk",
+                    format_size(free)
+                ));
+                self.stop_recording();
+            }
+            EngineEvent::MonitoringChanged(on) => self.monitoring = on,
+            EngineEvent::NoiseProfileLearned => self.noise_reduction = NoiseReduction::On,
+            EngineEvent::Disconnected(device) => self.disconnected = Some(device),
+            EngineEvent::Reconnected(device) => {
+                if self.disconnected.take().is_some_and(|lost| lost != device) {
+                    self.warnings
+                        .push(format!("Recording from {device} instead"));
+                }
+            }
+            EngineEvent::StreamError(err) => self.stream_error = Some(err),
+            EngineEvent::Failed(err) => {
+                self.recording = false;
+                self.error = Some(err);
+            }
+            EngineEvent::Cancelled => {
+                self.recording = false;
+                self.scheduled = None;
+                self.standing_by = None;
+                self.waiting_for_permission = None;
+                self.cancelled = true;
+                self.warnings
+                    .push(String::from("Stopped before the take began"));
+            }
+            EngineEvent::MarkerAdded(position) => self.markers.push(position),
+            EngineEvent::ClipSaved(Ok(clip)) => self.last_clip = Some(clip),
+            EngineEvent::ClipSaved(Err(err)) => self.warnings.push(err),
+            EngineEvent::SidecarSaved(Ok(sidecar)) => self.sidecars.push(sidecar),
+            EngineEvent::SidecarSaved(Err(err)) => self.warnings.push(err),
+            EngineEvent::Loudness(report) => self.loudness = Some(report),
+            EngineEvent::Transcoding(done) => self.transcoding = Some(done),
+            EngineEvent::Finished(result) => {
+                // The last of the take was queued before it finished
+                self.take_samples();
+                self.recording = false;
+                self.transcoding = None;
+                self.save_result = Some(result);
+                self.show_recording_overview();
+            }
+        }
+    }
+
+    /// Follows the audio written to the file since the last call.
+    fn take_samples(&mut self) {
+        let Some(samples) = self.engine.as_ref().and_then(Recorder::samples) else {
+            return;
+        };
+        if let Some(config) = &self.stream_config {
+            self.recorded_frames += samples.len() / config.channels.max(1) as usize;
+        }
+        self.overview.push(&samples);
+        if self.recording {
+            self.process_audio_samples(&samples);
+        }
+        let elapsed = self.recorded_duration();
+        if let Some(announcer) = &mut self.announcer {
+            if let Err(err) = announcer.process(&samples, elapsed) {
+                self.warnings
+                    .push(format!("Stopped speaking levels: {err:#}"));
+                self.announcer = None;
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        // Check if terminal width changed and update bar count
+        let current_width = frame.area().width;
+        if current_width != self.last_terminal_width {
+            self.update_bar_count(current_width);
+            self.last_terminal_width = current_width;
+        }
+        match &self.screen {
+            Screen::Recorder => frame.render_widget(&*self, frame.area()),
+            Screen::Browser(browser) => frame.render_widget(&**browser, frame.area()),
+            Screen::Preflight(preflight) => frame.render_widget(&**preflight, frame.area()),
+        }
+        if let Some(prompt) = &self.tagging {
+            frame.render_widget(prompt, frame.area());
+        }
+        if self.show_help {
+            frame.render_widget(Help(&self.config.keys), frame.area());
+        }
+    }
+
+    fn update_bar_count(&mut self, terminal_width: u16) {
+        // Calculate optimal bar count based on terminal width
+        // Account for border and spacing: 2 chars per bar (bar + gap), minus some padding
+        let usable_width = terminal_width.saturating_sub(4); // Account for borders
+        let optimal_bar_count = (usable_width / 2).max(10) as usize; // Minimum 10 bars
+
+        if let Ok(mut bars) = self.bar_values.lock() {
+            if self.config.bar_history_ms > 0 {
+                // Keep the newest bars, on the right
+                let len = bars.len();
+                if optimal_bar_count < len {
+                    bars.drain(..len - optimal_bar_count);
+                } else {
+                    bars.splice(..0, std::iter::repeat_n(0.0, optimal_bar_count - len));
+                }
+            } else {
+                bars.resize(optimal_bar_count, 0.0);
+            }
+        }
+
+        if !self.recording {
+            self.show_recording_overview();
+        }
+    }
+
+    /// Replaces the live bars with an overview of the whole take, so the playhead
+    /// has something meaningful to move across.
+    fn show_recording_overview(&mut self) {
+        if let Ok(mut bars) = self.bar_values.lock() {
+            let levels = self.overview.levels(bars.len());
+            for (bar_value, rms) in bars.iter_mut().zip(levels) {
+                *bar_value = bar_level(&self.config, rms);
+            }
+        }
+    }
+
+    fn exit(&mut self) {
+        self.broadcast(TransportCommand::Quit);
+        self.exit = true;
+    }
+
+    fn broadcast(&self, command: TransportCommand) {
+        if let Some(instance) = &self.instance {
+            instance.broadcast(command);
+        }
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if self.show_help {
+            self.show_help = false;
+            return;
+        }
+        if let Some(prompt) = &mut self.tagging {
+            let field = &mut prompt.fields[prompt.selected];
+            match key_event.code {
+                KeyCode::Char(c) => field.push(c),
+                KeyCode::Backspace => {
+                    field.pop();
+                }
+                KeyCode::Tab | KeyCode::Down => {
+                    prompt.selected = (prompt.selected + 1) % TAG_FIELDS.len();
+                }
+                KeyCode::BackTab | KeyCode::Up => {
+                    prompt.selected = (prompt.selected + TAG_FIELDS.len() - 1) % TAG_FIELDS.len();
+                }
+                KeyCode::Enter => {
+                    let tags = prompt.tags();
+                    self.tagging = None;
+                    self.tag_take(tags);
+                }
+                KeyCode::Esc => self.tagging = None,
+                _ => {}
+            }
+            return;
+        }
+        let renaming = matches!(&self.screen, Screen::Browser(browser) if browser.is_renaming());
+        if self.config.keys.help.matches(&key_event) && !renaming {
+            self.show_help = true;
+            return;
+        }
+        if let Screen::Browser(browser) = &mut self.screen {
+            match browser.handle_key(key_event) {
+                BrowserAction::None => {}
+                BrowserAction::Back => self.screen = Screen::Recorder,
+                BrowserAction::NewTake => {
+                    self.screen = Screen::Recorder;
+                    if self.options.is_some() {
+                        self.arm();
+                    } else {
+                        self.warnings
+                            .push(String::from("Run micrec without `play` to record"));
+                    }
+                }
+                BrowserAction::Quit => self.exit(),
+            }
+            return;
+        }
+        if let Screen::Preflight(preflight) = &mut self.screen {
+            match preflight.handle_key(key_event) {
+                PreflightAction::None => {}
+                PreflightAction::Arm => {
+                    self.screen = Screen::Recorder;
+                    self.start_recording();
+                }
+                PreflightAction::Quit => self.exit(),
+            }
+            return;
+        }
+        let keys = &self.config.keys;
+        let pressed = |key: Key| key.matches(&key_event);
+        if self.error.is_some() {
+            if pressed(keys.retry) {
+                self.start_recording();
+            } else if pressed(keys.quit) {
+                self.exit();
+            }
+        } else if pressed(keys.next_take) && (self.take_stopped() || self.cancelled) {
+            self.arm();
+        } else if pressed(keys.retake) && self.take_stopped() {
+            self.retake();
+        } else if pressed(keys.tag) && self.taggable_take().is_some() {
+            self.tagging = Some(TagPrompt::new(&self.tags));
+        } else if pressed(keys.retry) && self.waiting_for_permission.is_some() {
+            if let Err(err) = permission::open_settings() {
+                self.warnings
+                    .push(format!("Can't open the privacy settings: {err}"));
+            }
+        } else if pressed(keys.browser) && !self.recording {
+            self.open_browser();
+        } else if pressed(keys.marker) && self.recording {
+            if let Some(engine) = &self.engine {
+                engine.add_marker();
+            }
+        } else if pressed(keys.find_input)
+            && self.recording
+            && self.silent.is_some()
+            && self.finding_input.is_none()
+        {
+            self.switch_to_active_input();
+        } else if pressed(keys.record) && self.recording && self.standing_by.is_some() {
+            if let Some(engine) = &self.engine {
+                engine.record();
+            }
+        } else if pressed(keys.stop) && self.recording {
+            self.stop_recording();
+        } else if pressed(keys.pause) && self.recording {
+            if let Some(engine) = &self.engine {
+                engine.set_paused(!engine.paused());
+            }
+        } else if pressed(keys.play) && !self.recording {
+            self.toggle_playback();
+        } else if (pressed(keys.gain_up) || pressed(keys.gain_down)) && self.recording {
+            if let Some(engine) = &self.engine {
+                let step = if pressed(keys.gain_up) {
+                    GAIN_STEP_DB
+                } else {
+                    -GAIN_STEP_DB
+                };
+                engine.set_gain_db(engine.gain_db() + step);
+            }
+        } else if pressed(keys.save_clip) && self.recording && self.buffering() {
+            if let Some(engine) = &self.engine {
+                engine.save_clip(Duration::from_secs(self.config.clip_secs));
+            }
+        } else if pressed(keys.monitor) && self.recording {
+            if let Some(engine) = &self.engine {
+                engine.set_monitoring(!self.monitoring);
+            }
+        } else if pressed(keys.high_pass) && self.recording {
+            if let Some(engine) = &self.engine {
+                engine.set_high_pass(!engine.high_pass());
+            }
+        } else if pressed(keys.noise_gate) && self.recording {
+            if let Some(engine) = &self.engine {
+                engine.set_noise_gate(!engine.noise_gate());
+            }
+        } else if pressed(keys.learn_noise) && self.recording {
+            if let Some(engine) = &self.engine {
+                engine.learn_noise();
+                self.noise_reduction = NoiseReduction::Learning;
+            }
+        } else if pressed(keys.mid_side) && self.stereo.is_some() {
+            self.show_mid_side = !self.show_mid_side;
+        } else if pressed(keys.clear_clip) {
+            if let Some(engine) = &self.engine {
+                engine.reset_clip();
+            }
+        } else if let Some(channel) = self.channel_for_key(key_event) {
+            if let Some(engine) = &self.engine {
+                engine.set_inverted(channel, !engine.is_inverted(channel));
+            }
+        } else if pressed(keys.quit) {
+            self.exit();
+        }
+    }
+
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent) {
+        if self.tagging.is_some() {
+            return;
+        }
+        let at = Position::new(mouse_event.column, mouse_event.row);
+        let clicked = self
+            .click_targets
+            .borrow()
+            .iter()
+            .find(|(area, _)| area.contains(at))
+            .copied();
+        let slider = self
+            .click_targets
+            .borrow()
+            .iter()
+            .find(|(_, target)| matches!(target, ClickTarget::GainSlider))
+            .map(|(track, _)| *track);
+        let engine = self.engine.as_ref().filter(|_| self.recording);
+        match mouse_event.kind {
+            MouseEventKind::Down(MouseButton::Left) if self.show_help => self.show_help = false,
+            MouseEventKind::Down(MouseButton::Left) if matches!(self.screen, Screen::Recorder) => {
+                match clicked {
+                    Some((_, ClickTarget::Key(key))) => self.handle_key_event(key.event()),
+                    Some((track, ClickTarget::GainSlider)) => {
+                        if let Some(engine) = engine {
+                            engine.set_gain_db(gain_at(track, mouse_event.column));
+                            self.dragging_gain = true;
+                        }
+                    }
+                    Some((_, ClickTarget::Bars)) => {
+                        self.config.visualization = match self.config.visualization {
+                            VisualizationStyle::Mirrored => VisualizationStyle::Bars,
+                            VisualizationStyle::Bars => VisualizationStyle::Mirrored,
+                        };
+                    }
+                    None => {}
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.dragging_gain => {
+                if let (Some(engine), Some(track)) = (engine, slider) {
+                    engine.set_gain_db(gain_at(track, mouse_event.column));
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.dragging_gain = false,
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                if let (Some(engine), Some((_, ClickTarget::GainSlider))) = (engine, clicked) {
+                    let step = if mouse_event.kind == MouseEventKind::ScrollUp {
+                        GAIN_STEP_DB
+                    } else {
+                        -GAIN_STEP_DB
+                    };
+                    engine.set_gain_db(engine.gain_db() + step);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Switches to the list of previous takes. Only offered while not recording,
+    /// so the file being written can't be renamed or deleted from under the engine.
+    fn open_browser(&mut self) {
+        if let Some(playback) = self.playback.take() {
+            playback.stop();
+        }
+        let dir = match &self.options {
+            Some(options) => options.output_dir.clone(),
+            None => self.config.output_dir.clone(),
+        };
+        self.screen = Screen::Browser(Box::new(Browser::open(dir, self.config.keys.clone())));
+    }
+
+    /// Input channel whose polarity the digit key in `key_event` toggles while
+    /// recording.
+    fn channel_for_key(&self, key_event: KeyEvent) -> Option<usize> {
+        let KeyCode::Char(key) = key_event.code else {
+            return None;
+        };
+        let channel = key.to_digit(10)?.checked_sub(1)? as usize;
+        (self.recording && channel < self.levels.len()).then_some(channel)
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(engine) = &self.engine {
+            engine.stop();
+        }
+        self.broadcast(TransportCommand::Stop);
+        self.recording = false;
+        self.monitoring = false;
+    }
+
+    /// Probes the other inputs in the background, which takes a moment for each,
+    /// see [`App::poll_active_input`].
+    fn switch_to_active_input(&mut self) {
+        let host = self.options.as_ref().and_then(|o| o.host);
+        let current = self.options.as_ref().and_then(|o| o.device.clone());
+        let (result_tx, result) = mpsc::channel();
+        thread::spawn(move || {
+            result_tx
+                .send(probe::find_active_input(host, current.as_deref()))
+                .ok();
+        });
+        self.finding_input = Some(result);
+    }
+
+    /// Once the other inputs have been probed, restarts the take on one that picks
+    /// up sound, if there is one. The silent take is thrown away.
+    fn poll_active_input(&mut self) {
+        let Some(result) = &self.finding_input else {
+            return;
+        };
+        let result = match result.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Ok(None),
+        };
+        self.finding_input = None;
+        // The take stopped while the inputs were being probed
+        if !self.recording || self.silent.is_none() {
+            return;
+        }
+        self.silent = None;
+        let input = match result {
+            Ok(Some(input)) => input,
+            Ok(None) => {
+                self.warnings
+                    .push(String::from("No other input is picking up sound"));
+                return;
+            }
+            Err(err) => {
+                self.warnings
+                    .push(format!("Couldn't check other inputs: {err:#}"));
+                return;
+            }
+        };
+
+        self.discard_take();
+        self.warnings.push(format!("Switched to {}", input.name));
+        if let Some(options) = &mut self.options {
+            options.device = Some(input.name);
+        }
+        self.start_recording();
+    }
+
+    /// Does what the matching key would for a message from a remote controller.
+    fn handle_control_message(&mut self, message: ControlMessage) {
+        let engine = self.engine.as_ref().filter(|_| self.recording);
+        match message {
+            ControlMessage::Record if self.take_stopped() || self.cancelled => self.arm(),
+            ControlMessage::Record => {
+                if let Some(engine) = engine.filter(|_| self.standing_by.is_some()) {
+                    engine.record();
+                }
+            }
+            ControlMessage::Stop if self.recording => self.stop_recording(),
+            ControlMessage::Stop => {}
+            ControlMessage::Pause => {
+                if let Some(engine) = engine {
+                    engine.set_paused(!engine.paused());
+                }
+            }
+            ControlMessage::Marker => {
+                if let Some(engine) = engine {
+                    engine.add_marker();
+                }
+            }
+            ControlMessage::Gain(gain_db) => {
+                if let Some(engine) = engine {
+                    engine.set_gain_db(gain_db);
+                }
+            }
+        }
+    }
+
+    /// Whether a take was just recorded and saved, and the next one can follow.
+    fn take_stopped(&self) -> bool {
+        !self.recording
+            && self.error.is_none()
+            && self.options.is_some()
+            && !self.buffering()
+            && matches!(self.save_result, Some(Ok(_)))
+    }
+
+    /// The recording the tag key applies to: the take just saved, or the one
+    /// being reviewed.
+    fn taggable_take(&self) -> Option<&Path> {
+        if self.recording || self.error.is_some() || self.buffering() {
+            return None;
+        }
+        match (&self.save_result, &self.loaded_from) {
+            (Some(Ok(path)), _) | (None, Some(path)) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Writes `tags` into the stopped take, and keeps them for the next prompt.
+    fn tag_take(&mut self, tags: Tags) {
+        let Some(path) = self.taggable_take().map(Path::to_path_buf) else {
+            return;
+        };
+        match tags::write(&path, &tags) {
+            Ok(()) => self.warnings.push(format!("Tagged {}", path.display())),
+            Err(err) => self.warnings.push(format!("Tagging failed: {err}")),
+        }
+        self.tags = tags;
+    }
+
+    /// Deletes the stopped take, along with everything saved next to it, and arms
+    /// the next one. Its take number isn't reused.
+    fn retake(&mut self) {
+        if let Some(playback) = self.playback.take() {
+            playback.stop();
+        }
+        let Some(Ok(path)) = self.save_result.take() else {
+            return;
+        };
+        if let Some(options) = &mut self.options {
+            if options.safety_track {
+                fs::remove_file(naming::safety_track_path(&path)).ok();
+            }
+            let template = options.format.file_template(&options.file_template);
+            if let Some(take) = naming::take_number(&path, &template) {
+                options.after_take = options.after_take.max(take);
+            }
+        }
+        for sidecar in self.sidecars.drain(..) {
+            fs::remove_file(sidecar).ok();
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => self.warnings.push(format!("Discarded {}", path.display())),
+            Err(err) => self
+                .warnings
+                .push(format!("Couldn't delete {}: {err}", path.display())),
+        }
+        self.arm();
+    }
+
+    /// Stops the engine and deletes the file it was writing.
+    fn discard_take(&mut self) {
+        let Some(engine) = self.engine.take() else {
+            return;
+        };
+        engine.stop();
+        while let Ok(event) = engine.next_event(Duration::from_secs(1)) {
+            // Markers kept inside the recording go with it below
+            if let EngineEvent::SidecarSaved(Ok(sidecar)) = &event {
+                fs::remove_file(sidecar).ok();
+            }
+            if matches!(event, EngineEvent::Cancelled) {
+                break;
+            }
+            if let EngineEvent::Finished(result) = event {
+                // A buffer is gone already, and its clips were saved on purpose
+                if let Some(path) = result.ok().filter(|_| !self.buffering()) {
+                    if self.options.as_ref().is_some_and(|o| o.safety_track) {
+                        fs::remove_file(naming::safety_track_path(&path)).ok();
+                    }
+                    fs::remove_file(path).ok();
+                }
+                break;
+            }
+        }
+    }
+
+    /// Plays the stopped take from its file, decoded in the background, or stops it.
+    fn toggle_playback(&mut self) {
+        if self.loading.take().is_some() {
+            return;
+        }
+        if let Some(playback) = self.playback.take() {
+            if !playback.is_finished() {
+                playback.stop();
+                return;
+            }
+        }
+
+        let Some(path) = self.taggable_take().map(Path::to_path_buf) else {
+            return;
+        };
+        let (audio_tx, audio) = mpsc::channel();
+        thread::spawn(move || audio_tx.send(decoder::decode_file(&path)).ok());
+        self.loading = Some(audio);
+    }
+
+    /// Starts playing the take once it's decoded.
+    fn poll_playback(&mut self) {
+        let Some(result) = self
+            .loading
+            .as_ref()
+            .and_then(|audio| audio.try_recv().ok())
+        else {
+            return;
+        };
+        self.loading = None;
+        let result = result.and_then(|audio| {
+            let config = audio.stream_config();
+            Playback::start(Arc::from(audio.samples), config)
+        });
+        match result {
+            Ok(playback) => self.playback = Some(playback),
+            Err(err) => self.warnings.push(format!("Playback failed: {err:#}")),
+        }
+    }
+
+    /// Whether the take goes into a circular buffer rather than a file.
+    fn buffering(&self) -> bool {
+        self.options.as_ref().is_some_and(|o| o.buffer.is_some())
+    }
+
+    /// Length of audio received so far.
+    fn recorded_duration(&self) -> Duration {
+        if self.buffering() {
+            let stats = self.engine.as_ref().and_then(Recorder::stats);
+            return stats.map_or(Duration::ZERO, |stats| stats.elapsed);
+        }
+        let Some(config) = &self.stream_config else {
+            return Duration::ZERO;
+        };
+        Duration::from_secs_f64(self.recorded_frames as f64 / config.sample_rate.0 as f64)
+    }
+
+    fn is_playing(&self) -> bool {
+        self.playback.as_ref().is_some_and(|p| !p.is_finished())
+    }
+
+    fn process_audio_samples(&mut self, samples: &[f32]) {
+        if self.config.bar_history_ms > 0 {
+            self.scroll_bars(samples);
+            return;
+        }
+        // Per-block smoothing factors for the configured attack and release times
+        let block = self.stream_config.as_ref().map_or(0.01, |config| {
+            let frames = samples.len() / config.channels.max(1) as usize;
+            frames as f32 / config.sample_rate.0 as f32
+        });
+        let smoothing = |ms: u64| (-block * 1000.0 / ms.max(1) as f32).exp();
+        let attack = smoothing(self.config.bar_attack_ms);
+        let release = smoothing(self.config.bar_release_ms);
+
+        if let Ok(mut bars) = self.bar_values.lock() {
+            let chunk_size = samples.len() / bars.len();
+            if chunk_size == 0 {
+                return;
+            }
+
+            let num_bars = bars.len();
+            for (i, bar_value) in bars.iter_mut().enumerate() {
+                let start = i * chunk_size;
+                let end = if i == num_bars - 1 {
+                    samples.len()
+                } else {
+                    (i + 1) * chunk_size
+                };
+
+                let chunk = &samples[start..end];
+                let target_value = bar_level(&self.config, dsp::rms(chunk));
+
+                // Asymmetric smoothing: fast rise, slow decay
+                let smoothing = if target_value > *bar_value {
+                    attack
+                } else {
+                    release
+                };
+                *bar_value = *bar_value * smoothing + target_value * (1.0 - smoothing);
+            }
+        }
+    }
+
+    /// Fills the rightmost bar with the level of the current window, moving the
+    /// bars left each time one is complete.
+    fn scroll_bars(&mut self, samples: &[f32]) {
+        let Some(config) = &self.stream_config else {
+            return;
+        };
+        let frames = self.config.bar_history_ms * config.sample_rate.0 as u64 / 1000;
+        let window = (frames as usize * config.channels.max(1) as usize).max(1);
+        let Ok(mut bars) = self.bar_values.lock() else {
+            return;
+        };
+
+        let mut rest = samples;
+        while !rest.is_empty() {
+            let (chunk, after) = rest.split_at((window - self.bar_window.samples).min(rest.len()));
+            self.bar_window.sum_of_squares += chunk.iter().map(|&x| x * x).sum::<f32>();
+            self.bar_window.samples += chunk.len();
+            rest = after;
+
+            let level = bar_level(&self.config, self.bar_window.rms());
+            if let Some(last) = bars.last_mut() {
+                *last = level;
+            }
+            if self.bar_window.samples >= window {
+                bars.rotate_left(1);
+                if let Some(last) = bars.last_mut() {
+                    *last = 0.0;
+                }
+                self.bar_window = BarWindow::default();
+            }
+        }
+    }
+
+    fn handle_events(&mut self) -> io::Result<()> {
+        match event::read()? {
+            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                self.handle_key_event(key_event)
+            }
+            Event::Mouse(mouse_event) => self.handle_mouse_event(mouse_event),
+            Event::Resize(_, _) => {
+                // Terminal resize will be handled in the next draw call
+            }
+            _ => {}
+        };
+        Ok(())
+    }
+}
+
+impl Widget for &App {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let keys = &self.config.keys;
+        let (action, key) = if self.error.is_some() {
+            (" Retry ", keys.retry)
+        } else if self.recording && self.standing_by.is_some() {
+            (" Record ", keys.record)
+        } else if self.recording {
+            (" Stop ", keys.stop)
+        } else if self.is_playing() {
+            (" Stop ", keys.play)
+        } else {
+            (" Play ", keys.play)
+        };
+        let mut hints = Hints::default();
+        hints.push(action, key);
+        let paused = self.engine.as_ref().is_some_and(Recorder::paused);
+        if self.recording && self.waiting_for_permission.is_some() {
+            hints.push(" Open settings ", keys.retry);
+        }
+        if self.recording && self.error.is_none() {
+            hints.push(if paused { " Resume " } else { " Pause " }, keys.pause);
+        }
+        if self.recording && self.silent.is_some() && self.finding_input.is_none() {
+            hints.push(" Find input ", keys.find_input);
+        }
+        if self.recording {
+            let action = if self.monitoring {
+                " Stop monitoring "
+            } else {
+                " Monitor "
+            };
+            hints.push(action, keys.monitor);
+            let action = match self.noise_reduction {
+                NoiseReduction::Off => " Learn noise ",
+                NoiseReduction::Learning | NoiseReduction::On => " Relearn noise ",
+            };
+            hints.push(action, keys.learn_noise);
+            hints.push(" High-pass ", keys.high_pass);
+            hints.push(" Gate ", keys.noise_gate);
+        }
+        if self.recording && self.buffering() {
+            let length = Duration::from_secs(self.config.clip_secs);
+            hints.push(
+                format!(" Save last {} ", humantime::format_duration(length)),
+                keys.save_clip,
+            );
+        }
+        if self.recording {
+            hints.push(" Marker ", keys.marker);
+        }
+        if self.recording && self.stereo.is_some() {
+            let action = if self.show_mid_side {
+                " Hide M/S "
+            } else {
+                " M/S "
+            };
+            hints.push(action, keys.mid_side);
+        }
+        if self.recording && !self.levels.is_empty() {
+            hints.line.push_span(" Invert ");
+            let channels = match self.levels.len() {
+                1 => String::from("<1>"),
+                n => format!("<1-{}>", n.min(9)),
+            };
+            hints.line.push_span(channels.blue().bold());
+        }
+        if self.recording && self.levels.iter().any(|levels| levels.clipped) {
+            hints.push(" Clear clip ", keys.clear_clip);
+        }
+        if self.take_stopped() {
+            hints.push(" Next take ", keys.next_take);
+            hints.push(" Retake ", keys.retake);
+        } else if self.cancelled {
+            hints.push(" Next take ", keys.next_take);
+        }
+        if self.taggable_take().is_some() {
+            hints.push(" Tag ", keys.tag);
+        }
+        if !self.recording && self.error.is_none() {
+            hints.push(" Recordings ", keys.browser);
+        }
+        hints.push(" Help ", keys.help);
+        hints.push(" Quit ", keys.quit);
+        hints.line.push_span(" ");
+
+        let status = if self.error.is_some() {
+            " Can't record".red().bold()
+        } else if let Some(device) = self.disconnected.as_ref().filter(|_| self.recording) {
+            format!(" Device disconnected, waiting for {device}...")
+                .red()
+                .bold()
+        } else if let Some(message) = self
+            .waiting_for_permission
+            .as_ref()
+            .filter(|_| self.recording)
+        {
+            format!(" Waiting for microphone access: {message}")
+                .yellow()
+                .bold()
+        } else if let Some(at) = self.scheduled.filter(|_| self.recording) {
+            let left = (at - Local::now()).to_std().unwrap_or_default();
+            format!(" Armed (starts in {})", format_duration(left))
+                .yellow()
+                .bold()
+        } else if let Some(pre_roll) = self.standing_by.filter(|_| self.recording) {
+            format!(
+                " Standing by, keeping the last {}",
+                format_duration(pre_roll)
+            )
+            .yellow()
+            .bold()
+        } else if self.recording {
+            let action = match (self.buffering(), self.monitoring) {
+                _ if paused => "Paused",
+                (true, true) => "Buffering and monitoring",
+                (true, false) => "Buffering",
+                (false, true) => "Recording and monitoring",
+                (false, false) => "Recording",
+            };
+            let gain_db = self.engine.as_ref().map_or(0.0, Recorder::gain_db);
+            let limiter = if self.options.as_ref().is_some_and(|o| o.soft_limit) {
+                ", limiter on"
+            } else {
+                ""
+            };
+            let mut filters = String::new();
+            if self.engine.as_ref().is_some_and(Recorder::high_pass) {
+                filters.push_str(", high-pass");
+            }
+            if self.engine.as_ref().is_some_and(Recorder::noise_gate) {
+                filters.push_str(", gate");
+            }
+            let noise = match self.noise_reduction {
+                NoiseReduction::Off => String::new(),
+                NoiseReduction::Learning => format!(
+                    ", learning noise (stay quiet for {}s)",
+                    NOISE_LEARN_DURATION.as_secs()
+                ),
+                NoiseReduction::On => String::from(", noise reduction on"),
+            };
+            let markers = match (self.markers.len(), self.markers.last()) {
+                (_, None) => String::new(),
+                (1, Some(&last)) => format!(", 1 marker at {}", format_duration(last)),
+                (count, Some(&last)) => {
+                    format!(", {count} markers, last at {}", format_duration(last))
+                }
+            };
+            let clip = match &self.last_clip {
+                Some(clip) => format!(", saved {}", clip.display()),
+                None => String::new(),
+            };
+            let stats = self
+                .engine
+                .as_ref()
+                .and_then(Recorder::stats)
+                .map(format_stats)
+                .unwrap_or_default();
+            format!(
+                " {action}{stats}... Gain {gain_db:+.0} dB{limiter}{filters}{noise}{markers}{clip}"
+            )
+            .red()
+            .bold()
+        } else if let Some(playback) = self.playback.as_ref().filter(|_| self.is_playing()) {
+            format!(
+                " Playing {} / {}",
+                format_duration(playback.elapsed()),
+                format_duration(playback.duration())
+            )
+            .blue()
+            .bold()
+        } else {
+            match (&self.save_result, &self.loaded_from) {
+                (Some(Ok(path)), _) => format!(" Saved {}", path.display()).green().bold(),
+                (Some(Err(err)), _) => format!(" Save failed: {err}").red().bold(),
+                (None, Some(path)) => format!(" {}", path.display()).green().bold(),
+                (None, None) => match self.transcoding {
+                    Some(done) => format!(" Transcoding {}", progress_bar(done, 20))
+                        .green()
+                        .bold(),
+                    None => " Processing...".green().bold(),
+                },
+            }
+        };
+
+        let role = match self.instance.as_ref().map(|instance| instance.role) {
+            Some(Role::Master) => " Session master ".bold(),
+            Some(Role::Follower) => " Following session master ".bold(),
+            _ => "".into(),
+        };
+
+        let mut targets = self.click_targets.borrow_mut();
+        targets.clear();
+        let hints_width = hints.line.width() as u16;
+        if hints_width <= area.width && area.height > 0 {
+            let x = area.right() - hints_width;
+            for (columns, key) in &hints.keys {
+                let hint = Rect::new(
+                    x + columns.start,
+                    area.bottom() - 1,
+                    columns.len() as u16,
+                    1,
+                );
+                targets.push((hint, ClickTarget::Key(*key)));
+            }
+        }
+
+        let mut block = Block::new()
+            .title_top(Line::from(role).right_aligned())
+            .title_bottom(Line::from(status).left_aligned())
+            .title_bottom(hints.line.right_aligned());
+        if self.recording && !self.devices.is_empty() {
+            let mut legend = Line::default();
+            for (letter, (name, _)) in ('A'..='Z').zip(&self.devices) {
+                legend.push_span(format!(" {letter} ").bold());
+                legend.push_span(format!("{name} "));
+            }
+            block = block.title_top(legend);
+        }
+        for warning in self.warnings.iter().chain(&self.stream_error) {
+            block = block.title_top(Line::from(format!(" {warning} ").yellow().bold()));
+        }
+        if let Some(free) = self.low_disk_space.filter(|_| self.recording) {
+            block = block.title_top(Line::from(
+                format!(
+                    " Disk almost full: {} left, recording stops at {} MiB ",
+                    format_size(free),
+                    self.config.min_free_space_mb
+                )
+                .white()
+                .on_red()
+                .bold(),
+            ));
+        }
+        if self.muted && self.recording {
+            block = block.title_top(Line::from(
+                " Input appears muted: check the mic's mute switch and the system input volume "
+                    .white()
+                    .on_red()
+                    .bold(),
+            ));
+        } else if self.finding_input.is_some() && self.recording {
+            block = block.title_top(Line::from(
+                " Listening for sound on the other inputs... "
+                    .yellow()
+                    .bold(),
+            ));
+        } else if let Some(after) = self.silent.filter(|_| self.recording) {
+            block = block.title_top(Line::from(
+                format!(" No signal for {}s, is the mic muted? ", after.as_secs())
+                    .yellow()
+                    .bold(),
+            ));
+        }
+
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        if let Some(err) = &self.error {
+            let [_, message_area, _] = Layout::vertical([
+                Constraint::Fill(1),
+                Constraint::Length(3),
+                Constraint::Fill(1),
+            ])
+            .areas(inner);
+            Paragraph::new(vec![
+                Line::from("Recording could not start").red().bold(),
+                Line::from(err.as_str()),
+            ])
+            .centered()
+            .wrap(Wrap { trim: true })
+            .render(message_area, buf);
+            return;
+        }
+
+        // The take's loudness takes the bottom row once it's stopped
+        let inner = match self.loudness.filter(|_| !self.recording) {
+            Some(report) => {
+                let [rest, report_area] =
+                    Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(inner);
+                render_loudness(&report, &self.theme, report_area, buf);
+                rest
+            }
+            None => inner,
+        };
+
+        // The target progress bar takes the bottom row while recording
+        let inner = match self.config.target_secs.filter(|_| self.recording) {
+            Some(target) => {
+                let [rest, target_area] =
+                    Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(inner);
+                let target = Duration::from_secs(target);
+                render_target_progress(
+                    self.recorded_duration(),
+                    target,
+                    &self.theme,
+                    target_area,
+                    buf,
+                );
+                rest
+            }
+            None => inner,
+        };
+
+        // And the gain slider the row above, when the mouse can drag it
+        let inner = if self.recording && self.config.mouse {
+            let [rest, slider_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(inner);
+            let gain_db = self.engine.as_ref().map_or(0.0, Recorder::gain_db);
+            let track = render_gain_slider(gain_db, &self.theme, slider_area, buf);
+            targets.push((track, ClickTarget::GainSlider));
+            rest
+        } else {
+            inner
+        };
+
+        // Level meters take the bottom rows while recording, one per channel
+        let inner = if self.recording && !self.levels.is_empty() {
+            let labelled = self.levels.len() > 1;
+            let mut meters: Vec<(Option<String>, MeterReading)> = self
+                .levels
+                .iter()
+                .enumerate()
+                .map(|(i, levels)| {
+                    let inverted = self.engine.as_ref().is_some_and(|e| e.is_inverted(i));
+                    let channel = channel_label(i, self.levels.len(), &self.devices);
+                    let label = match (labelled, inverted) {
+                        (true, true) => Some(format!("Ø{channel}")),
+                        (true, false) => Some(channel),
+                        (false, true) => Some(String::from("Ø")),
+                        (false, false) => None,
+                    };
+                    (label, *levels)
+                })
+                .collect();
+            let stereo = self.stereo.filter(|_| self.show_mid_side);
+            if let Some(stereo) = stereo {
+                meters.push((Some(String::from("M")), stereo.mid));
+                meters.push((Some(String::from("S")), stereo.side));
+            }
+
+            let rows = (meters.len() as u16 + stereo.is_some() as u16).min(inner.height / 2);
+            let [bars_area, meters_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(rows)]).areas(inner);
+            let mut rows = meters_area.rows();
+            for ((label, levels), row) in meters.iter().zip(&mut rows) {
+                render_level_meter(*levels, label.as_deref(), &self.theme, row, buf);
+            }
+            if let (Some(stereo), Some(row)) = (stereo, rows.next()) {
+                render_stereo_readout(stereo, row, buf);
+            }
+            bars_area
+        } else {
+            inner
+        };
+
+        targets.push((inner, ClickTarget::Bars));
+        drop(targets);
+        let bar_values = self.bar_values.lock().unwrap();
+
+        let mirrored = self.config.visualization == VisualizationStyle::Mirrored;
+        let (center_y, max_bar_height) = if mirrored {
+            (
+                inner.y + inner.height / 2,
+                (inner.height / 2).saturating_sub(3),
+            )
+        } else {
+            // Baseline on the bottom row, leaving the same headroom as mirrored mode
+            let baseline = (inner.y + inner.height).saturating_sub(1);
+            (baseline, inner.height.saturating_sub(3))
+        };
+
+        let available_width = inner.width;
+        let bar_spacing = 2; // 1 char for bar + 1 char gap
+        let num_bars = bar_values.len() as u16;
+
+        if num_bars == 0 || available_width < bar_spacing {
+            return; // No bars to render or terminal too small
+        }
+
+        // Calculate starting position to center all bars
+        // Note: we don't need the gap after the last bar, so subtract 1 from total width
+        let total_width = (num_bars * bar_spacing).saturating_sub(1);
+        let start_x = inner.x + (available_width.saturating_sub(total_width)) / 2;
+
+        // Bars before the playhead are highlighted while the take is playing back
+        let playhead = self
+            .playback
+            .as_ref()
+            .filter(|_| self.is_playing())
+            .map(|playback| (playback.progress() * num_bars as f32) as usize);
+
+        for (i, &value) in bar_values.iter().enumerate() {
+            let bar_x = start_x + (i as u16 * bar_spacing);
+
+            // Ensure bar is within bounds
+            if bar_x >= inner.x + inner.width {
+                break;
+            }
+
+            let bar_height = (value * max_bar_height as f32) as u16;
+
+            let played = playhead.is_some_and(|played| i < played);
+            let bar_color = self.theme.bar(value, played);
+
+            for j in 0..bar_height {
+                if center_y > inner.y + j {
+                    buf[(bar_x, center_y - j - 1)]
+                        .set_char('█')
+                        .set_fg(bar_color);
+                }
+                if mirrored && center_y + j + 1 < inner.y + inner.height {
+                    buf[(bar_x, center_y + j + 1)]
+                        .set_char('█')
+                        .set_fg(bar_color);
+                }
+            }
+
+            buf[(bar_x, center_y)]
+                .set_char('█')
+                .set_fg(self.theme.baseline());
+        }
+    }
+}
+
+/// Short name for channel `index` of `count`: L and R for stereo, numbers otherwise.
+/// With several `devices`, the device's letter and its own channel number, as in
+/// A1, A2, B1.
+fn channel_label(index: usize, count: usize, devices: &[(String, u16)]) -> String {
+    let mut first = 0;
+    for (letter, (_, channels)) in ('A'..='Z').zip(devices) {
+        let channels = *channels as usize;
+        if index < first + channels {
+            return format!("{letter}{}", index - first + 1);
+        }
+        first += channels;
+    }
+    match (count, index) {
+        (2, 0) => String::from("L"),
+        (2, 1) => String::from("R"),
+        _ => (index + 1).to_string(),
+    }
+}
+
+/// Popup listing every action with the key bound to it.
+struct Help<'a>(&'a KeyBindings);
+
+impl Widget for Help<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let bindings = self.0.describe();
+        let [area] = Layout::horizontal([Constraint::Length(48)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Length(bindings.len() as u16 + 2)])
+            .flex(Flex::Center)
+            .areas(area);
+        Clear.render(area, buf);
+        let block = Block::bordered()
+            .title_top(Line::from(" Keys ".bold()).centered())
+            .title_bottom(Line::from(" Close <any key> ").centered());
+        let rows = bindings
+            .into_iter()
+            .map(|(action, key)| Row::new(vec![action.into(), key.label().blue().bold()]));
+        Table::new(rows, [Constraint::Fill(1), Constraint::Length(10)])
+            .block(block)
+            .render(area, buf);
+    }
+}
+
+/// Names of the fields of a [`TagPrompt`], in order.
+const TAG_FIELDS: [&str; 3] = ["Title", "Artist", "Comment"];
+
+/// Tags being typed in, one field at a time.
+#[derive(Debug)]
+struct TagPrompt {
+    fields: [String; 3],
+    selected: usize,
+}
+
+impl TagPrompt {
+    fn new(tags: &Tags) -> Self {
+        let field = |value: &Option<String>| value.clone().unwrap_or_default();
+        Self {
+            fields: [
+                field(&tags.title),
+                field(&tags.artist),
+                field(&tags.comment),
+            ],
+            selected: 0,
+        }
+    }
+
+    fn tags(&self) -> Tags {
+        let field = |i: usize| Some(self.fields[i].trim().to_string()).filter(|v| !v.is_empty());
+        Tags {
+            title: field(0),
+            artist: field(1),
+            comment: field(2),
+        }
+    }
+}
+
+impl Widget for &TagPrompt {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [area] = Layout::horizontal([Constraint::Length(60)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Length(TAG_FIELDS.len() as u16 + 2)])
+            .flex(Flex::Center)
+            .areas(area);
+        Clear.render(area, buf);
+        let block = Block::bordered()
+            .title_top(Line::from(" Tag take ".bold()).centered())
+            .title_bottom(Line::from(vec![
+                " Save ".into(),
+                "<Enter>".blue().bold(),
+                " Next field ".into(),
+                "<Tab>".blue().bold(),
+                " Cancel ".into(),
+                "<Esc> ".blue().bold(),
+            ]));
+        let rows = TAG_FIELDS
+            .iter()
+            .zip(&self.fields)
+            .enumerate()
+            .map(|(i, (name, value))| {
+                if i == self.selected {
+                    Row::new(vec![name.bold(), format!("{value}█").bold()])
+                } else {
+                    Row::new(vec![name.to_string(), value.clone()])
+                }
+            });
+        Table::new(rows, [Constraint::Length(8), Constraint::Fill(1)])
+            .block(block)
+            .render(area, buf);
+    }
+}
+
+/// The input gain as a slider across [`GAIN_RANGE_DB`], with a tick at 0 dB and a
+/// readout. Returns the area of the track, for the mouse to drag the knob along.
+fn render_gain_slider(gain_db: f32, theme: &Theme, area: Rect, buf: &mut Buffer) -> Rect {
+    let label = Line::from(" Gain ".bold());
+    let readout = Line::from(format!(" {gain_db:+3.0} dB "));
+    let label_width = (label.width() as u16).min(area.width);
+    let readout_width = (readout.width() as u16).min(area.width - label_width);
+    let track = Rect {
+        x: area.x + label_width,
+        width: area.width - label_width - readout_width,
+        ..area
+    };
+    label.render(area, buf);
+
+    let column = |gain_db: f32| {
+        let (low, high) = (*GAIN_RANGE_DB.start(), *GAIN_RANGE_DB.end());
+        ((gain_db - low) / (high - low) * track.width.saturating_sub(1) as f32).round() as u16
+    };
+    let (knob, unity) = (column(gain_db), column(0.0));
+    for x in 0..track.width {
+        let cell = &mut buf[(track.x + x, track.y)];
+        if x == knob {
+            cell.set_char('●').set_fg(theme.accent());
+        } else if x == unity {
+            cell.set_char('┼').set_fg(theme.track());
+        } else if x < knob {
+            cell.set_char('━').set_fg(theme.accent());
+        } else {
+            cell.set_char('─').set_fg(theme.track());
+        }
+    }
+
+    let readout_area = Rect {
+        x: track.right(),
+        width: readout_width,
+        ..area
+    };
+    readout.render(readout_area, buf);
+    track
+}
+
+/// Gain for the knob of the slider with `track` at `column`, in whole dB.
+fn gain_at(track: Rect, column: u16) -> f32 {
+    let (low, high) = (*GAIN_RANGE_DB.start(), *GAIN_RANGE_DB.end());
+    let position = column
+        .saturating_sub(track.x)
+        .min(track.width.saturating_sub(1));
+    let fraction = position as f32 / track.width.saturating_sub(1).max(1) as f32;
+    (low + fraction * (high - low)).round()
+}
+
+/// Horizontal peak/RMS gauge with a peak-hold marker, dBFS readout and clip light,
+/// optionally prefixed with a channel label.
+fn render_level_meter(
+    levels: MeterReading,
+    label: Option<&str>,
+    theme: &Theme,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let area = match label {
+        Some(label) => {
+            let label = Line::from(format!(" {label:>2} ").bold());
+            let label_width = (label.width() as u16).min(area.width);
+            label.render(area, buf);
+            Rect {
+                x: area.x + label_width,
+                width: area.width - label_width,
+                ..area
+            }
+        }
+        None => area,
+    };
+
+    let clip = if levels.clipped {
+        " CLIP ".white().on_red().bold()
+    } else {
+        " CLIP ".dark_gray()
+    };
+    let readout = Line::from(vec![
+        format!(
+            " {:6.1} dBFS pk {:6.1} dBFS rms ",
+            levels.peak_dbfs, levels.rms_dbfs
+        )
+        .into(),
+        clip,
+    ]);
+
+    let readout_width = (readout.width() as u16).min(area.width);
+    let gauge_width = area.width - readout_width;
+    let position = |db: f32| {
+        let fraction = ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0);
+        (fraction * gauge_width as f32) as u16
+    };
+    let rms_x = position(levels.rms_dbfs);
+    let peak_x = position(levels.peak_dbfs);
+
+    for x in 0..gauge_width {
+        // Color by the level this cell stands for, like a hardware meter
+        let db = METER_FLOOR_DB * (1.0 - x as f32 / gauge_width as f32);
+        let color = theme.zone(if db > -3.0 {
+            Zone::Danger
+        } else if db > -12.0 {
+            Zone::Caution
+        } else {
+            Zone::Safe
+        });
+
+        let cell = &mut buf[(area.x + x, area.y)];
+        if x < rms_x {
+            cell.set_char('█').set_fg(color);
+        } else if x < peak_x {
+            cell.set_char('▒').set_fg(color);
+        } else {
+            cell.set_char('─').set_fg(theme.track());
+        }
+    }
+
+    if levels.peak_hold_dbfs > METER_FLOOR_DB && gauge_width > 0 {
+        let hold_x = position(levels.peak_hold_dbfs).min(gauge_width - 1);
+        buf[(area.x + hold_x, area.y)]
+            .set_char('│')
+            .set_fg(Color::White);
+    }
+
+    let readout_area = Rect {
+        x: area.x + gauge_width,
+        width: readout_width,
+        ..area
+    };
+    readout.render(readout_area, buf);
+}
+
+/// Correlation and width of the stereo image.
+fn render_stereo_readout(stereo: StereoReading, area: Rect, buf: &mut Buffer) {
+    let correlation = format!(" Correlation {:+.2} ", stereo.correlation);
+    let correlation = if stereo.correlation < 0.0 {
+        // Out of phase: cancels out when summed to mono
+        correlation.red().bold()
+    } else {
+        correlation.into()
+    };
+    let width = if stereo.width > 9.99 {
+        String::from(" Width >999% ")
+    } else {
+        format!(" Width {:.0}% ", stereo.width * 100.0)
+    };
+    Line::from(vec![correlation, width.into()]).render(area, buf);
+}
+
+/// Loudness of a stopped take on one line, with the true peak in red above the
+/// -1 dBTP that EBU R128 allows.
+fn render_loudness(report: &LoudnessReport, theme: &Theme, area: Rect, buf: &mut Buffer) {
+    let peak_zone = if report.true_peak_dbtp > -1.0 {
+        Zone::Danger
+    } else {
+        Zone::Safe
+    };
+    Line::from(vec![
+        " Loudness ".bold(),
+        format!("{:.1} LUFS", report.integrated_lufs).into(),
+        "  Range ".bold(),
+        format!("{:.1} LU", report.loudness_range_lu).into(),
+        "  True peak ".bold(),
+        format!("{:.1} dBTP", report.true_peak_dbtp).fg(theme.zone(peak_zone)),
+        "  Noise floor ".bold(),
+        format!("{:.0} dBFS", report.noise_floor_dbfs).into(),
+    ])
+    .render(area, buf);
+}
+
+/// Elapsed time against the target length. The bar turns yellow for the last
+/// fifth and red once the target is exceeded.
+fn render_target_progress(
+    elapsed: Duration,
+    target: Duration,
+    theme: &Theme,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let progress = elapsed.as_secs_f32() / target.as_secs_f32().max(1.0);
+    let (color, readout) = if elapsed > target {
+        let over = format_duration(elapsed - target);
+        (
+            theme.zone(Zone::Danger),
+            format!(" +{over} over {} ", format_duration(target)),
+        )
+    } else {
+        let color = theme.zone(if progress >= 0.8 {
+            Zone::Caution
+        } else {
+            Zone::Safe
+        });
+        let readout = format!(
+            " {} / {} ",
+            format_duration(elapsed),
+            format_duration(target)
+        );
+        (color, readout)
+    };
+
+    let readout = Line::from(readout.fg(color).bold());
+    let readout_width = (readout.width() as u16).min(area.width);
+    let bar_width = area.width - readout_width;
+    let filled = (progress.min(1.0) * bar_width as f32) as u16;
+    for x in 0..bar_width {
+        let cell = &mut buf[(area.x + x, area.y)];
+        if x < filled {
+            cell.set_char('█').set_fg(color);
+        } else {
+            cell.set_char('─').set_fg(theme.track());
+        }
+    }
+
+    let readout_area = Rect {
+        x: area.x + bar_width,
+        width: readout_width,
+        ..area
+    };
+    readout.render(readout_area, buf);
+}
+
+/// Height of a bar for an RMS level, on the configured scale.
+fn bar_level(config: &Config, rms: f32) -> f32 {
+    match config.visualization_scale {
+        VisualizationScale::Db => dsp::db_bar_level(rms, config.visualization_floor_dbfs),
+        VisualizationScale::Linear => dsp::bar_level(rms),
+    }
+}
+
+/// Length, size and room left of a take, for the status line.
+fn format_stats(stats: RecordingStats) -> String {
+    let mut text = format!(
+        " {}, {}",
+        format_duration(stats.elapsed),
+        format_size(stats.file_size)
+    );
+    if let Some(free) = stats.free_space {
+        text.push_str(&format!(", {} free", format_size(free)));
+    }
+    if let Some(left) = stats.time_left() {
+        let minutes = left.as_secs() / 60;
+        text.push_str(&format!(" (~{}h{:02}m left)", minutes / 60, minutes % 60));
+    }
+    if stats.overruns > 0 {
+        text.push_str(&format!(", {} overruns", stats.overruns));
+    }
+    text
+}
+
+/// `done`, from 0 to 1, as a bar `width` characters wide and a percentage.
+fn progress_bar(done: f32, width: usize) -> String {
+    let filled = (done.clamp(0.0, 1.0) * width as f32) as usize;
+    format!(
+        "{}{} {:.0}%",
+        "█".repeat(filled),
+        "─".repeat(width - filled),
+        done.clamp(0.0, 1.0) * 100.0
+    )
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+    use crate::config::ThemeName;
+    use micrec::source::{AudioSource, Signal, Synth};
+
+    fn theme() -> Theme {
+        Theme::new(ThemeName::Default, ColorSupport::Basic)
+    }
+
+    /// The text of each row of `buf`, without its colors, which depend on the theme.
+    fn rows(buf: &Buffer) -> Vec<String> {
+        let area = buf.area;
+        (area.top()..area.bottom())
+            .map(|y| {
+                (area.left()..area.right())
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn render(width: u16, height: u16, draw: impl FnOnce(Rect, &mut Buffer)) -> Vec<String> {
+        let area = Rect::new(0, 0, width, height);
+        let mut buf = Buffer::empty(area);
+        draw(area, &mut buf);
+        rows(&buf)
+    }
+
+    #[test]
+    fn level_meter() {
+        let levels = MeterReading {
+            peak_dbfs: -6.0,
+            rms_dbfs: -18.0,
+            peak_hold_dbfs: -3.0,
+            clipped: false,
+        };
+        let rows = render(80, 1, |area, buf| {
+            render_level_meter(levels, Some("L"), &theme(), area, buf)
+        });
+
+        assert_eq!(
+            rows,
+            ["  L ██████████████████████████▒▒▒▒▒▒▒▒──│─   -6.0 dBFS pk  -18.0 dBFS rms  CLIP ",]
+        );
+    }
+
+    #[test]
+    fn gain_slider() {
+        let rows = render(40, 1, |area, buf| {
+            render_gain_slider(6.0, &theme(), area, buf);
+        });
+
+        assert_eq!(rows, [" Gain ━━━━━━━━━━┼━━●────────────  +6 dB "]);
+    }
+
+    #[test]
+    fn loudness() {
+        let report = LoudnessReport {
+            integrated_lufs: -16.04,
+            loudness_range_lu: 4.2,
+            true_peak_dbtp: -0.5,
+            noise_floor_dbfs: -62.3,
+        };
+        let rows = render(90, 1, |area, buf| {
+            render_loudness(&report, &theme(), area, buf)
+        });
+
+        assert_eq!(rows, [
+                " Loudness -16.0 LUFS  Range 4.2 LU  True peak -0.5 dBTP  Noise floor -62 dBFS             ",
+            ]);
+    }
+
+    #[test]
+    fn tag_prompt() {
+        let mut prompt = TagPrompt::new(&Tags {
+            title: Some(String::from("Interview")),
+            artist: None,
+            comment: None,
+        });
+        prompt.selected = 1;
+        let rows = render(62, 5, |area, buf| prompt.render(area, buf));
+
+        assert_eq!(
+            rows,
+            [
+                " ┌──────────────────────── Tag take ────────────────────────┐ ",
+                " │Title    Interview                                        │ ",
+                " │Artist   █                                                │ ",
+                " │Comment                                                   │ ",
+                " └ Save <Enter> Next field <Tab> Cancel <Esc> ──────────────┘ ",
+            ]
+        );
+    }
+
+    #[test]
+    fn review_screen() {
+        let mut synth = Synth::new(Signal::Sine, 8000, 1);
+        let mut samples = vec![0.0; 8000];
+        synth.fill(&mut samples);
+        let audio = DecodedAudio {
+            samples,
+            channels: 1,
+            sample_rate: 8000,
+        };
+        let mut app = App::review(Config::default(), PathBuf::from("take.wav"), audio);
+        let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+
+        assert_eq!(
+            rows(terminal.backend().buffer()),
+            [
+                "                                                                                ",
+                "                                                                                ",
+                "                                                                                ",
+                "                                                                                ",
+                "                                                                                ",
+                "  █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █   ",
+                "  █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █   ",
+                "  █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █ █   ",
+                "                                                                                ",
+                "                                                                                ",
+                "                                                                                ",
+                " take.wav                   Play <p> Tag <t> Recordings <Tab> Help <?> Quit <q> ",
+            ]
+        );
+    }
+}
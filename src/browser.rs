@@ -0,0 +1,428 @@
+//! Second screen of the TUI: the recordings in the output directory, to play back,
+//! rename or delete.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use chrono::{DateTime, Local};
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Paragraph, Row, Table, Widget},
+};
+
+use crate::app::format_duration;
+use crate::config::KeyBindings;
+use micrec::decoder::{self, DecodedAudio};
+use micrec::library::{self, format_size, RecordingFile};
+use micrec::playback::Playback;
+
+/// What the app should do after the browser handled a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowserAction {
+    None,
+    /// Go back to the recording screen.
+    Back,
+    /// Go back and record a new take.
+    NewTake,
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mode {
+    Browsing,
+    /// Typing a new name for the selected file.
+    Renaming(String),
+    /// Waiting for `y` before deleting the selected file.
+    ConfirmingDelete,
+}
+
+#[derive(Debug)]
+pub struct Browser {
+    dir: PathBuf,
+    keys: KeyBindings,
+    files: Vec<RecordingFile>,
+    /// The directory being read again, until it has been.
+    listing: Option<Receiver<io::Result<Vec<RecordingFile>>>>,
+    /// File to select once the directory has been read.
+    reselect: Option<PathBuf>,
+    selected: usize,
+    mode: Mode,
+    /// File being decoded to play, until it has been.
+    loading: Option<(PathBuf, Receiver<color_eyre::Result<DecodedAudio>>)>,
+    /// File being played and its playback.
+    playback: Option<(PathBuf, Playback)>,
+    /// Outcome of the last action: what was done, or why it failed.
+    message: Option<Result<String, String>>,
+}
+
+impl Browser {
+    /// Lists the recordings in `dir`.
+    pub fn open(dir: PathBuf, keys: KeyBindings) -> Self {
+        let mut browser = Self {
+            dir,
+            keys,
+            files: Vec::new(),
+            listing: None,
+            reselect: None,
+            selected: 0,
+            mode: Mode::Browsing,
+            loading: None,
+            playback: None,
+            message: None,
+        };
+        browser.refresh(None);
+        browser
+    }
+
+    /// Reads the directory again in the background, since every file is probed for
+    /// its length, then selects `path` or else the same file if it's still there.
+    fn refresh(&mut self, path: Option<PathBuf>) {
+        let path = path.or_else(|| self.selected_file().map(|file| file.path.clone()));
+        let (files_tx, files) = mpsc::channel();
+        let dir = self.dir.clone();
+        thread::spawn(move || files_tx.send(library::list(&dir)).ok());
+        self.listing = Some(files);
+        self.reselect = path;
+    }
+
+    /// Picks up the directory listing and the file to play once they're ready.
+    pub fn poll(&mut self) {
+        if let Some(files) = &self.listing {
+            if let Ok(result) = files.try_recv() {
+                self.listing = None;
+                match result {
+                    Ok(files) => self.files = files,
+                    Err(err) => {
+                        self.files.clear();
+                        let dir = self.dir.display();
+                        self.message = Some(Err(format!("Can't read {dir}: {err}")));
+                    }
+                }
+                let path = self.reselect.take();
+                self.select_path(path.as_deref());
+            }
+        }
+        if let Some((path, audio)) = &self.loading {
+            if let Ok(result) = audio.try_recv() {
+                let path = path.clone();
+                self.loading = None;
+                let result = result.and_then(|audio| {
+                    let config = audio.stream_config();
+                    Playback::start(Arc::from(audio.samples), config)
+                });
+                match result {
+                    Ok(playback) => self.playback = Some((path, playback)),
+                    Err(err) => self.message = Some(Err(format!("Playback failed: {err:#}"))),
+                }
+            }
+        }
+    }
+
+    fn select_path(&mut self, path: Option<&Path>) {
+        let index = path.and_then(|path| self.files.iter().position(|f| f.path == path));
+        self.selected = index
+            .unwrap_or(self.selected)
+            .min(self.files.len().saturating_sub(1));
+    }
+
+    /// Whether keys are being typed into a new name.
+    pub fn is_renaming(&self) -> bool {
+        matches!(self.mode, Mode::Renaming(_))
+    }
+
+    fn selected_file(&self) -> Option<&RecordingFile> {
+        self.files.get(self.selected)
+    }
+
+    pub fn handle_key(&mut self, key_event: KeyEvent) -> BrowserAction {
+        match &mut self.mode {
+            Mode::Renaming(name) => {
+                match key_event.code {
+                    KeyCode::Char(c) => name.push(c),
+                    KeyCode::Backspace => {
+                        name.pop();
+                    }
+                    KeyCode::Enter => {
+                        let name = name.clone();
+                        self.mode = Mode::Browsing;
+                        self.rename_selected(&name);
+                    }
+                    KeyCode::Esc => self.mode = Mode::Browsing,
+                    _ => {}
+                }
+                return BrowserAction::None;
+            }
+            Mode::ConfirmingDelete => {
+                self.mode = Mode::Browsing;
+                if key_event.code == KeyCode::Char('y') {
+                    self.delete_selected();
+                }
+                return BrowserAction::None;
+            }
+            Mode::Browsing => {}
+        }
+
+        match key_event.code {
+            KeyCode::Esc => {
+                self.stop_playback();
+                return BrowserAction::Back;
+            }
+            _ if self.keys.browser.matches(&key_event) => {
+                self.stop_playback();
+                return BrowserAction::Back;
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(self.files.len().saturating_sub(1))
+            }
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = self.files.len().saturating_sub(1),
+            KeyCode::Enter => self.toggle_playback(),
+            _ if self.keys.play.matches(&key_event) => self.toggle_playback(),
+            _ if self.keys.rename.matches(&key_event) => {
+                if let Some(file) = self.selected_file() {
+                    let stem = file.path.file_stem().unwrap_or_default();
+                    self.mode = Mode::Renaming(stem.to_string_lossy().into_owned());
+                }
+            }
+            _ if self.keys.delete.matches(&key_event) && self.selected_file().is_some() => {
+                self.mode = Mode::ConfirmingDelete;
+            }
+            _ if self.keys.new_take.matches(&key_event) => {
+                self.stop_playback();
+                return BrowserAction::NewTake;
+            }
+            _ if self.keys.quit.matches(&key_event) => {
+                self.stop_playback();
+                return BrowserAction::Quit;
+            }
+            _ => {}
+        }
+        BrowserAction::None
+    }
+
+    pub fn stop_playback(&mut self) {
+        self.loading = None;
+        if let Some((_, playback)) = self.playback.take() {
+            playback.stop();
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        self.playback
+            .as_ref()
+            .is_some_and(|(_, p)| !p.is_finished())
+    }
+
+    /// Plays the selected file, or stops it if it's already playing.
+    fn toggle_playback(&mut self) {
+        let Some(path) = self.selected_file().map(|file| file.path.clone()) else {
+            return;
+        };
+        let was_playing = self.loading.as_ref().is_some_and(|(p, _)| *p == path)
+            || self.is_playing()
+                && self
+                    .playback
+                    .as_ref()
+                    .is_some_and(|(playing, _)| *playing == path);
+        self.stop_playback();
+        if was_playing {
+            return;
+        }
+
+        // Decoding a long take takes a while
+        let (audio_tx, audio) = mpsc::channel();
+        let file = path.clone();
+        thread::spawn(move || audio_tx.send(decoder::decode_file(&file)).ok());
+        self.loading = Some((path, audio));
+    }
+
+    fn rename_selected(&mut self, name: &str) {
+        let Some(path) = self.selected_file().map(|file| file.path.clone()) else {
+            return;
+        };
+        if self
+            .playback
+            .as_ref()
+            .is_some_and(|(playing, _)| *playing == path)
+        {
+            self.stop_playback();
+        }
+
+        match library::rename(&path, name) {
+            Ok(renamed) => {
+                self.message = Some(Ok(format!("Renamed to {}", self.display_name(&renamed))));
+                self.refresh(Some(renamed));
+            }
+            Err(err) => self.message = Some(Err(format!("Rename failed: {err}"))),
+        }
+    }
+
+    fn delete_selected(&mut self) {
+        let Some(path) = self.selected_file().map(|file| file.path.clone()) else {
+            return;
+        };
+        if self
+            .playback
+            .as_ref()
+            .is_some_and(|(playing, _)| *playing == path)
+        {
+            self.stop_playback();
+        }
+
+        match library::delete(&path) {
+            Ok(()) => {
+                self.message = Some(Ok(format!("Deleted {}", self.display_name(&path))));
+                self.refresh(None);
+            }
+            Err(err) => self.message = Some(Err(format!("Delete failed: {err}"))),
+        }
+    }
+
+    /// `path` relative to the output directory.
+    fn display_name(&self, path: &Path) -> String {
+        path.strip_prefix(&self.dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+}
+
+impl Widget for &Browser {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let instructions = match &self.mode {
+            Mode::Renaming(_) => Line::from(vec![
+                " Save ".into(),
+                "<Enter>".blue().bold(),
+                " Cancel ".into(),
+                "<Esc> ".blue().bold(),
+            ]),
+            Mode::ConfirmingDelete => Line::from(vec![
+                " Delete ".into(),
+                "<y>".blue().bold(),
+                " Keep ".into(),
+                "<any key> ".blue().bold(),
+            ]),
+            Mode::Browsing => {
+                let keys = &self.keys;
+                let mut line = Line::default();
+                for (action, key) in [
+                    (" Play ", keys.play.label()),
+                    (" Rename ", keys.rename.label()),
+                    (" Delete ", keys.delete.label()),
+                    (" New take ", keys.new_take.label()),
+                    (" Back ", keys.browser.label()),
+                    (" Quit ", keys.quit.label()),
+                ] {
+                    line.push_span(action);
+                    line.push_span(key.blue().bold());
+                }
+                line.push_span(" ");
+                line
+            }
+        };
+
+        let status = match (&self.mode, &self.message) {
+            (Mode::Renaming(name), _) => format!(" Rename to: {name}█").bold(),
+            (Mode::ConfirmingDelete, _) => {
+                let name = self
+                    .selected_file()
+                    .map(|file| self.display_name(&file.path))
+                    .unwrap_or_default();
+                format!(" Delete {name}?").red().bold()
+            }
+            (_, _) if self.loading.is_some() => {
+                let (path, _) = self.loading.as_ref().unwrap();
+                format!(" Loading {}...", self.display_name(path))
+                    .blue()
+                    .bold()
+            }
+            (_, _) if self.is_playing() => {
+                let (_, playback) = self.playback.as_ref().unwrap();
+                format!(
+                    " Playing {} / {}",
+                    format_duration(playback.elapsed()),
+                    format_duration(playback.duration())
+                )
+                .blue()
+                .bold()
+            }
+            (_, Some(Ok(message))) => format!(" {message}").green().bold(),
+            (_, Some(Err(message))) => format!(" {message}").red().bold(),
+            (_, None) => format!(" {} recordings", self.files.len()).bold(),
+        };
+
+        let block = Block::new()
+            .title_top(Line::from(
+                format!(" Recordings in {} ", self.dir.display()).bold(),
+            ))
+            .title_bottom(Line::from(status).left_aligned())
+            .title_bottom(instructions.right_aligned());
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        if self.files.is_empty() {
+            let text = match self.listing {
+                Some(_) => "Reading recordings...",
+                None => "No recordings yet",
+            };
+            Paragraph::new(text).centered().render(inner, buf);
+            return;
+        }
+
+        // Keep the selection in view, with the header taking the first row
+        let visible = inner.height.saturating_sub(1).max(1) as usize;
+        let first = (self.selected + 1).saturating_sub(visible);
+        let playing = self
+            .playback
+            .as_ref()
+            .filter(|_| self.is_playing())
+            .map(|(path, _)| path);
+
+        let rows = self
+            .files
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .map(|(i, file)| {
+                let marker = if playing == Some(&file.path) {
+                    "▶ "
+                } else {
+                    "  "
+                };
+                let modified: DateTime<Local> = file.modified.into();
+                let row = Row::new(vec![
+                    format!("{marker}{}", self.display_name(&file.path)),
+                    file.duration.map_or(String::from("--:--"), format_duration),
+                    format_size(file.size),
+                    modified.format("%Y-%m-%d %H:%M").to_string(),
+                ]);
+                if i == self.selected {
+                    row.reversed()
+                } else {
+                    row
+                }
+            });
+
+        let header = Row::new(vec!["  Name", "Length", "Size", "Recorded"]).bold();
+        Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(8),
+                Constraint::Length(10),
+                Constraint::Length(16),
+            ],
+        )
+        .header(header)
+        .render(inner, buf);
+    }
+}
@@ -0,0 +1,706 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io};
+
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Report, Result, WrapErr};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use directories::{BaseDirs, ProjectDirs};
+use serde::{Deserialize, Deserializer};
+
+use micrec::encoder::OutputFormat;
+use micrec::engine::{CaptureSource, OutputChannels};
+use micrec::markers::MarkerFormat;
+use micrec::processing::DEFAULT_HIGH_PASS_HZ;
+use micrec::project::ProjectFormat;
+use micrec::transcode::TranscodeFormat;
+
+/// User settings loaded from `~/.config/micrec/config.toml`.
+///
+/// Every key is optional; anything missing falls back to the defaults below.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Audio system to record through, e.g. `alsa` or `jack`, or the platform's
+    /// default when unset. `micrec --list-hosts` shows the ones available.
+    pub host: Option<String>,
+    /// Name of the input device to record from, or an alias from
+    /// `device_aliases`. A list, as in `["interview_mic", "Built-in Microphone"]`,
+    /// records from the first one that's plugged in. The system default when
+    /// unset.
+    #[serde(deserialize_with = "one_or_many")]
+    pub device: Vec<String>,
+    /// More input devices, or aliases, to record at the same time as `device`.
+    /// Their channels follow its channels in the same file, as there's no way yet
+    /// to record each device to a file of its own.
+    pub extra_devices: Vec<String>,
+    /// Short names for devices, as in `interview_mic = "USB Audio CODEC"`, usable
+    /// wherever a device is named.
+    pub device_aliases: BTreeMap<String, String>,
+    /// `loopback` records what the computer is playing instead, from the output
+    /// device or sink named by `device`.
+    pub capture: CaptureSource,
+    /// Directory recordings are saved into. A leading `~` stands for the home
+    /// directory.
+    pub output_dir: PathBuf,
+    /// File name for new recordings, relative to `output_dir`. strftime fields like
+    /// `%Y-%m-%d_%H-%M-%S` expand to the local time, `{timestamp}` to seconds since
+    /// the epoch, `{year}`, `{month}` and `{day}` to today's date, and `{take}` to the
+    /// next free take number. Directories in the template, as in
+    /// `%Y/%m/%d/memo-{take}.wav`, are created on save. The extension follows
+    /// `format`.
+    pub file_template: String,
+    /// Sample rate of recordings in Hz, or the device default when unset. Takes are
+    /// resampled if the device can't capture at this rate.
+    pub sample_rate: Option<u32>,
+    /// Seconds of silence at the start of a recording before offering to look for
+    /// an input that picks up sound. 0 turns the check off.
+    pub silence_check_secs: u64,
+    /// Seconds of silence after which a recording stops by itself. 0 keeps
+    /// recording until stopped.
+    pub stop_after_silence_secs: u64,
+    /// RMS level below which input counts as silence for `stop_after_silence_secs`.
+    pub silence_threshold_dbfs: f32,
+    /// Length to aim for in seconds, e.g. for rehearsing a timed talk. Shows a
+    /// progress bar that turns yellow near the end and red once it's exceeded.
+    pub target_secs: Option<u64>,
+    /// Seconds between spoken readouts of the peak level and elapsed time, for
+    /// recording away from the screen. 0 keeps quiet. Needs a text-to-speech
+    /// command such as `spd-say` or `espeak-ng`.
+    pub speak_interval_secs: u64,
+    pub format: OutputFormat,
+    pub output_channels: OutputChannels,
+    /// Also record the input before the software gain and processing, 12 dB down and
+    /// named `<file>-safety`, to fall back on if the gain clips the main file. Input
+    /// that clips in the audio interface is clipped in both.
+    pub safety_track: bool,
+    /// Input channels, numbered from 1, to flip the polarity of, e.g. for a mic
+    /// whose cable is wired out of phase.
+    pub invert_channels: Vec<usize>,
+    /// Software gain for quiet inputs, in dB. Adjustable while recording.
+    pub gain_db: f32,
+    /// Round off peaks pushed past full scale by `gain_db` instead of clipping them.
+    pub soft_limiter: bool,
+    /// Cut rumble below `high_pass_hz`. Can be switched while recording.
+    pub high_pass: bool,
+    pub high_pass_hz: f32,
+    /// Mute the input while it stays below `gate_threshold_dbfs`, between words.
+    /// Can be switched while recording.
+    pub noise_gate: bool,
+    pub gate_threshold_dbfs: f32,
+    /// Keep only the last this many seconds in a circular buffer on disk instead
+    /// of recording a take, and save clips of it with the `save_clip` key. 0
+    /// records normally.
+    pub buffer_secs: u64,
+    /// Length of the clips saved from the buffer, in seconds.
+    pub clip_secs: u64,
+    /// Keep this many seconds of the input before each take, and start the file
+    /// with them. Takes then wait for the `record` key, capturing meanwhile. 0
+    /// starts recording right away.
+    pub pre_roll_secs: u64,
+    /// Stop recording when free space on the drive drops below this many MiB,
+    /// with a warning from twice as much. 0 records until the disk is full.
+    pub min_free_space_mb: u64,
+    /// Editor project to write next to each recording, with the take as a region.
+    pub project: Option<ProjectFormat>,
+    /// How markers placed with Enter while recording are saved.
+    pub marker_format: MarkerFormat,
+    /// Write `<file>-info.json` next to each recording with the device, settings,
+    /// processing, markers and levels of the take.
+    pub info_file: bool,
+    /// Write `<file>-loudness.json` next to each recording with its integrated
+    /// loudness, loudness range, true peak and noise floor.
+    pub loudness_file: bool,
+    /// Also save an MP3 or Opus copy of each recording once it's stopped, for
+    /// sharing. The recording itself is kept.
+    pub transcode: Option<TranscodeFormat>,
+    /// Shell command to stream each take to as 16 kHz mono 16-bit PCM while
+    /// recording, e.g. a live transcriber. Its output is saved as
+    /// `<file>-transcript.txt`.
+    pub pipe_command: Option<String>,
+    pub visualization: VisualizationStyle,
+    pub visualization_scale: VisualizationScale,
+    /// Level at the bottom of the bars with the `db` scale.
+    pub visualization_floor_dbfs: f32,
+    /// Time for the bars to rise to a louder level, in milliseconds.
+    pub bar_attack_ms: u64,
+    /// Time for the bars to fall back once it gets quieter, in milliseconds.
+    pub bar_release_ms: u64,
+    /// Make each bar the level over this many milliseconds, scrolling right to
+    /// left, so the bars cover the same stretch of time whatever the device's
+    /// buffer size. 0 spreads each block of audio across all the bars instead.
+    pub bar_history_ms: u64,
+    /// Colors of the bars and meters. Picked to suit the terminal: without
+    /// truecolor they fall back to the nearest indexed colors.
+    pub theme: ThemeName,
+    /// Let the mouse click the key hints, switch the bars' style by clicking
+    /// them and drag a gain slider. Turn off to select text with the mouse.
+    pub mouse: bool,
+    pub preflight: PreflightConfig,
+    pub control: ControlConfig,
+    pub keys: KeyBindings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: None,
+            device: Vec::new(),
+            extra_devices: Vec::new(),
+            device_aliases: BTreeMap::new(),
+            capture: CaptureSource::default(),
+            output_dir: PathBuf::from("~/Recordings"),
+            file_template: String::from("micrec-%Y-%m-%d_%H-%M-%S.wav"),
+            sample_rate: None,
+            silence_check_secs: 5,
+            stop_after_silence_secs: 0,
+            silence_threshold_dbfs: -45.0,
+            target_secs: None,
+            speak_interval_secs: 0,
+            format: OutputFormat::default(),
+            output_channels: OutputChannels::default(),
+            safety_track: false,
+            invert_channels: Vec::new(),
+            gain_db: 0.0,
+            soft_limiter: false,
+            high_pass: false,
+            high_pass_hz: DEFAULT_HIGH_PASS_HZ,
+            noise_gate: false,
+            gate_threshold_dbfs: -50.0,
+            buffer_secs: 0,
+            clip_secs: 300,
+            pre_roll_secs: 0,
+            min_free_space_mb: 100,
+            project: None,
+            marker_format: MarkerFormat::default(),
+            info_file: true,
+            loudness_file: false,
+            transcode: None,
+            pipe_command: None,
+            visualization: VisualizationStyle::default(),
+            visualization_scale: VisualizationScale::default(),
+            visualization_floor_dbfs: -60.0,
+            bar_attack_ms: 5,
+            bar_release_ms: 25,
+            bar_history_ms: 0,
+            theme: ThemeName::default(),
+            mouse: true,
+            preflight: PreflightConfig::default(),
+            control: ControlConfig::default(),
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum VisualizationStyle {
+    /// Bars grow up and down from a center line
+    #[default]
+    Mirrored,
+    /// Bars grow up from the bottom of the screen
+    Bars,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum VisualizationScale {
+    /// Bar height follows the level in dB, so quiet speech stays visible
+    #[default]
+    Db,
+    /// Bar height is proportional to the level
+    Linear,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    /// Gray bars that brighten with the level
+    #[default]
+    Default,
+    /// The terminal's own foreground only, for light themes and monochrome
+    /// terminals
+    Mono,
+    /// Bars going from green through yellow to red as the level rises
+    Gradient,
+    /// Bright colors on the base 16, for low-contrast displays
+    HighContrast,
+}
+
+/// Checklist to get through before each take is armed, under `[preflight]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreflightConfig {
+    /// Show the checklist before recording. Failed checks can be overridden.
+    pub enabled: bool,
+    /// Least free space in the output directory, in MiB.
+    pub min_free_mb: u64,
+    /// Listen to the input for a few seconds and check it's neither silent nor
+    /// clipping.
+    pub check_levels: bool,
+    /// Check that the default output looks like headphones, for monitoring
+    /// without feedback.
+    pub require_headphones: bool,
+}
+
+impl Default for PreflightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_free_mb: 1024,
+            check_levels: true,
+            require_headphones: false,
+        }
+    }
+}
+
+/// Remote control from a stream deck or DAW controller, under `[control]`. OSC
+/// and MIDI each need micrec built with the feature of the same name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlConfig {
+    /// Listen for OSC messages on this UDP address, e.g. `127.0.0.1:9000`, or
+    /// `0.0.0.0:9000` to take them from other machines too.
+    pub osc_listen: Option<SocketAddr>,
+    /// Take control changes from the MIDI input whose name contains this.
+    pub midi_input: Option<String>,
+    /// Controller numbers, on any channel, that start a take, stop it, pause or
+    /// resume it and add a marker when sent a value of 64 or more.
+    pub record_cc: u8,
+    pub stop_cc: u8,
+    pub pause_cc: u8,
+    pub marker_cc: u8,
+    /// Controller number that sets the gain, across its whole range.
+    pub gain_cc: u8,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            osc_listen: None,
+            midi_input: None,
+            record_cc: 20,
+            stop_cc: 21,
+            pause_cc: 22,
+            marker_cc: 23,
+            gain_cc: 24,
+        }
+    }
+}
+
+/// Keys for each action, under `[keys]`. A key is a single character, a named
+/// key such as `space`, `enter`, `tab`, `esc`, `backspace`, `up` or `f5`, or
+/// either with a `ctrl-` prefix, as in `ctrl-s`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub stop: Key,
+    /// Starts a take waiting with a pre-roll, see `pre_roll_secs`.
+    pub record: Key,
+    /// Pauses and resumes a take; nothing is written while paused.
+    pub pause: Key,
+    pub play: Key,
+    pub quit: Key,
+    /// Places a marker at the current position of the take.
+    pub marker: Key,
+    pub clear_clip: Key,
+    pub retry: Key,
+    pub find_input: Key,
+    pub mid_side: Key,
+    pub monitor: Key,
+    pub gain_up: Key,
+    pub gain_down: Key,
+    pub high_pass: Key,
+    pub noise_gate: Key,
+    /// Takes a few seconds of room tone and reduces that noise from then on.
+    pub learn_noise: Key,
+    /// Saves the last `clip_secs` when recording into a buffer.
+    pub save_clip: Key,
+    /// Starts recording although some pre-flight checks failed.
+    pub skip_checks: Key,
+    /// Shows and hides the list of these bindings.
+    pub help: Key,
+    /// Opens and closes the recording browser.
+    pub browser: Key,
+    /// Recording browser keys.
+    pub rename: Key,
+    pub delete: Key,
+    pub new_take: Key,
+    /// Keeps the stopped take and starts the next one.
+    pub next_take: Key,
+    /// Deletes the stopped take and starts it over.
+    pub retake: Key,
+    /// Tags the stopped take with a title, artist and comment.
+    pub tag: Key,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            stop: Key::char(' '),
+            record: Key::char(' '),
+            pause: Key::char('p'),
+            play: Key::char('p'),
+            quit: Key::char('q'),
+            marker: Key::new(KeyCode::Enter),
+            clear_clip: Key::char('c'),
+            retry: Key::char('r'),
+            find_input: Key::char('i'),
+            mid_side: Key::char('w'),
+            monitor: Key::char('m'),
+            gain_up: Key::char('+'),
+            gain_down: Key::char('-'),
+            high_pass: Key::char('h'),
+            noise_gate: Key::char('g'),
+            learn_noise: Key::char('l'),
+            save_clip: Key::char('x'),
+            skip_checks: Key::char('o'),
+            help: Key::char('?'),
+            browser: Key::new(KeyCode::Tab),
+            rename: Key::char('e'),
+            delete: Key::char('d'),
+            new_take: Key::char('n'),
+            next_take: Key::char('s'),
+            retake: Key::char('r'),
+            tag: Key::char('t'),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Every action with its key, in the order the help lists them.
+    pub fn describe(&self) -> [(&'static str, Key); 26] {
+        [
+            ("Stop recording", self.stop),
+            ("Start recording, pre-roll first", self.record),
+            ("Pause or resume recording", self.pause),
+            ("Add a marker", self.marker),
+            ("Monitor the input", self.monitor),
+            ("Gain up", self.gain_up),
+            ("Gain down", self.gain_down),
+            ("High-pass filter", self.high_pass),
+            ("Noise gate", self.noise_gate),
+            ("Learn noise", self.learn_noise),
+            ("Save a clip from the buffer", self.save_clip),
+            ("Show mid/side levels", self.mid_side),
+            ("Clear the clip indicator", self.clear_clip),
+            ("Find an input that hears sound", self.find_input),
+            ("Play the last take", self.play),
+            ("Keep the take and record the next", self.next_take),
+            ("Discard the take and record it again", self.retake),
+            ("Tag the take", self.tag),
+            ("Retry after an error", self.retry),
+            ("Record despite failed checks", self.skip_checks),
+            ("Open or close recordings", self.browser),
+            ("Rename a recording", self.rename),
+            ("Delete a recording", self.delete),
+            ("Record a new take", self.new_take),
+            ("Show or hide this help", self.help),
+            ("Quit", self.quit),
+        ]
+    }
+}
+
+/// A key binding, matched against terminal key presses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Key {
+    code: KeyCode,
+    ctrl: bool,
+}
+
+impl Key {
+    pub const fn new(code: KeyCode) -> Self {
+        Self { code, ctrl: false }
+    }
+
+    pub const fn char(c: char) -> Self {
+        Self::new(KeyCode::Char(c))
+    }
+
+    /// Whether `event` is this key. Shift is left out, since it's part of
+    /// characters like `?` and `+` on most layouts.
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        event.code == self.code && event.modifiers.contains(KeyModifiers::CONTROL) == self.ctrl
+    }
+
+    /// The key as shown in the instructions bar, e.g. `<Space>` or `<Ctrl-s>`.
+    pub fn label(&self) -> String {
+        format!("<{self}>")
+    }
+
+    /// A press of this key, for acting as if it was pressed.
+    pub fn event(&self) -> KeyEvent {
+        let modifiers = if self.ctrl {
+            KeyModifiers::CONTROL
+        } else {
+            KeyModifiers::NONE
+        };
+        KeyEvent::new(self.code, modifiers)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            f.write_str("Ctrl-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::Backspace => f.write_str("Backspace"),
+            KeyCode::Delete => f.write_str("Delete"),
+            KeyCode::Insert => f.write_str("Insert"),
+            KeyCode::Up => f.write_str("Up"),
+            KeyCode::Down => f.write_str("Down"),
+            KeyCode::Left => f.write_str("Left"),
+            KeyCode::Right => f.write_str("Right"),
+            KeyCode::Home => f.write_str("Home"),
+            KeyCode::End => f.write_str("End"),
+            KeyCode::PageUp => f.write_str("PageUp"),
+            KeyCode::PageDown => f.write_str("PageDown"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut chars = s.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Self::char(c));
+        }
+        let lower = s.to_lowercase();
+        if let Some(rest) = lower.strip_prefix("ctrl-").or(lower.strip_prefix("ctrl+")) {
+            let key: Key = rest.parse()?;
+            return Ok(Self { ctrl: true, ..key });
+        }
+        let code = match lower.as_str() {
+            "space" => KeyCode::Char(' '),
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "esc" | "escape" => KeyCode::Esc,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            name => match name.strip_prefix('f').map(str::parse) {
+                Some(Ok(n @ 1..=24)) => KeyCode::F(n),
+                _ => return Err(eyre!("unknown key \"{s}\"")),
+            },
+        };
+        Ok(Self::new(code))
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = Report;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl Config {
+    /// Default location of the config file, if the platform has a config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "micrec").map(|dirs| dirs.config_dir().join("config.toml"))
+    }
+
+    /// Loads the config from `path`, or the default location when `None`.
+    ///
+    /// A missing file is not an error and yields the default config.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut config = match path.map(Path::to_path_buf).or_else(Self::default_path) {
+            Some(path) => Self::read(&path)?,
+            None => Self::default(),
+        };
+        config.output_dir = expand_home(&config.output_dir);
+        Ok(config)
+    }
+
+    /// The devices to try in order, with aliases replaced by the names they
+    /// stand for.
+    pub fn device_names(&self) -> Vec<String> {
+        self.device.iter().map(|name| self.resolve(name)).collect()
+    }
+
+    /// `extra_devices`, with aliases replaced by the names they stand for.
+    pub fn extra_device_names(&self) -> Vec<String> {
+        self.extra_devices
+            .iter()
+            .map(|name| self.resolve(name))
+            .collect()
+    }
+
+    fn resolve(&self, name: &String) -> String {
+        self.device_aliases.get(name).unwrap_or(name).clone()
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .wrap_err_with(|| format!("invalid config file {}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).wrap_err_with(|| format!("failed to read {}", path.display())),
+        }
+    }
+}
+
+/// Accepts a single string where a list of them is expected.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(name) => vec![name],
+        OneOrMany::Many(names) => names,
+    })
+}
+
+/// Replaces a leading `~` with the home directory, as a shell would.
+fn expand_home(path: &Path) -> PathBuf {
+    let Ok(rest) = path.strip_prefix("~") else {
+        return path.to_path_buf();
+    };
+    match BaseDirs::new() {
+        Some(dirs) => dirs.home_dir().join(rest),
+        None => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_device_aliases() {
+        let aliases = r#"
+            [device_aliases]
+            interview_mic = "USB Audio CODEC"
+        "#;
+        let config =
+            |devices: &str| -> Config { toml::from_str(&format!("{devices}\n{aliases}")).unwrap() };
+
+        let one = config(r#"device = "interview_mic""#);
+        assert_eq!(one.device_names(), ["USB Audio CODEC"]);
+
+        let many = config(r#"device = ["interview_mic", "Built-in Microphone"]"#);
+        assert_eq!(
+            many.device_names(),
+            ["USB Audio CODEC", "Built-in Microphone"]
+        );
+
+        let extra = config(r#"extra_devices = ["Scarlett 2i2", "interview_mic"]"#);
+        assert!(extra.device_names().is_empty());
+        assert_eq!(
+            extra.extra_device_names(),
+            ["Scarlett 2i2", "USB Audio CODEC"]
+        );
+    }
+
+    #[test]
+    fn parses_keys() {
+        let parsed = |s: &str| s.parse::<Key>().unwrap();
+        assert_eq!(parsed("x"), Key::char('x'));
+        assert_eq!(parsed("+"), Key::char('+'));
+        assert_eq!(parsed("Space"), Key::char(' '));
+        assert_eq!(parsed("return"), Key::new(KeyCode::Enter));
+        assert_eq!(parsed("ESC"), Key::new(KeyCode::Esc));
+        assert_eq!(parsed("pagedown"), Key::new(KeyCode::PageDown));
+        assert_eq!(parsed("f1"), Key::new(KeyCode::F(1)));
+        assert_eq!(parsed("F24"), Key::new(KeyCode::F(24)));
+
+        let ctrl_s = Key {
+            ctrl: true,
+            ..Key::char('s')
+        };
+        assert_eq!(parsed("ctrl-s"), ctrl_s);
+        assert_eq!(parsed("Ctrl+s"), ctrl_s);
+        assert_eq!(
+            parsed("ctrl-f5"),
+            Key {
+                ctrl: true,
+                ..Key::new(KeyCode::F(5))
+            }
+        );
+
+        for unknown in ["", "f0", "f25", "ctrl-", "hyper-x", "spacebar"] {
+            assert!(unknown.parse::<Key>().is_err(), "{unknown:?}");
+        }
+    }
+
+    #[test]
+    fn keys_read_back_as_shown() {
+        for s in [
+            "q", "?", "Space", "Enter", "Tab", "PageUp", "F12", "Ctrl-s", "Ctrl-Up",
+        ] {
+            let key: Key = s.parse().unwrap();
+            assert_eq!(key.to_string(), s);
+            assert_eq!(key.to_string().parse::<Key>().unwrap(), key);
+        }
+        assert_eq!(Key::char(' ').label(), "<Space>");
+    }
+
+    #[test]
+    fn ctrl_has_to_match_but_shift_doesnt() {
+        let ctrl_s: Key = "ctrl-s".parse().unwrap();
+        let s = Key::char('s');
+        let ctrl = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        let plain = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::NONE);
+
+        assert!(ctrl_s.matches(&ctrl));
+        assert!(!ctrl_s.matches(&plain));
+        assert!(s.matches(&plain));
+        assert!(!s.matches(&ctrl));
+        assert!(Key::char('?').matches(&KeyEvent::new(KeyCode::Char('?'), KeyModifiers::SHIFT)));
+        assert!(ctrl_s.matches(&ctrl_s.event()));
+    }
+
+    #[test]
+    fn reads_key_bindings() {
+        let config: Config = toml::from_str(
+            r#"
+            [keys]
+            stop = "ctrl-s"
+            marker = "m"
+            help = "F1"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.keys.stop, "ctrl-s".parse().unwrap());
+        assert_eq!(config.keys.marker, Key::char('m'));
+        assert_eq!(config.keys.help, Key::new(KeyCode::F(1)));
+        // Anything not given keeps its default
+        assert_eq!(config.keys.quit, Key::char('q'));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let unknown_key = toml::from_str::<Config>("[keys]\nstop = \"hyper-s\"\n");
+        let unknown_action = toml::from_str::<Config>("[keys]\nexplode = \"x\"\n");
+
+        assert!(unknown_key.is_err());
+        assert!(unknown_action.is_err());
+    }
+}
@@ -0,0 +1,386 @@
+//! Remote control of the recorder over OSC and MIDI, for starting and stopping
+//! takes from a stream deck or a DAW controller.
+//!
+//! OSC messages go to `/micrec/record`, `/micrec/stop`, `/micrec/pause`,
+//! `/micrec/marker` and `/micrec/gain`, the last with the gain in dB. MIDI control
+//! changes map to the same actions as `[control]` in the config says. Each listens
+//! on a thread of its own and passes what it hears on as [`ControlMessage`]s, which
+//! the front-end handles alongside its keys.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryIter};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use color_eyre::eyre::Result;
+
+use crate::config::ControlConfig;
+
+/// An action asked for by a remote controller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlMessage {
+    /// Starts the next take, or one standing by with a pre-roll.
+    Record,
+    Stop,
+    /// Pauses the take, or resumes it.
+    Pause,
+    Marker,
+    /// Sets the input gain, in dB.
+    Gain(f32),
+}
+
+#[derive(Debug)]
+pub struct ControlServer {
+    messages: Receiver<ControlMessage>,
+    /// Tells the OSC thread to stop and close its socket.
+    closed: Arc<AtomicBool>,
+    osc: Option<JoinHandle<()>>,
+    /// Closes the MIDI input when dropped.
+    #[cfg(feature = "midi")]
+    _midi: Option<midi::Connection>,
+}
+
+impl ControlServer {
+    /// Starts listening as `config` asks, or returns `None` if it asks for neither
+    /// OSC nor MIDI.
+    pub fn start(config: &ControlConfig) -> Result<Option<Self>> {
+        if config.osc_listen.is_none() && config.midi_input.is_none() {
+            return Ok(None);
+        }
+        let (messages_tx, messages) = channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let osc = config
+            .osc_listen
+            .map(|address| osc::listen(address, messages_tx.clone(), Arc::clone(&closed)))
+            .transpose()?;
+        #[cfg(feature = "midi")]
+        let midi = config
+            .midi_input
+            .as_deref()
+            .map(|name| midi::connect(name, config, messages_tx))
+            .transpose()?;
+        #[cfg(not(feature = "midi"))]
+        if config.midi_input.is_some() {
+            return Err(color_eyre::eyre::eyre!(
+                "MIDI control needs micrec built with --features midi"
+            ));
+        }
+
+        Ok(Some(Self {
+            messages,
+            closed,
+            osc,
+            #[cfg(feature = "midi")]
+            _midi: midi,
+        }))
+    }
+
+    /// Messages that have arrived since the last call, without blocking.
+    pub fn messages(&self) -> TryIter<'_, ControlMessage> {
+        self.messages.try_iter()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Some(osc) = self.osc.take() {
+            osc.join().ok();
+        }
+    }
+}
+
+#[cfg(feature = "osc")]
+mod osc {
+    use std::io;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    use color_eyre::eyre::{Result, WrapErr};
+    use rosc::{OscMessage, OscPacket, OscType};
+
+    use super::{ControlMessage, Sender};
+
+    /// How often the thread looks up from the socket to see if it should stop.
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Listens on `address` until `closed`, when the socket is let go.
+    pub fn listen(
+        address: SocketAddr,
+        messages_tx: Sender<ControlMessage>,
+        closed: Arc<AtomicBool>,
+    ) -> Result<JoinHandle<()>> {
+        let socket = UdpSocket::bind(address)
+            .and_then(|socket| {
+                socket.set_read_timeout(Some(POLL_INTERVAL))?;
+                Ok(socket)
+            })
+            .wrap_err_with(|| format!("can't listen for OSC on {address}"))?;
+        Ok(thread::spawn(move || {
+            let mut buf = vec![0; rosc::decoder::MTU];
+            while !closed.load(Ordering::Relaxed) {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    // Nothing arrived in time
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        continue
+                    }
+                    Err(_) => return,
+                };
+                let Ok((_, packet)) = rosc::decoder::decode_udp(&buf[..len]) else {
+                    continue;
+                };
+                let mut messages = Vec::new();
+                flatten(packet, &mut messages);
+                for message in messages.iter().filter_map(parse) {
+                    if messages_tx.send(message).is_err() {
+                        return;
+                    }
+                }
+            }
+        }))
+    }
+
+    /// The messages in a packet, taken out of any bundles.
+    fn flatten(packet: OscPacket, messages: &mut Vec<OscMessage>) {
+        match packet {
+            OscPacket::Message(message) => messages.push(message),
+            OscPacket::Bundle(bundle) => {
+                for packet in bundle.content {
+                    flatten(packet, messages);
+                }
+            }
+        }
+    }
+
+    fn parse(message: &OscMessage) -> Option<ControlMessage> {
+        let value = message.args.first().and_then(|arg| match *arg {
+            OscType::Float(value) => Some(value),
+            OscType::Double(value) => Some(value as f32),
+            OscType::Int(value) => Some(value as f32),
+            OscType::Bool(value) => Some(if value { 1.0 } else { 0.0 }),
+            _ => None,
+        });
+        // Buttons send 1 when pressed and 0 when let go
+        let pressed = value.is_none_or(|value| value != 0.0);
+        match message.addr.as_str() {
+            "/micrec/gain" => value.map(ControlMessage::Gain),
+            _ if !pressed => None,
+            "/micrec/record" => Some(ControlMessage::Record),
+            "/micrec/stop" => Some(ControlMessage::Stop),
+            "/micrec/pause" => Some(ControlMessage::Pause),
+            "/micrec/marker" => Some(ControlMessage::Marker),
+            _ => None,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use rosc::{OscBundle, OscTime};
+
+        use super::*;
+
+        fn message(addr: &str, args: Vec<OscType>) -> OscMessage {
+            OscMessage {
+                addr: addr.to_string(),
+                args,
+            }
+        }
+
+        #[test]
+        fn buttons_act_when_pressed() {
+            let parsed = |addr, args| parse(&message(addr, args));
+            assert_eq!(
+                parsed("/micrec/record", vec![]),
+                Some(ControlMessage::Record)
+            );
+            assert_eq!(
+                parsed("/micrec/stop", vec![OscType::Float(1.0)]),
+                Some(ControlMessage::Stop)
+            );
+            assert_eq!(
+                parsed("/micrec/marker", vec![OscType::Bool(true)]),
+                Some(ControlMessage::Marker)
+            );
+            assert_eq!(parsed("/micrec/stop", vec![OscType::Float(0.0)]), None);
+            assert_eq!(parsed("/micrec/pause", vec![OscType::Int(0)]), None);
+            assert_eq!(parsed("/micrec/explode", vec![]), None);
+        }
+
+        #[test]
+        fn gain_is_taken_as_sent() {
+            let parsed = |args| parse(&message("/micrec/gain", args));
+            assert_eq!(
+                parsed(vec![OscType::Float(-6.0)]),
+                Some(ControlMessage::Gain(-6.0))
+            );
+            assert_eq!(
+                parsed(vec![OscType::Int(0)]),
+                Some(ControlMessage::Gain(0.0))
+            );
+            assert_eq!(parsed(vec![]), None);
+        }
+
+        #[test]
+        fn bundles_are_flattened() {
+            let bundle = |content| {
+                OscPacket::Bundle(OscBundle {
+                    timetag: OscTime {
+                        seconds: 0,
+                        fractional: 1,
+                    },
+                    content,
+                })
+            };
+            let packet = bundle(vec![
+                OscPacket::Message(message("/micrec/record", vec![])),
+                bundle(vec![OscPacket::Message(message("/micrec/marker", vec![]))]),
+            ]);
+
+            let mut messages = Vec::new();
+            flatten(packet, &mut messages);
+            let parsed: Vec<ControlMessage> = messages.iter().filter_map(parse).collect();
+
+            assert_eq!(parsed, [ControlMessage::Record, ControlMessage::Marker]);
+        }
+    }
+}
+
+#[cfg(not(feature = "osc"))]
+mod osc {
+    use std::net::SocketAddr;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    use color_eyre::eyre::{eyre, Result};
+
+    use super::{ControlMessage, Sender};
+
+    pub fn listen(
+        _address: SocketAddr,
+        _messages_tx: Sender<ControlMessage>,
+        _closed: Arc<AtomicBool>,
+    ) -> Result<JoinHandle<()>> {
+        Err(eyre!("OSC control needs micrec built with --features osc"))
+    }
+}
+
+#[cfg(feature = "midi")]
+mod midi {
+    use color_eyre::eyre::{eyre, Result};
+    use midir::{MidiInput, MidiInputConnection};
+
+    use micrec::engine::GAIN_RANGE_DB;
+
+    use super::{ControlMessage, Sender};
+    use crate::config::ControlConfig;
+
+    /// An open MIDI input, which isn't `Debug` itself.
+    pub struct Connection {
+        _input: MidiInputConnection<()>,
+    }
+
+    impl std::fmt::Debug for Connection {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("Connection")
+        }
+    }
+
+    /// Opens the first MIDI input whose name contains `name`.
+    pub fn connect(
+        name: &str,
+        config: &ControlConfig,
+        messages_tx: Sender<ControlMessage>,
+    ) -> Result<Connection> {
+        let input = MidiInput::new("micrec").map_err(|err| eyre!("can't use MIDI: {err}"))?;
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|port| input.port_name(port).is_ok_and(|port| port.contains(name)))
+            .ok_or_else(|| eyre!("there is no MIDI input named {name}"))?;
+        let config = config.clone();
+        let connection = input
+            .connect(
+                &port,
+                "micrec-control",
+                move |_, bytes, _| {
+                    if let Some(message) = parse(bytes, &config) {
+                        messages_tx.send(message).ok();
+                    }
+                },
+                (),
+            )
+            .map_err(|err| eyre!("can't open the MIDI input {name}: {err}"))?;
+        Ok(Connection { _input: connection })
+    }
+
+    fn parse(bytes: &[u8], config: &ControlConfig) -> Option<ControlMessage> {
+        // A control change, on any channel
+        let &[status, controller, value] = bytes else {
+            return None;
+        };
+        if status & 0xf0 != 0xb0 {
+            return None;
+        }
+        if controller == config.gain_cc {
+            let (low, high) = (*GAIN_RANGE_DB.start(), *GAIN_RANGE_DB.end());
+            return Some(ControlMessage::Gain(
+                low + (high - low) * value as f32 / 127.0,
+            ));
+        }
+        if value < 64 {
+            return None;
+        }
+        [
+            (config.record_cc, ControlMessage::Record),
+            (config.stop_cc, ControlMessage::Stop),
+            (config.pause_cc, ControlMessage::Pause),
+            (config.marker_cc, ControlMessage::Marker),
+        ]
+        .into_iter()
+        .find(|&(cc, _)| cc == controller)
+        .map(|(_, message)| message)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn buttons_act_when_pressed() {
+            let config = ControlConfig::default();
+            let parsed = |bytes: &[u8]| parse(bytes, &config);
+            assert_eq!(parsed(&[0xb0, 20, 127]), Some(ControlMessage::Record));
+            // Any channel
+            assert_eq!(parsed(&[0xb5, 21, 64]), Some(ControlMessage::Stop));
+            assert_eq!(parsed(&[0xb0, 22, 63]), None);
+            assert_eq!(parsed(&[0xb0, 23, 0]), None);
+            assert_eq!(parsed(&[0xb0, 99, 127]), None);
+            // A note on, not a control change
+            assert_eq!(parsed(&[0x90, 20, 127]), None);
+            assert_eq!(parsed(&[0xb0, 20]), None);
+        }
+
+        #[test]
+        fn gain_spans_the_whole_range() {
+            let config = ControlConfig::default();
+            let gain = |value| match parse(&[0xb0, config.gain_cc, value], &config) {
+                Some(ControlMessage::Gain(gain_db)) => gain_db,
+                other => panic!("{other:?}"),
+            };
+            assert_eq!(gain(0), *GAIN_RANGE_DB.start());
+            assert_eq!(gain(127), *GAIN_RANGE_DB.end());
+            let middle = (GAIN_RANGE_DB.start() + GAIN_RANGE_DB.end()) / 2.0;
+            assert!((gain(64) - middle).abs() < 0.5);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use cpal::{SampleRate, StreamConfig};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, Track};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// An audio file decoded fully into memory.
+#[derive(Debug, Clone)]
+pub struct DecodedAudio {
+    /// Interleaved samples.
+    pub samples: Vec<f32>,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl DecodedAudio {
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// Stream config matching the decoded audio, for playback.
+    pub fn stream_config(&self) -> StreamConfig {
+        StreamConfig {
+            channels: self.channels,
+            sample_rate: SampleRate(self.sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        }
+    }
+}
+
+/// Length of `path` according to its headers, without decoding it. `None` if the
+/// headers don't say, e.g. for a FLAC file still being recorded.
+pub fn probe_duration(path: &Path) -> Result<Option<Duration>> {
+    let format = open_format(path)?;
+    let track = audio_track(format.as_ref(), path)?;
+    let params = &track.codec_params;
+    Ok(params
+        .n_frames
+        .zip(params.sample_rate)
+        .filter(|&(_, rate)| rate > 0)
+        .map(|(frames, rate)| Duration::from_secs_f64(frames as f64 / rate as f64)))
+}
+
+/// Decodes the first audio track of `path` (WAV, FLAC, Ogg Vorbis, ...).
+pub fn decode_file(path: &Path) -> Result<DecodedAudio> {
+    let mut format = open_format(path)?;
+    let track = audio_track(format.as_ref(), path)?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .wrap_err("unsupported codec")?;
+
+    let mut audio = DecodedAudio {
+        samples: Vec::new(),
+        channels: track.codec_params.channels.map_or(1, |c| c.count() as u16),
+        sample_rate: track.codec_params.sample_rate.unwrap_or(48_000),
+    };
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err))
+                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(err) => return Err(err).wrap_err("failed to read audio packet"),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Corrupt packets are skipped rather than aborting the whole file
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(err).wrap_err("failed to decode audio"),
+        };
+
+        let spec = *decoded.spec();
+        audio.channels = spec.channels.count() as u16;
+        audio.sample_rate = spec.rate;
+
+        let buffer =
+            buffer.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        if buffer.capacity() < decoded.capacity() * spec.channels.count() {
+            *buffer = SampleBuffer::new(decoded.capacity() as u64, spec);
+        }
+        buffer.copy_interleaved_ref(decoded);
+        audio.samples.extend_from_slice(buffer.samples());
+    }
+
+    Ok(audio)
+}
+
+fn open_format(path: &Path) -> Result<Box<dyn FormatReader>> {
+    let file = File::open(path).wrap_err_with(|| format!("failed to open {}", path.display()))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .wrap_err_with(|| format!("unsupported audio file {}", path.display()))?;
+    Ok(probed.format)
+}
+
+fn audio_track<'a>(format: &'a dyn FormatReader, path: &Path) -> Result<&'a Track> {
+    format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| eyre!("no audio track in {}", path.display()))
+}
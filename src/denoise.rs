@@ -0,0 +1,283 @@
+//! Spectral subtraction against a learned noise profile: a few seconds of room
+//! tone tell us what the steady background sounds like, and that much is taken
+//! out of every frame that follows.
+
+use std::sync::Arc;
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use crate::dsp;
+
+/// Length of each analysis frame, in samples per channel.
+const FRAME_LEN: usize = 1024;
+/// Frames overlap by half, where Hann windows add up to exactly one.
+const HOP: usize = FRAME_LEN / 2;
+/// How much more than the measured noise is subtracted, to catch its peaks too.
+const OVER_SUBTRACTION: f32 = 2.0;
+/// Lowest gain applied to a bin, about -20 dB. Removing noise entirely leaves
+/// "musical" chirps behind, so some is kept.
+const SPECTRAL_FLOOR: f32 = 0.1;
+
+/// Average magnitude spectrum of the background noise, per channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseProfile {
+    channels: Vec<Vec<f32>>,
+}
+
+impl NoiseProfile {
+    /// Learns the noise from interleaved `room_tone`, which should hold nothing but
+    /// the background. Shorter than a frame, it learns silence.
+    pub fn learn(room_tone: &[f32], channels: usize) -> Self {
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(FRAME_LEN);
+        let window = hann_window();
+        let channels = dsp::deinterleave(room_tone, channels)
+            .into_iter()
+            .map(|samples| {
+                let mut sum = vec![0.0; FRAME_LEN];
+                let mut frames = 0;
+                for start in (0..samples.len().saturating_sub(FRAME_LEN - 1)).step_by(HOP) {
+                    let mut spectrum = windowed(&samples[start..start + FRAME_LEN], &window);
+                    fft.process(&mut spectrum);
+                    for (sum, bin) in sum.iter_mut().zip(&spectrum) {
+                        *sum += bin.norm();
+                    }
+                    frames += 1;
+                }
+                if frames > 0 {
+                    sum.iter_mut().for_each(|sum| *sum /= frames as f32);
+                }
+                sum
+            })
+            .collect();
+        Self { channels }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
+}
+
+/// Streaming noise reduction. Output runs about a frame behind the input;
+/// [`SpectralSubtractor::flush`] returns the rest at the end.
+pub struct SpectralSubtractor {
+    transform: Transform,
+    channels: Vec<ChannelState>,
+}
+
+struct Transform {
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+}
+
+struct ChannelState {
+    noise: Vec<f32>,
+    /// Samples not yet consumed, starting at the current frame.
+    input: Vec<f32>,
+    /// Second half of the last frame, waiting for the next one to overlap it.
+    overlap: Vec<f32>,
+    /// The first frame reaches back into audio that came before, which is not
+    /// ours to output.
+    primed: bool,
+    /// Samples taken in but not yet returned.
+    pending: usize,
+}
+
+impl SpectralSubtractor {
+    /// `history` is the interleaved input just before the first block to be
+    /// processed, normally the end of the room tone. Frames reach back into it,
+    /// so the output continues seamlessly from audio that was passed through
+    /// untouched.
+    pub fn new(profile: NoiseProfile, history: &[f32]) -> Self {
+        let mut planner = FftPlanner::<f32>::new();
+        let history = dsp::deinterleave(history, profile.channels());
+        let channels = profile
+            .channels
+            .into_iter()
+            .zip(history)
+            .map(|(noise, history)| {
+                let mut input = vec![0.0; HOP.saturating_sub(history.len())];
+                input.extend_from_slice(&history[history.len().saturating_sub(HOP)..]);
+                ChannelState {
+                    noise,
+                    input,
+                    overlap: vec![0.0; HOP],
+                    primed: false,
+                    pending: 0,
+                }
+            })
+            .collect();
+        Self {
+            transform: Transform {
+                forward: planner.plan_fft_forward(FRAME_LEN),
+                inverse: planner.plan_fft_inverse(FRAME_LEN),
+                window: hann_window(),
+            },
+            channels,
+        }
+    }
+
+    /// Takes interleaved samples and returns as many denoised ones as are ready.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let split = dsp::deinterleave(samples, self.channels.len());
+        let outputs: Vec<Vec<f32>> = self
+            .channels
+            .iter_mut()
+            .zip(split)
+            .map(|(channel, samples)| {
+                channel.pending += samples.len();
+                channel.input.extend_from_slice(&samples);
+                let mut output = Vec::new();
+                while channel.input.len() >= FRAME_LEN {
+                    self.transform.frame(channel, &mut output);
+                }
+                channel.pending -= output.len();
+                output
+            })
+            .collect();
+        interleave(&outputs)
+    }
+
+    /// Returns whatever is still held back, padding the input with silence to
+    /// complete the last frames.
+    pub fn flush(&mut self) -> Vec<f32> {
+        let outputs: Vec<Vec<f32>> = self
+            .channels
+            .iter_mut()
+            .map(|channel| {
+                let mut output = Vec::new();
+                while output.len() < channel.pending {
+                    channel
+                        .input
+                        .resize(channel.input.len().max(FRAME_LEN), 0.0);
+                    self.transform.frame(channel, &mut output);
+                }
+                output.truncate(channel.pending);
+                channel.pending = 0;
+                output
+            })
+            .collect();
+        interleave(&outputs)
+    }
+}
+
+impl Transform {
+    /// Denoises the frame at the start of `channel.input`, appending the hop it
+    /// completes to `output`.
+    fn frame(&self, channel: &mut ChannelState, output: &mut Vec<f32>) {
+        let mut spectrum = windowed(&channel.input[..FRAME_LEN], &self.window);
+        self.forward.process(&mut spectrum);
+        for (bin, &noise) in spectrum.iter_mut().zip(&channel.noise) {
+            let magnitude = bin.norm();
+            if magnitude > 0.0 {
+                let cleaned =
+                    (magnitude - OVER_SUBTRACTION * noise).max(SPECTRAL_FLOOR * magnitude);
+                *bin *= cleaned / magnitude;
+            }
+        }
+        self.inverse.process(&mut spectrum);
+
+        let scale = 1.0 / FRAME_LEN as f32;
+        if channel.primed {
+            output.extend(
+                spectrum[..HOP]
+                    .iter()
+                    .zip(&channel.overlap)
+                    .map(|(bin, overlap)| bin.re * scale + overlap),
+            );
+        }
+        channel.primed = true;
+        for (overlap, bin) in channel.overlap.iter_mut().zip(&spectrum[HOP..]) {
+            *overlap = bin.re * scale;
+        }
+        channel.input.drain(..HOP);
+    }
+}
+
+/// Periodic Hann window, so that windows half a frame apart sum to one.
+fn hann_window() -> Vec<f32> {
+    (0..FRAME_LEN)
+        .map(|i| {
+            let phase = 2.0 * std::f32::consts::PI * i as f32 / FRAME_LEN as f32;
+            0.5 - 0.5 * phase.cos()
+        })
+        .collect()
+}
+
+fn windowed(samples: &[f32], window: &[f32]) -> Vec<Complex<f32>> {
+    samples
+        .iter()
+        .zip(window)
+        .map(|(&x, &w)| Complex::new(x * w, 0.0))
+        .collect()
+}
+
+/// Inverse of [`dsp::deinterleave`], for channels of equal length.
+fn interleave(channels: &[Vec<f32>]) -> Vec<f32> {
+    let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+    (0..frames)
+        .flat_map(|frame| channels.iter().map(move |channel| channel[frame]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic white noise at roughly `level` RMS.
+    fn noise(len: usize, level: f32) -> Vec<f32> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * level * 3_f32.sqrt()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn passes_audio_through_without_noise() {
+        let input = noise(10_000, 0.3);
+        let history = noise(HOP, 0.3);
+        let mut subtractor = SpectralSubtractor::new(NoiseProfile::learn(&[], 1), &history);
+
+        let mut output = subtractor.process(&input[..3000]);
+        output.extend(subtractor.process(&input[3000..]));
+        output.extend(subtractor.flush());
+
+        assert_eq!(output.len(), input.len());
+        for (out, expected) in output.iter().zip(&input) {
+            assert!((out - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn reduces_the_learned_noise() {
+        let room_tone = noise(48_000, 0.05);
+        let profile = NoiseProfile::learn(&room_tone, 1);
+        let mut subtractor = SpectralSubtractor::new(profile, &room_tone);
+
+        let more_noise = noise(96_000, 0.05);
+        let output = subtractor.process(&more_noise[48_000..]);
+
+        let reduction = dsp::to_dbfs(dsp::rms(&output)) - dsp::to_dbfs(0.05);
+        assert!(reduction < -12.0, "only {reduction:.1} dB quieter");
+    }
+
+    #[test]
+    fn keeps_speech_level_tones() {
+        let room_tone = noise(48_000, 0.01);
+        let profile = NoiseProfile::learn(&room_tone, 2);
+        let mut subtractor = SpectralSubtractor::new(profile, &room_tone);
+
+        let tone: Vec<f32> = (0..48_000)
+            .map(|i| 0.5 * ((i / 2) as f32 * 0.05).sin())
+            .collect();
+        let output = subtractor.process(&tone);
+
+        let change = dsp::to_dbfs(dsp::rms(&output[4096..])) - dsp::to_dbfs(dsp::rms(&tone));
+        assert!(change.abs() < 0.5, "tone changed by {change:.1} dB");
+    }
+}
@@ -0,0 +1,195 @@
+/// Root-mean-square level of `samples`, or 0.0 when empty.
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|&x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Largest absolute sample value.
+pub fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0_f32, |acc, &x| acc.max(x.abs()))
+}
+
+/// Converts a linear level to dBFS, clamping silence to a finite floor.
+pub fn to_dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-10).log10()
+}
+
+/// Converts a gain in dB to a linear factor.
+pub fn db_to_gain(db: f32) -> f32 {
+    10_f32.powf(db / 20.0)
+}
+
+/// Multiplies `samples` by `gain`, in place, keeping them within full scale. With
+/// `soft_limit`, peaks above [`SOFT_LIMIT_KNEE`] are rounded off smoothly instead
+/// of being clipped flat.
+pub fn apply_gain(samples: &mut [f32], gain: f32, soft_limit: bool) {
+    for sample in samples {
+        let boosted = *sample * gain;
+        *sample = if soft_limit {
+            self::soft_limit(boosted)
+        } else {
+            boosted.clamp(-1.0, 1.0)
+        };
+    }
+}
+
+/// Level where the soft limiter starts to compress.
+pub const SOFT_LIMIT_KNEE: f32 = 0.8;
+
+/// Linear up to the knee, then eases into full scale without exceeding it.
+fn soft_limit(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= SOFT_LIMIT_KNEE {
+        return sample;
+    }
+    let headroom = 1.0 - SOFT_LIMIT_KNEE;
+    let limited = SOFT_LIMIT_KNEE + headroom * ((magnitude - SOFT_LIMIT_KNEE) / headroom).tanh();
+    limited.copysign(sample)
+}
+
+/// Maps an RMS level to a bar height in `0.0..=1.0`, linearly.
+pub fn bar_level(rms: f32) -> f32 {
+    (rms * 10.0).min(1.0)
+}
+
+/// Maps an RMS level to a bar height in `0.0..=1.0` on a dB scale, from `floor_dbfs`
+/// up to full scale. Floors above -1 dBFS are treated as -1 dBFS.
+pub fn db_bar_level(rms: f32, floor_dbfs: f32) -> f32 {
+    let floor_dbfs = floor_dbfs.min(-1.0);
+    ((to_dbfs(rms) - floor_dbfs) / -floor_dbfs).clamp(0.0, 1.0)
+}
+
+/// Averages interleaved frames down to a single channel.
+pub fn mixdown(samples: &[f32], channels: usize) -> Vec<f32> {
+    let channels = channels.max(1);
+    samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// Converts interleaved frames from `from` to `to` channels. Mono is copied to
+/// every channel and anything becoming mono is averaged; otherwise channels are
+/// dropped, or repeated in order.
+pub fn remap_channels(samples: &[f32], from: usize, to: usize) -> Vec<f32> {
+    let mut remapped = Vec::with_capacity(samples.len() / from.max(1) * to.max(1));
+    remap_channels_into(samples, from, to, &mut remapped);
+    remapped
+}
+
+/// Like [`remap_channels`], appending to `out` so its allocation can be reused.
+pub fn remap_channels_into(samples: &[f32], from: usize, to: usize, out: &mut Vec<f32>) {
+    let (from, to) = (from.max(1), to.max(1));
+    if from == to {
+        out.extend_from_slice(samples);
+    } else if to == 1 {
+        out.extend(
+            samples
+                .chunks(from)
+                .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32),
+        );
+    } else {
+        out.extend(
+            samples
+                .chunks_exact(from)
+                .flat_map(|frame| (0..to).map(move |channel| frame[channel % from])),
+        );
+    }
+}
+
+/// Flips the polarity of the channels whose bit is set in `mask`, in place.
+pub fn invert_channels(samples: &mut [f32], channels: usize, mask: u64) {
+    for frame in samples.chunks_mut(channels.max(1)) {
+        for (channel, sample) in frame.iter_mut().enumerate().take(64) {
+            if mask & (1 << channel) != 0 {
+                *sample = -*sample;
+            }
+        }
+    }
+}
+
+/// Splits interleaved frames into one buffer per channel.
+pub fn deinterleave(samples: &[f32], channels: usize) -> Vec<Vec<f32>> {
+    let channels = channels.max(1);
+    let mut split = vec![Vec::with_capacity(samples.len() / channels); channels];
+    for frame in samples.chunks_exact(channels) {
+        for (channel, &sample) in split.iter_mut().zip(frame) {
+            channel.push(sample);
+        }
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_of_a_square_wave() {
+        let samples = [0.5, -0.5, 0.5, -0.5];
+        assert_eq!(rms(&samples), 0.5);
+        assert_eq!(peak(&samples), 0.5);
+        assert!((to_dbfs(0.5) - -6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn silence_has_a_finite_level() {
+        assert_eq!(rms(&[]), 0.0);
+        assert_eq!(peak(&[]), 0.0);
+        assert_eq!(to_dbfs(0.0), -200.0);
+    }
+
+    #[test]
+    fn gain_is_clamped_to_full_scale() {
+        let mut samples = [0.25, -0.25, 0.75, -0.75];
+        apply_gain(&mut samples, db_to_gain(6.0206), false);
+        assert!((samples[0] - 0.5).abs() < 1e-4);
+        assert!((samples[1] + 0.5).abs() < 1e-4);
+        assert_eq!(&samples[2..], [1.0, -1.0]);
+    }
+
+    #[test]
+    fn soft_limiter_stays_below_full_scale() {
+        let mut samples = [0.5, 0.9, -4.0, 100.0];
+        apply_gain(&mut samples, 1.0, true);
+        assert_eq!(samples[0], 0.5);
+        assert!(samples[1] > SOFT_LIMIT_KNEE && samples[1] < 0.9);
+        assert!(samples[2] >= -1.0 && samples[2] < -0.99);
+        assert!(samples[3] <= 1.0 && samples[3] > 0.99);
+    }
+
+    #[test]
+    fn db_bars_span_the_floor_to_full_scale() {
+        assert_eq!(db_bar_level(0.0, -60.0), 0.0);
+        assert_eq!(db_bar_level(1.0, -60.0), 1.0);
+        assert!((db_bar_level(db_to_gain(-30.0), -60.0) - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn mixdown_averages_each_frame() {
+        assert_eq!(mixdown(&[1.0, 0.0, 0.5, 0.5], 2), [0.5, 0.5]);
+        assert_eq!(mixdown(&[0.25, 0.5], 1), [0.25, 0.5]);
+    }
+
+    #[test]
+    fn remap_spreads_mono_and_drops_extra_channels() {
+        assert_eq!(remap_channels(&[0.1, 0.2], 1, 2), [0.1, 0.1, 0.2, 0.2]);
+        assert_eq!(remap_channels(&[0.1, 0.2, 0.3, 0.4], 4, 2), [0.1, 0.2]);
+        assert_eq!(remap_channels(&[0.2, 0.4], 2, 1), [0.3_f32]);
+    }
+
+    #[test]
+    fn invert_flips_only_masked_channels() {
+        let mut samples = [0.5, 0.5, -0.25, -0.25];
+        invert_channels(&mut samples, 2, 0b10);
+        assert_eq!(samples, [0.5, -0.5, -0.25, 0.25]);
+    }
+
+    #[test]
+    fn deinterleave_splits_channels() {
+        let split = deinterleave(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3);
+        assert_eq!(split, [vec![1.0, 4.0], vec![2.0, 5.0], vec![3.0, 6.0]]);
+    }
+}
@@ -0,0 +1,501 @@
+//! Writers for each output format, behind one trait so the engine doesn't care
+//! which format it's feeding.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use cpal::StreamConfig;
+use flacenc::bitsink::MemSink;
+use flacenc::component::{BitRepr, Stream, StreamInfo};
+use flacenc::error::Verify;
+use flacenc::source::{Fill, FrameBuf};
+use serde::{Deserialize, Serialize};
+
+/// Frames per FLAC block, the reference encoder's default.
+const FLAC_BLOCK_SIZE: usize = 4096;
+/// FLAC has no float samples, so captured audio is stored at 24 bits.
+const FLAC_BITS_PER_SAMPLE: usize = 24;
+/// Opus bitrate for each channel, plenty for speech.
+const OPUS_BITRATE_PER_CHANNEL: i32 = 32_000;
+/// Sample rates Opus encodes; anything else has to be resampled first.
+pub const OPUS_SAMPLE_RATES: [u32; 5] = [8_000, 12_000, 16_000, 24_000, 48_000];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Uncompressed 32-bit float
+    #[default]
+    Wav,
+    /// Lossless, about half the size of WAV
+    Flac,
+    /// Ogg Opus at 32 kbps per channel, made for speech
+    Opus,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Wav => "wav",
+            OutputFormat::Flac => "flac",
+            OutputFormat::Opus => "opus",
+        }
+    }
+
+    /// Format a file name's extension stands for, if any.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "wav" => Some(OutputFormat::Wav),
+            "flac" => Some(OutputFormat::Flac),
+            "opus" | "ogg" => Some(OutputFormat::Opus),
+            _ => None,
+        }
+    }
+
+    /// Roughly how many bytes a second of audio takes up in this format. FLAC is
+    /// assumed to get speech down to about 60% of its 24-bit samples.
+    pub fn estimated_bytes_per_sec(self, sample_rate: u32, channels: u16) -> u64 {
+        let samples = sample_rate as u64 * channels as u64;
+        match self {
+            OutputFormat::Wav => samples * 4,
+            OutputFormat::Flac => samples * FLAC_BITS_PER_SAMPLE as u64 / 8 * 6 / 10,
+            OutputFormat::Opus => channels as u64 * OPUS_BITRATE_PER_CHANNEL as u64 / 8,
+        }
+    }
+
+    /// `template` with its extension swapped for this format's, or with one added
+    /// when it has none we recognize.
+    pub fn file_template(self, template: &str) -> String {
+        let stem = match template.rsplit_once('.') {
+            Some((stem, extension)) if Self::from_extension(extension).is_some() => stem,
+            _ => template,
+        };
+        format!("{stem}.{}", self.extension())
+    }
+}
+
+/// Destination for captured audio in one of the [`OutputFormat`]s.
+pub trait AudioWriter: Send {
+    /// Appends interleaved samples.
+    fn write(&mut self, samples: &[f32]) -> Result<()>;
+
+    /// Makes everything written so far readable by other processes.
+    fn publish(&mut self) -> Result<()>;
+
+    /// Writes out anything still buffered and completes the file's headers.
+    fn finalize(self: Box<Self>) -> Result<()>;
+}
+
+/// Starts writing `file` in `format`, for audio captured with `config`.
+pub fn create(
+    format: OutputFormat,
+    file: File,
+    config: &StreamConfig,
+) -> Result<Box<dyn AudioWriter>> {
+    match format {
+        OutputFormat::Wav => Ok(Box::new(WavFile::new(file, config)?)),
+        OutputFormat::Flac => Ok(Box::new(FlacFile::new(file, config)?)),
+        #[cfg(feature = "opus")]
+        OutputFormat::Opus => Ok(Box::new(opus_file::OpusFile::new(file, config)?)),
+        #[cfg(not(feature = "opus"))]
+        OutputFormat::Opus => Err(eyre!(
+            "this build of micrec has no Opus support (rebuild with --features opus)"
+        )),
+    }
+}
+
+struct WavFile(hound::WavWriter<BufWriter<File>>);
+
+impl WavFile {
+    fn new(file: File, config: &StreamConfig) -> Result<Self> {
+        let spec = hound::WavSpec {
+            channels: config.channels,
+            sample_rate: config.sample_rate.0,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        Ok(Self(hound::WavWriter::new(BufWriter::new(file), spec)?))
+    }
+}
+
+impl AudioWriter for WavFile {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        for &sample in samples {
+            self.0.write_sample(sample)?;
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self) -> Result<()> {
+        // Rewrites the header's lengths to cover what has been written so far
+        Ok(self.0.flush()?)
+    }
+
+    fn finalize(self: Box<Self>) -> Result<()> {
+        Ok(self.0.finalize()?)
+    }
+}
+
+/// Streams FLAC frames to disk as blocks fill up. The STREAMINFO header is written
+/// up front with an unknown length and rewritten with the real one on finalize.
+struct FlacFile {
+    file: BufWriter<File>,
+    encoder: flacenc::error::Verified<flacenc::config::Encoder>,
+    info: StreamInfo,
+    channels: usize,
+    /// Interleaved samples waiting for a full block.
+    pending: Vec<i32>,
+    framebuf: FrameBuf,
+    frames: usize,
+}
+
+impl FlacFile {
+    fn new(file: File, config: &StreamConfig) -> Result<Self> {
+        let channels = config.channels.max(1) as usize;
+        let mut info = StreamInfo::new(
+            config.sample_rate.0 as usize,
+            channels,
+            FLAC_BITS_PER_SAMPLE,
+        )
+        .map_err(|err| eyre!("can't write FLAC: {err}"))?;
+        // The minimum frame size stays 0, meaning unknown
+        info.set_frame_sizes(0, 0)
+            .map_err(|err| eyre!("can't write FLAC: {err}"))?;
+        let encoder = flacenc::config::Encoder::default()
+            .into_verified()
+            .map_err(|(_, err)| eyre!("invalid FLAC encoder settings: {err}"))?;
+        let framebuf = FrameBuf::with_size(channels, FLAC_BLOCK_SIZE)
+            .map_err(|err| eyre!("can't write FLAC: {err}"))?;
+
+        let mut flac = Self {
+            file: BufWriter::new(file),
+            encoder,
+            info,
+            channels,
+            pending: Vec::with_capacity(FLAC_BLOCK_SIZE * channels),
+            framebuf,
+            frames: 0,
+        };
+        flac.write_header()?;
+        Ok(flac)
+    }
+
+    /// Writes the `fLaC` marker and STREAMINFO block at the current position.
+    fn write_header(&mut self) -> Result<()> {
+        // Every block but the last is full size, which marks a fixed block size
+        // stream even though `info` has seen the shorter last one
+        let mut info = self.info.clone();
+        info.set_block_sizes(FLAC_BLOCK_SIZE, FLAC_BLOCK_SIZE)
+            .map_err(|err| eyre!("failed to encode the FLAC header: {err}"))?;
+
+        let mut sink = MemSink::<u8>::new();
+        Stream::with_stream_info(info)
+            .write(&mut sink)
+            .map_err(|err| eyre!("failed to encode the FLAC header: {err}"))?;
+        self.file.write_all(sink.as_slice())?;
+        Ok(())
+    }
+
+    /// Encodes up to one block of pending samples as a frame.
+    fn write_frame(&mut self) -> Result<()> {
+        let len = self.pending.len().min(FLAC_BLOCK_SIZE * self.channels);
+        self.framebuf
+            .fill_interleaved(&self.pending[..len])
+            .map_err(|err| eyre!("failed to encode FLAC: {err}"))?;
+        let frame = flacenc::encode_fixed_size_frame(
+            &self.encoder,
+            &self.framebuf,
+            self.frames,
+            &self.info,
+        )
+        .map_err(|err| eyre!("failed to encode FLAC: {err}"))?;
+
+        let mut sink = MemSink::<u8>::new();
+        frame
+            .write(&mut sink)
+            .map_err(|err| eyre!("failed to encode FLAC: {err}"))?;
+        self.file.write_all(sink.as_slice())?;
+
+        self.info.update_frame_info(&frame);
+        self.pending.drain(..len);
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl AudioWriter for FlacFile {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        let full_scale = ((1 << (FLAC_BITS_PER_SAMPLE - 1)) - 1) as f32;
+        self.pending.extend(
+            samples
+                .iter()
+                .map(|&sample| (sample.clamp(-1.0, 1.0) * full_scale).round() as i32),
+        );
+        while self.pending.len() >= FLAC_BLOCK_SIZE * self.channels {
+            self.write_frame()?;
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self) -> Result<()> {
+        // FLAC allows an unknown length, so complete frames are enough
+        Ok(self.file.flush()?)
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<()> {
+        if !self.pending.is_empty() {
+            self.write_frame()?;
+        }
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush().wrap_err("failed to write FLAC")
+    }
+}
+
+#[cfg(feature = "opus")]
+mod opus_file {
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    use color_eyre::eyre::{bail, Result};
+    use cpal::StreamConfig;
+    use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+
+    use super::AudioWriter;
+
+    /// Packet length; 20 ms is what Opus is tuned for.
+    const FRAME_MS: u32 = 20;
+    /// Granule positions always count samples at 48 kHz, whatever the input rate.
+    const GRANULE_RATE: u64 = 48_000;
+
+    /// Ogg Opus stream, as laid out in RFC 7845.
+    pub struct OpusFile {
+        packets: PacketWriter<'static, BufWriter<File>>,
+        encoder: opus::Encoder,
+        serial: u32,
+        channels: usize,
+        sample_rate: u64,
+        /// Samples per channel in each packet.
+        frame_size: usize,
+        pending: Vec<f32>,
+        /// Frames captured so far.
+        input_frames: u64,
+        /// Frames encoded so far, including padding.
+        encoded_frames: u64,
+        /// Encoder delay, in input frames.
+        lookahead: usize,
+        /// Encoder delay at 48 kHz, which players skip.
+        pre_skip: u64,
+        /// End the current page on the next packet so readers can see it.
+        end_page: bool,
+    }
+
+    impl OpusFile {
+        pub fn new(file: File, config: &StreamConfig) -> Result<Self> {
+            let sample_rate = config.sample_rate.0;
+            if !super::OPUS_SAMPLE_RATES.contains(&sample_rate) {
+                bail!(
+                    "Opus can't encode {sample_rate} Hz audio, \
+                     record at 48000 Hz with --sample-rate 48000"
+                );
+            }
+            let channels = match config.channels {
+                1 => opus::Channels::Mono,
+                2 => opus::Channels::Stereo,
+                n => bail!("Opus can't encode {n} channels, try --output-channels mono"),
+            };
+
+            let mut encoder = opus::Encoder::new(sample_rate, channels, opus::Application::Voip)?;
+            encoder.set_bitrate(opus::Bitrate::Bits(
+                super::OPUS_BITRATE_PER_CHANNEL * config.channels as i32,
+            ))?;
+            let lookahead = encoder.get_lookahead()? as usize;
+            let pre_skip = lookahead as u64 * GRANULE_RATE / sample_rate as u64;
+
+            let mut opus = Self {
+                packets: PacketWriter::new(BufWriter::new(file)),
+                encoder,
+                serial: std::process::id(),
+                channels: config.channels as usize,
+                sample_rate: sample_rate as u64,
+                frame_size: (sample_rate * FRAME_MS / 1000) as usize,
+                pending: Vec::new(),
+                input_frames: 0,
+                encoded_frames: 0,
+                lookahead,
+                pre_skip,
+                end_page: false,
+            };
+            opus.write_headers(sample_rate)?;
+            Ok(opus)
+        }
+
+        /// Writes the identification and comment headers, each on its own page.
+        fn write_headers(&mut self, sample_rate: u32) -> Result<()> {
+            let mut head = Vec::from(*b"OpusHead");
+            head.push(1); // version
+            head.push(self.channels as u8);
+            head.extend((self.pre_skip as u16).to_le_bytes());
+            head.extend(sample_rate.to_le_bytes());
+            head.extend(0i16.to_le_bytes()); // output gain
+            head.push(0); // mono or stereo channel mapping
+            self.packets
+                .write_packet(head, self.serial, PacketWriteEndInfo::EndPage, 0)?;
+
+            let vendor = b"micrec";
+            let mut tags = Vec::from(*b"OpusTags");
+            tags.extend((vendor.len() as u32).to_le_bytes());
+            tags.extend(vendor);
+            tags.extend(0u32.to_le_bytes()); // no user comments
+            self.packets
+                .write_packet(tags, self.serial, PacketWriteEndInfo::EndPage, 0)?;
+            Ok(())
+        }
+
+        /// Encodes one packet from the start of `pending`, padding it with silence.
+        fn write_packet(&mut self, end: PacketWriteEndInfo) -> Result<()> {
+            let len = self.frame_size * self.channels;
+            self.pending.resize(self.pending.len().max(len), 0.0);
+
+            let mut packet = vec![0; 4000];
+            let size = self
+                .encoder
+                .encode_float(&self.pending[..len], &mut packet)?;
+            packet.truncate(size);
+            self.pending.drain(..len);
+            self.encoded_frames += self.frame_size as u64;
+
+            // The last granule position trims the encoder delay and padding off again
+            let granule = match end {
+                PacketWriteEndInfo::EndStream => {
+                    self.pre_skip + self.input_frames * GRANULE_RATE / self.sample_rate
+                }
+                _ => self.encoded_frames * GRANULE_RATE / self.sample_rate,
+            };
+            let end = match end {
+                PacketWriteEndInfo::NormalPacket if self.end_page => PacketWriteEndInfo::EndPage,
+                end => end,
+            };
+            self.end_page = false;
+            self.packets
+                .write_packet(packet, self.serial, end, granule)?;
+            Ok(())
+        }
+    }
+
+    impl AudioWriter for OpusFile {
+        fn write(&mut self, samples: &[f32]) -> Result<()> {
+            self.input_frames += (samples.len() / self.channels) as u64;
+            self.pending.extend_from_slice(samples);
+            while self.pending.len() >= self.frame_size * self.channels {
+                self.write_packet(PacketWriteEndInfo::NormalPacket)?;
+            }
+            Ok(())
+        }
+
+        fn publish(&mut self) -> Result<()> {
+            self.end_page = true;
+            Ok(self.packets.inner_mut().flush()?)
+        }
+
+        fn finalize(mut self: Box<Self>) -> Result<()> {
+            // Push the last samples through the encoder's delay
+            let flushed = self.pending.len() + self.lookahead * self.channels;
+            self.pending.resize(flushed, 0.0);
+            while self.pending.len() > self.frame_size * self.channels {
+                self.write_packet(PacketWriteEndInfo::NormalPacket)?;
+            }
+            self.write_packet(PacketWriteEndInfo::EndStream)?;
+            Ok(self.packets.inner_mut().flush()?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use cpal::{BufferSize, SampleRate};
+
+    use super::*;
+    use crate::decoder;
+
+    /// Writes `samples` to a scratch file in `format` and decodes them again.
+    fn round_trip(format: OutputFormat, channels: u16, samples: &[f32]) -> Vec<f32> {
+        let path = std::env::temp_dir().join(format!(
+            "micrec-round-trip-{}.{}",
+            process::id(),
+            format.extension()
+        ));
+        let config = StreamConfig {
+            channels,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Default,
+        };
+
+        let mut writer = create(format, File::create(&path).unwrap(), &config).unwrap();
+        for block in samples.chunks(1000) {
+            writer.write(block).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let audio = decoder::decode_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!((audio.channels, audio.sample_rate), (channels, 48_000));
+        audio.samples
+    }
+
+    /// A few blocks' worth of stereo ramps, ending mid-block.
+    fn ramps() -> Vec<f32> {
+        (0..10_001)
+            .flat_map(|i| {
+                let ramp = (i % 200) as f32 / 200.0 - 0.5;
+                [ramp, -ramp]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn template_extension_follows_the_format() {
+        assert_eq!(OutputFormat::Flac.file_template("memo.wav"), "memo.flac");
+        assert_eq!(OutputFormat::Wav.file_template("memo"), "memo.wav");
+        assert_eq!(OutputFormat::Opus.file_template("memo.v2"), "memo.v2.opus");
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            OutputFormat::from_path(Path::new("a.FLAC")),
+            Some(OutputFormat::Flac)
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("a.ogg")),
+            Some(OutputFormat::Opus)
+        );
+        assert_eq!(OutputFormat::from_path(Path::new("a.mp3")), None);
+        assert_eq!(OutputFormat::from_path(Path::new("a")), None);
+    }
+
+    #[test]
+    fn wav_round_trips_exactly() {
+        let samples = ramps();
+        assert_eq!(round_trip(OutputFormat::Wav, 2, &samples), samples);
+    }
+
+    #[test]
+    fn flac_round_trips_to_24_bits() {
+        let samples = ramps();
+        let decoded = round_trip(OutputFormat::Flac, 2, &samples);
+
+        assert_eq!(decoded.len(), samples.len());
+        for (decoded, original) in decoded.iter().zip(&samples) {
+            assert!((decoded - original).abs() < 1e-6);
+        }
+    }
+}
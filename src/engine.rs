@@ -0,0 +1,2388 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryIter};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, Host, HostId, SampleFormat, SampleRate, Stream, StreamConfig};
+use serde::{Deserialize, Serialize};
+
+use crate::denoise::{NoiseProfile, SpectralSubtractor};
+use crate::dsp;
+use crate::encoder::{self, AudioWriter, OutputFormat};
+use crate::loopback;
+use crate::loudness::{self, LoudnessMeter, LoudnessReport};
+use crate::markers::{self, Marker, MarkerFormat};
+use crate::merge::ExtraInputs;
+pub use crate::meter::{Meter, MeterReading, StereoReading};
+use crate::monitor::Monitor;
+use crate::naming;
+use crate::network::{NetworkStream, StreamTarget};
+use crate::permission::{self, Permission};
+use crate::pipe::{PcmPipe, PipeFormat, PipeTarget};
+use crate::processing::{Chain, HighPass, NoiseGate, Processor};
+use crate::project::{self, ProjectFormat, Region, Take};
+use crate::provenance::{
+    self, DeviceInfo, LevelSummary, OutputInfo, ProcessingInfo, Provenance, StreamInfo,
+};
+use crate::queue::CaptureQueue;
+use crate::resample::FileResampler;
+use crate::ring::{Clip, RingFile, RingWriter};
+use crate::source::{AudioSource, Signal, Synth};
+use crate::stats::{DiskSpace, StatsTracker};
+pub use crate::stats::{RecordingStats, LOW_DISK_SPACE_FACTOR};
+use crate::tags::{self, Tags};
+use crate::transcode::{self, TranscodeFormat};
+
+/// How often the output file is brought up to date while recording, so the file on
+/// disk is always playable up to roughly that long ago.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+/// A stream that delivers nothing for this long is taken to have lost its device;
+/// some backends stop calling back without reporting an error.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often to look for the input device again after it went away.
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+/// How long to wait for the original device to come back before settling for the
+/// default input.
+const FALLBACK_AFTER: Duration = Duration::from_secs(5);
+/// How often to check whether microphone access was granted after it was denied.
+const PERMISSION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest wait before looking at the clock again while a take is scheduled, so a
+/// clock that's set or a machine that slept doesn't make it start late.
+const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the capture thread sleeps when no audio is queued. Devices deliver
+/// blocks every few milliseconds.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(2);
+/// Frames of the input stream's blocks to allocate room for up front.
+const CALLBACK_BLOCK_FRAMES: usize = 8192;
+/// How much of an [`AudioSource`] is played at a time.
+const SOURCE_BLOCK: Duration = Duration::from_millis(10);
+/// Layout of [`RecordingOptions::demo`] takes, unless a sample rate is asked for.
+const DEMO_SAMPLE_RATE: u32 = 48_000;
+const DEMO_CHANNELS: u16 = 2;
+/// Input whose peak stays below this level counts as silence. Even a quiet room
+/// through a working mic sits well above it.
+pub const SILENCE_THRESHOLD_DBFS: f32 = -70.0;
+/// Level of the safety track relative to the input, as on field recorders.
+pub const SAFETY_TRACK_GAIN_DB: f32 = -12.0;
+/// Input that is exactly zero for this long is taken to be muted. Even a quiet
+/// room through a working mic has some noise in the lowest bits.
+pub const MUTED_AFTER: Duration = Duration::from_secs(2);
+/// Room tone captured by [`Recorder::learn_noise`].
+pub const NOISE_LEARN_DURATION: Duration = Duration::from_secs(3);
+/// Range of the software input gain, see [`Recorder::set_gain_db`].
+pub const GAIN_RANGE_DB: RangeInclusive<f32> = -24.0..=36.0;
+
+/// What to record and where to put it.
+#[derive(Debug, Clone)]
+pub struct RecordingOptions {
+    /// Record a made-up signal instead of any device, see [`Synth`].
+    pub demo: Option<Signal>,
+    /// Audio system to capture through, see [`find_host`], or the platform's
+    /// default when `None`.
+    pub host: Option<HostId>,
+    pub capture: CaptureSource,
+    /// Input device name, or the system default when `None`. With
+    /// [`CaptureSource::Loopback`], the output device or sink to record instead.
+    pub device: Option<String>,
+    /// More input devices to record at the same time, see [`ExtraInputs`]. Their
+    /// channels follow the main device's in the file.
+    pub extra_devices: Vec<String>,
+    /// Sample rate of the file, or the device default when `None`. The device is
+    /// asked to capture at it, and if it can't, the take is resampled from the
+    /// device's own rate with a [`FileResampler`].
+    pub sample_rate: Option<u32>,
+    pub output_dir: PathBuf,
+    /// File name template, see [`naming::create_recording_file`]. Its extension is
+    /// replaced to match `format`.
+    pub file_template: String,
+    /// Take numbers in the file name up to this one are used up, by takes that
+    /// were thrown away, and not given out again.
+    pub after_take: u32,
+    pub format: OutputFormat,
+    pub output_channels: OutputChannels,
+    /// Also write the input before the gain and processing, at
+    /// [`SAFETY_TRACK_GAIN_DB`], see [`naming::safety_track_path`].
+    pub safety_track: bool,
+    /// Input channels, counted from 0, whose polarity is flipped. See
+    /// [`Recorder::set_inverted`].
+    pub inverted_channels: Vec<usize>,
+    /// Software gain applied to the input, see [`Recorder::set_gain_db`].
+    pub gain_db: f32,
+    /// Round off peaks with a soft limiter rather than clipping them flat, see
+    /// [`dsp::apply_gain`].
+    pub soft_limit: bool,
+    /// Start with the high-pass filter on, see [`Recorder::set_high_pass`].
+    pub high_pass: bool,
+    /// Cutoff of the high-pass filter.
+    pub high_pass_hz: f32,
+    /// Start with the noise gate on, see [`Recorder::set_noise_gate`].
+    pub noise_gate: bool,
+    /// Blocks with an RMS level below this are muted by the noise gate.
+    pub gate_threshold_dbfs: f32,
+    /// Editor project to write next to the finished recording, see
+    /// [`project::export`].
+    pub project: Option<ProjectFormat>,
+    /// How markers from [`Recorder::add_marker`] are saved, if there are any.
+    pub marker_format: MarkerFormat,
+    /// Write a [`Provenance`] file next to the finished recording, see
+    /// [`provenance::export`].
+    pub provenance: bool,
+    /// Make a compressed copy of the finished recording, see
+    /// [`transcode::transcode`]. Not done when recording into a buffer.
+    pub transcode: Option<TranscodeFormat>,
+    /// Embedded in the finished recording, see [`tags::write`].
+    pub tags: Tags,
+    /// Write the take's [`LoudnessReport`] next to it, see [`loudness::export`].
+    pub loudness_file: bool,
+    /// Also stream the take as it's recorded, see [`PcmPipe`].
+    pub pipe: Option<PipeTarget>,
+    /// How the streamed copy is encoded.
+    pub pipe_format: PipeFormat,
+    /// Also serve the take over the network as it's recorded, see
+    /// [`NetworkStream`].
+    pub stream: Option<StreamTarget>,
+    /// Record into a circular buffer holding this much, instead of a file that
+    /// grows for as long as the take lasts. Clips of it are saved with
+    /// [`Recorder::save_clip`], and the buffer is deleted at the end.
+    pub buffer: Option<Duration>,
+    /// Stop on our own once free space on the drive being recorded to drops
+    /// below this many bytes.
+    pub min_free_space: Option<u64>,
+    /// Wait until this time to open the device and start capturing, see
+    /// [`EngineEvent::Scheduled`].
+    pub start_at: Option<DateTime<Local>>,
+    /// Start the file with this much of the input from before the take began, be
+    /// it at `start_at` or on [`Recorder::record`]. Not done when recording into a
+    /// buffer, which already keeps what came before.
+    pub pre_roll: Option<Duration>,
+    /// Capture without writing until [`Recorder::record`] is called, keeping the
+    /// last `pre_roll` of the input meanwhile.
+    pub standby: bool,
+    /// Stop on our own after this much audio has been captured.
+    pub duration: Option<Duration>,
+    /// Report [`EngineEvent::Silent`] if the input stays silent this long after
+    /// starting.
+    pub silence_check: Option<Duration>,
+    /// Stop on our own once nobody is talking any more.
+    pub vad: Option<VadConfig>,
+}
+
+/// What a take records.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureSource {
+    /// An input device, such as a microphone
+    #[default]
+    Microphone,
+    /// Whatever an output device is playing, see the loopback module
+    Loopback,
+}
+
+/// Channel layout of the recorded file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputChannels {
+    /// Every input channel, as captured
+    #[default]
+    Multichannel,
+    /// All input channels averaged into one
+    Mono,
+}
+
+/// Voice activity settings for stopping a take hands-free.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VadConfig {
+    /// Blocks with an RMS level below this count as silence.
+    pub threshold_dbfs: f32,
+    /// Stop after this much silence. Counting starts once sound has been heard, so
+    /// a pause before the first word doesn't end the take.
+    pub stop_after: Duration,
+}
+
+/// Messages sent from the engine to whichever front-end is driving it.
+#[derive(Debug)]
+pub enum EngineEvent {
+    /// Several devices are being recorded: their names and channel counts, in the
+    /// order their channels appear in the take. Sent before `Started`.
+    Devices(Vec<(String, u16)>),
+    /// The input stream started. Samples are written, and handed out by
+    /// [`Recorder::samples`], in this configuration, which has one channel when
+    /// mixing down to mono.
+    Started(StreamConfig),
+    /// Nothing but silence arrived during the first `silence_check` of the
+    /// recording; the mic is probably muted or the wrong input is selected.
+    Silent(Duration),
+    /// The input has been perfect digital silence for [`MUTED_AFTER`] (`true`),
+    /// as from a hardware mute switch or a muted system input, or has come back to
+    /// life (`false`).
+    Muted(bool),
+    /// The take is being stopped after this much silence, see [`VadConfig`].
+    StoppedOnSilence(Duration),
+    /// Only this many bytes are left on the drive, less than
+    /// [`LOW_DISK_SPACE_FACTOR`] times `min_free_space`. Sent once per take.
+    LowDiskSpace(u64),
+    /// The take is being stopped with only this many bytes left on the drive,
+    /// less than `min_free_space`.
+    StoppedOnLowDiskSpace(u64),
+    /// Monitoring through the default output was turned on or off. It is turned
+    /// off again if the output can't be opened, with a `StreamError` saying why.
+    MonitoringChanged(bool),
+    /// The room tone asked for by [`Recorder::learn_noise`] has been captured, and
+    /// from here on that noise is taken out of the recording.
+    NoiseProfileLearned,
+    /// The input device went away mid-take, e.g. a USB mic was unplugged. The file
+    /// stays open and capture resumes with `Reconnected`.
+    Disconnected(String),
+    /// Capture resumed after `Disconnected`, on the device named here: the same one
+    /// or, if it didn't come back, the default input. New audio is appended to the
+    /// same file, converted to its channel layout if the device differs.
+    Reconnected(String),
+    /// A problem reported by the audio backend while the stream keeps running.
+    StreamError(String),
+    /// The take is armed and starts by itself at this time, with `Started`.
+    /// Stopping it before then reports `Cancelled`.
+    Scheduled(DateTime<Local>),
+    /// The input is being captured and metered but not written yet, keeping this
+    /// much of it for the start of the take. Comes early by as much when
+    /// scheduled. `Started` and `Rolling` follow once the take begins, and
+    /// stopping before then reports `Cancelled`.
+    StandingBy(Duration),
+    /// The take began after `StandingBy`, starting with the pre-roll kept.
+    Rolling,
+    /// The take was stopped before it began, so there's no file. Nothing follows.
+    Cancelled,
+    /// Capture is waiting for access to the microphone, for the reason given.
+    /// It starts by itself once access is granted, with `Started`, and stopping
+    /// before then reports `Cancelled`.
+    WaitingForPermission(String),
+    /// Capture could not start, e.g. there is no microphone or it is busy.
+    Failed(String),
+    /// A marker was placed this far into the recording.
+    MarkerAdded(Duration),
+    /// A clip asked for with [`Recorder::save_clip`] was saved (or failed to be).
+    ClipSaved(Result<PathBuf, String>),
+    /// A file accompanying the recording, such as the editor project, the markers
+    /// or the output of a [`PipeTarget::Command`], was written (or failed to be).
+    /// Sent just before `Finished`. The path is the recording itself for markers
+    /// stored inside it.
+    SidecarSaved(Result<PathBuf, String>),
+    /// How loud the finished take is, sent once it's saved and before any
+    /// `SidecarSaved`. Not measured when recording into a buffer.
+    Loudness(LoudnessReport),
+    /// The fraction of the compressed copy asked for with
+    /// [`RecordingOptions::transcode`] written so far. The copy itself is reported
+    /// with `SidecarSaved` once it's done.
+    Transcoding(f32),
+    /// Capture has stopped and the file is finalized (or failed to be). When
+    /// recording into a buffer, the path is the last clip saved.
+    Finished(Result<PathBuf, String>),
+}
+
+/// Captures audio on a background thread, writing it to the output file and
+/// forwarding it to the front-end for metering.
+#[derive(Debug)]
+pub struct Recorder {
+    events: Receiver<EngineEvent>,
+    shutdown_tx: Sender<()>,
+    commands: Sender<Command>,
+    /// Set up once the stream config is known.
+    meter: Arc<Mutex<Option<Meter>>>,
+    /// Set once the file is open.
+    stats: Arc<Mutex<Option<RecordingStats>>>,
+    /// What's written to the file, for [`Recorder::samples`]. Set once the file
+    /// is open.
+    samples: Arc<Mutex<Option<CaptureQueue>>>,
+    controls: Arc<InputControls>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Requests from the [`Recorder`] to its capture thread.
+#[derive(Debug)]
+enum Command {
+    Monitor(bool),
+    LearnNoise,
+    AddMarker,
+    SaveClip(Duration),
+    Record,
+}
+
+/// Live adjustments applied to the input as it arrives, ahead of metering,
+/// monitoring and the file. Atomic, so the audio callback never waits on a lock.
+/// The callback only flips channels; the gain is applied on the capture thread.
+#[derive(Debug, Default)]
+struct InputControls {
+    /// One bit per channel whose polarity is flipped.
+    inverted: AtomicU64,
+    /// Bits of the `f32` gain in dB.
+    gain_db: AtomicU32,
+    high_pass: AtomicBool,
+    noise_gate: AtomicBool,
+    paused: AtomicBool,
+}
+
+impl Recorder {
+    pub fn start(options: RecordingOptions) -> Self {
+        let source = options.demo.map(|signal| -> Box<dyn AudioSource> {
+            let sample_rate = options.sample_rate.unwrap_or(DEMO_SAMPLE_RATE);
+            Box::new(Synth::new(signal, sample_rate, DEMO_CHANNELS))
+        });
+        Self::spawn(options, source)
+    }
+
+    /// Records `source` instead of a device: `options` still say where the take
+    /// goes and how it's processed, but not what's captured.
+    pub fn start_with_source(options: RecordingOptions, source: Box<dyn AudioSource>) -> Self {
+        Self::spawn(options, Some(source))
+    }
+
+    fn spawn(options: RecordingOptions, source: Option<Box<dyn AudioSource>>) -> Self {
+        let (events_tx, events) = channel::<EngineEvent>();
+        let (shutdown_tx, shutdown_rx) = channel::<()>();
+        let (commands, commands_rx) = channel::<Command>();
+        let meter = Arc::new(Mutex::new(None));
+        let stats = Arc::new(Mutex::new(None));
+        let samples = Arc::new(Mutex::new(None));
+        let controls = Arc::new(InputControls::default());
+        for &channel in &options.inverted_channels {
+            controls.set_inverted(channel, true);
+        }
+        controls.set_gain_db(options.gain_db);
+        controls
+            .high_pass
+            .store(options.high_pass, Ordering::Relaxed);
+        controls
+            .noise_gate
+            .store(options.noise_gate, Ordering::Relaxed);
+
+        let shared = Shared {
+            meter: Arc::clone(&meter),
+            stats: Arc::clone(&stats),
+            samples: Arc::clone(&samples),
+            controls: Arc::clone(&controls),
+        };
+        let thread = thread::spawn(move || {
+            record(options, source, events_tx, shutdown_rx, commands_rx, shared)
+        });
+
+        Self {
+            events,
+            shutdown_tx,
+            commands,
+            meter,
+            stats,
+            samples,
+            controls,
+            thread: Some(thread),
+        }
+    }
+
+    /// Events that have arrived since the last call, without blocking.
+    pub fn events(&self) -> TryIter<'_, EngineEvent> {
+        self.events.try_iter()
+    }
+
+    /// Waits up to `timeout` for the next event.
+    pub fn next_event(&self, timeout: Duration) -> Result<EngineEvent, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// Samples written to the output file since the last call, in the
+    /// configuration of [`EngineEvent::Started`], if there are any. They're
+    /// queued apart from the events, so a front-end that falls behind loses the
+    /// oldest audio, counted as an overrun, instead of holding on to all of it.
+    pub fn samples(&self) -> Option<Arc<[f32]>> {
+        self.samples.lock().ok()?.as_mut()?.pop()
+    }
+
+    /// Current level of each input channel, or nothing before capture has started.
+    pub fn levels(&self) -> Vec<MeterReading> {
+        self.meter
+            .lock()
+            .ok()
+            .and_then(|meter| meter.as_ref().map(Meter::readings))
+            .unwrap_or_default()
+    }
+
+    /// Length, file size and free disk space of the take, once it has started.
+    pub fn stats(&self) -> Option<RecordingStats> {
+        *self.stats.lock().ok()?
+    }
+
+    /// Mid/side levels and stereo width, when capturing in stereo.
+    pub fn stereo_levels(&self) -> Option<StereoReading> {
+        self.meter.lock().ok()?.as_ref()?.stereo_reading()
+    }
+
+    /// Clears a latched clip indicator.
+    pub fn reset_clip(&self) {
+        if let Ok(mut meter) = self.meter.lock() {
+            if let Some(meter) = meter.as_mut() {
+                meter.reset_clip();
+            }
+        }
+    }
+
+    /// Flips the polarity of input `channel`, counted from 0, or puts it back.
+    /// Only the first 64 channels can be inverted.
+    pub fn set_inverted(&self, channel: usize, inverted: bool) {
+        self.controls.set_inverted(channel, inverted);
+    }
+
+    /// Whether input `channel` has its polarity flipped.
+    pub fn is_inverted(&self, channel: usize) -> bool {
+        self.controls.is_inverted(channel)
+    }
+
+    /// Sets the software gain applied to the input before it's metered, monitored
+    /// or written, clamped to [`GAIN_RANGE_DB`]. Boosted samples never exceed full
+    /// scale. A gain that isn't finite is ignored.
+    pub fn set_gain_db(&self, gain_db: f32) {
+        self.controls.set_gain_db(gain_db);
+    }
+
+    pub fn gain_db(&self) -> f32 {
+        self.controls.gain_db()
+    }
+
+    /// Turns the high-pass filter, which cuts rumble below
+    /// [`RecordingOptions::high_pass_hz`], on or off. Takes effect before the
+    /// input is metered and written.
+    pub fn set_high_pass(&self, on: bool) {
+        self.controls.high_pass.store(on, Ordering::Relaxed);
+    }
+
+    pub fn high_pass(&self) -> bool {
+        self.controls.high_pass.load(Ordering::Relaxed)
+    }
+
+    /// Turns the noise gate, which mutes the input while it stays below
+    /// [`RecordingOptions::gate_threshold_dbfs`], on or off.
+    pub fn set_noise_gate(&self, on: bool) {
+        self.controls.noise_gate.store(on, Ordering::Relaxed);
+    }
+
+    pub fn noise_gate(&self) -> bool {
+        self.controls.noise_gate.load(Ordering::Relaxed)
+    }
+
+    /// Pauses or resumes the take. Input arriving while paused can still be heard
+    /// on the monitor, but isn't metered, processed or written, so the take picks
+    /// up where it paused.
+    pub fn set_paused(&self, paused: bool) {
+        self.controls.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn paused(&self) -> bool {
+        self.controls.paused.load(Ordering::Relaxed)
+    }
+
+    /// Starts or stops playing the input through the default output device. A
+    /// `MonitoringChanged` event confirms the change.
+    pub fn set_monitoring(&self, on: bool) {
+        self.commands.send(Command::Monitor(on)).ok();
+    }
+
+    /// Treats the next [`NOISE_LEARN_DURATION`] of input as room tone, then
+    /// subtracts that noise from the rest of the take; `NoiseProfileLearned`
+    /// follows. Asking again learns a new profile.
+    pub fn learn_noise(&self) {
+        self.commands.send(Command::LearnNoise).ok();
+    }
+
+    /// Marks the current position in the recording; `MarkerAdded` says where. The
+    /// markers are saved when the take finishes, see
+    /// [`RecordingOptions::marker_format`].
+    pub fn add_marker(&self) {
+        self.commands.send(Command::AddMarker).ok();
+    }
+
+    /// Saves the last `length` of a take recorded into a buffer as a recording of
+    /// its own, named from the template. `ClipSaved` reports the outcome.
+    pub fn save_clip(&self, length: Duration) {
+        self.commands.send(Command::SaveClip(length)).ok();
+    }
+
+    /// Begins writing a take started with [`RecordingOptions::standby`], pre-roll
+    /// first. `Rolling` confirms it.
+    pub fn record(&self) {
+        self.commands.send(Command::Record).ok();
+    }
+
+    /// Asks the engine to stop; a `Finished` event follows once the file is closed.
+    pub fn stop(&self) {
+        self.shutdown_tx.send(()).ok();
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.stop();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl InputControls {
+    fn set_inverted(&self, channel: usize, inverted: bool) {
+        let Some(bit) = channel_bit(channel) else {
+            return;
+        };
+        if inverted {
+            self.inverted.fetch_or(bit, Ordering::Relaxed);
+        } else {
+            self.inverted.fetch_and(!bit, Ordering::Relaxed);
+        }
+    }
+
+    fn is_inverted(&self, channel: usize) -> bool {
+        channel_bit(channel).is_some_and(|bit| self.inverted.load(Ordering::Relaxed) & bit != 0)
+    }
+
+    fn set_gain_db(&self, gain_db: f32) {
+        if !gain_db.is_finite() {
+            return;
+        }
+        let gain_db = gain_db.clamp(*GAIN_RANGE_DB.start(), *GAIN_RANGE_DB.end());
+        self.gain_db.store(gain_db.to_bits(), Ordering::Relaxed);
+    }
+
+    fn gain_db(&self) -> f32 {
+        f32::from_bits(self.gain_db.load(Ordering::Relaxed))
+    }
+}
+
+/// Bit standing for `channel` in [`InputControls::inverted`].
+fn channel_bit(channel: usize) -> Option<u64> {
+    1_u64.checked_shl(u32::try_from(channel).ok()?)
+}
+
+/// Hosts that are only there when micrec is built with the feature of the same
+/// name.
+const OPTIONAL_HOSTS: [&str; 2] = ["jack", "asio"];
+
+/// Finds the audio host called `name`, ignoring case, e.g. `alsa` or `jack`.
+pub fn find_host(name: &str) -> Result<HostId> {
+    if let Some(&id) = cpal::ALL_HOSTS
+        .iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+    {
+        return Ok(id);
+    }
+    let lower = name.to_lowercase();
+    if OPTIONAL_HOSTS.contains(&lower.as_str()) {
+        Err(eyre!(
+            "micrec was built without {name} support, rebuild it with `--features {lower}`"
+        ))
+    } else {
+        Err(eyre!("unknown audio host \"{name}\", see --list-hosts"))
+    }
+}
+
+/// Connects to the audio host `id`, or the platform's default when `None`.
+pub fn open_host(id: Option<HostId>) -> Result<Host> {
+    match id {
+        Some(id) => cpal::host_from_id(id)
+            .wrap_err_with(|| format!("{} isn't available (is it running?)", id.name())),
+        None => Ok(cpal::default_host()),
+    }
+}
+
+/// Finds the input device called `name`, or the default input device when `None`.
+pub fn select_input_device(host: &Host, name: Option<&str>) -> Result<Device> {
+    match name {
+        Some(name) => host
+            .input_devices()
+            .wrap_err("failed to list input devices")?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| eyre!("input device \"{name}\" not found")),
+        None => host
+            .default_input_device()
+            .ok_or_else(|| eyre!("no microphone found")),
+    }
+}
+
+/// The first of `names` that `host` can record `capture` from, for setups that
+/// list fallback devices in order of preference.
+pub fn first_available_device(
+    host: Option<HostId>,
+    capture: CaptureSource,
+    names: &[String],
+) -> Result<String> {
+    let host = open_host(host)?;
+    names
+        .iter()
+        .find(|name| select_capture_device(&host, capture, Some(name)).is_ok())
+        .cloned()
+        .ok_or_else(|| {
+            let names: Vec<String> = names.iter().map(|name| format!("\"{name}\"")).collect();
+            eyre!("none of the devices {} were found", names.join(", "))
+        })
+}
+
+/// Finds the device to record `capture` from, see [`select_input_device`] and
+/// [`loopback::select_device`].
+pub fn select_capture_device(
+    host: &Host,
+    capture: CaptureSource,
+    name: Option<&str>,
+) -> Result<Device> {
+    match capture {
+        CaptureSource::Microphone => select_input_device(host, name),
+        CaptureSource::Loopback => loopback::select_device(host, name),
+    }
+}
+
+/// Stream config for `device`, at `sample_rate` if the device supports it.
+///
+/// Samples are always captured as `f32`, so only configs in that format are used.
+pub fn input_stream_config(device: &Device, sample_rate: Option<u32>) -> Result<StreamConfig> {
+    let default = device
+        .default_input_config()
+        .wrap_err("failed to query the input device")?;
+    let rate = sample_rate.map_or(default.sample_rate(), SampleRate);
+
+    if default.sample_format() == SampleFormat::F32 && rate == default.sample_rate() {
+        return Ok(default.into());
+    }
+
+    device
+        .supported_input_configs()
+        .wrap_err("failed to query the input device")?
+        .filter(|range| range.sample_format() == SampleFormat::F32)
+        .find_map(|range| range.try_with_sample_rate(rate))
+        .map(Into::into)
+        .ok_or_else(|| eyre!("input device can't capture 32-bit float at {} Hz", rate.0))
+}
+
+fn create_writer(
+    options: &RecordingOptions,
+    config: &StreamConfig,
+) -> Result<(PathBuf, Box<dyn AudioWriter>)> {
+    let template = options.format.file_template(&options.file_template);
+    let (path, file) =
+        naming::create_recording_file(&options.output_dir, &template, options.after_take)
+            .wrap_err_with(|| {
+                format!(
+                    "failed to create a file in {}",
+                    options.output_dir.display()
+                )
+            })?;
+
+    let writer = match encoder::create(options.format, file, config) {
+        Ok(writer) => writer,
+        Err(err) => {
+            // Don't leave an empty placeholder behind
+            fs::remove_file(&path).ok();
+            return Err(err).wrap_err_with(|| format!("failed to write {}", path.display()));
+        }
+    };
+    Ok((path, writer))
+}
+
+/// A copy of the take for when the software gain clips it: the input before the
+/// gain and any processing, [`SAFETY_TRACK_GAIN_DB`] down, and mixed down and
+/// resampled like the take. Clipping in the device itself is in both files alike.
+struct SafetyTrack {
+    writer: Box<dyn AudioWriter>,
+    /// Channels of the input.
+    channels: usize,
+    mono: bool,
+    resampler: Option<FileResampler>,
+    block: Vec<f32>,
+    /// Samples of input left before the take's duration limit, if any.
+    remaining: Option<usize>,
+}
+
+impl SafetyTrack {
+    /// Creates the safety track of the take at `path`, for input laid out like
+    /// `config` and a take laid out like `output_config`.
+    fn create(
+        path: &Path,
+        options: &RecordingOptions,
+        config: &StreamConfig,
+        output_config: &StreamConfig,
+    ) -> Result<Self> {
+        let resampler = (output_config.sample_rate != config.sample_rate)
+            .then(|| {
+                let channels = output_config.channels as usize;
+                FileResampler::new(config.sample_rate.0, output_config.sample_rate.0, channels)
+            })
+            .transpose()?;
+        let (safety_path, file) = naming::create_safety_file(path).wrap_err_with(|| {
+            format!(
+                "failed to create {}",
+                naming::safety_track_path(path).display()
+            )
+        })?;
+        let writer = encoder::create(options.format, file, output_config).inspect_err(|_| {
+            fs::remove_file(&safety_path).ok();
+        })?;
+        let channels = config.channels.max(1) as usize;
+        let remaining = options.duration.map(|duration| {
+            (duration.as_secs_f64() * config.sample_rate.0 as f64) as usize * channels
+        });
+        Ok(Self {
+            writer,
+            channels,
+            mono: options.output_channels == OutputChannels::Mono,
+            resampler,
+            block: Vec::new(),
+            remaining,
+        })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        let samples = match self.remaining.as_mut() {
+            Some(remaining) => {
+                let keep = samples.len().min(*remaining);
+                *remaining -= keep;
+                &samples[..keep]
+            }
+            None => samples,
+        };
+        self.block.clear();
+        if self.mono {
+            self.block.extend(dsp::mixdown(samples, self.channels));
+        } else {
+            self.block.extend_from_slice(samples);
+        }
+        let gain = dsp::db_to_gain(SAFETY_TRACK_GAIN_DB);
+        for sample in &mut self.block {
+            *sample *= gain;
+        }
+        match self.resampler.as_mut() {
+            Some(resampler) => self.writer.write(&resampler.process(&self.block)?),
+            None => self.writer.write(&self.block),
+        }
+    }
+
+    fn publish(&mut self) -> Result<()> {
+        self.writer.publish()
+    }
+
+    fn finalize(mut self) -> Result<()> {
+        if let Some(resampler) = self.resampler.as_mut() {
+            self.writer.write(&resampler.flush()?)?;
+        }
+        self.writer
+            .finalize()
+            .wrap_err("failed to finish the safety track")
+    }
+}
+
+/// `samples` with `gain_db` applied, kept within full scale.
+fn amplify(samples: Arc<[f32]>, gain_db: f32, soft_limit: bool) -> Arc<[f32]> {
+    if gain_db == 0.0 && !soft_limit {
+        return samples;
+    }
+    let mut block = samples.to_vec();
+    dsp::apply_gain(&mut block, dsp::db_to_gain(gain_db), soft_limit);
+    Arc::from(block)
+}
+
+/// Starts or stops playing the input through the default output of `host`,
+/// reporting how that went.
+fn set_monitoring(
+    monitor: &mut Option<Monitor>,
+    on: bool,
+    host: Result<&Host>,
+    config: &StreamConfig,
+    events_tx: &Sender<EngineEvent>,
+) {
+    if on == monitor.is_some() {
+        return;
+    }
+    *monitor = if on {
+        let errors_tx = events_tx.clone();
+        host.and_then(|host| {
+            Monitor::start(host, config, move |err| {
+                errors_tx.send(EngineEvent::StreamError(err)).ok();
+            })
+        })
+        .inspect_err(|err| {
+            let message = format!("can't monitor: {err:#}");
+            events_tx.send(EngineEvent::StreamError(message)).ok();
+        })
+        .ok()
+    } else {
+        None
+    };
+    events_tx
+        .send(EngineEvent::MonitoringChanged(monitor.is_some()))
+        .ok();
+}
+
+/// Opens the circular buffer for a take recorded with [`RecordingOptions::buffer`],
+/// hidden in the output directory.
+fn create_buffer(
+    options: &RecordingOptions,
+    config: &StreamConfig,
+    length: Duration,
+) -> Result<(PathBuf, Arc<Mutex<RingFile>>)> {
+    fs::create_dir_all(&options.output_dir)
+        .wrap_err_with(|| format!("failed to create {}", options.output_dir.display()))?;
+    let path = options
+        .output_dir
+        .join(format!(".micrec-buffer-{}.raw", std::process::id()));
+    let channels = config.channels as usize;
+    let ring = RingFile::create(&path, config.sample_rate.0, channels, length)
+        .wrap_err_with(|| format!("failed to create {}", path.display()))?;
+    Ok((path, Arc::new(Mutex::new(ring))))
+}
+
+/// Saves `clip` as a recording of its own, named from the template. Safety tracks
+/// are only made for takes recorded straight to a file.
+fn save_clip(clip: &Clip, options: &RecordingOptions, config: &StreamConfig) -> Result<PathBuf> {
+    let (path, mut writer) = create_writer(options, config)?;
+    let written = clip.read(|samples| writer.write(samples));
+    if let Err(err) = written.and_then(|_| writer.finalize()) {
+        fs::remove_file(&path).ok();
+        return Err(err);
+    }
+    Ok(path)
+}
+
+/// The input stream of a take, reopened if its device goes away.
+struct Input {
+    host_id: Option<HostId>,
+    /// Opened only once needed when the take comes from a source, see [`Input::host`].
+    host: Option<Host>,
+    capture: CaptureSource,
+    /// Device the take started on, tried first when reconnecting.
+    device_name: String,
+    /// Layout samples are delivered in, whatever the device captures.
+    config: StreamConfig,
+    controls: Arc<InputControls>,
+    /// Audio delivered by the stream, whichever device it's on.
+    queue: CaptureQueue,
+    events_tx: Sender<EngineEvent>,
+    /// Set by the stream's error callback when the device is gone.
+    lost: Arc<AtomicBool>,
+    /// `None` while disconnected.
+    stream: Option<InputStream>,
+    disconnected_at: Option<Instant>,
+    last_attempt: Instant,
+    last_samples: Instant,
+    /// Devices capture resumed on, in order.
+    reconnected_to: Vec<String>,
+}
+
+/// Where a take's audio comes from.
+enum Origin {
+    Device(Device),
+    Source(Box<dyn AudioSource>),
+}
+
+/// What delivers audio into [`Input::queue`].
+enum InputStream {
+    Device(Stream),
+    Source(SourceFeed),
+}
+
+impl InputStream {
+    fn play(&self) -> Result<()> {
+        match self {
+            InputStream::Device(stream) => {
+                stream.play().wrap_err("failed to start the input stream")
+            }
+            InputStream::Source(feed) => {
+                feed.playing.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An [`AudioSource`] played into the queue in real time from a thread of its
+/// own, until dropped.
+struct SourceFeed {
+    playing: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for SourceFeed {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Input {
+    /// Takes blocks of `from` channels as the device or source delivers them,
+    /// converts them to `self.config`'s channels, flips the inverted ones and queues
+    /// them.
+    fn deliver(&self, from: u16) -> Result<impl FnMut(&[f32]) + Send + 'static> {
+        let from = from.max(1) as usize;
+        let channels = self.config.channels.max(1) as usize;
+        let controls = Arc::clone(&self.controls);
+        let mut queue = self.queue.writer()?;
+        // Grown only if the device delivers bigger blocks than this
+        let mut block = Vec::with_capacity(CALLBACK_BLOCK_FRAMES * channels);
+        Ok(move |data: &[f32]| {
+            if data.is_empty() {
+                return;
+            }
+
+            let inverted = controls.inverted.load(Ordering::Relaxed);
+            if from == channels && inverted == 0 {
+                queue.push(data);
+                return;
+            }
+            block.clear();
+            dsp::remap_channels_into(data, from, channels, &mut block);
+            dsp::invert_channels(&mut block, channels, inverted);
+            queue.push(&block);
+        })
+    }
+
+    /// Builds a stream on `device` capturing with `device_config`, delivering
+    /// samples converted to `self.config`'s channels.
+    fn open(&self, device: &Device, device_config: &StreamConfig) -> Result<InputStream> {
+        let mut deliver = self.deliver(device_config.channels)?;
+        let errors_tx = self.events_tx.clone();
+        let lost = Arc::clone(&self.lost);
+        let stream = device
+            .build_input_stream(
+                device_config,
+                move |data: &[f32], _| deliver(data),
+                move |err| match err {
+                    cpal::StreamError::DeviceNotAvailable => lost.store(true, Ordering::Relaxed),
+                    err => {
+                        errors_tx
+                            .send(EngineEvent::StreamError(err.to_string()))
+                            .ok();
+                    }
+                },
+                None,
+            )
+            .wrap_err("failed to open the input stream (is the device busy?)")?;
+        Ok(InputStream::Device(stream))
+    }
+
+    /// Plays `source` in blocks of [`SOURCE_BLOCK`], paced by the clock, once the
+    /// stream is played.
+    fn feed(&self, mut source: Box<dyn AudioSource>) -> Result<InputStream> {
+        let config = source.config();
+        let mut deliver = self.deliver(config.channels)?;
+        let frames = (SOURCE_BLOCK.as_secs_f64() * config.sample_rate.0 as f64) as usize;
+        let mut block = vec![0.0; frames.max(1) * config.channels.max(1) as usize];
+        let playing = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (thread_playing, thread_stopped) = (Arc::clone(&playing), Arc::clone(&stopped));
+        let thread = thread::spawn(move || {
+            let mut due = Instant::now();
+            while !thread_stopped.load(Ordering::Relaxed) {
+                if !thread_playing.load(Ordering::Relaxed) {
+                    thread::sleep(QUEUE_POLL_INTERVAL);
+                    due = Instant::now();
+                    continue;
+                }
+                source.fill(&mut block);
+                deliver(&block);
+                due += SOURCE_BLOCK;
+                thread::sleep(due.saturating_duration_since(Instant::now()));
+            }
+        });
+        Ok(InputStream::Source(SourceFeed {
+            playing,
+            stopped,
+            thread: Some(thread),
+        }))
+    }
+
+    fn play(&self) -> Result<()> {
+        match &self.stream {
+            Some(stream) => stream.play(),
+            None => Ok(()),
+        }
+    }
+
+    /// The audio host, opened now if the take hasn't needed it yet.
+    fn host(&mut self) -> Result<&Host> {
+        if self.host.is_none() {
+            self.host = Some(open_host(self.host_id)?);
+        }
+        Ok(self.host.as_ref().unwrap())
+    }
+
+    /// Notices a lost device and, once disconnected, periodically tries to get
+    /// capture going again.
+    fn check(&mut self) {
+        // A source can't be unplugged, and a slow one would only be reopened the same
+        if matches!(self.stream, Some(InputStream::Source(_))) {
+            return;
+        }
+        if self.stream.is_some() {
+            let stalled = self.last_samples.elapsed() >= STALL_TIMEOUT;
+            if self.lost.swap(false, Ordering::Relaxed) || stalled {
+                self.stream = None;
+                self.disconnected_at = Some(Instant::now());
+                self.events_tx
+                    .send(EngineEvent::Disconnected(self.device_name.clone()))
+                    .ok();
+            }
+            return;
+        }
+
+        let Some(disconnected_at) = self.disconnected_at else {
+            return;
+        };
+        if self.last_attempt.elapsed() < RECONNECT_INTERVAL {
+            return;
+        }
+        self.last_attempt = Instant::now();
+
+        let fallback = disconnected_at.elapsed() >= FALLBACK_AFTER;
+        if let Some((name, stream)) = self.reconnect(fallback) {
+            self.lost.store(false, Ordering::Relaxed);
+            self.stream = Some(stream);
+            self.disconnected_at = None;
+            self.last_samples = Instant::now();
+            self.reconnected_to.push(name.clone());
+            self.events_tx.send(EngineEvent::Reconnected(name)).ok();
+        }
+    }
+
+    /// Opens and starts the original device, or with `fallback` the default input
+    /// if the original is still missing.
+    fn reconnect(&self, fallback: bool) -> Option<(String, InputStream)> {
+        let host = self.host.as_ref()?;
+        let device = select_capture_device(host, self.capture, Some(&self.device_name))
+            .ok()
+            .or_else(|| {
+                let fallback = fallback && self.capture == CaptureSource::Microphone;
+                fallback.then(|| host.default_input_device()).flatten()
+            })?;
+        let config = match self.capture {
+            CaptureSource::Microphone => reconnect_config(&device, &self.config).ok()?,
+            CaptureSource::Loopback => {
+                loopback::stream_config(&device, Some(self.config.sample_rate.0)).ok()?
+            }
+        };
+        let stream = self.open(&device, &config).ok()?;
+        stream.play().ok()?;
+        Some((device.name().unwrap_or_default(), stream))
+    }
+}
+
+/// Config for picking a take back up on `device`: the take's own if the device
+/// supports it, otherwise its sample rate with whatever channels the device has.
+fn reconnect_config(device: &Device, take: &StreamConfig) -> Result<StreamConfig> {
+    let ranges: Vec<_> = device
+        .supported_input_configs()
+        .wrap_err("failed to query the input device")?
+        .filter(|range| range.sample_format() == SampleFormat::F32)
+        .collect();
+    let exact = ranges
+        .iter()
+        .filter(|range| range.channels() == take.channels)
+        .find_map(|range| range.try_with_sample_rate(take.sample_rate));
+    exact
+        .or_else(|| {
+            ranges
+                .iter()
+                .find_map(|range| range.try_with_sample_rate(take.sample_rate))
+        })
+        .map(Into::into)
+        .ok_or_else(|| eyre!("input device can't capture at {} Hz", take.sample_rate.0))
+}
+
+/// Writes the editor project for a finished take, with one region spanning it
+/// and the markers as points.
+fn export_project(
+    format: ProjectFormat,
+    options: &RecordingOptions,
+    path: &Path,
+    duration: Duration,
+    sample_rate: u32,
+    markers: &[Marker],
+) -> Result<PathBuf> {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    let take = Region {
+        start: Duration::ZERO,
+        end: duration,
+        name: name.into_owned(),
+    };
+    let points = markers.iter().map(|marker| Region {
+        start: marker.position(),
+        end: marker.position(),
+        name: marker.label.clone(),
+    });
+    let regions: Vec<Region> = std::iter::once(take).chain(points).collect();
+    let safety_track = naming::safety_track_path(path);
+    let take = Take {
+        recording: path,
+        format: options.format,
+        safety_track: options.safety_track.then_some(safety_track.as_path()),
+        duration,
+        sample_rate,
+        regions: &regions,
+    };
+    project::export(format, &take).wrap_err("failed to write the project file")
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Noise reduction for a take, see [`Recorder::learn_noise`].
+enum NoiseReduction {
+    Off,
+    /// Collecting room tone, passing the input through untouched meanwhile.
+    Learning {
+        room_tone: Vec<f32>,
+        channels: usize,
+        /// Samples of room tone wanted.
+        target: usize,
+    },
+    On(SpectralSubtractor),
+}
+
+impl NoiseReduction {
+    /// Starts collecting room tone. Returns the audio a running subtractor was
+    /// still holding back.
+    fn learn(&mut self, config: &StreamConfig) -> Arc<[f32]> {
+        let tail = self.flush();
+        let channels = config.channels.max(1) as usize;
+        let frames = NOISE_LEARN_DURATION.as_secs_f64() * config.sample_rate.0 as f64;
+        *self = Self::Learning {
+            room_tone: Vec::new(),
+            channels,
+            target: frames as usize * channels,
+        };
+        tail
+    }
+
+    /// Denoises a block once a profile has been learned. The result may be
+    /// shorter or longer than `samples`, as the subtractor works in whole frames.
+    fn process(&mut self, samples: Arc<[f32]>, events_tx: &Sender<EngineEvent>) -> Arc<[f32]> {
+        match self {
+            Self::Off => samples,
+            Self::Learning {
+                room_tone,
+                channels,
+                target,
+            } => {
+                room_tone.extend_from_slice(&samples);
+                if room_tone.len() >= *target {
+                    let profile = NoiseProfile::learn(room_tone, *channels);
+                    *self = Self::On(SpectralSubtractor::new(profile, room_tone));
+                    events_tx.send(EngineEvent::NoiseProfileLearned).ok();
+                }
+                samples
+            }
+            Self::On(subtractor) => Arc::from(subtractor.process(&samples)),
+        }
+    }
+
+    /// Whatever the subtractor is still holding back, at the end of the take.
+    fn flush(&mut self) -> Arc<[f32]> {
+        match self {
+            Self::On(subtractor) => Arc::from(subtractor.flush()),
+            _ => Arc::from([]),
+        }
+    }
+}
+
+/// The latest stretch of input, kept while standing by, see
+/// [`RecordingOptions::pre_roll`].
+#[derive(Debug)]
+struct PreRoll {
+    blocks: VecDeque<Arc<[f32]>>,
+    /// Samples held, and the most to hold: always whole frames.
+    len: usize,
+    capacity: usize,
+}
+
+impl PreRoll {
+    fn new(length: Duration, config: &StreamConfig) -> Self {
+        let frames = (length.as_secs_f64() * config.sample_rate.0 as f64) as usize;
+        Self {
+            blocks: VecDeque::new(),
+            len: 0,
+            capacity: frames * config.channels.max(1) as usize,
+        }
+    }
+
+    /// Adds a block of interleaved input, letting go of the oldest once there's
+    /// more than the capacity.
+    fn push(&mut self, block: Arc<[f32]>) {
+        self.len += block.len();
+        self.blocks.push_back(block);
+        while self.len > self.capacity {
+            let Some(oldest) = self.blocks.pop_front() else {
+                break;
+            };
+            let excess = self.len - self.capacity;
+            if excess < oldest.len() {
+                self.blocks.push_front(Arc::from(&oldest[excess..]));
+                self.len -= excess;
+            } else {
+                self.len -= oldest.len();
+            }
+        }
+    }
+
+    /// The blocks held, oldest first.
+    fn drain(&mut self) -> impl Iterator<Item = Arc<[f32]>> + '_ {
+        self.len = 0;
+        self.blocks.drain(..)
+    }
+}
+
+/// Per-block bookkeeping for a take: the duration limit, the silence check at the
+/// start and voice activity detection. Counts in samples, so it needs no device.
+#[derive(Debug)]
+struct TakeState {
+    /// Samples left before reaching `duration`.
+    remaining: Option<usize>,
+    /// Timeout and samples left before reporting silence, until decided either way.
+    silence_check: Option<(Duration, usize)>,
+    /// VAD settings and how many quiet samples end the take.
+    vad: Option<(VadConfig, usize)>,
+    heard_sound: bool,
+    quiet: usize,
+    /// Samples of exact zeros in a row, and how many mean the input is muted.
+    zeros: usize,
+    muted_after: usize,
+    muted: bool,
+}
+
+/// What [`TakeState::process`] decided about a block.
+#[derive(Debug, Default, PartialEq)]
+struct BlockOutcome {
+    /// How many of the block's samples belong to the take.
+    keep: usize,
+    /// Set once, if the silence check just failed.
+    silent: Option<Duration>,
+    /// Set if voice activity detection ended the take.
+    stopped_on_silence: Option<Duration>,
+    /// Set when the input starts or stops looking muted.
+    muted: Option<bool>,
+    /// The take is over after this block.
+    stop: bool,
+}
+
+impl TakeState {
+    fn new(options: &RecordingOptions, config: &StreamConfig) -> Self {
+        let samples = |duration: Duration| {
+            let frames = (duration.as_secs_f64() * config.sample_rate.0 as f64) as usize;
+            frames * config.channels.max(1) as usize
+        };
+        Self {
+            remaining: options.duration.map(samples),
+            silence_check: options
+                .silence_check
+                .map(|timeout| (timeout, samples(timeout))),
+            vad: options.vad.map(|vad| (vad, samples(vad.stop_after))),
+            heard_sound: false,
+            quiet: 0,
+            zeros: 0,
+            muted_after: samples(MUTED_AFTER),
+            muted: false,
+        }
+    }
+
+    /// Accounts for a block of interleaved input.
+    fn process(&mut self, samples: &[f32]) -> BlockOutcome {
+        let keep = match self.remaining.as_mut() {
+            Some(remaining) => {
+                let keep = samples.len().min(*remaining);
+                *remaining -= keep;
+                keep
+            }
+            None => samples.len(),
+        };
+        let samples = &samples[..keep];
+        let mut outcome = BlockOutcome {
+            keep,
+            ..BlockOutcome::default()
+        };
+
+        if let Some((timeout, remaining)) = self.silence_check.as_mut() {
+            if dsp::to_dbfs(dsp::peak(samples)) >= SILENCE_THRESHOLD_DBFS {
+                self.silence_check = None;
+            } else {
+                *remaining = remaining.saturating_sub(samples.len());
+                if *remaining == 0 {
+                    outcome.silent = Some(*timeout);
+                    self.silence_check = None;
+                }
+            }
+        }
+        if let Some((vad, stop_after)) = &self.vad {
+            if dsp::to_dbfs(dsp::rms(samples)) >= vad.threshold_dbfs {
+                self.heard_sound = true;
+                self.quiet = 0;
+            } else if self.heard_sound {
+                self.quiet += samples.len();
+                if self.quiet >= *stop_after {
+                    outcome.stopped_on_silence = Some(vad.stop_after);
+                }
+            }
+        }
+
+        if samples.iter().all(|&sample| sample == 0.0) {
+            self.zeros += samples.len();
+        } else {
+            self.zeros = 0;
+        }
+        let muted = self.zeros >= self.muted_after;
+        if muted != self.muted {
+            self.muted = muted;
+            outcome.muted = Some(muted);
+        }
+
+        outcome.stop = self.remaining == Some(0) || outcome.stopped_on_silence.is_some();
+        outcome
+    }
+}
+
+/// What the engine thread shares with its [`Recorder`].
+struct Shared {
+    meter: Arc<Mutex<Option<Meter>>>,
+    stats: Arc<Mutex<Option<RecordingStats>>>,
+    samples: Arc<Mutex<Option<CaptureQueue>>>,
+    controls: Arc<InputControls>,
+}
+
+fn record(
+    options: RecordingOptions,
+    source: Option<Box<dyn AudioSource>>,
+    events_tx: Sender<EngineEvent>,
+    shutdown_rx: Receiver<()>,
+    commands: Receiver<Command>,
+    shared: Shared,
+) {
+    let result = capture(options, source, &events_tx, shutdown_rx, commands, shared);
+    if let Err(err) = result {
+        events_tx.send(EngineEvent::Failed(format!("{err:#}"))).ok();
+    }
+}
+
+/// Waits for the wall clock to reach `at`, following it if it's changed meanwhile.
+/// Returns `false` if the take is stopped first.
+fn wait_until(at: DateTime<Local>, shutdown_rx: &Receiver<()>) -> bool {
+    while let Ok(left) = (at - Local::now()).to_std() {
+        match shutdown_rx.recv_timeout(left.min(SCHEDULE_POLL_INTERVAL)) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+    true
+}
+
+/// Makes sure the microphone may be used, bringing up the system's prompt if the
+/// user hasn't been asked yet, and waits for access to be granted in the system
+/// settings if it was denied. Returns `false` if the take is stopped first.
+fn wait_for_permission(events_tx: &Sender<EngineEvent>, shutdown_rx: &Receiver<()>) -> bool {
+    if permission::status() == Permission::NotDetermined {
+        let message = String::from("allow access to the microphone when the system asks");
+        events_tx
+            .send(EngineEvent::WaitingForPermission(message))
+            .ok();
+    }
+    if permission::request() == Permission::Granted {
+        return true;
+    }
+    let message = permission::instructions().to_string();
+    events_tx
+        .send(EngineEvent::WaitingForPermission(message))
+        .ok();
+    loop {
+        match shutdown_rx.recv_timeout(PERMISSION_POLL_INTERVAL) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => return false,
+        }
+        if permission::status() == Permission::Granted {
+            return true;
+        }
+    }
+}
+
+/// Runs the capture loop. Setup problems are returned as errors; once the file
+/// is open, the outcome is reported with a `Finished` event instead.
+fn capture(
+    options: RecordingOptions,
+    source: Option<Box<dyn AudioSource>>,
+    events_tx: &Sender<EngineEvent>,
+    shutdown_rx: Receiver<()>,
+    commands: Receiver<Command>,
+    shared: Shared,
+) -> Result<()> {
+    let Shared {
+        meter,
+        stats,
+        samples: forwarded,
+        controls,
+    } = shared;
+    let microphone = source.is_none() && options.capture == CaptureSource::Microphone;
+    if microphone && !wait_for_permission(events_tx, &shutdown_rx) {
+        events_tx.send(EngineEvent::Cancelled).ok();
+        return Ok(());
+    }
+    if let Some(start_at) = options.start_at {
+        events_tx.send(EngineEvent::Scheduled(start_at)).ok();
+        // Early enough to have the pre-roll by then
+        let lead = options
+            .pre_roll
+            .filter(|_| options.buffer.is_none())
+            .and_then(|pre_roll| chrono::Duration::from_std(pre_roll).ok())
+            .unwrap_or(chrono::Duration::zero());
+        if !wait_until(start_at - lead, &shutdown_rx) {
+            events_tx.send(EngineEvent::Cancelled).ok();
+            return Ok(());
+        }
+    }
+    // A source needs no audio host, unless it's monitored or joined by devices
+    let (host, device_name, config, origin) = match source {
+        Some(source) => (None, source.name(), source.config(), Origin::Source(source)),
+        None => {
+            let host = open_host(options.host)?;
+            let device = select_capture_device(&host, options.capture, options.device.as_deref())?;
+            // Failing the file's rate, capture at the device's and resample
+            let config = match options.capture {
+                CaptureSource::Microphone => input_stream_config(&device, options.sample_rate)
+                    .or_else(|_| input_stream_config(&device, None))?,
+                CaptureSource::Loopback => loopback::stream_config(&device, options.sample_rate)?,
+            };
+            (
+                Some(host),
+                device.name().unwrap_or_default(),
+                config,
+                Origin::Device(device),
+            )
+        }
+    };
+
+    let channels = config.channels.max(1) as usize;
+    let mut input = Input {
+        host_id: options.host,
+        host,
+        capture: options.capture,
+        device_name,
+        config: config.clone(),
+        controls,
+        queue: CaptureQueue::new(&config),
+        events_tx: events_tx.clone(),
+        lost: Arc::new(AtomicBool::new(false)),
+        stream: None,
+        disconnected_at: None,
+        last_attempt: Instant::now(),
+        last_samples: Instant::now(),
+        reconnected_to: Vec::new(),
+    };
+    input.stream = Some(match origin {
+        Origin::Device(device) => input.open(&device, &config)?,
+        Origin::Source(source) => input.feed(source)?,
+    });
+    let mut extras = if options.extra_devices.is_empty() {
+        None
+    } else {
+        let errors_tx = events_tx.clone();
+        let extras = ExtraInputs::open(
+            input.host()?,
+            &options.extra_devices,
+            config.sample_rate.0,
+            move |err| {
+                errors_tx.send(EngineEvent::StreamError(err)).ok();
+            },
+        )?;
+        let mut devices = vec![(input.device_name.clone(), config.channels)];
+        devices.extend(extras.devices());
+        events_tx.send(EngineEvent::Devices(devices)).ok();
+        Some(extras)
+    };
+    // From here on, the layout of the whole take
+    let main_channels = channels;
+    let config = StreamConfig {
+        channels: config.channels + extras.as_ref().map_or(0, |e| e.channels() as u16),
+        ..config
+    };
+    let channels = config.channels as usize;
+
+    let output_config = StreamConfig {
+        channels: match options.output_channels {
+            OutputChannels::Multichannel => config.channels,
+            OutputChannels::Mono => 1,
+        },
+        sample_rate: SampleRate(options.sample_rate.unwrap_or(config.sample_rate.0)),
+        ..config.clone()
+    };
+    let output_rate = output_config.sample_rate.0;
+    // Mixed down first, so mono takes are resampled only once
+    let mut resampler = (output_config.sample_rate != config.sample_rate)
+        .then(|| {
+            let channels = output_config.channels as usize;
+            FileResampler::new(config.sample_rate.0, output_rate, channels)
+        })
+        .transpose()?;
+
+    // The queue keeps what's captured until the file is ready for it
+    input
+        .play()
+        .and_then(|_| extras.as_ref().map_or(Ok(()), ExtraInputs::play))?;
+    if let Ok(mut meter) = meter.lock() {
+        *meter = Some(Meter::new(config.sample_rate.0, config.channels));
+    }
+    let controls = Arc::clone(&input.controls);
+    let extra_overruns = Cell::new(0_u64);
+    // Lines up the extra devices' audio with each block of the main device's
+    let mut merge = |samples: Arc<[f32]>| -> Arc<[f32]> {
+        match &mut extras {
+            Some(extras) => {
+                let merged = extras.merge(
+                    &samples,
+                    main_channels,
+                    controls.inverted.load(Ordering::Relaxed),
+                );
+                extra_overruns.set(extras.overruns());
+                Arc::from(merged)
+            }
+            None => samples,
+        }
+    };
+    let mut monitor: Option<Monitor> = None;
+
+    // Waits for `Recorder::record` or the scheduled start, if there's a pre-roll.
+    // The file is only created then, so it's named for when the take began.
+    let mut deferred = Vec::new();
+    let standby = options
+        .pre_roll
+        .filter(|_| options.buffer.is_none())
+        .filter(|_| options.standby || options.start_at.is_some_and(|at| Local::now() < at));
+    let pre_roll = match standby {
+        Some(length) => {
+            events_tx.send(EngineEvent::StandingBy(length)).ok();
+            let mut pre_roll = PreRoll::new(length, &config);
+            let mut record_now = false;
+            input.last_samples = Instant::now();
+            while !record_now && options.start_at.is_none_or(|at| Local::now() < at) {
+                if shutdown_rx.try_recv().is_ok() {
+                    events_tx.send(EngineEvent::Cancelled).ok();
+                    return Ok(());
+                }
+                input.check();
+                for command in commands.try_iter() {
+                    match command {
+                        Command::Record => record_now = true,
+                        Command::Monitor(on) => {
+                            set_monitoring(&mut monitor, on, input.host(), &config, events_tx)
+                        }
+                        // The rest wait for the take
+                        command => deferred.push(command),
+                    }
+                }
+                let Some(samples) = input.queue.pop() else {
+                    thread::sleep(QUEUE_POLL_INTERVAL);
+                    continue;
+                };
+                input.last_samples = Instant::now();
+                let samples = merge(samples);
+                let heard = amplify(Arc::clone(&samples), controls.gain_db(), options.soft_limit);
+                if let Some(monitor) = monitor.as_mut() {
+                    monitor.push(&heard);
+                }
+                if let Ok(Some(meter)) = meter.lock().as_deref_mut() {
+                    meter.process(&heard);
+                }
+                pre_roll.push(samples);
+            }
+            Some(pre_roll)
+        }
+        None => None,
+    };
+
+    let (path, mut writer, ring) = match options.buffer {
+        Some(length) => {
+            let (path, ring) = create_buffer(&options, &output_config, length)?;
+            let writer: Box<dyn AudioWriter> = Box::new(RingWriter(Arc::clone(&ring)));
+            (path, writer, Some(ring))
+        }
+        None => {
+            let (path, writer) = create_writer(&options, &output_config)?;
+            (path, writer, None)
+        }
+    };
+    let mut pipe = match &options.pipe {
+        Some(target) => {
+            let output_channels = output_config.channels as usize;
+            match PcmPipe::start(
+                target,
+                options.pipe_format,
+                &path,
+                output_rate,
+                output_channels,
+            ) {
+                Ok(pipe) => Some(pipe),
+                Err(err) => {
+                    drop(writer);
+                    fs::remove_file(&path).ok();
+                    return Err(err).wrap_err("failed to start the pipe");
+                }
+            }
+        }
+        None => None,
+    };
+    let network = match &options.stream {
+        Some(target) => match NetworkStream::start(target, output_rate, output_config.channels) {
+            Ok(network) => Some(network),
+            Err(err) => {
+                drop(writer);
+                fs::remove_file(&path).ok();
+                return Err(err).wrap_err_with(|| format!("failed to stream to {target}"));
+            }
+        },
+        None => None,
+    };
+    let safety = if options.safety_track && ring.is_none() {
+        match SafetyTrack::create(&path, &options, &config, &output_config) {
+            Ok(safety) => Some(safety),
+            Err(err) => {
+                drop(writer);
+                fs::remove_file(&path).ok();
+                return Err(err);
+            }
+        }
+    } else {
+        None
+    };
+    let safety = RefCell::new(safety);
+
+    let queue = CaptureQueue::new(&output_config);
+    let mut forward = queue.writer()?;
+    if let Ok(mut forwarded) = forwarded.lock() {
+        *forwarded = Some(queue);
+    }
+
+    let started_at = Local::now();
+    events_tx
+        .send(EngineEvent::Started(output_config.clone()))
+        .ok();
+    let mut files = vec![path.clone()];
+    if options.safety_track && ring.is_none() {
+        files.push(naming::safety_track_path(&path));
+    }
+    let bytes_per_sec = options
+        .format
+        .estimated_bytes_per_sec(output_rate, output_config.channels)
+        * files.len() as u64;
+    let mut stats = StatsTracker::new(
+        files,
+        options.min_free_space,
+        output_rate,
+        bytes_per_sec,
+        stats,
+    );
+
+    let mut take = TakeState::new(&options, &config);
+    let mut last_publish = Instant::now();
+    let mut chain = Chain::new();
+    let rate = config.sample_rate.0;
+    let high_pass = chain.push(HighPass::new(options.high_pass_hz, rate, channels), false);
+    let gate = chain.push(
+        NoiseGate::new(options.gate_threshold_dbfs, rate, channels),
+        false,
+    );
+
+    let frames_written = Cell::new(0_u64);
+    let overruns = Cell::new(0_u64);
+    let mut levels = LevelSummary::default();
+    let mut loudness = match ring {
+        Some(_) => None,
+        None => Some(LoudnessMeter::new(output_config.channels, output_rate)?),
+    };
+    // Takes blocks as they go into the file, and says whether to keep going
+    let mut output = |samples: Arc<[f32]>| -> Result<bool> {
+        writer.write(&samples)?;
+        levels.add(&samples);
+        if let Some(loudness) = loudness.as_mut() {
+            loudness.add(&samples)?;
+        }
+        if let Some(stream) = pipe.as_mut() {
+            if let Err(err) = stream.push(&samples) {
+                let message = format!("stopped piping audio: {err}");
+                events_tx.send(EngineEvent::StreamError(message)).ok();
+                pipe = None;
+            }
+        }
+        if let Some(network) = &network {
+            network.push(&samples);
+        }
+        let frames = samples.len() / output_config.channels.max(1) as usize;
+        frames_written.set(frames_written.get() + frames as u64);
+        stats.set_overruns(overruns.get() + forward.overruns());
+        stats.set_frames(frames_written.get());
+        // Lets `micrec play` or any other reader open the file mid-recording
+        let mut disk_full = false;
+        if last_publish.elapsed() >= PUBLISH_INTERVAL {
+            writer.publish()?;
+            if let Some(safety) = safety.borrow_mut().as_mut() {
+                safety.publish()?;
+            }
+            last_publish = Instant::now();
+            match stats.check_disk() {
+                DiskSpace::Ok => {}
+                DiskSpace::Low(free) => {
+                    events_tx.send(EngineEvent::LowDiskSpace(free)).ok();
+                }
+                DiskSpace::Full(free) => {
+                    events_tx
+                        .send(EngineEvent::StoppedOnLowDiskSpace(free))
+                        .ok();
+                    disk_full = true;
+                }
+            }
+        }
+        forward.push(&samples);
+        Ok(!disk_full)
+    };
+    // Takes the input as it arrives, before noise reduction holds any of it back
+    let keep_safe = |samples: &[f32]| -> Result<()> {
+        match safety.borrow_mut().as_mut() {
+            Some(safety) => safety.write(samples),
+            None => Ok(()),
+        }
+    };
+    let mut write = |samples: Arc<[f32]>| -> Result<bool> {
+        let samples = amplify(samples, controls.gain_db(), options.soft_limit);
+        // Judged on the input before the gate can hide it
+        let outcome = take.process(&samples);
+        chain.set_enabled(high_pass, controls.high_pass.load(Ordering::Relaxed));
+        chain.set_enabled(gate, controls.noise_gate.load(Ordering::Relaxed));
+        let samples = if chain.is_active() {
+            let mut block = samples[..outcome.keep].to_vec();
+            chain.process(&mut block);
+            Arc::from(block)
+        } else if outcome.keep < samples.len() {
+            Arc::from(&samples[..outcome.keep])
+        } else {
+            samples
+        };
+
+        if let Some(timeout) = outcome.silent {
+            events_tx.send(EngineEvent::Silent(timeout)).ok();
+        }
+        if let Some(muted) = outcome.muted {
+            events_tx.send(EngineEvent::Muted(muted)).ok();
+        }
+        if let Some(after) = outcome.stopped_on_silence {
+            events_tx.send(EngineEvent::StoppedOnSilence(after)).ok();
+        }
+        // Meter what's kept for the file, with the gain and filters, before any mixdown
+        if let Ok(Some(meter)) = meter.lock().as_deref_mut() {
+            meter.process(&samples);
+        }
+
+        let samples = match options.output_channels {
+            OutputChannels::Multichannel => samples,
+            OutputChannels::Mono => Arc::from(dsp::mixdown(&samples, channels)),
+        };
+        let samples = match resampler.as_mut() {
+            Some(resampler) => Arc::from(resampler.process(&samples)?),
+            None => samples,
+        };
+        let keep_going = output(samples)?;
+        Ok(!outcome.stop && keep_going)
+    };
+
+    let mut noise = NoiseReduction::Off;
+    let mut markers: Vec<Marker> = Vec::new();
+    let mut clips: Vec<JoinHandle<Option<PathBuf>>> = Vec::new();
+    let mut result = Ok(true);
+    if let Some(mut pre_roll) = pre_roll {
+        events_tx.send(EngineEvent::Rolling).ok();
+        for samples in pre_roll.drain() {
+            if !matches!(result, Ok(true)) {
+                break;
+            }
+            result = keep_safe(&samples).and_then(|()| {
+                let samples = noise.process(samples, events_tx);
+                if samples.is_empty() {
+                    Ok(true)
+                } else {
+                    write(samples)
+                }
+            });
+        }
+    }
+    input.last_samples = Instant::now();
+    while matches!(result, Ok(true)) && shutdown_rx.try_recv().is_err() {
+        input.check();
+        for command in deferred.drain(..).chain(commands.try_iter()) {
+            match command {
+                Command::Monitor(on) => {
+                    set_monitoring(&mut monitor, on, input.host(), &config, events_tx)
+                }
+                Command::LearnNoise => {
+                    let tail = noise.learn(&config);
+                    if !tail.is_empty() {
+                        result = result.and_then(|_| write(tail));
+                    }
+                }
+                Command::AddMarker => {
+                    let marker = Marker::new(markers.len() + 1, frames_written.get(), output_rate);
+                    events_tx
+                        .send(EngineEvent::MarkerAdded(marker.position()))
+                        .ok();
+                    markers.push(marker);
+                }
+                Command::SaveClip(length) => {
+                    let clip = match &ring {
+                        Some(ring) => ring
+                            .lock()
+                            .map_err(|_| eyre!("the buffer is unavailable"))
+                            .and_then(|mut ring| Ok(ring.clip(length)?)),
+                        None => Err(eyre!("not recording into a buffer")),
+                    };
+                    match clip {
+                        Ok(clip) => {
+                            let options = options.clone();
+                            let config = output_config.clone();
+                            let events_tx = events_tx.clone();
+                            clips.push(thread::spawn(move || {
+                                let saved = save_clip(&clip, &options, &config)
+                                    .map_err(|err| format!("{err:#}"));
+                                events_tx.send(EngineEvent::ClipSaved(saved.clone())).ok();
+                                saved.ok()
+                            }));
+                        }
+                        Err(err) => {
+                            let message = format!("can't save a clip: {err:#}");
+                            events_tx.send(EngineEvent::ClipSaved(Err(message))).ok();
+                        }
+                    }
+                }
+                Command::Record => {}
+            }
+        }
+
+        let Some(samples) = input.queue.pop() else {
+            thread::sleep(QUEUE_POLL_INTERVAL);
+            continue;
+        };
+        input.last_samples = Instant::now();
+        overruns.set(input.queue.overruns() + extra_overruns.get());
+        let samples = merge(samples);
+        // What's heard has the gain, which `write` applies to the rest
+        if let Some(monitor) = monitor.as_mut() {
+            monitor.push(&amplify(
+                Arc::clone(&samples),
+                controls.gain_db(),
+                options.soft_limit,
+            ));
+        }
+        if controls.paused.load(Ordering::Relaxed) {
+            continue;
+        }
+        result = keep_safe(&samples).and_then(|()| {
+            let samples = noise.process(samples, events_tx);
+            if samples.is_empty() {
+                Ok(true)
+            } else {
+                write(samples)
+            }
+        });
+    }
+
+    drop(monitor);
+    input.stream = None;
+
+    // Keep whatever the device delivered before the stream went away
+    while let (Ok(true), Some(samples)) = (&result, input.queue.pop()) {
+        let samples = merge(samples);
+        result = keep_safe(&samples).and_then(|()| write(noise.process(samples, events_tx)));
+    }
+    let tail = noise.flush();
+    if matches!(result, Ok(true)) && !tail.is_empty() {
+        result = write(tail);
+    }
+    // Holds the end of what was kept, even when the take stopped itself
+    if let (Ok(_), Some(resampler)) = (&result, resampler.as_mut()) {
+        result = resampler.flush().and_then(|tail| output(Arc::from(tail)));
+    }
+    // Listeners hear the end of the take now, not after the sidecars
+    drop(network);
+
+    // The buffer goes once the clips being saved from it are done
+    let last_clip = clips
+        .into_iter()
+        .filter_map(|clip| clip.join().ok().flatten())
+        .last();
+    let safety = safety.into_inner().map_or(Ok(()), SafetyTrack::finalize);
+    let result = result.and_then(|_| writer.finalize()).and(safety);
+    let result = match ring {
+        Some(_) => result.and_then(|_| last_clip.ok_or_else(|| eyre!("no clip was saved"))),
+        None => result.map(|_| path),
+    }
+    .map_err(|err| format!("{err:#}"));
+    if let Some(pipe) = pipe {
+        if pipe.dropped() > 0 {
+            let message = format!(
+                "the piped audio fell behind, {} blocks were skipped",
+                pipe.dropped()
+            );
+            events_tx.send(EngineEvent::StreamError(message)).ok();
+        }
+        match pipe.finish() {
+            Ok(Some(transcript)) => {
+                events_tx
+                    .send(EngineEvent::SidecarSaved(Ok(transcript)))
+                    .ok();
+            }
+            Ok(None) => {}
+            Err(err) => {
+                let message = format!("failed to finish piping audio: {err}");
+                events_tx.send(EngineEvent::SidecarSaved(Err(message))).ok();
+            }
+        }
+    }
+    if let Some(path) = result.as_ref().ok().filter(|_| options.buffer.is_none()) {
+        if let Some(report) = loudness.as_ref().map(LoudnessMeter::report) {
+            events_tx.send(EngineEvent::Loudness(report)).ok();
+            if options.loudness_file {
+                let saved = loudness::export(path, &report)
+                    .wrap_err("failed to save the loudness report")
+                    .map_err(|err| format!("{err:#}"));
+                events_tx.send(EngineEvent::SidecarSaved(saved)).ok();
+            }
+        }
+        if !options.tags.is_empty() {
+            if let Err(err) = tags::write(path, &options.tags) {
+                let message = format!("failed to tag the recording: {err}");
+                events_tx.send(EngineEvent::SidecarSaved(Err(message))).ok();
+            }
+        }
+        if !markers.is_empty() {
+            let saved = markers::export(options.marker_format, path, &markers, output_rate)
+                .wrap_err("failed to save the markers")
+                .map_err(|err| format!("{err:#}"));
+            events_tx.send(EngineEvent::SidecarSaved(saved)).ok();
+        }
+        if let Some(format) = options.project {
+            let duration =
+                Duration::from_secs_f64(frames_written.get() as f64 / output_rate as f64);
+            let saved = export_project(format, &options, path, duration, output_rate, &markers)
+                .map_err(|err| format!("{err:#}"));
+            events_tx.send(EngineEvent::SidecarSaved(saved)).ok();
+        }
+        if options.provenance {
+            let controls = &input.controls;
+            let provenance = Provenance {
+                file: file_name(path),
+                app: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                started_at: started_at.to_rfc3339(),
+                device: DeviceInfo {
+                    capture: options.capture,
+                    name: input.device_name.clone(),
+                    reconnected_to: input.reconnected_to.clone(),
+                    extra_devices: options.extra_devices.clone(),
+                },
+                stream: StreamInfo {
+                    sample_rate: rate,
+                    channels: config.channels,
+                },
+                output: OutputInfo {
+                    format: options.format,
+                    channels: options.output_channels,
+                    sample_rate: output_rate,
+                    safety_track: options
+                        .safety_track
+                        .then(|| file_name(&naming::safety_track_path(path))),
+                },
+                processing: ProcessingInfo {
+                    inverted_channels: (0..channels)
+                        .filter(|&channel| controls.is_inverted(channel))
+                        .map(|channel| channel + 1)
+                        .collect(),
+                    gain_db: controls.gain_db(),
+                    soft_limit: options.soft_limit,
+                    noise_reduction: matches!(noise, NoiseReduction::On(_)),
+                    high_pass_hz: controls
+                        .high_pass
+                        .load(Ordering::Relaxed)
+                        .then_some(options.high_pass_hz),
+                    gate_threshold_dbfs: controls
+                        .noise_gate
+                        .load(Ordering::Relaxed)
+                        .then_some(options.gate_threshold_dbfs),
+                },
+                markers,
+                summary: levels.summary(frames_written.get(), output_rate),
+            };
+            let saved = provenance::export(path, &provenance)
+                .wrap_err("failed to save the recording info")
+                .map_err(|err| format!("{err:#}"));
+            events_tx.send(EngineEvent::SidecarSaved(saved)).ok();
+        }
+        if let Some(format) = options.transcode {
+            let saved = transcode::transcode(path, format, |done| {
+                events_tx.send(EngineEvent::Transcoding(done)).ok();
+            })
+            .map_err(|err| format!("{err:#}"));
+            events_tx.send(EngineEvent::SidecarSaved(saved)).ok();
+        }
+    }
+    events_tx.send(EngineEvent::Finished(result)).ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use cpal::BufferSize;
+
+    use super::*;
+    use crate::decoder;
+
+    fn options() -> RecordingOptions {
+        RecordingOptions {
+            demo: None,
+            host: None,
+            capture: CaptureSource::Microphone,
+            device: None,
+            extra_devices: Vec::new(),
+            sample_rate: None,
+            output_dir: PathBuf::from("."),
+            file_template: String::from("take.wav"),
+            after_take: 0,
+            format: OutputFormat::Wav,
+            output_channels: OutputChannels::Multichannel,
+            safety_track: false,
+            inverted_channels: Vec::new(),
+            gain_db: 0.0,
+            soft_limit: false,
+            high_pass: false,
+            high_pass_hz: crate::processing::DEFAULT_HIGH_PASS_HZ,
+            noise_gate: false,
+            gate_threshold_dbfs: -50.0,
+            project: None,
+            marker_format: MarkerFormat::Cue,
+            provenance: false,
+            transcode: None,
+            tags: Tags::default(),
+            loudness_file: false,
+            pipe: None,
+            pipe_format: PipeFormat::Speech,
+            stream: None,
+            buffer: None,
+            min_free_space: None,
+            start_at: None,
+            pre_roll: None,
+            standby: false,
+            duration: None,
+            silence_check: None,
+            vad: None,
+        }
+    }
+
+    fn config(channels: u16) -> StreamConfig {
+        StreamConfig {
+            channels,
+            sample_rate: SampleRate(1000),
+            buffer_size: BufferSize::Default,
+        }
+    }
+
+    #[test]
+    fn duration_limit_trims_the_last_block() {
+        let options = RecordingOptions {
+            duration: Some(Duration::from_secs(1)),
+            ..options()
+        };
+        let mut take = TakeState::new(&options, &config(2));
+
+        let first = take.process(&[0.1; 1200]);
+        assert_eq!((first.keep, first.stop), (1200, false));
+        let second = take.process(&[0.1; 1200]);
+        assert_eq!((second.keep, second.stop), (800, true));
+    }
+
+    #[test]
+    fn silence_check_reports_once() {
+        let options = RecordingOptions {
+            silence_check: Some(Duration::from_secs(1)),
+            ..options()
+        };
+        let mut take = TakeState::new(&options, &config(1));
+
+        assert_eq!(take.process(&[0.0; 600]).silent, None);
+        assert_eq!(
+            take.process(&[0.0; 600]).silent,
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(take.process(&[0.0; 2000]).silent, None);
+    }
+
+    #[test]
+    fn silence_check_passes_once_sound_arrives() {
+        let options = RecordingOptions {
+            silence_check: Some(Duration::from_secs(1)),
+            ..options()
+        };
+        let mut take = TakeState::new(&options, &config(1));
+
+        assert_eq!(take.process(&[0.01; 100]).silent, None);
+        assert_eq!(take.process(&[0.0; 2000]).silent, None);
+    }
+
+    #[test]
+    fn digital_silence_counts_as_muted() {
+        let mut take = TakeState::new(&options(), &config(2));
+
+        assert_eq!(take.process(&[0.0; 3000]).muted, None);
+        assert_eq!(take.process(&[0.0; 1000]).muted, Some(true));
+        assert_eq!(take.process(&[0.0; 1000]).muted, None);
+        assert_eq!(take.process(&[1e-6; 10]).muted, Some(false));
+    }
+
+    #[test]
+    fn quiet_input_is_not_muted() {
+        let mut take = TakeState::new(&options(), &config(1));
+        let mut block = [0.0; 1000];
+        block[500] = 1.0 / 32768.0;
+        for _ in 0..5 {
+            assert_eq!(take.process(&block).muted, None);
+        }
+    }
+
+    #[test]
+    fn vad_waits_for_sound_before_counting_silence() {
+        let stop_after = Duration::from_secs(1);
+        let options = RecordingOptions {
+            vad: Some(VadConfig {
+                threshold_dbfs: -45.0,
+                stop_after,
+            }),
+            ..options()
+        };
+        let mut take = TakeState::new(&options, &config(1));
+
+        assert!(!take.process(&[0.0; 3000]).stop);
+        assert!(!take.process(&[0.5; 100]).stop);
+        assert!(!take.process(&[0.0; 999]).stop);
+
+        let outcome = take.process(&[0.0; 1]);
+        assert!(outcome.stop);
+        assert_eq!(outcome.stopped_on_silence, Some(stop_after));
+    }
+
+    #[test]
+    fn vad_restarts_the_count_on_sound() {
+        let options = RecordingOptions {
+            vad: Some(VadConfig {
+                threshold_dbfs: -45.0,
+                stop_after: Duration::from_secs(1),
+            }),
+            ..options()
+        };
+        let mut take = TakeState::new(&options, &config(1));
+
+        take.process(&[0.5; 100]);
+        take.process(&[0.0; 900]);
+        take.process(&[0.5; 100]);
+        assert!(!take.process(&[0.0; 900]).stop);
+    }
+
+    #[test]
+    fn gain_that_isnt_a_number_is_ignored() {
+        let controls = InputControls::default();
+        controls.set_gain_db(6.0);
+        controls.set_gain_db(f32::NAN);
+        controls.set_gain_db(f32::INFINITY);
+
+        assert_eq!(controls.gain_db(), 6.0);
+    }
+
+    #[test]
+    fn pre_roll_keeps_the_latest_input() {
+        let mut pre_roll = PreRoll::new(Duration::from_millis(500), &config(2));
+        for block in 0..4 {
+            pre_roll.push(Arc::from(vec![block as f32; 400]));
+        }
+
+        let kept: Vec<f32> = pre_roll.drain().flat_map(|block| block.to_vec()).collect();
+        assert_eq!(kept.len(), 1000);
+        assert!(kept[..200].iter().all(|&sample| sample == 1.0));
+        assert!(kept[200..].iter().all(|&sample| sample >= 2.0));
+        assert_eq!(pre_roll.drain().count(), 0);
+    }
+
+    /// A tone for `tone`, then silence.
+    struct ToneThenSilence {
+        tone: usize,
+    }
+
+    impl AudioSource for ToneThenSilence {
+        fn name(&self) -> String {
+            String::from("Tone then silence")
+        }
+
+        fn config(&self) -> StreamConfig {
+            config(1)
+        }
+
+        fn fill(&mut self, block: &mut [f32]) {
+            for sample in block {
+                *sample = if self.tone > 0 { 0.5 } else { 0.0 };
+                self.tone = self.tone.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Records until the take finishes, and decodes it and its safety track.
+    fn record_from(
+        name: &str,
+        options: RecordingOptions,
+        source: Box<dyn AudioSource>,
+    ) -> (
+        Vec<EngineEvent>,
+        decoder::DecodedAudio,
+        Option<decoder::DecodedAudio>,
+    ) {
+        let dir = std::env::temp_dir().join(format!("micrec-{name}-{}", process::id()));
+        let recorder = Recorder::start_with_source(
+            RecordingOptions {
+                output_dir: dir.clone(),
+                ..options
+            },
+            source,
+        );
+        let mut events = Vec::new();
+        let path = loop {
+            match recorder.next_event(Duration::from_secs(10)) {
+                Ok(EngineEvent::Finished(result)) => break result.unwrap(),
+                Ok(event) => events.push(event),
+                Err(err) => panic!("no end to the take: {err}"),
+            }
+        };
+        let audio = decoder::decode_file(&path).unwrap();
+        let safety = decoder::decode_file(&naming::safety_track_path(&path)).ok();
+        fs::remove_dir_all(&dir).ok();
+        (events, audio, safety)
+    }
+
+    #[test]
+    fn stopping_while_standing_by_leaves_no_file() {
+        let dir = std::env::temp_dir().join(format!("micrec-standby-{}", process::id()));
+        let recorder = Recorder::start_with_source(
+            RecordingOptions {
+                output_dir: dir.clone(),
+                pre_roll: Some(Duration::from_millis(100)),
+                standby: true,
+                ..options()
+            },
+            Box::new(Synth::new(Signal::Sine, 8000, 1)),
+        );
+        loop {
+            match recorder.next_event(Duration::from_secs(10)) {
+                Ok(EngineEvent::StandingBy(_)) => recorder.stop(),
+                Ok(EngineEvent::Cancelled) => break,
+                Ok(EngineEvent::Started(_) | EngineEvent::Finished(_)) => {
+                    panic!("the take began")
+                }
+                Ok(_) => {}
+                Err(err) => panic!("no end to the take: {err}"),
+            }
+        }
+        let files = fs::read_dir(&dir).map_or(0, |files| files.count());
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(files, 0);
+    }
+
+    #[test]
+    fn records_a_simulated_source() {
+        let options = RecordingOptions {
+            duration: Some(Duration::from_millis(300)),
+            ..options()
+        };
+        let (_, audio, _) = record_from(
+            "source",
+            options,
+            Box::new(Synth::new(Signal::Sine, 8000, 1)),
+        );
+
+        assert_eq!((audio.channels, audio.sample_rate), (1, 8000));
+        assert_eq!(audio.frames(), 2400);
+        assert!((dsp::to_dbfs(dsp::peak(&audio.samples)) - -12.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn vad_stops_a_simulated_take() {
+        let stop_after = Duration::from_millis(200);
+        let options = RecordingOptions {
+            vad: Some(VadConfig {
+                threshold_dbfs: -40.0,
+                stop_after,
+            }),
+            duration: Some(Duration::from_secs(5)),
+            ..options()
+        };
+        let (events, audio, _) =
+            record_from("vad", options, Box::new(ToneThenSilence { tone: 300 }));
+
+        assert!(events.iter().any(
+            |event| matches!(event, EngineEvent::StoppedOnSilence(after) if *after == stop_after)
+        ));
+        // The tone, the silence that ended the take, and no more than a block past it
+        assert!(
+            audio.frames() >= 500 && audio.frames() < 600,
+            "{}",
+            audio.frames()
+        );
+    }
+
+    #[test]
+    fn safety_track_is_12_db_below_the_input() {
+        let options = RecordingOptions {
+            duration: Some(Duration::from_millis(300)),
+            safety_track: true,
+            gain_db: 20.0,
+            output_channels: OutputChannels::Mono,
+            sample_rate: Some(16_000),
+            ..options()
+        };
+        let (_, audio, safety) = record_from(
+            "safety",
+            options,
+            Box::new(Synth::new(Signal::Sine, 8000, 2)),
+        );
+        let safety = safety.unwrap();
+
+        // The gain clips the take, but not its safety track
+        assert!(dsp::peak(&audio.samples) >= 1.0);
+        assert_eq!((safety.channels, safety.sample_rate), (1, 16_000));
+        assert_eq!((audio.frames(), safety.frames()), (4800, 4800));
+        let peak = dsp::to_dbfs(dsp::peak(&safety.samples));
+        assert!(
+            (peak - (-12.0 + SAFETY_TRACK_GAIN_DB)).abs() < 0.5,
+            "{peak}"
+        );
+    }
+
+    #[test]
+    fn hosts_are_found_by_name() {
+        #[cfg(target_os = "linux")]
+        assert_eq!(find_host("Alsa").unwrap(), HostId::Alsa);
+        assert!(find_host("sndio").is_err());
+    }
+}
@@ -0,0 +1,404 @@
+use std::io::{self, BufRead, IsTerminal};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, Once, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use cpal::HostId;
+
+use crate::control::{ControlMessage, ControlServer};
+use crate::instance::{Instance, TransportCommand};
+use crate::speech::Announcer;
+use micrec::dsp;
+use micrec::engine::{EngineEvent, Recorder, RecordingOptions, RecordingStats};
+use micrec::library::format_size;
+use micrec::probe;
+
+/// How often a level line is printed.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// Width of a text meter, in characters.
+const METER_WIDTH: usize = 30;
+/// Level at the left end of a text meter.
+const METER_FLOOR_DBFS: f32 = -60.0;
+
+/// What goes in each level line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+    /// Peak and RMS of all channels together, in dBFS.
+    Levels,
+    /// A text meter of each channel's peak, for following along on a terminal that
+    /// can't run the TUI, such as a serial console.
+    Meters,
+}
+
+/// Set while a headless recording is running, so Ctrl-C stops it cleanly.
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INSTALL_HANDLER: Once = Once::new();
+/// Lines typed on stdin, read by one thread for the whole process.
+static LINES: OnceLock<Mutex<Receiver<()>>> = OnceLock::new();
+
+/// Installs the Ctrl-C handler once per process, since `run` can be called again
+/// for every meeting in watch mode. Outside a recording, Ctrl-C exits as usual.
+fn install_interrupt_handler() -> Result<()> {
+    let mut result = Ok(());
+    INSTALL_HANDLER.call_once(|| {
+        result = ctrlc::set_handler(|| {
+            if RECORDING.load(Ordering::Relaxed) {
+                INTERRUPTED.store(true, Ordering::Relaxed);
+            } else {
+                std::process::exit(130);
+            }
+        });
+    });
+    result.wrap_err("failed to install Ctrl-C handler")
+}
+
+/// Whether Enter was pressed since the last call, for starting a take standing by.
+fn enter_pressed() -> bool {
+    let lines = LINES.get_or_init(|| {
+        let (lines_tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for _ in io::stdin().lock().lines().map_while(Result::ok) {
+                if lines_tx.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Mutex::new(lines)
+    });
+    lines.lock().is_ok_and(|lines| lines.try_iter().count() > 0)
+}
+
+/// Records without a terminal UI, printing a line to stderr every second as
+/// `report` says. Nothing is drawn in place, so the output reads the same in a log.
+///
+/// Stops on Ctrl-C (SIGINT), once `options.duration` has been captured, after
+/// `options.vad` detects enough silence, when the session master stops, or when
+/// a remote controller says to.
+///
+/// With `speak_every`, the level and elapsed time are also read out loud.
+///
+/// A pre-roll without a start time stands by until Enter is pressed or a remote
+/// controller says to record.
+pub fn run(
+    mut options: RecordingOptions,
+    instance: Instance,
+    control: Option<ControlServer>,
+    warnings: Vec<String>,
+    speak_every: Option<Duration>,
+    report: Report,
+) -> Result<()> {
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+    let announcer = speak_every.and_then(|interval| {
+        Announcer::new(interval)
+            .inspect_err(|err| eprintln!("Warning: {err:#}"))
+            .ok()
+    });
+
+    options.standby =
+        options.pre_roll.is_some() && options.start_at.is_none() && options.buffer.is_none();
+    if options.standby && control.is_none() && !io::stdin().is_terminal() {
+        return Err(eyre!(
+            "nothing can start a take standing by for its pre-roll, give --start-at, \
+             run it in a terminal or enable remote control"
+        ));
+    }
+
+    install_interrupt_handler()?;
+    INTERRUPTED.store(false, Ordering::Relaxed);
+    RECORDING.store(true, Ordering::Relaxed);
+    let result = record(options, &instance, control.as_ref(), announcer, report);
+    RECORDING.store(false, Ordering::Relaxed);
+    result
+}
+
+fn record(
+    options: RecordingOptions,
+    instance: &Instance,
+    control: Option<&ControlServer>,
+    mut announcer: Option<Announcer>,
+    report: Report,
+) -> Result<()> {
+    let (host, device) = (options.host, options.device.clone());
+    if let Some(target) = options.stream {
+        eprintln!("Streaming to {target}");
+    }
+    let engine = Recorder::start(options);
+    let mut stopping = false;
+    let mut standing_by = false;
+    let mut frames = 0usize;
+    let mut sample_rate = 0u32;
+    let mut channels = 1usize;
+    let mut window: Vec<f32> = Vec::new();
+    let mut window_frames = usize::MAX;
+    // Tenths of the compressed copy reported so far
+    let mut transcoded = None;
+    let mut finished: Option<Result<PathBuf, String>> = None;
+
+    loop {
+        let master_stopped = instance.commands().next().is_some();
+        let mut stop_asked = false;
+        for message in control.iter().flat_map(|control| control.messages()) {
+            match message {
+                ControlMessage::Record => engine.record(),
+                ControlMessage::Stop => stop_asked = true,
+                ControlMessage::Pause => engine.set_paused(!engine.paused()),
+                ControlMessage::Marker => engine.add_marker(),
+                ControlMessage::Gain(gain_db) => engine.set_gain_db(gain_db),
+            }
+        }
+        if standing_by && enter_pressed() {
+            engine.record();
+        }
+        if (INTERRUPTED.load(Ordering::Relaxed) || master_stopped || stop_asked) && !stopping {
+            engine.stop();
+            instance.broadcast(TransportCommand::Stop);
+            stopping = true;
+        }
+
+        // Samples follow `Started`, which says how to read them
+        let samples = if sample_rate > 0 {
+            engine.samples()
+        } else {
+            None
+        };
+        if let Some(samples) = samples {
+            frames += samples.len() / channels;
+            window.extend_from_slice(&samples);
+
+            if let Some(speaker) = &mut announcer {
+                let elapsed = Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+                if let Err(err) = speaker.process(&samples, elapsed) {
+                    eprintln!("Warning: stopped speaking levels: {err:#}");
+                    announcer = None;
+                }
+            }
+
+            if window.len() / channels >= window_frames {
+                match report {
+                    Report::Levels => print_levels(frames, sample_rate, &window, engine.stats()),
+                    Report::Meters => print_meters(frames, sample_rate, &window, channels),
+                }
+                window.clear();
+            }
+        }
+        // Reported once the last of the take's samples are counted
+        if let Some(result) = finished.take() {
+            let path = result.map_err(|err| eyre!("recording failed: {err}"))?;
+            eprintln!(
+                "Saved {} ({})",
+                path.display(),
+                format_elapsed(frames, sample_rate)
+            );
+            return Ok(());
+        }
+
+        let event = match engine.next_event(Duration::from_millis(50)) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(eyre!("audio engine stopped unexpectedly"))
+            }
+        };
+
+        match event {
+            EngineEvent::Started(config) => {
+                sample_rate = config.sample_rate.0;
+                channels = config.channels.max(1) as usize;
+                window_frames = (REPORT_INTERVAL.as_secs_f64() * sample_rate as f64) as usize;
+                eprintln!("Recording at {} Hz, {} channel(s)", sample_rate, channels);
+            }
+            EngineEvent::Silent(after) => suggest_input(host, device.as_deref(), after),
+            EngineEvent::Muted(true) => eprintln!(
+                "Warning: the input appears muted (nothing but digital silence), check the \
+                 mic's mute switch and the system input volume"
+            ),
+            EngineEvent::Muted(false) => eprintln!("The input is no longer muted"),
+            EngineEvent::StoppedOnSilence(after) => {
+                eprintln!("Stopping after {}s of silence", after.as_secs_f32());
+            }
+            EngineEvent::LowDiskSpace(free) => {
+                eprintln!("Warning: only {} left on disk", format_size(free))
+            }
+            EngineEvent::StoppedOnLowDiskSpace(free) => {
+                eprintln!("Stopping with only {} left on disk", format_size(free));
+            }
+            EngineEvent::MonitoringChanged(_) | EngineEvent::NoiseProfileLearned => {}
+            EngineEvent::Disconnected(device) => {
+                eprintln!("Warning: {device} disconnected, waiting for it to come back")
+            }
+            EngineEvent::Reconnected(device) => eprintln!("Recording again from {device}"),
+            EngineEvent::StreamError(err) => eprintln!("Warning: {err}"),
+            EngineEvent::Devices(devices) => {
+                let names: Vec<String> = devices
+                    .iter()
+                    .map(|(name, channels)| format!("{name} ({channels} channel(s))"))
+                    .collect();
+                eprintln!("Recording from {}", names.join(" + "));
+            }
+            EngineEvent::Scheduled(at) => {
+                eprintln!(
+                    "Armed, recording starts at {}",
+                    at.format("%Y-%m-%d %H:%M:%S")
+                )
+            }
+            EngineEvent::WaitingForPermission(message) => {
+                eprintln!("Waiting for microphone access: {message}")
+            }
+            EngineEvent::StandingBy(pre_roll) => {
+                standing_by = true;
+                eprintln!(
+                    "Keeping the last {} until the start{}",
+                    humantime::format_duration(pre_roll),
+                    if io::stdin().is_terminal() {
+                        ", press Enter to start"
+                    } else {
+                        ""
+                    }
+                )
+            }
+            EngineEvent::Rolling => {
+                standing_by = false;
+                eprintln!("Started, pre-roll first")
+            }
+            EngineEvent::Cancelled => {
+                eprintln!("Stopped before the take began, nothing was recorded");
+                return Ok(());
+            }
+            EngineEvent::Failed(err) => return Err(eyre!("recording could not start: {err}")),
+            EngineEvent::MarkerAdded(_) => {}
+            EngineEvent::ClipSaved(Ok(clip)) => eprintln!("Saved clip {}", clip.display()),
+            EngineEvent::ClipSaved(Err(err)) => eprintln!("Warning: {err}"),
+            EngineEvent::SidecarSaved(Ok(sidecar)) => eprintln!("Saved {}", sidecar.display()),
+            EngineEvent::SidecarSaved(Err(err)) => eprintln!("Warning: {err}"),
+            EngineEvent::Loudness(report) => eprintln!("Loudness: {report}"),
+            EngineEvent::Transcoding(done) => {
+                let tenths = (done * 10.0) as u32;
+                if transcoded < Some(tenths) {
+                    eprintln!("Transcoding {}%", tenths * 10);
+                    transcoded = Some(tenths);
+                }
+            }
+            EngineEvent::Finished(result) => finished = Some(result),
+        }
+    }
+}
+
+/// Warns that the input is silent and names another input that hears something.
+fn suggest_input(host: Option<HostId>, device: Option<&str>, after: Duration) {
+    eprintln!(
+        "Warning: no signal for {}s, is the mic muted or the wrong input selected?",
+        after.as_secs()
+    );
+    match probe::find_active_input(host, device) {
+        Ok(Some(input)) => eprintln!(
+            "{} is picking up sound ({:.1} dBFS), try --device \"{}\"",
+            input.name, input.peak_dbfs, input.name
+        ),
+        Ok(None) => eprintln!("No other input is picking up sound either"),
+        Err(err) => eprintln!("Couldn't check other inputs: {err:#}"),
+    }
+}
+
+fn print_levels(frames: usize, sample_rate: u32, window: &[f32], stats: Option<RecordingStats>) {
+    let mut size = match stats {
+        Some(RecordingStats {
+            file_size,
+            free_space: Some(free),
+            ..
+        }) => format!("  {}, {} free", format_size(file_size), format_size(free)),
+        Some(stats) => format!("  {}", format_size(stats.file_size)),
+        None => String::new(),
+    };
+    if let Some(overruns) = stats.map(|stats| stats.overruns).filter(|&n| n > 0) {
+        size.push_str(&format!(", {overruns} overruns"));
+    }
+    eprintln!(
+        "{}  peak {:6.1} dBFS  rms {:6.1} dBFS{size}",
+        format_elapsed(frames, sample_rate),
+        dsp::to_dbfs(dsp::peak(window)),
+        dsp::to_dbfs(dsp::rms(window)),
+    );
+}
+
+fn print_meters(frames: usize, sample_rate: u32, window: &[f32], channels: usize) {
+    eprintln!("{}", meter_line(frames, sample_rate, window, channels));
+}
+
+/// The elapsed time and a [`text_meter`] for each channel of `window`.
+fn meter_line(frames: usize, sample_rate: u32, window: &[f32], channels: usize) -> String {
+    let mut line = format_elapsed(frames, sample_rate);
+    for channel in 0..channels {
+        let samples: Vec<f32> = window
+            .iter()
+            .skip(channel)
+            .step_by(channels)
+            .copied()
+            .collect();
+        let label = match (channels, channel) {
+            (2, 0) => String::from("L"),
+            (2, _) => String::from("R"),
+            _ => (channel + 1).to_string(),
+        };
+        line.push_str(&format!("  {label} {}", text_meter(dsp::peak(&samples))));
+    }
+    line
+}
+
+/// A peak level as an ASCII bar and a reading, e.g. `[#######.....] -18.2`, with
+/// `CLIP` once it reaches full scale.
+fn text_meter(peak: f32) -> String {
+    let filled = (dsp::db_bar_level(peak, METER_FLOOR_DBFS) * METER_WIDTH as f32).round() as usize;
+    let dbfs = dsp::to_dbfs(peak).max(METER_FLOOR_DBFS);
+    let mut meter = format!(
+        "[{}{}] {dbfs:5.1}",
+        "#".repeat(filled),
+        ".".repeat(METER_WIDTH - filled)
+    );
+    if peak >= 1.0 {
+        meter.push_str(" CLIP");
+    }
+    meter
+}
+
+fn format_elapsed(frames: usize, sample_rate: u32) -> String {
+    let secs = frames / sample_rate.max(1) as usize;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meters_show_the_peak() {
+        assert_eq!(text_meter(0.0), format!("[{}] -60.0", ".".repeat(30)));
+        assert_eq!(
+            text_meter(0.1),
+            format!("[{}{}] -20.0", "#".repeat(20), ".".repeat(10))
+        );
+        assert_eq!(text_meter(1.0), format!("[{}]   0.0 CLIP", "#".repeat(30)));
+    }
+
+    #[test]
+    fn meter_lines_have_a_meter_per_channel() {
+        // Silence on the left and full scale on the right, a second in
+        let window: Vec<f32> = [0.0, 1.0].repeat(100);
+        let line = meter_line(48_000, 48_000, &window, 2);
+
+        assert_eq!(
+            line,
+            format!(
+                "00:00:01  L [{}] -60.0  R [{}]   0.0 CLIP",
+                ".".repeat(30),
+                "#".repeat(30)
+            )
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Discovery of other running micrec instances over per-process control sockets.
+//!
+//! Every instance listens on `<runtime dir>/<pid>.sock`. Listing that directory
+//! finds the others; asking each one for `info` tells us which device it records
+//! from, so we can warn before two instances fight over the same input.
+//!
+//! Instances started with `--sync` form a session: the first becomes the master,
+//! later ones follow it and obey its stop and quit. Each binds its socket before
+//! looking for the others and claims to be the master until it finds an earlier
+//! claim, so two instances started together still agree on which one is.
+
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, TryIter};
+use std::time::SystemTime;
+use std::{env, fs, io};
+
+use directories::ProjectDirs;
+
+/// Transport actions a master forwards to its followers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportCommand {
+    Stop,
+    Quit,
+}
+
+impl TransportCommand {
+    fn as_str(self) -> &'static str {
+        match self {
+            TransportCommand::Stop => "stop",
+            TransportCommand::Quit => "quit",
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        match line {
+            "stop" => Some(TransportCommand::Stop),
+            "quit" => Some(TransportCommand::Quit),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Not part of a session.
+    Standalone,
+    /// Controls the transport of every follower.
+    Master,
+    /// Stops and quits when the master does.
+    Follower,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Standalone => "standalone",
+            Role::Master => "master",
+            Role::Follower => "follower",
+        }
+    }
+
+    fn parse(role: &str) -> Option<Self> {
+        match role {
+            "standalone" => Some(Role::Standalone),
+            "master" => Some(Role::Master),
+            "follower" => Some(Role::Follower),
+            _ => None,
+        }
+    }
+}
+
+/// Another running instance, as reported over its control socket.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub pid: u32,
+    pub role: Role,
+    /// Device name, or `default` for the system default input.
+    pub device: String,
+    socket: PathBuf,
+    /// When the peer bound its socket.
+    bound: SystemTime,
+}
+
+/// This process's registration; the control socket is closed and removed on drop.
+#[derive(Debug)]
+pub struct Instance {
+    pub role: Role,
+    socket: Option<PathBuf>,
+    commands: Receiver<TransportCommand>,
+    #[cfg(unix)]
+    followers: std::sync::Arc<std::sync::Mutex<Vec<std::os::unix::net::UnixStream>>>,
+    /// Tells the thread answering on the socket to stop.
+    #[cfg(unix)]
+    closed: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    #[cfg(unix)]
+    serving: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Instance {
+    /// An instance that's not part of a session, for when registering fails.
+    pub fn standalone() -> Self {
+        let (_, commands) = channel();
+        Self {
+            role: Role::Standalone,
+            socket: None,
+            commands,
+            #[cfg(unix)]
+            followers: std::sync::Arc::default(),
+            #[cfg(unix)]
+            closed: std::sync::Arc::default(),
+            #[cfg(unix)]
+            serving: None,
+        }
+    }
+
+    /// Commands received from the master since the last call.
+    pub fn commands(&self) -> TryIter<'_, TransportCommand> {
+        self.commands.try_iter()
+    }
+
+    /// Forwards `command` to every follower. Does nothing unless we are the master.
+    pub fn broadcast(&self, command: TransportCommand) {
+        #[cfg(unix)]
+        if self.role == Role::Master {
+            use std::io::Write;
+
+            if let Ok(mut followers) = self.followers.lock() {
+                // Followers that went away are dropped on the first failed write
+                followers.retain_mut(|stream| writeln!(stream, "{}", command.as_str()).is_ok());
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = command;
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            self.closed
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+            if let Some(serving) = self.serving.take() {
+                serving.join().ok();
+            }
+        }
+        if let Some(socket) = &self.socket {
+            fs::remove_file(socket).ok();
+        }
+    }
+}
+
+/// Warnings for peers that record from the same device as `device`.
+pub fn contention_warnings(peers: &[Peer], device: Option<&str>) -> Vec<String> {
+    let device = device.unwrap_or("default");
+    peers
+        .iter()
+        .filter(|peer| peer.device == device)
+        .map(|peer| {
+            format!(
+                "micrec (pid {}) is already recording from {}",
+                peer.pid, peer.device
+            )
+        })
+        .collect()
+}
+
+/// Directory holding every instance's control socket.
+fn runtime_dir() -> PathBuf {
+    ProjectDirs::from("", "", "micrec")
+        .and_then(|dirs| dirs.runtime_dir().map(PathBuf::from))
+        .unwrap_or_else(|| {
+            let user = env::var("USER").unwrap_or_default();
+            env::temp_dir().join(format!("micrec-{user}"))
+        })
+}
+
+#[cfg(unix)]
+pub use unix::register;
+
+#[cfg(not(unix))]
+/// Control sockets need Unix domain sockets; elsewhere every instance stands alone.
+pub fn register(_device: Option<&str>, _sync: bool) -> io::Result<(Instance, Vec<Peer>)> {
+    Ok((Instance::standalone(), Vec::new()))
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Sender;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// How long to wait for a peer to answer before treating it as gone.
+    const PEER_TIMEOUT: Duration = Duration::from_millis(200);
+    /// How often the socket is checked for connections, and for being closed.
+    const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    type Followers = Arc<Mutex<Vec<UnixStream>>>;
+
+    /// Starts listening for our own control connections and finds running peers.
+    ///
+    /// With `sync`, we follow the session master if there is one, or become it.
+    pub fn register(device: Option<&str>, sync: bool) -> io::Result<(Instance, Vec<Peer>)> {
+        let dir = runtime_dir();
+        fs::create_dir_all(&dir)?;
+
+        let pid = std::process::id();
+        let socket = dir.join(format!("{pid}.sock"));
+        fs::remove_file(&socket).ok();
+        let listener = UnixListener::bind(&socket)?;
+        listener.set_nonblocking(true)?;
+        let bound = fs::metadata(&socket)?.modified()?;
+        let role = Arc::new(Mutex::new(if sync {
+            Role::Master
+        } else {
+            Role::Standalone
+        }));
+        let followers: Followers = Arc::default();
+        let info = Info {
+            pid,
+            role: Arc::clone(&role),
+            device: device.unwrap_or("default").to_string(),
+        };
+        let accepted = Arc::clone(&followers);
+        let closed = Arc::new(AtomicBool::new(false));
+        let serve_closed = Arc::clone(&closed);
+        let serving = thread::spawn(move || serve(listener, info, accepted, serve_closed));
+        // Closes and removes the socket again if following fails
+        let mut instance = Instance {
+            role: Role::Standalone,
+            socket: Some(socket),
+            commands: channel().1,
+            followers,
+            closed,
+            serving: Some(serving),
+        };
+
+        let peers: Vec<Peer> = discover(&dir)
+            .into_iter()
+            .filter(|peer| peer.pid != pid)
+            .collect();
+        // The earliest claim wins, whoever found whom first
+        let master = peers
+            .iter()
+            .filter(|peer| peer.role == Role::Master)
+            .min_by_key(|peer| (peer.bound, peer.pid))
+            .filter(|master| (master.bound, master.pid) < (bound, pid));
+        instance.role = match (sync, master) {
+            (true, Some(master)) => {
+                let (commands_tx, commands) = channel();
+                follow(&master.socket, commands_tx)?;
+                instance.commands = commands;
+                Role::Follower
+            }
+            (true, None) => Role::Master,
+            (false, _) => Role::Standalone,
+        };
+        if let Ok(mut role) = role.lock() {
+            *role = instance.role;
+        }
+        Ok((instance, peers))
+    }
+
+    /// What we tell peers that ask who we are.
+    struct Info {
+        pid: u32,
+        /// Changes once we know whether we lead the session or follow it.
+        role: Arc<Mutex<Role>>,
+        device: String,
+    }
+
+    /// Asks every socket in `dir` who it is, cleaning up sockets nobody answers on.
+    fn discover(dir: &std::path::Path) -> Vec<Peer> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "sock"))
+            .filter_map(|socket| match query_info(&socket) {
+                Ok(peer) => Some(peer),
+                Err(err) => {
+                    // Nobody listening: left behind by an instance that crashed
+                    if err.kind() == io::ErrorKind::ConnectionRefused {
+                        fs::remove_file(&socket).ok();
+                    }
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn query_info(socket: &std::path::Path) -> io::Result<Peer> {
+        let mut stream = UnixStream::connect(socket)?;
+        stream.set_read_timeout(Some(PEER_TIMEOUT))?;
+        writeln!(stream, "info")?;
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+
+        let mut fields = line.trim_end().splitn(3, '\t');
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed peer info");
+        let pid = fields
+            .next()
+            .and_then(|pid| pid.parse().ok())
+            .ok_or_else(invalid)?;
+        let role = fields.next().and_then(Role::parse).ok_or_else(invalid)?;
+        let device = fields.next().ok_or_else(invalid)?.to_string();
+
+        Ok(Peer {
+            pid,
+            role,
+            device,
+            socket: socket.to_path_buf(),
+            bound: fs::metadata(socket)?.modified()?,
+        })
+    }
+
+    /// Subscribes to the master's transport, forwarding its commands to `commands_tx`.
+    fn follow(master: &std::path::Path, commands_tx: Sender<TransportCommand>) -> io::Result<()> {
+        let mut stream = UnixStream::connect(master)?;
+        writeln!(stream, "follow")?;
+
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                if let Some(command) = TransportCommand::parse(&line) {
+                    if commands_tx.send(command).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Answers peers on `listener` until `closed`, when the socket is let go.
+    fn serve(listener: UnixListener, info: Info, followers: Followers, closed: Arc<AtomicBool>) {
+        while !closed.load(Ordering::Relaxed) {
+            let mut stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                // Nobody connected in time
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            // Some platforms hand the listener's nonblocking mode on
+            stream.set_nonblocking(false).ok();
+            stream.set_read_timeout(Some(PEER_TIMEOUT)).ok();
+
+            let mut request = String::new();
+            let Ok(reader) = stream.try_clone() else {
+                continue;
+            };
+            if BufReader::new(reader).read_line(&mut request).is_err() {
+                continue;
+            }
+
+            match request.trim_end() {
+                "info" => {
+                    let role = info.role.lock().map_or(Role::Standalone, |role| *role);
+                    writeln!(stream, "{}\t{}\t{}", info.pid, role.as_str(), info.device).ok();
+                }
+                "follow" => {
+                    if let Ok(mut followers) = followers.lock() {
+                        followers.push(stream);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
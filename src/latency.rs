@@ -0,0 +1,233 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{HostId, StreamConfig};
+
+use micrec::engine;
+
+/// Number of clicks emitted during a test run.
+const CLICKS: usize = 8;
+/// Time between clicks; also the longest round trip we can measure.
+const CLICK_INTERVAL: Duration = Duration::from_millis(500);
+/// Time spent listening to the room before the first click to set the threshold.
+const NOISE_WINDOW: Duration = Duration::from_millis(400);
+/// Length of the emitted click.
+const CLICK_LENGTH: Duration = Duration::from_millis(2);
+
+/// Plays a series of clicks on the default output device of `host`, listens for
+/// them on its default input device, and prints the measured round-trip latency.
+///
+/// Works best with a loopback cable, or with headphones held against the mic.
+pub fn run(host: Option<HostId>) -> Result<()> {
+    let host = engine::open_host(host)?;
+    let input = host
+        .default_input_device()
+        .ok_or_else(|| eyre!("no microphone found"))?;
+    let output = host
+        .default_output_device()
+        .ok_or_else(|| eyre!("no output device found"))?;
+    let input_config: StreamConfig = input
+        .default_input_config()
+        .wrap_err("failed to query the input device")?
+        .into();
+    let output_config: StreamConfig = output
+        .default_output_config()
+        .wrap_err("failed to query the output device")?
+        .into();
+
+    println!(
+        "Measuring round-trip latency: {} -> {}",
+        output.name().unwrap_or_default(),
+        input.name().unwrap_or_default()
+    );
+
+    // When each click plays, in nanoseconds after `epoch` and 0 until it does, set
+    // without blocking from the output callback
+    let epoch = Instant::now();
+    let played: Arc<[AtomicU64]> = (0..CLICKS).map(|_| AtomicU64::new(0)).collect();
+    let clicks_played = Arc::clone(&played);
+    let (detect_tx, detect_rx) = channel::<Instant>();
+
+    let out_channels = output_config.channels.max(1) as usize;
+    let out_rate = output_config.sample_rate.0 as f64;
+    let first_click = (NOISE_WINDOW.as_secs_f64() * out_rate) as u64;
+    let interval = (CLICK_INTERVAL.as_secs_f64() * out_rate) as u64;
+    let click_length = (CLICK_LENGTH.as_secs_f64() * out_rate).max(1.0) as u64;
+    let mut frame_count = 0u64;
+
+    let output_stream = output
+        .build_output_stream(
+            &output_config,
+            move |data: &mut [f32], info| {
+                let stamp = info.timestamp();
+                let delay = stamp
+                    .playback
+                    .duration_since(&stamp.callback)
+                    .unwrap_or_default();
+                let now = Instant::now();
+
+                for (i, frame) in data.chunks_mut(out_channels).enumerate() {
+                    let offset = frame_count.checked_sub(first_click).map(|f| f % interval);
+                    let clicks_done = frame_count.saturating_sub(first_click) / interval;
+                    let value = match offset {
+                        Some(0) if clicks_done < CLICKS as u64 => {
+                            let played_at =
+                                now + delay + Duration::from_secs_f64(i as f64 / out_rate);
+                            let nanos = (played_at - epoch).as_nanos() as u64;
+                            clicks_played[clicks_done as usize]
+                                .store(nanos.max(1), Ordering::Relaxed);
+                            0.9
+                        }
+                        Some(o) if o < click_length && clicks_done < CLICKS as u64 => 0.9,
+                        _ => 0.0,
+                    };
+                    frame.fill(value);
+                    frame_count += 1;
+                }
+            },
+            |err| eprintln!("Playback error: {}", err),
+            None,
+        )
+        .wrap_err("failed to open the output stream")?;
+
+    let in_channels = input_config.channels.max(1) as usize;
+    let in_rate = input_config.sample_rate.0 as f64;
+    let listen_start = Instant::now();
+    let mut noise_peak = 0.0_f32;
+    let mut last_detection: Option<Instant> = None;
+
+    let input_stream = input
+        .build_input_stream(
+            &input_config,
+            move |data: &[f32], info| {
+                let stamp = info.timestamp();
+                let age = stamp
+                    .callback
+                    .duration_since(&stamp.capture)
+                    .unwrap_or_default();
+                let captured_at = Instant::now() - age;
+
+                for (i, frame) in data.chunks(in_channels).enumerate() {
+                    let level = frame.iter().fold(0.0_f32, |acc, &x| acc.max(x.abs()));
+
+                    if captured_at < listen_start + NOISE_WINDOW {
+                        noise_peak = noise_peak.max(level);
+                        continue;
+                    }
+
+                    // Well above the room, with a floor for very quiet setups
+                    let threshold = (noise_peak * 4.0).max(0.02);
+                    let at = captured_at + Duration::from_secs_f64(i as f64 / in_rate);
+                    let recently_detected =
+                        last_detection.is_some_and(|last| at < last + CLICK_INTERVAL / 2);
+                    if level > threshold && !recently_detected {
+                        last_detection = Some(at);
+                        detect_tx.send(at).ok();
+                    }
+                }
+            },
+            |err| eprintln!("Audio error: {}", err),
+            None,
+        )
+        .wrap_err("failed to open the input stream")?;
+
+    input_stream
+        .play()
+        .wrap_err("failed to start the input stream")?;
+    output_stream
+        .play()
+        .wrap_err("failed to start the output stream")?;
+
+    thread::sleep(NOISE_WINDOW + CLICK_INTERVAL * (CLICKS as u32 + 1));
+
+    drop(output_stream);
+    drop(input_stream);
+
+    let clicks: Vec<Instant> = played
+        .iter()
+        .map(|nanos| nanos.load(Ordering::Relaxed))
+        .filter(|&nanos| nanos > 0)
+        .map(|nanos| epoch + Duration::from_nanos(nanos))
+        .collect();
+    let detections: Vec<Instant> = detect_rx.try_iter().collect();
+    let mut latencies = match_clicks(&clicks, &detections);
+
+    if latencies.is_empty() {
+        println!("No clicks detected. Check that the output can reach the input and try again.");
+        return Ok(());
+    }
+
+    latencies.sort();
+    let median = latencies[latencies.len() / 2];
+    println!(
+        "Detected {}/{} clicks. Round trip: min {:.1} ms, median {:.1} ms, max {:.1} ms",
+        latencies.len(),
+        clicks.len(),
+        as_millis(latencies[0]),
+        as_millis(median),
+        as_millis(latencies[latencies.len() - 1]),
+    );
+    Ok(())
+}
+
+/// Pairs each click with the first detection that follows it within one interval.
+fn match_clicks(clicks: &[Instant], detections: &[Instant]) -> Vec<Duration> {
+    clicks
+        .iter()
+        .filter_map(|&click| {
+            detections
+                .iter()
+                .find(|&&detected| detected >= click && detected < click + CLICK_INTERVAL)
+                .map(|&detected| detected - click)
+        })
+        .collect()
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_clicks_with_what_follows_them() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let ms = Duration::from_millis;
+        let interval = CLICK_INTERVAL.as_millis() as u64;
+
+        // Heard before the click was played
+        assert!(match_clicks(&[at(100)], &[at(90)]).is_empty());
+        assert_eq!(match_clicks(&[at(100)], &[at(100)]), [ms(0)]);
+        // Late, but before the next click
+        assert_eq!(
+            match_clicks(&[at(100)], &[at(100 + interval - 1)]),
+            [ms(interval - 1)]
+        );
+        // Never heard
+        assert!(match_clicks(&[at(100)], &[]).is_empty());
+        // Only heard once the next click was due
+        assert!(match_clicks(&[at(100)], &[at(100 + interval)]).is_empty());
+    }
+
+    #[test]
+    fn a_missed_click_doesnt_take_the_next_ones() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let interval = CLICK_INTERVAL.as_millis() as u64;
+        let clicks = [at(0), at(interval), at(2 * interval)];
+        let detections = [at(10), at(2 * interval + 20)];
+
+        assert_eq!(
+            match_clicks(&clicks, &detections),
+            [Duration::from_millis(10), Duration::from_millis(20)]
+        );
+    }
+}
@@ -0,0 +1,37 @@
+//! Audio capture, metering and file writing behind the `micrec` recorder.
+//!
+//! [`engine::Recorder`] records a take on a background thread and reports what
+//! happens through [`engine::EngineEvent`]s, leaving the presentation to the
+//! front-end. The `micrec` binary drives it from a TUI or headless.
+
+pub mod analysis;
+pub mod decoder;
+pub mod denoise;
+pub mod dsp;
+pub mod encoder;
+pub mod engine;
+pub mod library;
+pub mod loopback;
+pub mod loudness;
+pub mod markers;
+pub mod merge;
+pub mod meter;
+mod monitor;
+pub mod naming;
+pub mod network;
+pub mod permission;
+pub mod pipe;
+pub mod playback;
+pub mod probe;
+pub mod processing;
+pub mod project;
+pub mod provenance;
+mod queue;
+pub mod resample;
+mod riff;
+pub mod ring;
+pub mod schedule;
+pub mod source;
+pub mod stats;
+pub mod tags;
+pub mod transcode;
@@ -0,0 +1,321 @@
+//! The recordings already on disk, for browsing, renaming and deleting takes.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::decoder;
+use crate::encoder::OutputFormat;
+use crate::naming;
+
+/// What's saved next to a recording, as suffixes of its name without the
+/// extension: cue sheet, markers, project, labels, provenance, loudness report
+/// and transcript.
+const SIDECARS: [&str; 7] = [
+    ".cue",
+    "-markers.json",
+    ".rpp",
+    "-labels.txt",
+    "-info.json",
+    "-loudness.json",
+    "-transcript.txt",
+];
+
+/// A recording found in the output directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingFile {
+    pub path: PathBuf,
+    /// Size in bytes.
+    pub size: u64,
+    pub modified: SystemTime,
+    /// Length from the file's headers, if they say.
+    pub duration: Option<Duration>,
+}
+
+/// Every recording in `dir` and its subdirectories, newest first. Files are
+/// recognized by the extensions micrec writes.
+pub fn list(dir: &Path) -> io::Result<Vec<RecordingFile>> {
+    let mut recordings = Vec::new();
+    collect(dir, &mut recordings)?;
+    recordings.sort_by(|a, b| b.modified.cmp(&a.modified).then(a.path.cmp(&b.path)));
+    Ok(recordings)
+}
+
+fn collect(dir: &Path, recordings: &mut Vec<RecordingFile>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // Nothing recorded yet
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            collect(&path, recordings)?;
+        } else if metadata.is_file() && OutputFormat::from_path(&path).is_some() {
+            recordings.push(RecordingFile {
+                duration: decoder::probe_duration(&path).ok().flatten(),
+                size: metadata.len(),
+                modified: metadata.modified()?,
+                path,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Renames `path` to `name` in the same directory, keeping the extension unless
+/// `name` ends in one micrec writes. Its safety track and whatever else was saved
+/// next to it are renamed along with it. Never replaces an existing file: nothing
+/// is renamed if any of the new names is taken.
+pub fn rename(path: &Path, name: &str) -> io::Result<PathBuf> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("\"{name}\" is not a valid file name"),
+        ));
+    }
+
+    let mut target = path.with_file_name(name);
+    if OutputFormat::from_path(&target).is_none() {
+        if let Some(extension) = path.extension() {
+            target = path.with_file_name(format!("{name}.{}", extension.to_string_lossy()));
+        }
+    }
+    if target == path {
+        return Ok(target);
+    }
+    let companions: Vec<(PathBuf, PathBuf)> = companions(path)
+        .zip(companions(&target))
+        .filter(|(from, to)| from != to && from.exists())
+        .collect();
+    let targets = std::iter::once(&target).chain(companions.iter().map(|(_, to)| to));
+    for to in targets {
+        if to.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to.display()),
+            ));
+        }
+    }
+
+    fs::rename(path, &target)?;
+    for (from, to) in &companions {
+        fs::rename(from, to)?;
+    }
+    Ok(target)
+}
+
+/// Deletes the recording at `path` along with its safety track and whatever else
+/// was saved next to it. Only the recording has to be there.
+pub fn delete(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    for companion in companions(path) {
+        fs::remove_file(companion).ok();
+    }
+    Ok(())
+}
+
+/// Where the safety track and sidecars of the recording at `path` would be,
+/// whether they're there or not.
+fn companions(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    SIDECARS
+        .iter()
+        .map(move |suffix| path.with_file_name(format!("{stem}{suffix}")))
+        .chain([naming::safety_track_path(path)])
+}
+
+/// Size in bytes as a short human-readable string.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Bytes free for new files on the filesystem holding `dir`. The directory
+/// doesn't have to exist yet; the nearest parent that does is asked instead.
+pub fn free_space(dir: &Path) -> io::Result<u64> {
+    let existing = dir
+        .ancestors()
+        .find(|path| path.exists())
+        .unwrap_or(Path::new("."));
+    statvfs_free(existing)
+}
+
+#[cfg(unix)]
+fn statvfs_free(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read after success
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)] // The field types differ between platforms
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn statvfs_free(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space can't be checked on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::naming::tests::scratch_dir;
+
+    #[test]
+    fn lists_recordings_in_subdirectories() {
+        let dir = scratch_dir("list");
+        fs::create_dir_all(dir.join("2024/05")).unwrap();
+        File::create(dir.join("memo.wav")).unwrap();
+        File::create(dir.join("2024/05/take.flac")).unwrap();
+        File::create(dir.join("notes.txt")).unwrap();
+
+        let mut paths: Vec<PathBuf> = list(&dir).unwrap().into_iter().map(|r| r.path).collect();
+        paths.sort();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(paths, [dir.join("2024/05/take.flac"), dir.join("memo.wav")]);
+    }
+
+    #[test]
+    fn missing_directory_has_no_recordings() {
+        let dir = std::env::temp_dir().join("micrec-does-not-exist");
+        assert!(list(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rename_keeps_the_extension() {
+        let dir = scratch_dir("rename");
+        let path = dir.join("memo.wav");
+        File::create(&path).unwrap();
+
+        let renamed = rename(&path, "interview v1.2").unwrap();
+        let exists = renamed.exists();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(renamed, dir.join("interview v1.2.wav"));
+        assert!(exists);
+    }
+
+    #[test]
+    fn rename_never_overwrites() {
+        let dir = scratch_dir("rename-collide");
+        File::create(dir.join("a.wav")).unwrap();
+        File::create(dir.join("b.wav")).unwrap();
+
+        let result = rename(&dir.join("a.wav"), "b");
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn rename_takes_the_sidecars_along() {
+        let dir = scratch_dir("rename-sidecars");
+        for name in ["memo.wav", "memo-safety.wav", "memo.cue"] {
+            File::create(dir.join(name)).unwrap();
+        }
+
+        rename(&dir.join("memo.wav"), "interview").unwrap();
+        let mut names: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        names.sort();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(
+            names,
+            [
+                dir.join("interview-safety.wav"),
+                dir.join("interview.cue"),
+                dir.join("interview.wav"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rename_checks_every_new_name_first() {
+        let dir = scratch_dir("rename-sidecar-collide");
+        for name in ["a.wav", "a.cue", "b.cue"] {
+            File::create(dir.join(name)).unwrap();
+        }
+
+        let result = rename(&dir.join("a.wav"), "b");
+        let untouched = dir.join("a.wav").exists() && dir.join("a.cue").exists();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert!(untouched);
+    }
+
+    #[test]
+    fn rename_stays_in_the_same_directory() {
+        let path = Path::new("/recordings/memo.wav");
+        assert!(rename(path, "../escape").is_err());
+        assert!(rename(path, "  ").is_err());
+    }
+
+    #[test]
+    fn delete_takes_the_sidecars_along() {
+        let dir = scratch_dir("delete");
+        for name in [
+            "memo.wav",
+            "memo-safety.wav",
+            "memo.cue",
+            "memo-loudness.json",
+        ] {
+            File::create(dir.join(name)).unwrap();
+        }
+        File::create(dir.join("memo-2.wav")).unwrap();
+
+        delete(&dir.join("memo.wav")).unwrap();
+        let left: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(left, [dir.join("memo-2.wav")]);
+    }
+
+    #[test]
+    fn sizes_are_in_binary_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn free_space_looks_at_the_nearest_existing_parent() {
+        let missing = std::env::temp_dir().join("micrec-does-not-exist/2024/05");
+        assert!(free_space(&missing).unwrap() > 0);
+    }
+}
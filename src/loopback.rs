@@ -0,0 +1,96 @@
+//! Recording what the computer is playing instead of a microphone: WASAPI loopback
+//! on an output device on Windows, and the monitor of a PulseAudio or PipeWire sink
+//! on Linux.
+
+#[cfg(any(windows, target_os = "linux"))]
+use color_eyre::eyre::WrapErr;
+use color_eyre::eyre::{eyre, Result};
+#[cfg(any(windows, target_os = "linux"))]
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{Device, Host, StreamConfig};
+
+#[cfg(not(windows))]
+use crate::engine;
+
+/// ALSA device of PulseAudio's plugin, which PipeWire provides as well. It records
+/// from the source named in `PULSE_SOURCE`.
+#[cfg(target_os = "linux")]
+const PULSE_DEVICE: &str = "pulse";
+
+/// The device to capture the output device called `name` from, or the default
+/// output.
+#[cfg(windows)]
+pub fn select_device(host: &Host, name: Option<&str>) -> Result<Device> {
+    match name {
+        Some(name) => host
+            .output_devices()
+            .wrap_err("failed to list output devices")?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| eyre!("output device \"{name}\" not found")),
+        None => host
+            .default_output_device()
+            .ok_or_else(|| eyre!("no output device found")),
+    }
+}
+
+/// The device to capture the sink called `name` from, or the default sink.
+#[cfg(target_os = "linux")]
+pub fn select_device(host: &Host, name: Option<&str>) -> Result<Device> {
+    let sink = match name {
+        Some(name) => name.to_string(),
+        None => default_sink()?,
+    };
+    // Read by the plugin whenever a stream opens, reconnects included
+    std::env::set_var("PULSE_SOURCE", format!("{sink}.monitor"));
+    host.input_devices()
+        .wrap_err("failed to list input devices")?
+        .find(|device| device.name().is_ok_and(|n| n == PULSE_DEVICE))
+        .ok_or_else(|| {
+            eyre!(
+                "loopback needs PulseAudio or PipeWire, and their \"{PULSE_DEVICE}\" ALSA \
+                 device wasn't found"
+            )
+        })
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn select_device(_host: &Host, _name: Option<&str>) -> Result<Device> {
+    Err(eyre!(
+        "loopback isn't supported here; install a loopback driver such as BlackHole and \
+         pick it with --device"
+    ))
+}
+
+/// Stream config for capturing from `device`. An output device runs at its own
+/// rate in shared mode, so `sample_rate` is left to resampling.
+#[cfg(windows)]
+pub fn stream_config(device: &Device, _sample_rate: Option<u32>) -> Result<StreamConfig> {
+    let config = device
+        .default_output_config()
+        .wrap_err("failed to query the output device")?;
+    if config.sample_format() != cpal::SampleFormat::F32 {
+        return Err(eyre!("output device doesn't play 32-bit float"));
+    }
+    Ok(config.into())
+}
+
+/// Stream config for capturing from `device`, at `sample_rate` if it can.
+#[cfg(not(windows))]
+pub fn stream_config(device: &Device, sample_rate: Option<u32>) -> Result<StreamConfig> {
+    engine::input_stream_config(device, sample_rate)
+        .or_else(|_| engine::input_stream_config(device, None))
+}
+
+/// Name of the sink things play on by default.
+#[cfg(target_os = "linux")]
+fn default_sink() -> Result<String> {
+    let output = std::process::Command::new("pactl")
+        .arg("get-default-sink")
+        .output()
+        .wrap_err("failed to run pactl, is PulseAudio or PipeWire running?")?;
+    let sink = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || sink.is_empty() {
+        return Err(eyre!("pactl didn't name a default sink"));
+    }
+    Ok(sink)
+}
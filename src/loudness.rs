@@ -0,0 +1,180 @@
+//! Loudness of a take in the terms of EBU R128: integrated loudness, loudness
+//! range and true peak, along with the noise floor under it all.
+//!
+//! [`LoudnessMeter`] takes the audio a block at a time, so a take can be measured
+//! as it's recorded and reported as soon as it stops.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Result, WrapErr};
+use ebur128::{EbuR128, Mode};
+use serde::Serialize;
+
+use crate::dsp;
+
+/// Length of the windows the noise floor is picked from, in milliseconds.
+const NOISE_WINDOW_MS: u32 = 100;
+/// The noise floor is the level this fraction of the windows stay under.
+const NOISE_PERCENTILE: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LoudnessReport {
+    /// Negative infinity for takes shorter than a gating block (400 ms) or
+    /// entirely below the absolute gate.
+    pub integrated_lufs: f64,
+    pub loudness_range_lu: f64,
+    pub true_peak_dbtp: f64,
+    /// RMS level of the quiet stretches between words or notes.
+    pub noise_floor_dbfs: f32,
+}
+
+pub struct LoudnessMeter {
+    meter: EbuR128,
+    channels: usize,
+    window_samples: usize,
+    /// Sum of squares and sample count of the window being filled.
+    sum_squares: f64,
+    count: usize,
+    /// RMS level of each window so far, in dBFS.
+    windows: Vec<f32>,
+}
+
+impl fmt::Debug for LoudnessMeter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoudnessMeter")
+            .field("channels", &self.channels)
+            .field("windows", &self.windows.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: u16, sample_rate: u32) -> Result<Self> {
+        let channels = channels.max(1) as usize;
+        let mode = Mode::I | Mode::LRA | Mode::TRUE_PEAK;
+        let meter = EbuR128::new(channels as u32, sample_rate, mode)
+            .wrap_err("failed to set up loudness meter")?;
+        let window_frames = (sample_rate * NOISE_WINDOW_MS / 1000).max(1) as usize;
+        Ok(Self {
+            meter,
+            channels,
+            window_samples: window_frames * channels,
+            sum_squares: 0.0,
+            count: 0,
+            windows: Vec::new(),
+        })
+    }
+
+    /// Measures a block of interleaved samples, in whole frames.
+    pub fn add(&mut self, samples: &[f32]) -> Result<()> {
+        self.meter
+            .add_frames_f32(samples)
+            .wrap_err("failed to measure loudness")?;
+        let mut samples = samples;
+        while !samples.is_empty() {
+            let (taken, rest) =
+                samples.split_at((self.window_samples - self.count).min(samples.len()));
+            self.sum_squares += taken.iter().map(|&x| (x as f64).powi(2)).sum::<f64>();
+            self.count += taken.len();
+            if self.count == self.window_samples {
+                let rms = (self.sum_squares / self.count as f64).sqrt();
+                self.windows.push(dsp::to_dbfs(rms as f32));
+                self.sum_squares = 0.0;
+                self.count = 0;
+            }
+            samples = rest;
+        }
+        Ok(())
+    }
+
+    pub fn report(&self) -> LoudnessReport {
+        let true_peak = (0..self.channels as u32)
+            .filter_map(|channel| self.meter.true_peak(channel).ok())
+            .fold(0.0_f64, f64::max);
+        let mut windows = self.windows.clone();
+        windows.sort_by(f32::total_cmp);
+        let noise_floor = match windows.len() {
+            0 => dsp::to_dbfs((self.sum_squares / self.count.max(1) as f64).sqrt() as f32),
+            len => windows[((len - 1) as f32 * NOISE_PERCENTILE) as usize],
+        };
+        LoudnessReport {
+            integrated_lufs: self.meter.loudness_global().unwrap_or(f64::NEG_INFINITY),
+            loudness_range_lu: self.meter.loudness_range().unwrap_or(0.0),
+            true_peak_dbtp: 20.0 * true_peak.max(1e-10).log10(),
+            noise_floor_dbfs: noise_floor,
+        }
+    }
+}
+
+impl fmt::Display for LoudnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} LUFS, range {:.1} LU, true peak {:.1} dBTP, noise floor {:.0} dBFS",
+            self.integrated_lufs,
+            self.loudness_range_lu,
+            self.true_peak_dbtp,
+            self.noise_floor_dbfs
+        )
+    }
+}
+
+/// Writes `report` as `<file>-loudness.json` next to `recording`, and returns its
+/// path. An existing file is never overwritten.
+pub fn export(recording: &Path, report: &LoudnessReport) -> io::Result<PathBuf> {
+    let stem = recording.file_stem().unwrap_or_default().to_string_lossy();
+    let path = recording.with_file_name(format!("{stem}-loudness.json"));
+    let json = serde_json::to_string_pretty(report).map_err(io::Error::other)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    file.write_all(json.as_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48_000.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn measures_a_tone_in_blocks() {
+        let mut meter = LoudnessMeter::new(1, 48_000).unwrap();
+        for block in tone(0.5, 48_000 * 3).chunks(1000) {
+            meter.add(block).unwrap();
+        }
+        let report = meter.report();
+
+        // A 1 kHz sine at -6 dBFS peak, mono: about -9 LUFS
+        assert!((report.integrated_lufs - -9.0).abs() < 0.5, "{report:?}");
+        assert!((report.true_peak_dbtp - -6.0).abs() < 0.2, "{report:?}");
+        assert!(report.loudness_range_lu < 1.0);
+    }
+
+    #[test]
+    fn noise_floor_is_the_quiet_between_sound() {
+        let mut meter = LoudnessMeter::new(1, 48_000).unwrap();
+        let quiet = tone(0.001, 48_000);
+        let loud = tone(0.5, 48_000);
+        for block in [&quiet, &loud, &quiet, &loud] {
+            meter.add(block).unwrap();
+        }
+
+        // RMS of a sine at 0.001 peak
+        let floor = meter.report().noise_floor_dbfs;
+        assert!(
+            (floor - dsp::to_dbfs(0.001 / 2.0_f32.sqrt())).abs() < 0.5,
+            "{floor}"
+        );
+    }
+}
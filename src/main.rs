@@ -1,266 +1,678 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex};
-use std::{io, thread, time::Duration};
-
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
-use ratatui::{
-    buffer::Buffer,
-    layout::Rect,
-    style::Stylize,
-    text::Line,
-    widgets::{Block, Widget},
-    DefaultTerminal, Frame,
-};
-
-#[derive(Debug)]
-pub struct App {
-    bar_values: Arc<Mutex<Vec<f32>>>,
-    exit: bool,
-    recording: bool,
-    shutdown_tx: Option<Sender<()>>,
-    last_terminal_width: u16,
+use std::env;
+use std::io::{self, IsTerminal};
+use std::mem;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use clap::{Parser, Subcommand};
+use cpal::HostId;
+use ratatui::crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::prelude::CrosstermBackend;
+use ratatui::Terminal;
+
+use micrec::encoder::OutputFormat;
+use micrec::engine::{self, CaptureSource, OutputChannels, RecordingOptions, VadConfig};
+use micrec::markers::MarkerFormat;
+use micrec::network::StreamTarget;
+use micrec::pipe::{PipeFormat, PipeTarget};
+use micrec::project::ProjectFormat;
+use micrec::schedule;
+use micrec::source::Signal;
+use micrec::tags::Tags;
+use micrec::transcode::TranscodeFormat;
+use micrec::{analysis, decoder};
+
+use app::App;
+use config::{Config, ThemeName, VisualizationScale, VisualizationStyle};
+use control::ControlServer;
+use headless::Report;
+use meetings::MeetingApp;
+
+mod app;
+mod browser;
+mod config;
+mod control;
+mod headless;
+mod instance;
+mod latency;
+mod meetings;
+mod preflight;
+mod speech;
+mod theme;
+
+/// Record audio from the terminal.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Config file to use instead of ~/.config/micrec/config.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Audio system to record through, e.g. alsa or jack on Linux, wasapi or asio on Windows
+    #[arg(long, global = true)]
+    host: Option<String>,
+
+    /// List the audio systems micrec can record through, and exit
+    #[arg(long)]
+    list_hosts: bool,
+
+    /// Input device to record from, or an alias from the config. Repeat it to
+    /// record from the first of several that's plugged in
+    #[arg(long)]
+    device: Vec<String>,
+
+    /// Another input device, or alias, to record at the same time as --device.
+    /// Repeat it for more. Their channels follow the main device's in the same
+    /// file; there's no file per device yet
+    #[arg(long, value_name = "DEVICE", conflicts_with = "demo")]
+    add_device: Vec<String>,
+
+    /// Record a made-up signal instead of any device, to try micrec out without a
+    /// microphone
+    #[arg(
+        long,
+        value_name = "SIGNAL",
+        num_args = 0..=1,
+        default_missing_value = "speech",
+        conflicts_with = "device"
+    )]
+    demo: Option<Signal>,
+
+    /// Record the microphone, or what the computer is playing (WASAPI loopback on
+    /// Windows, the default sink's monitor on PulseAudio and PipeWire). With
+    /// loopback, --device names the output device or sink
+    #[arg(long, value_enum)]
+    capture: Option<CaptureSource>,
+
+    /// Directory recordings are saved into [default: ~/Recordings]
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// File name template for new recordings, with strftime fields and {take}, e.g.
+    /// "%Y-%m-%d/memo-{take}.wav"
+    #[arg(long)]
+    template: Option<String>,
+
+    /// Sample rate of the recording in Hz, resampled if the device can't capture at it
+    #[arg(long)]
+    sample_rate: Option<u32>,
+
+    /// How levels are drawn
+    #[arg(long, value_enum)]
+    visualization: Option<VisualizationStyle>,
+
+    /// How levels map to bar heights
+    #[arg(long, value_enum)]
+    visualization_scale: Option<VisualizationScale>,
+
+    /// Level at the bottom of the bars in dBFS, with the db scale (e.g. -60)
+    #[arg(long, allow_negative_numbers = true)]
+    visualization_floor: Option<f32>,
+
+    /// Scroll the bars through time, each one this long (e.g. 100ms)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    bar_history: Option<Duration>,
+
+    /// Colors of the bars and meters
+    #[arg(long, value_enum)]
+    theme: Option<ThemeName>,
+
+    /// File format to record in. Defaults to the extension of --output, if given
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Keep every input channel in the file, or mix them down to mono
+    #[arg(long, value_enum)]
+    output_channels: Option<OutputChannels>,
+
+    /// Also record the input before the gain, 12 dB down, to recover takes the gain
+    /// clips. Clipping in the audio interface can't be recovered
+    #[arg(long)]
+    safety_track: bool,
+
+    /// Flip the polarity of an input channel, numbered from 1. Can be repeated
+    #[arg(long = "invert", value_name = "CHANNEL", value_parser = clap::value_parser!(u16).range(1..=64))]
+    invert_channels: Vec<u16>,
+
+    /// Software input gain in dB, for quiet inputs
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_gain)]
+    gain: Option<f32>,
+
+    /// Round off peaks instead of clipping them when gain pushes them past full scale
+    #[arg(long)]
+    soft_limiter: bool,
+
+    /// Cut rumble below this frequency in Hz (e.g. 80)
+    #[arg(long, value_name = "HZ")]
+    high_pass: Option<f32>,
+
+    /// Mute the input while its level stays below this many dBFS (e.g. -50)
+    #[arg(long, value_name = "DBFS", allow_negative_numbers = true)]
+    gate: Option<f32>,
+
+    /// Also write an editor project next to the recording, with the take marked as
+    /// a region
+    #[arg(long, value_enum)]
+    project: Option<ProjectFormat>,
+
+    /// Also save a compressed copy of the recording once it's stopped, keeping the
+    /// original
+    #[arg(long, value_enum)]
+    transcode: Option<TranscodeFormat>,
+
+    /// Title to tag the recording with, in its INFO chunk or Vorbis comments
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Artist to tag the recording with
+    #[arg(long)]
+    artist: Option<String>,
+
+    /// Comment to tag the recording with, e.g. where it was made
+    #[arg(long)]
+    comment: Option<String>,
+
+    /// How markers placed with Enter are saved
+    #[arg(long, value_enum)]
+    markers: Option<MarkerFormat>,
+
+    /// Don't write a JSON file describing how each recording was made
+    #[arg(long)]
+    no_info_file: bool,
+
+    /// Write a JSON file with the loudness of each recording next to it
+    #[arg(long)]
+    loudness_file: bool,
+
+    /// Go through the pre-flight checklist (device, disk space, levels, headphones)
+    /// before recording
+    #[arg(long)]
+    preflight: bool,
+
+    /// Also write the take to stdout as 16 kHz mono s16le while recording, e.g. for
+    /// live transcription. The TUI is drawn on stderr instead
+    #[arg(long, conflicts_with = "pipe_to")]
+    pipe: bool,
+
+    /// Also stream the take to this shell command's stdin as 16 kHz mono s16le,
+    /// saving what it prints as <file>-transcript.txt
+    #[arg(long, value_name = "COMMAND")]
+    pipe_to: Option<String>,
+
+    /// Also write the take to FILE while recording, as raw interleaved f32le at the
+    /// recording's own rate and channels, e.g. for `ffmpeg -f f32le`. With `-` it
+    /// goes to stdout and the TUI is drawn on stderr
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["pipe", "pipe_to"])]
+    raw: Option<PathBuf>,
+
+    /// Also serve the take over the network while recording: to every client of
+    /// tcp://ADDRESS:PORT as a WAV stream (e.g. for `ffplay tcp://host:9000`), or
+    /// to udp://HOST:PORT as RTP
+    #[arg(long, value_name = "URL")]
+    stream: Option<StreamTarget>,
+
+    /// Record without the TUI, printing levels to stderr until Ctrl-C
+    #[arg(long)]
+    headless: bool,
+
+    /// Print text meters line by line instead of drawing the TUI, for dumb
+    /// terminals, serial consoles and CI logs. The default when TERM is dumb or unset
+    #[arg(long, conflicts_with = "headless")]
+    plain: bool,
+
+    /// File to record to, instead of one named from the template. An existing file
+    /// is never overwritten; a numbered suffix is added instead
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Keep only the last this much (e.g. 2h) in a circular buffer on disk, and
+    /// save clips of it with a key instead of recording everything
+    #[arg(long, value_parser = parse_seconds)]
+    buffer: Option<Duration>,
+
+    /// Length of the clips saved from the buffer (e.g. 5m)
+    #[arg(long, value_parser = parse_seconds)]
+    clip_length: Option<Duration>,
+
+    /// Start each recording with this much (e.g. 5s) of what came before it. Takes
+    /// wait for a key while keeping it, Enter when headless, unless --start-at is
+    /// given
+    #[arg(long, value_parser = parse_seconds)]
+    pre_roll: Option<Duration>,
+
+    /// Arm and start recording at this time (e.g. 14:30, or 2024-05-17 14:30)
+    #[arg(long, value_parser = schedule::parse_start_time)]
+    start_at: Option<DateTime<Local>>,
+
+    /// Stop recording after this long (e.g. 90s, 5m, 1h30m)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+
+    /// Stop recording once it has been quiet this long (e.g. 5s), after something
+    /// has been said
+    #[arg(long, value_parser = humantime::parse_duration)]
+    stop_after_silence: Option<Duration>,
+
+    /// Length to aim for (e.g. 5m), shown as a progress bar while recording
+    #[arg(long, value_parser = humantime::parse_duration)]
+    target: Option<Duration>,
+
+    /// Speak the peak level and elapsed time this often (e.g. 30s), for recording
+    /// away from the screen
+    #[arg(long, value_parser = parse_seconds)]
+    speak_every: Option<Duration>,
+
+    /// Join a session with other instances started with --sync. The first one
+    /// becomes the master, and stopping or quitting it does the same for the rest
+    #[arg(long)]
+    sync: bool,
+
+    /// Take OSC messages such as /micrec/stop on this UDP address (e.g.
+    /// 127.0.0.1:9000). Needs the osc feature
+    #[arg(long, value_name = "ADDRESS")]
+    osc: Option<SocketAddr>,
+
+    /// Take control changes from the MIDI input whose name contains this. Needs the
+    /// midi feature
+    #[arg(long, value_name = "NAME")]
+    midi: Option<String>,
 }
 
-impl Default for App {
-    fn default() -> Self {
-        Self {
-            bar_values: Arc::new(Mutex::new(vec![0.0; 50])), // Start with fewer bars
-            exit: false,
-            recording: true,
-            shutdown_tx: None,
-            last_terminal_width: 0,
+impl Cli {
+    /// Overrides config values with any flags given on the command line.
+    fn apply_to(&mut self, config: &mut Config) {
+        if let Some(host) = self.host.take() {
+            config.host = Some(host);
+        }
+        if !self.device.is_empty() {
+            config.device = mem::take(&mut self.device);
+        }
+        if !self.add_device.is_empty() {
+            config.extra_devices = mem::take(&mut self.add_device);
+        }
+        if let Some(capture) = self.capture.take() {
+            config.capture = capture;
+        }
+        if let Some(output_dir) = self.output_dir.take() {
+            config.output_dir = output_dir;
+        }
+        if let Some(template) = self.template.take() {
+            config.file_template = template;
+        }
+        if let Some(sample_rate) = self.sample_rate.take() {
+            config.sample_rate = Some(sample_rate);
+        }
+        if let Some(visualization) = self.visualization.take() {
+            config.visualization = visualization;
+        }
+        if let Some(scale) = self.visualization_scale.take() {
+            config.visualization_scale = scale;
+        }
+        if let Some(floor) = self.visualization_floor.take() {
+            config.visualization_floor_dbfs = floor;
+        }
+        if let Some(history) = self.bar_history.take() {
+            config.bar_history_ms = history.as_millis() as u64;
+        }
+        if let Some(theme) = self.theme.take() {
+            config.theme = theme;
+        }
+        if let Some(format) = self
+            .format
+            .take()
+            .or_else(|| self.output.as_deref().and_then(OutputFormat::from_path))
+        {
+            config.format = format;
+        }
+        if let Some(output_channels) = self.output_channels.take() {
+            config.output_channels = output_channels;
+        }
+        if self.safety_track {
+            config.safety_track = true;
+        }
+        if !self.invert_channels.is_empty() {
+            config.invert_channels = self.invert_channels.drain(..).map(usize::from).collect();
+        }
+        if let Some(gain) = self.gain.take() {
+            config.gain_db = gain;
+        }
+        if self.soft_limiter {
+            config.soft_limiter = true;
+        }
+        if let Some(cutoff) = self.high_pass.take() {
+            config.high_pass = true;
+            config.high_pass_hz = cutoff;
+        }
+        if let Some(threshold) = self.gate.take() {
+            config.noise_gate = true;
+            config.gate_threshold_dbfs = threshold;
+        }
+        if let Some(format) = self.markers.take() {
+            config.marker_format = format;
+        }
+        if let Some(project) = self.project.take() {
+            config.project = Some(project);
+        }
+        if let Some(format) = self.transcode.take() {
+            config.transcode = Some(format);
+        }
+        if self.no_info_file {
+            config.info_file = false;
+        }
+        if self.loudness_file {
+            config.loudness_file = true;
+        }
+        if let Some(command) = self.pipe_to.take() {
+            config.pipe_command = Some(command);
+        }
+        if self.preflight {
+            config.preflight.enabled = true;
+        }
+        if let Some(address) = self.osc.take() {
+            config.control.osc_listen = Some(address);
+        }
+        if let Some(name) = self.midi.take() {
+            config.control.midi_input = Some(name);
+        }
+        if let Some(speak_every) = self.speak_every.take() {
+            config.speak_interval_secs = speak_every.as_secs();
+        }
+        if let Some(buffer) = self.buffer.take() {
+            config.buffer_secs = buffer.as_secs();
+        }
+        if let Some(clip_length) = self.clip_length.take() {
+            config.clip_secs = clip_length.as_secs();
+        }
+        if let Some(pre_roll) = self.pre_roll.take() {
+            config.pre_roll_secs = pre_roll.as_secs();
+        }
+        if let Some(target) = self.target.take() {
+            config.target_secs = Some(target.as_secs());
         }
     }
 }
 
-impl App {
-    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
-        let (audio_tx, audio_rx) = channel::<Arc<[f32]>>();
-        let (shutdown_tx, shutdown_rx) = channel::<()>();
-
-        self.shutdown_tx = Some(shutdown_tx);
-
-        let audio_thread = thread::spawn(move || {
-            record_audio(audio_tx, shutdown_rx);
-        });
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Measure round-trip latency by playing clicks and listening for them on the input
+    Latency,
+    /// Review an existing recording, or analyze it with --analyze
+    Play {
+        /// Audio file to open (WAV, FLAC, Ogg Vorbis). A WAV still being recorded opens
+        /// with everything captured up to about a second ago
+        file: PathBuf,
+
+        /// Print levels, loudness, clipping and a spectrogram instead of opening the player
+        #[arg(long)]
+        analyze: bool,
+    },
+    /// Wait for Zoom, Google Meet or Microsoft Teams meetings and offer to record them
+    Watch {
+        /// Start recording as soon as a meeting is detected, without asking
+        #[arg(long)]
+        auto: bool,
+    },
+}
 
-        while !self.exit {
-            while let Ok(samples) = audio_rx.try_recv() {
-                self.process_audio_samples(&samples);
-            }
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let mut cli = Cli::parse();
 
-            terminal.draw(|frame| self.draw(frame))?;
+    let mut config = Config::load(cli.config.as_deref())?;
+    let command = cli.command.take();
+    cli.apply_to(&mut config);
 
-            if crossterm::event::poll(Duration::from_millis(16))? {
-                self.handle_events()?;
+    if cli.list_hosts {
+        list_hosts();
+        return Ok(());
+    }
+    match command {
+        Some(Command::Latency) => latency::run(host(&config)?),
+        Some(Command::Play { file, analyze }) => {
+            let audio = decoder::decode_file(&file)?;
+            if analyze {
+                print!("{}", analysis::analyze(&audio)?);
+                return Ok(());
             }
+            run_tui(App::review(config, file, audio))
         }
-
-        if let Some(tx) = &self.shutdown_tx {
-            tx.send(()).ok();
+        Some(Command::Watch { auto }) => {
+            meetings::watch(auto, |meeting| record(&config, &cli, Some(meeting)))
         }
-        audio_thread.join().ok();
-
-        Ok(())
+        None => record(&config, &cli, None),
     }
+}
 
-    fn draw(&mut self, frame: &mut Frame) {
-        // Check if terminal width changed and update bar count
-        let current_width = frame.area().width;
-        if current_width != self.last_terminal_width {
-            self.update_bar_count(current_width);
-            self.last_terminal_width = current_width;
+/// Prints the hosts this build supports, marking the default and any that can't be
+/// used right now.
+fn list_hosts() {
+    let default = cpal::default_host().id();
+    let available = cpal::available_hosts();
+    for id in cpal::ALL_HOSTS {
+        let mut line = id.name().to_lowercase();
+        if *id == default {
+            line.push_str(" (default)");
         }
-        frame.render_widget(&*self, frame.area());
-    }
-
-    fn update_bar_count(&mut self, terminal_width: u16) {
-        // Calculate optimal bar count based on terminal width
-        // Account for border and spacing: 2 chars per bar (bar + gap), minus some padding
-        let usable_width = terminal_width.saturating_sub(4); // Account for borders
-        let optimal_bar_count = (usable_width / 2).max(10) as usize; // Minimum 10 bars
-
-        if let Ok(mut bars) = self.bar_values.lock() {
-            bars.resize(optimal_bar_count, 0.0);
+        if !available.contains(id) {
+            line.push_str(" (not available)");
         }
+        println!("{line}");
     }
+}
 
-    fn exit(&mut self) {
-        self.exit = true;
-    }
+/// The audio host chosen in the config, if any.
+fn host(config: &Config) -> color_eyre::Result<Option<HostId>> {
+    config.host.as_deref().map(engine::find_host).transpose()
+}
 
-    fn handle_key_event(&mut self, key_event: KeyEvent) {
-        match key_event.code {
-            KeyCode::Char(' ') if self.recording => self.stop_recording(),
-            KeyCode::Char('q') => self.exit(),
-            _ => {}
-        }
+/// The device chosen in the config, with aliases resolved. Of several, the first
+/// that's plugged in.
+fn device(config: &Config, host: Option<HostId>) -> color_eyre::Result<Option<String>> {
+    match config.device_names().as_slice() {
+        [] => Ok(None),
+        [name] => Ok(Some(name.clone())),
+        names => engine::first_available_device(host, config.capture, names).map(Some),
     }
+}
 
-    fn stop_recording(&mut self) {
-        if let Some(tx) = &self.shutdown_tx {
-            tx.send(()).ok();
-        }
-        self.recording = false;
+/// Records a take with the TUI, or headless when asked to. A `meeting` being
+/// recorded titles the take, unless `--title` does.
+fn record(config: &Config, cli: &Cli, meeting: Option<MeetingApp>) -> color_eyre::Result<()> {
+    // An explicit output file is just a template without placeholders, once any
+    // `%` is escaped
+    let (output_dir, file_template) = match &cli.output {
+        Some(output) => (
+            output
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+            output
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .replace('%', "%%"),
+        ),
+        None => (config.output_dir.clone(), config.file_template.clone()),
+    };
+    let host = host(config)?;
+    let device = match cli.demo {
+        Some(_) => None,
+        None => device(config, host)?,
+    };
+    let options = RecordingOptions {
+        demo: cli.demo,
+        host,
+        capture: config.capture,
+        device: device.clone(),
+        extra_devices: match cli.demo {
+            Some(_) => Vec::new(),
+            None => config.extra_device_names(),
+        },
+        sample_rate: config.sample_rate,
+        output_dir,
+        file_template,
+        after_take: 0,
+        format: config.format,
+        output_channels: config.output_channels,
+        safety_track: config.safety_track,
+        inverted_channels: config
+            .invert_channels
+            .iter()
+            .filter_map(|channel| channel.checked_sub(1))
+            .collect(),
+        gain_db: config.gain_db,
+        soft_limit: config.soft_limiter,
+        high_pass: config.high_pass,
+        high_pass_hz: config.high_pass_hz,
+        noise_gate: config.noise_gate,
+        gate_threshold_dbfs: config.gate_threshold_dbfs,
+        project: config.project,
+        marker_format: config.marker_format,
+        provenance: config.info_file,
+        transcode: config.transcode,
+        tags: Tags {
+            title: cli
+                .title
+                .clone()
+                .or_else(|| meeting.map(|meeting| format!("{meeting} meeting"))),
+            artist: cli.artist.clone(),
+            comment: cli.comment.clone(),
+        },
+        loudness_file: config.loudness_file,
+        buffer: (config.buffer_secs > 0).then(|| Duration::from_secs(config.buffer_secs)),
+        min_free_space: (config.min_free_space_mb > 0)
+            .then(|| config.min_free_space_mb * 1024 * 1024),
+        pipe: match &cli.raw {
+            Some(path) if path == Path::new("-") => Some(PipeTarget::Stdout),
+            Some(path) => Some(PipeTarget::File(path.clone())),
+            None if cli.pipe => Some(PipeTarget::Stdout),
+            None => config.pipe_command.clone().map(PipeTarget::Command),
+        },
+        pipe_format: match cli.raw {
+            Some(_) => PipeFormat::Raw,
+            None => PipeFormat::Speech,
+        },
+        stream: cli.stream,
+        start_at: cli.start_at,
+        pre_roll: (config.pre_roll_secs > 0).then(|| Duration::from_secs(config.pre_roll_secs)),
+        standby: false,
+        duration: cli.duration,
+        // Nothing playing is no sign of a problem when recording the output
+        silence_check: (config.silence_check_secs > 0
+            && config.capture == CaptureSource::Microphone)
+            .then(|| Duration::from_secs(config.silence_check_secs)),
+        vad: cli
+            .stop_after_silence
+            .or_else(|| {
+                (config.stop_after_silence_secs > 0)
+                    .then(|| Duration::from_secs(config.stop_after_silence_secs))
+            })
+            .map(|stop_after| VadConfig {
+                threshold_dbfs: config.silence_threshold_dbfs,
+                stop_after,
+            }),
+    };
+    // Other instances are worth knowing about, but not worth failing the take over
+    let (instance, peers, registered) = match instance::register(device.as_deref(), cli.sync) {
+        Ok((instance, peers)) => (instance, peers, Ok(())),
+        Err(err) => (instance::Instance::standalone(), Vec::new(), Err(err)),
+    };
+    let mut warnings = instance::contention_warnings(&peers, device.as_deref());
+    if let Err(err) = registered {
+        warnings.push(format!(
+            "Couldn't look for other instances, recording on its own: {err}"
+        ));
     }
-
-    fn process_audio_samples(&mut self, samples: &[f32]) {
-        if let Ok(mut bars) = self.bar_values.lock() {
-            let chunk_size = samples.len() / bars.len();
-            if chunk_size == 0 {
-                return;
-            }
-
-            let num_bars = bars.len();
-            for (i, bar_value) in bars.iter_mut().enumerate() {
-                let start = i * chunk_size;
-                let end = if i == num_bars - 1 {
-                    samples.len()
-                } else {
-                    (i + 1) * chunk_size
-                };
-
-                let chunk = &samples[start..end];
-                let rms = (chunk.iter().map(|&x| x * x).sum::<f32>() / chunk.len() as f32).sqrt();
-                let target_value = (rms * 10.0).min(1.0);
-
-                // Asymmetric smoothing: fast rise, slow decay
-                if target_value > *bar_value {
-                    // Rising: respond quickly to peaks (low smoothing)
-                    let rise_smoothing = 0.1;
-                    *bar_value =
-                        *bar_value * rise_smoothing + target_value * (1.0 - rise_smoothing);
-                } else {
-                    // Falling: decay slowly for smooth animation (high smoothing)
-                    let decay_smoothing = 0.65;
-                    *bar_value =
-                        *bar_value * decay_smoothing + target_value * (1.0 - decay_smoothing);
-                }
-            }
-        }
+    // The keys still work without it
+    let control = ControlServer::start(&config.control).unwrap_or_else(|err| {
+        warnings.push(format!("Remote control is off: {err:#}"));
+        None
+    });
+
+    // With the audio on stdout, there's only stderr left to draw on
+    let audio_on_stdout = options.pipe == Some(PipeTarget::Stdout);
+    let report = if cli.headless || (audio_on_stdout && !io::stderr().is_terminal()) {
+        Some(Report::Levels)
+    } else if cli.plain || dumb_terminal() {
+        Some(Report::Meters)
+    } else {
+        None
+    };
+    if let Some(report) = report {
+        let speak_every = (config.speak_interval_secs > 0)
+            .then(|| Duration::from_secs(config.speak_interval_secs));
+        return headless::run(options, instance, control, warnings, speak_every, report);
     }
-
-    fn handle_events(&mut self) -> io::Result<()> {
-        match event::read()? {
-            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                self.handle_key_event(key_event)
-            }
-            Event::Resize(_, _) => {
-                // Terminal resize will be handled in the next draw call
-            }
-            _ => {}
-        };
-        Ok(())
+    let app = App::new(config.clone(), options, instance, control, warnings);
+    if audio_on_stdout {
+        run_tui_on_stderr(app)
+    } else {
+        run_tui(app)
     }
 }
 
-impl Widget for &App {
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        let instructions = Line::from(vec![
-            " Stop ".into(),
-            "<Space>".blue().bold(),
-            " Quit ".into(),
-            "<q> ".blue().bold(),
-        ]);
-
-        let status = if self.recording {
-            " Recording...".red().bold()
-        } else {
-            " Processing...".green().bold()
-        };
-
-        let block = Block::new()
-            .title_bottom(Line::from(status).left_aligned())
-            .title_bottom(instructions.right_aligned());
-
-        let inner = block.inner(area);
-        block.render(area, buf);
-
-        let bar_values = self.bar_values.lock().unwrap();
-
-        let center_y = inner.y + inner.height / 2;
-        let max_bar_height = (inner.height / 2).saturating_sub(3);
-
-        let available_width = inner.width;
-        let bar_spacing = 2; // 1 char for bar + 1 char gap
-        let num_bars = bar_values.len() as u16;
-
-        if num_bars == 0 || available_width < bar_spacing {
-            return; // No bars to render or terminal too small
-        }
-
-        // Calculate starting position to center all bars
-        // Note: we don't need the gap after the last bar, so subtract 1 from total width
-        let total_width = (num_bars * bar_spacing).saturating_sub(1);
-        let start_x = inner.x + (available_width.saturating_sub(total_width)) / 2;
-
-        for (i, &value) in bar_values.iter().enumerate() {
-            let bar_x = start_x + (i as u16 * bar_spacing);
-
-            // Ensure bar is within bounds
-            if bar_x >= inner.x + inner.width {
-                break;
-            }
-
-            let bar_height = (value * max_bar_height as f32) as u16;
-
-            let brightness = ((value + 0.1) * 255.0) as u8;
-            let bar_color = ratatui::style::Color::Rgb(brightness, brightness, brightness);
-
-            for j in 0..bar_height {
-                if center_y >= inner.y + j + 1 {
-                    buf[(bar_x, center_y - j - 1)]
-                        .set_char('█')
-                        .set_fg(bar_color);
-                }
-                if center_y + j + 1 < inner.y + inner.height {
-                    buf[(bar_x, center_y + j + 1)]
-                        .set_char('█')
-                        .set_fg(bar_color);
-                }
-            }
-
-            buf[(bar_x, center_y)]
-                .set_char('█')
-                .set_fg(ratatui::style::Color::Rgb(50, 50, 50));
-        }
+/// Parses `--gain`, which has to be a number: NaN would silence the whole take.
+fn parse_gain(value: &str) -> Result<f32, String> {
+    let gain: f32 = value.parse().map_err(|err| format!("{err}"))?;
+    if gain.is_finite() {
+        Ok(gain)
+    } else {
+        Err(String::from("expected a number of dB"))
     }
 }
 
-fn record_audio(ui_tx: Sender<Arc<[f32]>>, shutdown_rx: Receiver<()>) {
-    let host = cpal::default_host();
-    let device = host.default_input_device().unwrap();
-    let config = device.default_input_config().unwrap();
-
-    let stream = device
-        .build_input_stream(
-            &config.into(),
-            move |data: &[f32], _| {
-                if data.is_empty() {
-                    return;
-                }
-
-                let arc: Arc<[f32]> = Arc::from(data);
-                ui_tx.send(arc).ok();
-            },
-            |err| eprintln!("Audio error: {}", err),
-            None,
-        )
-        .unwrap();
-
-    stream.play().unwrap();
-
-    while shutdown_rx.try_recv().is_err() {
-        thread::sleep(Duration::from_millis(10));
+/// Parses a length the config keeps in seconds, which has to be a whole number of
+/// them: anything else would be cut down, and under a second to 0, which is off.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let duration = humantime::parse_duration(value).map_err(|err| format!("{err}"))?;
+    if duration.subsec_nanos() == 0 && duration >= Duration::from_secs(1) {
+        Ok(duration)
+    } else {
+        Err(String::from("expected a whole number of seconds, e.g. 5s"))
     }
+}
 
-    drop(stream);
+/// Whether the terminal can't move the cursor around, which the TUI needs.
+fn dumb_terminal() -> bool {
+    cfg!(unix) && env::var("TERM").map_or(true, |term| term.is_empty() || term == "dumb")
 }
 
-fn main() -> io::Result<()> {
+fn run_tui(mut app: App) -> color_eyre::Result<()> {
+    let mouse = app.wants_mouse();
     let mut terminal = ratatui::init();
-    let result = App::default().run(&mut terminal);
+    if mouse {
+        execute!(io::stdout(), EnableMouseCapture)?;
+    }
+    let result = app.run(&mut terminal);
+    if mouse {
+        execute!(io::stdout(), DisableMouseCapture)?;
+    }
     ratatui::restore();
-    result
+    Ok(result?)
+}
+
+/// Like [`run_tui`], for when stdout carries audio.
+fn run_tui_on_stderr(mut app: App) -> color_eyre::Result<()> {
+    let mouse = app.wants_mouse();
+    terminal::enable_raw_mode()?;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+    if mouse {
+        execute!(io::stderr(), EnableMouseCapture)?;
+    }
+    let result = Terminal::new(CrosstermBackend::new(io::stderr()))
+        .and_then(|mut terminal| app.run(&mut terminal));
+    if mouse {
+        execute!(io::stderr(), DisableMouseCapture)?;
+    }
+    terminal::disable_raw_mode()?;
+    execute!(io::stderr(), LeaveAlternateScreen)?;
+    Ok(result?)
 }
@@ -0,0 +1,242 @@
+//! Markers dropped while recording, saved as a cue sheet, JSON or cue chunks in
+//! the WAV file itself.
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::encoder::OutputFormat;
+use crate::riff::{self, append_chunk};
+
+/// CD frames per second, the finest position a cue sheet can express.
+const CUE_FRAMES_PER_SEC: u64 = 75;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerFormat {
+    /// Cue sheet (`<file>.cue`), one track per marker
+    #[default]
+    Cue,
+    /// JSON (`<file>-markers.json`) with times in seconds and sample frames
+    Json,
+    /// Cue points inside the WAV file, read by most audio editors. Other formats
+    /// get a cue sheet instead
+    Wav,
+}
+
+/// A point in the recording, counted in sample frames from the start.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Marker {
+    pub label: String,
+    pub frame: u64,
+    pub seconds: f64,
+}
+
+impl Marker {
+    pub fn new(number: usize, frame: u64, sample_rate: u32) -> Self {
+        Self {
+            label: format!("Marker {number}"),
+            frame,
+            seconds: frame as f64 / sample_rate.max(1) as f64,
+        }
+    }
+
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.seconds)
+    }
+}
+
+#[derive(Serialize)]
+struct MarkerFile<'a> {
+    file: &'a str,
+    sample_rate: u32,
+    markers: &'a [Marker],
+}
+
+/// Saves `markers` for `recording` in `format`, and returns the file they went
+/// into. Sidecar files are never overwritten.
+pub fn export(
+    format: MarkerFormat,
+    recording: &Path,
+    markers: &[Marker],
+    sample_rate: u32,
+) -> io::Result<PathBuf> {
+    let stem = recording.file_stem().unwrap_or_default().to_string_lossy();
+    let name = recording.file_name().unwrap_or_default().to_string_lossy();
+    let is_wav = OutputFormat::from_path(recording) == Some(OutputFormat::Wav);
+    let (path, contents) = match format {
+        MarkerFormat::Wav if is_wav => {
+            append_wav_cues(recording, markers)?;
+            return Ok(recording.to_path_buf());
+        }
+        MarkerFormat::Cue | MarkerFormat::Wav => (
+            recording.with_file_name(format!("{stem}.cue")),
+            cue_sheet(&name, markers),
+        ),
+        MarkerFormat::Json => {
+            let file = MarkerFile {
+                file: &name,
+                sample_rate,
+                markers,
+            };
+            let json = serde_json::to_string_pretty(&file).map_err(io::Error::other)?;
+            (
+                recording.with_file_name(format!("{stem}-markers.json")),
+                json + "\n",
+            )
+        }
+    };
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(path)
+}
+
+/// A cue sheet splitting the recording into tracks at each marker, with a first
+/// track covering the start.
+pub fn cue_sheet(file_name: &str, markers: &[Marker]) -> String {
+    let mut cue = format!("FILE \"{}\" WAVE\n", file_name.replace('"', "'"));
+    let start = Marker {
+        label: String::from("Start"),
+        frame: 0,
+        seconds: 0.0,
+    };
+    let tracks = std::iter::once(&start).chain(markers.iter().filter(|m| m.frame > 0));
+    for (number, marker) in tracks.enumerate() {
+        let frames = (marker.seconds * CUE_FRAMES_PER_SEC as f64) as u64;
+        let seconds = frames / CUE_FRAMES_PER_SEC;
+        let (minutes, seconds) = (seconds / 60, seconds % 60);
+        writeln!(cue, "  TRACK {:02} AUDIO", number + 1).unwrap();
+        writeln!(cue, "    TITLE \"{}\"", marker.label.replace('"', "'")).unwrap();
+        writeln!(
+            cue,
+            "    INDEX 01 {minutes:02}:{seconds:02}:{:02}",
+            frames % CUE_FRAMES_PER_SEC
+        )
+        .unwrap();
+    }
+    cue
+}
+
+/// Appends a `cue ` chunk and an `adtl` list naming each point to a finished WAV
+/// file, and updates its RIFF header to match.
+pub fn append_wav_cues(path: &Path, markers: &[Marker]) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a WAV file", path.display()),
+        ));
+    }
+
+    let mut cue = Vec::new();
+    cue.extend_from_slice(&(markers.len() as u32).to_le_bytes());
+    let mut labels = Vec::from(*b"adtl");
+    for (id, marker) in (1_u32..).zip(markers) {
+        let frame = u32::try_from(marker.frame).unwrap_or(u32::MAX);
+        cue.extend_from_slice(&id.to_le_bytes());
+        cue.extend_from_slice(&frame.to_le_bytes());
+        cue.extend_from_slice(b"data");
+        cue.extend_from_slice(&[0; 8]); // Chunk and block start, for compressed data
+        cue.extend_from_slice(&frame.to_le_bytes());
+
+        let mut text = id.to_le_bytes().to_vec();
+        text.extend_from_slice(marker.label.as_bytes());
+        text.push(0);
+        append_chunk(&mut labels, b"labl", &text);
+    }
+    let mut chunks = Vec::new();
+    append_chunk(&mut chunks, b"cue ", &cue);
+    append_chunk(&mut chunks, b"LIST", &labels);
+
+    riff::append_to_file(&mut file, &chunks, "cues")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::process;
+
+    use super::*;
+
+    /// Reads the cue points back out of a WAV file, as `(id, frame)` pairs.
+    fn read_wav_cues(path: &Path) -> io::Result<Vec<(u32, u32)>> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+
+        let mut at = 12;
+        while at + 8 <= bytes.len() {
+            let size = u32_at(at + 4) as usize;
+            if &bytes[at..at + 4] == b"cue " {
+                let count = u32_at(at + 8) as usize;
+                return Ok((0..count)
+                    .map(|i| at + 12 + i * 24)
+                    .filter(|&point| point + 24 <= bytes.len())
+                    .map(|point| (u32_at(point), u32_at(point + 20)))
+                    .collect());
+            }
+            at += 8 + size + size % 2;
+        }
+        Ok(Vec::new())
+    }
+
+    fn markers() -> Vec<Marker> {
+        vec![
+            Marker::new(1, 96_000, 48_000),
+            Marker::new(2, 3_000_000, 48_000),
+        ]
+    }
+
+    #[test]
+    fn cue_sheet_starts_a_track_at_each_marker() {
+        assert_eq!(
+            cue_sheet("memo.wav", &markers()),
+            "FILE \"memo.wav\" WAVE\n\
+             \x20 TRACK 01 AUDIO\n    TITLE \"Start\"\n    INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n    TITLE \"Marker 1\"\n    INDEX 01 00:02:00\n\
+             \x20 TRACK 03 AUDIO\n    TITLE \"Marker 2\"\n    INDEX 01 01:02:37\n"
+        );
+    }
+
+    #[test]
+    fn wav_keeps_playing_with_cues_appended() {
+        let path = std::env::temp_dir().join(format!("micrec-cues-{}.wav", process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..480 {
+            writer.write_sample(1000_i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let markers = [Marker::new(1, 100, 48_000), Marker::new(2, 300, 48_000)];
+        export(MarkerFormat::Wav, &path, &markers, 48_000).unwrap();
+        let cues = read_wav_cues(&path).unwrap();
+        let samples = hound::WavReader::open(&path)
+            .unwrap()
+            .samples::<i16>()
+            .count();
+        let riff_size = u32::from_le_bytes(fs::read(&path).unwrap()[4..8].try_into().unwrap());
+        let file_size = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(cues, [(1, 100), (2, 300)]);
+        assert_eq!(samples, 480);
+        assert_eq!(u64::from(riff_size), file_size - 8);
+    }
+}
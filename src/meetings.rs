@@ -0,0 +1,167 @@
+//! Watches for video meetings starting so a recording is never forgotten.
+//!
+//! Two signals are checked, whichever the desktop provides:
+//! - applications capturing the microphone, from `pactl list source-outputs`
+//!   (PulseAudio, or PipeWire's pulse server)
+//! - window titles, from `wmctrl -l` (X11 and XWayland windows)
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+
+/// How often the desktop is checked for meetings.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeetingApp {
+    Zoom,
+    Meet,
+    Teams,
+}
+
+impl fmt::Display for MeetingApp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MeetingApp::Zoom => "Zoom",
+            MeetingApp::Meet => "Google Meet",
+            MeetingApp::Teams => "Microsoft Teams",
+        })
+    }
+}
+
+/// Waits for meetings and offers to record each one, calling `record` with the
+/// meeting's app to do it.
+///
+/// With `auto`, recording starts without asking. After a recording, the same
+/// meeting isn't offered again until it has ended.
+pub fn watch(auto: bool, mut record: impl FnMut(MeetingApp) -> Result<()>) -> Result<()> {
+    println!("Watching for Zoom, Google Meet and Microsoft Teams meetings (Ctrl-C to stop)");
+    let mut handled: Option<MeetingApp> = None;
+
+    loop {
+        match detect() {
+            Some(meeting) if handled != Some(meeting) => {
+                handled = Some(meeting);
+                if auto || confirm(&format!("{meeting} meeting detected. Record it? [Y/n] "))? {
+                    record(meeting)?;
+                    println!("Recording finished, watching for the next meeting");
+                }
+            }
+            Some(_) => {}
+            None => handled = None,
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The meeting currently in progress, if any.
+pub fn detect() -> Option<MeetingApp> {
+    let streams = command_lines("pactl", &["list", "source-outputs"]);
+    let windows = command_lines("wmctrl", &["-l"]);
+
+    streams
+        .iter()
+        .filter_map(|line| line.trim().strip_prefix("application.name = "))
+        .find_map(classify_stream)
+        .or_else(|| windows.iter().find_map(|line| classify_window(line)))
+}
+
+/// Meeting app behind a microphone stream's `application.name`.
+fn classify_stream(name: &str) -> Option<MeetingApp> {
+    let name = name.trim_matches('"').to_lowercase();
+    if name.contains("zoom") {
+        Some(MeetingApp::Zoom)
+    } else if name.contains("teams") {
+        Some(MeetingApp::Teams)
+    } else {
+        None
+    }
+}
+
+/// Meeting app behind a window title. Only in-meeting titles count, not an idle
+/// Zoom or Teams main window.
+fn classify_window(title: &str) -> Option<MeetingApp> {
+    if title.contains("Zoom Meeting") || title.contains("Zoom Webinar") {
+        Some(MeetingApp::Zoom)
+    } else if title.contains("Meet - ") || title.contains("Google Meet") {
+        Some(MeetingApp::Meet)
+    } else if title.contains("Microsoft Teams")
+        && (title.contains("Meeting") || title.contains("Call"))
+    {
+        Some(MeetingApp::Teams)
+    } else {
+        None
+    }
+}
+
+/// Stdout lines of a helper command, or nothing if it isn't installed.
+fn command_lines(program: &str, args: &[&str]) -> Vec<String> {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn confirm(prompt: &str) -> io::Result<bool> {
+    print!("{prompt}");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer.is_empty() || answer == "y" || answer == "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_microphone_streams() {
+        assert_eq!(
+            classify_stream("\"ZOOM VoiceEngine\""),
+            Some(MeetingApp::Zoom)
+        );
+        assert_eq!(
+            classify_stream("\"Microsoft Teams - Preview\""),
+            Some(MeetingApp::Teams)
+        );
+        assert_eq!(classify_stream("\"Firefox\""), None);
+    }
+
+    #[test]
+    fn only_meeting_windows_count() {
+        let window = |title: &str| classify_window(&format!("0x04a00007  0 host {title}"));
+        assert_eq!(window("Zoom"), None);
+        assert_eq!(window("Zoom Workplace"), None);
+        assert_eq!(window("Zoom Meeting"), Some(MeetingApp::Zoom));
+        assert_eq!(window("Zoom Webinar"), Some(MeetingApp::Zoom));
+        assert_eq!(
+            window("Meet - abc-defg-hij - Mozilla Firefox"),
+            Some(MeetingApp::Meet)
+        );
+        assert_eq!(window("Chat | Microsoft Teams"), None);
+        assert_eq!(
+            window("Meeting with Sam | Microsoft Teams"),
+            Some(MeetingApp::Teams)
+        );
+        assert_eq!(
+            window("Call with Sam | Microsoft Teams"),
+            Some(MeetingApp::Teams)
+        );
+        assert_eq!(window("Inbox - Mozilla Thunderbird"), None);
+    }
+}
@@ -0,0 +1,234 @@
+//! Recording from several input devices at once, e.g. a host's mic and a guest's
+//! USB mic. Each extra device runs a stream of its own, and its audio is lined up
+//! with the blocks of the take's main device and added as more channels.
+//!
+//! Like the main device's, each stream's callback only copies into a
+//! [`CaptureQueue`]; lining the audio up happens on the capture thread.
+
+use std::collections::VecDeque;
+
+use color_eyre::eyre::{Result, WrapErr};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Host, Stream};
+
+use crate::dsp;
+use crate::engine;
+use crate::queue::CaptureQueue;
+
+/// Audio of one extra device waiting to be merged into the take.
+///
+/// The device runs on a clock of its own, which drifts from the main device's.
+/// Running slow, the frames it's missing are filled by repeating its last one;
+/// running fast, the backlog is dropped before it can put the device out of step.
+#[derive(Debug)]
+pub struct DriftBuffer {
+    channels: usize,
+    samples: VecDeque<f32>,
+    /// Most frames the device has delivered at once. Up to that much backlog is
+    /// just the two devices delivering at different times.
+    largest_block: usize,
+    last_frame: Vec<f32>,
+    /// Frames filled in or dropped so far.
+    corrections: u64,
+}
+
+impl DriftBuffer {
+    pub fn new(channels: usize) -> Self {
+        let channels = channels.max(1);
+        Self {
+            channels,
+            samples: VecDeque::new(),
+            largest_block: 0,
+            last_frame: vec![0.0; channels],
+            corrections: 0,
+        }
+    }
+
+    /// Adds a block of interleaved samples from the device.
+    pub fn push(&mut self, samples: &[f32]) {
+        self.largest_block = self.largest_block.max(samples.len() / self.channels);
+        self.samples.extend(samples);
+    }
+
+    /// Takes the next `frames` frames, appending them to `out`.
+    pub fn take(&mut self, frames: usize, out: &mut Vec<f32>) {
+        let available = self.samples.len() / self.channels;
+        let backlog = available.saturating_sub(frames);
+        if backlog > 2 * self.largest_block {
+            let drop = backlog - self.largest_block;
+            self.samples.drain(..drop * self.channels);
+            self.corrections += drop as u64;
+        }
+
+        let taken = available.min(frames);
+        let start = out.len();
+        out.extend(self.samples.drain(..taken * self.channels));
+        if taken > 0 {
+            self.last_frame
+                .copy_from_slice(&out[out.len() - self.channels..]);
+        }
+        for _ in taken..frames {
+            out.extend_from_slice(&self.last_frame);
+        }
+        self.corrections += (frames - taken) as u64;
+        debug_assert_eq!(out.len() - start, frames * self.channels);
+    }
+
+    /// Frames filled in or dropped so far to keep the device in step.
+    pub fn corrections(&self) -> u64 {
+        self.corrections
+    }
+}
+
+/// An extra device of the take, see [`ExtraInputs`].
+struct ExtraInput {
+    name: String,
+    channels: usize,
+    queue: CaptureQueue,
+    buffer: DriftBuffer,
+    stream: Stream,
+}
+
+/// The streams of the devices recorded alongside the main one, in the order
+/// their channels follow the main device's.
+pub struct ExtraInputs {
+    inputs: Vec<ExtraInput>,
+}
+
+impl ExtraInputs {
+    /// Opens the input devices called `names` on `host`, capturing at
+    /// `sample_rate` like the main device. Stream errors go to `on_error`.
+    pub fn open(
+        host: &Host,
+        names: &[String],
+        sample_rate: u32,
+        on_error: impl Fn(String) + Clone + Send + 'static,
+    ) -> Result<Self> {
+        let inputs = names
+            .iter()
+            .map(|name| {
+                let device = engine::select_input_device(host, Some(name))?;
+                let config = engine::input_stream_config(&device, Some(sample_rate))
+                    .wrap_err_with(|| format!("can't record {name} along with the main device"))?;
+                let channels = config.channels.max(1) as usize;
+                let queue = CaptureQueue::new(&config);
+                let mut writer = queue.writer()?;
+                let on_error = on_error.clone();
+                let device_name = name.clone();
+                let stream = device
+                    .build_input_stream(
+                        &config,
+                        move |data: &[f32], _| writer.push(data),
+                        move |err| on_error(format!("{device_name}: {err}")),
+                        None,
+                    )
+                    .wrap_err_with(|| format!("failed to open {name} (is the device busy?)"))?;
+                Ok(ExtraInput {
+                    name: name.clone(),
+                    channels,
+                    queue,
+                    buffer: DriftBuffer::new(channels),
+                    stream,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { inputs })
+    }
+
+    pub fn play(&self) -> Result<()> {
+        for input in &self.inputs {
+            input
+                .stream
+                .play()
+                .wrap_err_with(|| format!("failed to start {}", input.name))?;
+        }
+        Ok(())
+    }
+
+    /// Channels of all the extra devices together.
+    pub fn channels(&self) -> usize {
+        self.inputs.iter().map(|input| input.channels).sum()
+    }
+
+    /// Name and channel count of each device.
+    pub fn devices(&self) -> Vec<(String, u16)> {
+        self.inputs
+            .iter()
+            .map(|input| (input.name.clone(), input.channels as u16))
+            .collect()
+    }
+
+    /// How many times audio was dropped so far, on all the devices together.
+    pub fn overruns(&self) -> u64 {
+        self.inputs.iter().map(|input| input.queue.overruns()).sum()
+    }
+
+    /// Adds the extra devices' audio for the frames of `main`, a block of the main
+    /// device with `main_channels` channels. The extra channels are inverted where
+    /// set in `inverted`, which counts channels of the whole take.
+    pub fn merge(&mut self, main: &[f32], main_channels: usize, inverted: u64) -> Vec<f32> {
+        let main_channels = main_channels.max(1);
+        let frames = main.len() / main_channels;
+        let extra_channels = self.channels();
+        let blocks: Vec<Vec<f32>> = self
+            .inputs
+            .iter_mut()
+            .map(|input| {
+                while let Some(samples) = input.queue.pop() {
+                    input.buffer.push(&samples);
+                }
+                let mut block = Vec::with_capacity(frames * input.channels);
+                input.buffer.take(frames, &mut block);
+                block
+            })
+            .collect();
+        let mut extra_block = Vec::with_capacity(frames * extra_channels);
+        for frame in 0..frames {
+            for (input, block) in self.inputs.iter().zip(&blocks) {
+                let start = frame * input.channels;
+                extra_block.extend_from_slice(&block[start..start + input.channels]);
+            }
+        }
+        dsp::invert_channels(
+            &mut extra_block,
+            extra_channels,
+            inverted.checked_shr(main_channels as u32).unwrap_or(0),
+        );
+
+        let mut merged = Vec::with_capacity(frames * (main_channels + extra_channels));
+        for (main, extra) in main
+            .chunks_exact(main_channels)
+            .zip(extra_block.chunks_exact(extra_channels))
+        {
+            merged.extend_from_slice(main);
+            merged.extend_from_slice(extra);
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drift_buffer_fills_in_and_drops_frames() {
+        let mut buffer = DriftBuffer::new(1);
+        buffer.push(&[1.0, 2.0, 3.0]);
+
+        // Running slow: the last frame is repeated
+        let mut out = Vec::new();
+        buffer.take(4, &mut out);
+        assert_eq!(out, [1.0, 2.0, 3.0, 3.0]);
+        assert_eq!(buffer.corrections(), 1);
+
+        // Running fast: a backlog beyond two blocks is dropped, oldest first
+        for block in [[4.0, 5.0, 6.0], [7.0, 8.0, 9.0], [10.0, 11.0, 12.0]] {
+            buffer.push(&block);
+        }
+        out.clear();
+        buffer.take(2, &mut out);
+        assert_eq!(out, [8.0, 9.0]);
+        assert_eq!(buffer.corrections(), 5);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::dsp;
+
+/// Time constant of the RMS average, in seconds.
+const RMS_WINDOW: f32 = 0.3;
+/// How long the peak-hold marker stays put before it starts to fall.
+const PEAK_HOLD_TIME: f32 = 1.5;
+/// How fast the peak-hold marker falls once released.
+const PEAK_DECAY_DB_PER_SEC: f32 = 20.0;
+/// Samples at or beyond this magnitude latch the clip indicator.
+const CLIP_LEVEL: f32 = 1.0;
+
+/// Level meter fed with every captured block, so its ballistics don't depend on how
+/// often the UI redraws. Each channel is metered on its own.
+#[derive(Debug, Clone)]
+pub struct Meter {
+    sample_rate: f32,
+    channels: Vec<ChannelMeter>,
+    /// Only for stereo input.
+    stereo: Option<StereoMeter>,
+}
+
+/// Mid/side levels and the L/R relationship, for checking MS and XY rigs.
+#[derive(Debug, Clone, Default)]
+struct StereoMeter {
+    mid: ChannelMeter,
+    side: ChannelMeter,
+    /// One-pole averages of L*R, L^2 and R^2 for the correlation.
+    product: f32,
+    left_square: f32,
+    right_square: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ChannelMeter {
+    peak: f32,
+    mean_square: f32,
+    peak_hold: f32,
+    hold_remaining: f32,
+    clipped: bool,
+}
+
+/// Snapshot of one channel of a [`Meter`], in dBFS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterReading {
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+    pub peak_hold_dbfs: f32,
+    /// Latched once any sample reaches full scale, until [`Meter::reset_clip`].
+    pub clipped: bool,
+}
+
+/// Snapshot of the stereo image of a [`Meter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoReading {
+    pub mid: MeterReading,
+    pub side: MeterReading,
+    /// From -1 (out of phase) through 0 (unrelated) to +1 (mono).
+    pub correlation: f32,
+    /// Side level relative to mid: 0 for mono, 1 when L and R are unrelated.
+    pub width: f32,
+}
+
+impl Meter {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1) as f32,
+            channels: vec![ChannelMeter::default(); channels.max(1) as usize],
+            stereo: (channels == 2).then(StereoMeter::default),
+        }
+    }
+
+    /// Updates the meter with a block of interleaved samples.
+    pub fn process(&mut self, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        let channels = dsp::deinterleave(samples, self.channels.len());
+        let elapsed = channels[0].len() as f32 / self.sample_rate;
+        for (meter, samples) in self.channels.iter_mut().zip(&channels) {
+            meter.process(samples, elapsed);
+        }
+        if let Some(stereo) = &mut self.stereo {
+            stereo.process(&channels[0], &channels[1], elapsed);
+        }
+    }
+
+    pub fn reset_clip(&mut self) {
+        for meter in &mut self.channels {
+            meter.clipped = false;
+        }
+        if let Some(stereo) = &mut self.stereo {
+            stereo.mid.clipped = false;
+            stereo.side.clipped = false;
+        }
+    }
+
+    /// One reading per channel, in channel order.
+    pub fn readings(&self) -> Vec<MeterReading> {
+        self.channels.iter().map(ChannelMeter::reading).collect()
+    }
+
+    /// Mid/side reading, for stereo input only.
+    pub fn stereo_reading(&self) -> Option<StereoReading> {
+        self.stereo.as_ref().map(StereoMeter::reading)
+    }
+}
+
+impl StereoMeter {
+    fn process(&mut self, left: &[f32], right: &[f32], elapsed: f32) {
+        let mid: Vec<f32> = left.iter().zip(right).map(|(l, r)| (l + r) / 2.0).collect();
+        let side: Vec<f32> = left.iter().zip(right).map(|(l, r)| (l - r) / 2.0).collect();
+        self.mid.process(&mid, elapsed);
+        self.side.process(&side, elapsed);
+
+        let (mut product, mut left_square, mut right_square) = (0.0, 0.0, 0.0);
+        for (&l, &r) in left.iter().zip(right) {
+            product += l * r;
+            left_square += l * l;
+            right_square += r * r;
+        }
+
+        // Same one-pole averaging as the RMS, so the readouts move together
+        let frames = left.len().max(1) as f32;
+        let coefficient = (-elapsed / RMS_WINDOW).exp();
+        self.product = self.product * coefficient + product / frames * (1.0 - coefficient);
+        self.left_square =
+            self.left_square * coefficient + left_square / frames * (1.0 - coefficient);
+        self.right_square =
+            self.right_square * coefficient + right_square / frames * (1.0 - coefficient);
+    }
+
+    fn reading(&self) -> StereoReading {
+        let power = (self.left_square * self.right_square).sqrt();
+        let correlation = if power > 1e-10 {
+            (self.product / power).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        let mid = self.mid.mean_square.sqrt();
+        let side = self.side.mean_square.sqrt();
+
+        StereoReading {
+            mid: self.mid.reading(),
+            side: self.side.reading(),
+            correlation,
+            width: if mid > 1e-10 { side / mid } else { 0.0 },
+        }
+    }
+}
+
+impl ChannelMeter {
+    /// Updates the channel with `samples` covering `elapsed` seconds.
+    fn process(&mut self, samples: &[f32], elapsed: f32) {
+        let peak = dsp::peak(samples);
+        self.peak = peak;
+        self.clipped |= peak >= CLIP_LEVEL;
+
+        // One-pole average of the block's mean square
+        let block_rms = dsp::rms(samples);
+        let coefficient = (-elapsed / RMS_WINDOW).exp();
+        self.mean_square =
+            self.mean_square * coefficient + block_rms * block_rms * (1.0 - coefficient);
+
+        if peak >= self.peak_hold {
+            self.peak_hold = peak;
+            self.hold_remaining = PEAK_HOLD_TIME;
+        } else if self.hold_remaining > 0.0 {
+            self.hold_remaining -= elapsed;
+        } else {
+            let decay = 10.0_f32.powf(-PEAK_DECAY_DB_PER_SEC * elapsed / 20.0);
+            self.peak_hold = (self.peak_hold * decay).max(peak);
+        }
+    }
+
+    fn reading(&self) -> MeterReading {
+        MeterReading {
+            peak_dbfs: dsp::to_dbfs(self.peak),
+            rms_dbfs: dsp::to_dbfs(self.mean_square.sqrt()),
+            peak_hold_dbfs: dsp::to_dbfs(self.peak_hold),
+            clipped: self.clipped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three seconds of a 1 kHz sine at `amplitude`, interleaved over `channels`.
+    /// Long enough for the RMS average to settle.
+    fn sine(amplitude: f32, channels: usize) -> Vec<f32> {
+        (0..3 * 48_000)
+            .flat_map(|i| {
+                let phase = i as f32 * 1000.0 / 48_000.0 * std::f32::consts::TAU;
+                vec![amplitude * phase.sin(); channels]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sine_reads_its_peak_and_rms() {
+        let mut meter = Meter::new(48_000, 1);
+        for block in sine(0.5, 1).chunks(480) {
+            meter.process(block);
+        }
+
+        let reading = meter.readings()[0];
+        assert!((reading.peak_dbfs - -6.02).abs() < 0.1);
+        assert!((reading.rms_dbfs - -9.03).abs() < 0.1);
+        assert!(!reading.clipped);
+    }
+
+    #[test]
+    fn clip_latches_until_reset() {
+        let mut meter = Meter::new(48_000, 1);
+        meter.process(&[1.0]);
+        meter.process(&[0.0; 48_000]);
+        assert!(meter.readings()[0].clipped);
+
+        meter.reset_clip();
+        assert!(!meter.readings()[0].clipped);
+    }
+
+    #[test]
+    fn channels_are_metered_separately() {
+        let mut meter = Meter::new(48_000, 2);
+        meter.process(&[0.5, 0.0, -0.5, 0.0]);
+
+        let readings = meter.readings();
+        assert!((readings[0].peak_dbfs - -6.02).abs() < 0.01);
+        assert_eq!(readings[1].peak_dbfs, -200.0);
+    }
+
+    #[test]
+    fn identical_channels_are_mono() {
+        let mut meter = Meter::new(48_000, 2);
+        for block in sine(0.5, 2).chunks(960) {
+            meter.process(block);
+        }
+
+        let stereo = meter.stereo_reading().unwrap();
+        assert!(stereo.correlation > 0.99);
+        assert_eq!(stereo.width, 0.0);
+    }
+
+    #[test]
+    fn inverted_channel_is_out_of_phase() {
+        let mut meter = Meter::new(48_000, 2);
+        let mut samples = sine(0.5, 2);
+        for frame in samples.chunks_mut(2) {
+            frame[1] = -frame[1];
+        }
+        for block in samples.chunks(960) {
+            meter.process(block);
+        }
+
+        assert!(meter.stereo_reading().unwrap().correlation < -0.99);
+    }
+
+    #[test]
+    fn only_stereo_has_a_stereo_reading() {
+        assert!(Meter::new(48_000, 1).stereo_reading().is_none());
+        assert!(Meter::new(48_000, 4).stereo_reading().is_none());
+    }
+}
@@ -0,0 +1,113 @@
+//! Plays captured audio through the default output device while recording, so
+//! you can hear yourself on headphones.
+
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, Host, SampleFormat, SampleRate, Stream, StreamConfig};
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapProd, HeapRb};
+
+/// Audio kept queued for the output, enough to ride out scheduling jitter.
+const TARGET_LATENCY: Duration = Duration::from_millis(30);
+/// Ring buffer size; anything beyond this is dropped rather than delayed.
+const BUFFER_LENGTH: Duration = Duration::from_millis(250);
+
+/// Running output stream fed from the capture side through a lock-free ring
+/// buffer. Dropping it stops monitoring.
+pub struct Monitor {
+    producer: HeapProd<f32>,
+    channels: usize,
+    _stream: Stream,
+}
+
+impl Monitor {
+    /// Opens the default output device of `host` at the capture rate of `input`.
+    ///
+    /// Problems reported by the output stream once it runs go to `on_error`.
+    pub fn start(
+        host: &Host,
+        input: &StreamConfig,
+        mut on_error: impl FnMut(String) + Send + 'static,
+    ) -> Result<Self> {
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| eyre!("no output device found"))?;
+        let config = output_stream_config(&device, input.sample_rate)?;
+
+        let channels = input.channels.max(1) as usize;
+        let out_channels = config.channels.max(1) as usize;
+        let frames = |duration: Duration| {
+            (duration.as_secs_f64() * input.sample_rate.0 as f64) as usize * channels
+        };
+        let target = frames(TARGET_LATENCY);
+        let (producer, mut consumer) = HeapRb::<f32>::new(frames(BUFFER_LENGTH)).split();
+
+        let mut frame = vec![0.0; channels];
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _| {
+                    // Skip ahead if we fell behind, so the delay doesn't keep growing
+                    let needed = data.len() / out_channels * channels;
+                    let excess = consumer.occupied_len().saturating_sub(target + needed);
+                    consumer.skip(excess - excess % channels);
+
+                    for out in data.chunks_mut(out_channels) {
+                        if consumer.occupied_len() < channels {
+                            out.fill(0.0);
+                            continue;
+                        }
+                        consumer.pop_slice(&mut frame);
+
+                        if out_channels == 1 {
+                            out[0] = frame.iter().sum::<f32>() / channels as f32;
+                        } else {
+                            for (c, sample) in out.iter_mut().enumerate() {
+                                *sample = frame[c.min(channels - 1)];
+                            }
+                        }
+                    }
+                },
+                move |err| on_error(format!("monitoring: {err}")),
+                None,
+            )
+            .wrap_err("failed to open the output stream")?;
+        stream
+            .play()
+            .wrap_err("failed to start the output stream")?;
+
+        Ok(Self {
+            producer,
+            channels,
+            _stream: stream,
+        })
+    }
+
+    /// Queues interleaved captured samples for playback. Whole frames that don't
+    /// fit are dropped.
+    pub fn push(&mut self, samples: &[f32]) {
+        let room = self.producer.vacant_len() / self.channels * self.channels;
+        self.producer
+            .push_slice(&samples[..samples.len().min(room)]);
+    }
+}
+
+/// Stream config for `device` at `sample_rate`, so monitoring needs no resampling.
+fn output_stream_config(device: &Device, sample_rate: SampleRate) -> Result<StreamConfig> {
+    let default = device
+        .default_output_config()
+        .wrap_err("failed to query the output device")?;
+    if default.sample_format() == SampleFormat::F32 && default.sample_rate() == sample_rate {
+        return Ok(default.into());
+    }
+
+    device
+        .supported_output_configs()
+        .wrap_err("failed to query the output device")?
+        .filter(|range| range.sample_format() == SampleFormat::F32)
+        .find_map(|range| range.try_with_sample_rate(sample_rate))
+        .map(Into::into)
+        .ok_or_else(|| eyre!("output device can't play {} Hz audio", sample_rate.0))
+}
@@ -0,0 +1,288 @@
+use chrono::{DateTime, Datelike, Local};
+use regex::Regex;
+use std::fmt::Write;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Give up after this many collisions rather than spinning forever.
+const MAX_ATTEMPTS: u32 = 10_000;
+
+/// Creates a new, empty recording file in `dir` named from `template`.
+///
+/// strftime fields such as `%Y-%m-%d_%H-%M-%S`, and `{timestamp}`, `{year}`,
+/// `{month}` and `{day}`, expand from the current local time; `%%` is a literal `%`.
+/// The template may include directories, e.g. `%Y/%m/%d/memo.wav`, which are
+/// created as needed.
+///
+/// The file is created with `create_new`, so an existing recording is never
+/// overwritten, even when several micrec instances share the directory:
+///
+/// - `{take}` expands to the next free take number, zero-padded to three digits.
+///   Existing files matching the template are scanned to find where to start, and
+///   numbers up to `after_take` are skipped as well, for takes that were thrown
+///   away. It is only expanded in the file name, not in directories.
+/// - Without `{take}`, a colliding name gets a `-2`, `-3`, ... suffix.
+pub fn create_recording_file(
+    dir: &Path,
+    template: &str,
+    after_take: u32,
+) -> io::Result<(PathBuf, File)> {
+    let now = Local::now();
+    let template = Path::new(template);
+    let dir = match template
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        Some(parent) => dir.join(expand(&parent.to_string_lossy(), &now, 0)?),
+        None => dir.to_path_buf(),
+    };
+    let template = template
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    fs::create_dir_all(&dir)?;
+
+    if template.contains("{take}") {
+        let first_take = highest_take(&dir, &template)?.max(after_take) + 1;
+        for take in first_take..first_take + MAX_ATTEMPTS {
+            let path = dir.join(expand(&template, &now, take)?);
+            if let Some(file) = create_new(&path)? {
+                return Ok((path, file));
+            }
+        }
+    } else {
+        let name = expand(&template, &now, 0)?;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let path = dir.join(with_suffix(&name, attempt));
+            if let Some(file) = create_new(&path)? {
+                return Ok((path, file));
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("no free file name for {template} in {}", dir.display()),
+    ))
+}
+
+/// Creates the safety track next to `recording`, named like it with a `-safety`
+/// suffix. Fails if that file already exists.
+pub fn create_safety_file(recording: &Path) -> io::Result<(PathBuf, File)> {
+    let path = safety_track_path(recording);
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    Ok((path, file))
+}
+
+/// Where the safety track of `recording` goes, e.g. `memo-safety.wav` for `memo.wav`.
+pub fn safety_track_path(recording: &Path) -> PathBuf {
+    let stem = recording.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{stem}-safety");
+    if let Some(extension) = recording.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    recording.with_file_name(name)
+}
+
+/// Number `{take}` expanded to in `path`, a recording named from `template`.
+pub fn take_number(path: &Path, template: &str) -> Option<u32> {
+    let template = Path::new(template).file_name()?.to_string_lossy();
+    if !template.contains("{take}") {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    template_pattern(&template)
+        .captures(name)?
+        .get(1)?
+        .as_str()
+        .parse()
+        .ok()
+}
+
+/// Expands every placeholder and strftime field in `template`.
+fn expand(template: &str, now: &DateTime<Local>, take: u32) -> io::Result<String> {
+    let template = template
+        .replace("{timestamp}", &now.timestamp().to_string())
+        .replace("{year}", &format!("{:04}", now.year()))
+        .replace("{month}", &format!("{:02}", now.month()))
+        .replace("{day}", &format!("{:02}", now.day()))
+        .replace("{take}", &format!("{take:03}"));
+
+    // Formatting fails rather than panicking on an unknown field
+    let mut name = String::new();
+    write!(name, "{}", now.format(&template)).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid strftime field in file name template {template}"),
+        )
+    })?;
+    Ok(name)
+}
+
+/// Opens `path` only if it doesn't exist yet, returning `None` if it does.
+fn create_new(path: &Path) -> io::Result<Option<File>> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// `name` for the first attempt, then `name-2.ext`, `name-3.ext`, ...
+fn with_suffix(name: &str, attempt: u32) -> String {
+    if attempt == 1 {
+        return name.to_string();
+    }
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem}-{attempt}.{extension}"),
+        _ => format!("{name}-{attempt}"),
+    }
+}
+
+/// Highest take number among files in `dir` that match `template`, or 0.
+fn highest_take(dir: &Path, template: &str) -> io::Result<u32> {
+    let pattern = template_pattern(template);
+    let mut highest = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let Some(captures) = name.to_str().and_then(|name| pattern.captures(name)) else {
+            continue;
+        };
+        if let Some(take) = captures.get(1).and_then(|m| m.as_str().parse().ok()) {
+            highest = highest.max(take);
+        }
+    }
+
+    Ok(highest)
+}
+
+/// Regex matching file names produced by `template`, capturing the take number.
+fn template_pattern(template: &str) -> Regex {
+    let placeholder = Regex::new(r"\{[a-z]+\}|%[-_0^#]?[0-9]*[.:]?[0-9]*[A-Za-z+%]").unwrap();
+    let mut pattern = String::from("^");
+    let mut last = 0;
+
+    for found in placeholder.find_iter(template) {
+        pattern.push_str(&regex::escape(&template[last..found.start()]));
+        pattern.push_str(match found.as_str() {
+            "{take}" => r"(\d+)",
+            "%%" => "%",
+            _ => ".*?",
+        });
+        last = found.end();
+    }
+    pattern.push_str(&regex::escape(&template[last..]));
+    pattern.push('$');
+
+    Regex::new(&pattern).unwrap()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::process;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// Empty scratch directory unique to this test.
+    pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("micrec-{name}-{}", process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn expands_date_placeholders() {
+        let now = Local.with_ymd_and_hms(2024, 3, 9, 14, 30, 0).unwrap();
+        assert_eq!(
+            expand("{year}/{month}/{day}/memo-{take}.wav", &now, 7).unwrap(),
+            "2024/03/09/memo-007.wav"
+        );
+        assert_eq!(
+            expand("{timestamp}.wav", &now, 0).unwrap(),
+            format!("{}.wav", now.timestamp())
+        );
+    }
+
+    #[test]
+    fn expands_strftime_fields() {
+        let now = Local.with_ymd_and_hms(2024, 5, 17, 14, 32, 5).unwrap();
+        assert_eq!(
+            expand("micrec-%Y-%m-%d_%H-%M-%S.wav", &now, 0).unwrap(),
+            "micrec-2024-05-17_14-32-05.wav"
+        );
+        assert_eq!(
+            expand("%F/take-{take}-100%%.wav", &now, 2).unwrap(),
+            "2024-05-17/take-002-100%.wav"
+        );
+        assert!(expand("memo-%Q.wav", &now, 0).is_err());
+    }
+
+    #[test]
+    fn takes_are_found_behind_strftime_fields() {
+        let pattern = template_pattern("%Y-%m-%d_%H-%M take {take} (100%%).wav");
+        let captures = pattern
+            .captures("2024-05-17_14-32 take 012 (100%).wav")
+            .unwrap();
+        assert_eq!(&captures[1], "012");
+        assert!(!pattern.is_match("2024-05-17_14-32 take 012.wav"));
+    }
+
+    #[test]
+    fn collisions_get_a_numbered_suffix() {
+        assert_eq!(with_suffix("memo.wav", 1), "memo.wav");
+        assert_eq!(with_suffix("memo.wav", 3), "memo-3.wav");
+        assert_eq!(with_suffix("memo", 2), "memo-2");
+    }
+
+    #[test]
+    fn takes_continue_after_the_highest_existing_one() {
+        let dir = scratch_dir("takes");
+        File::create(dir.join("memo-004.wav")).unwrap();
+        File::create(dir.join("other-009.wav")).unwrap();
+
+        let (path, _) = create_recording_file(&dir, "memo-{take}.wav", 0).unwrap();
+        assert_eq!(path, dir.join("memo-005.wav"));
+        let (path, _) = create_recording_file(&dir, "memo-{take}.wav", 0).unwrap();
+        assert_eq!(path, dir.join("memo-006.wav"));
+
+        // A take thrown away keeps its number
+        fs::remove_file(&path).unwrap();
+        assert_eq!(take_number(&path, "%Y/memo-{take}.wav"), Some(6));
+        let (path, _) = create_recording_file(&dir, "memo-{take}.wav", 6).unwrap();
+        assert_eq!(path, dir.join("memo-007.wav"));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn never_overwrites_an_existing_file() {
+        let dir = scratch_dir("collide");
+        let (first, _) = create_recording_file(&dir, "memo.wav", 0).unwrap();
+        let (second, _) = create_recording_file(&dir, "memo.wav", 0).unwrap();
+        assert_eq!(take_number(&second, "memo.wav"), None);
+
+        assert_eq!(first, dir.join("memo.wav"));
+        assert_eq!(second, dir.join("memo-2.wav"));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn safety_track_sits_next_to_the_recording() {
+        assert_eq!(
+            safety_track_path(Path::new("/takes/memo-001.flac")),
+            Path::new("/takes/memo-001-safety.flac")
+        );
+        assert_eq!(
+            safety_track_path(Path::new("memo")),
+            Path::new("memo-safety")
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! A live copy of the take served over the network while recording, to listen in
+//! on a mic from another room.
+//!
+//! Over TCP, micrec listens on the address given and every client that connects
+//! gets a WAV header and then 16-bit PCM as it's recorded, so
+//! `ffplay tcp://host:9000` plays it. Over UDP, it sends RTP packets of 16-bit PCM
+//! (L16, payload type 96) to the address given, for a player given an SDP file:
+//!
+//! ```text
+//! v=0
+//! o=- 0 0 IN IP4 127.0.0.1
+//! s=micrec
+//! c=IN IP4 127.0.0.1
+//! t=0 0
+//! m=audio 9000 RTP/AVP 96
+//! a=rtpmap:96 L16/48000/2
+//! ```
+
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::pipe;
+
+/// Most audio in one RTP packet, in bytes, to stay clear of the usual MTU.
+const RTP_PAYLOAD_BYTES: usize = 1200;
+/// First of the dynamic payload types, which L16 at any rate has to use.
+const RTP_PAYLOAD_TYPE: u8 = 96;
+/// How often the TCP listener looks for new clients.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// A client that takes longer than this to accept a block is dropped, so one slow
+/// listener doesn't hold up the others.
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Blocks waiting to be sent. Any more are dropped, so a stalled network can't
+/// take up memory while recording.
+const QUEUED_BLOCKS: usize = 64;
+
+/// Where the audio is served, written as `tcp://host:port` or `udp://host:port`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamTarget {
+    /// Address to listen on for clients.
+    Tcp(SocketAddr),
+    /// Address to send RTP packets to.
+    Udp(SocketAddr),
+}
+
+impl FromStr for StreamTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, address) = s
+            .split_once("://")
+            .ok_or_else(|| format!("expected tcp://host:port or udp://host:port, not {s}"))?;
+        let address = address
+            .to_socket_addrs()
+            .map_err(|err| format!("can't resolve {address}: {err}"))?
+            .next()
+            .ok_or_else(|| format!("{address} has no addresses"))?;
+        match scheme {
+            "tcp" => Ok(StreamTarget::Tcp(address)),
+            "udp" | "rtp" => Ok(StreamTarget::Udp(address)),
+            _ => Err(format!("can't stream over {scheme}, only tcp or udp")),
+        }
+    }
+}
+
+impl fmt::Display for StreamTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamTarget::Tcp(address) => write!(f, "tcp://{address}"),
+            StreamTarget::Udp(address) => write!(f, "udp://{address}"),
+        }
+    }
+}
+
+/// Serves audio to a [`StreamTarget`] from threads of its own, so the network
+/// never holds up recording. Stops serving when dropped.
+#[derive(Debug)]
+pub struct NetworkStream {
+    blocks_tx: Option<SyncSender<Vec<f32>>>,
+    threads: Vec<JoinHandle<()>>,
+    closed: Arc<AtomicBool>,
+    local_addr: SocketAddr,
+}
+
+impl NetworkStream {
+    /// Starts serving audio of `channels` at `sample_rate`.
+    pub fn start(target: &StreamTarget, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let (blocks_tx, blocks) = sync_channel::<Vec<f32>>(QUEUED_BLOCKS);
+        let closed = Arc::new(AtomicBool::new(false));
+        let (threads, local_addr) = match *target {
+            StreamTarget::Tcp(address) => {
+                let listener = TcpListener::bind(address)?;
+                listener.set_nonblocking(true)?;
+                let local_addr = listener.local_addr()?;
+                let clients = Arc::new(Mutex::new(Vec::new()));
+                let header = wav_header(sample_rate, channels);
+                let accepted = Arc::clone(&clients);
+                let accept_closed = Arc::clone(&closed);
+                let accept = thread::spawn(move || {
+                    accept_clients(listener, &header, &accepted, &accept_closed)
+                });
+                let serve = thread::spawn(move || {
+                    for block in blocks {
+                        let bytes = pipe::to_s16le(&block);
+                        // Written to outside the lock, so new clients aren't kept waiting
+                        let served = clients.lock().map(|mut clients| mem::take(&mut *clients));
+                        let Ok(mut served) = served else {
+                            return;
+                        };
+                        served.retain_mut(|client| client.write_all(&bytes).is_ok());
+                        if let Ok(mut clients) = clients.lock() {
+                            clients.extend(served);
+                        }
+                    }
+                });
+                (vec![accept, serve], local_addr)
+            }
+            StreamTarget::Udp(address) => {
+                let unspecified: SocketAddr = match address {
+                    SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+                    SocketAddr::V6(_) => ([0; 8], 0).into(),
+                };
+                let socket = UdpSocket::bind(unspecified)?;
+                socket.connect(address)?;
+                let local_addr = socket.local_addr()?;
+                let mut packetizer = RtpPacketizer::new(channels, random_ssrc());
+                let serve = thread::spawn(move || {
+                    for block in blocks {
+                        for packet in packetizer.push(&block) {
+                            // Nobody listening is no reason to stop
+                            socket.send(&packet).ok();
+                        }
+                    }
+                });
+                (vec![serve], local_addr)
+            }
+        };
+        Ok(Self {
+            blocks_tx: Some(blocks_tx),
+            threads,
+            closed,
+            local_addr,
+        })
+    }
+
+    /// Where clients connect over TCP, or where the packets come from over UDP.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Queues a block of interleaved samples for whoever is listening, or drops it
+    /// if the network has fallen too far behind.
+    pub fn push(&self, samples: &[f32]) {
+        if let Some(blocks_tx) = &self.blocks_tx {
+            blocks_tx.try_send(samples.to_vec()).ok();
+        }
+    }
+}
+
+impl Drop for NetworkStream {
+    fn drop(&mut self) {
+        self.blocks_tx = None;
+        self.closed.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Takes in clients until `closed`, greeting each with `header`. The header goes
+/// out under the lock, so no block can get ahead of it, and a fresh connection
+/// takes its 44 bytes without waiting.
+fn accept_clients(
+    listener: TcpListener,
+    header: &[u8],
+    clients: &Mutex<Vec<TcpStream>>,
+    closed: &AtomicBool,
+) {
+    while !closed.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((mut client, _)) => {
+                let Ok(mut clients) = clients.lock() else {
+                    return;
+                };
+                let ready = client
+                    .set_nonblocking(false)
+                    .and_then(|_| client.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT)))
+                    .and_then(|_| client.set_nodelay(true))
+                    .and_then(|_| client.write_all(header));
+                if ready.is_ok() {
+                    clients.push(client);
+                }
+            }
+            // Nobody new, or one who hung up before being taken in
+            Err(_) => thread::sleep(ACCEPT_POLL_INTERVAL),
+        }
+    }
+}
+
+/// Header of a 16-bit PCM WAV file of unknown length, as players expect when
+/// reading one from a stream.
+fn wav_header(sample_rate: u32, channels: u16) -> Vec<u8> {
+    let channels = channels.max(1);
+    let block_align = channels * 2;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16_u32.to_le_bytes());
+    header.extend_from_slice(&1_u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16_u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header
+}
+
+fn random_ssrc() -> u32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    nanos ^ std::process::id().rotate_left(16)
+}
+
+/// Cuts interleaved audio into RTP packets of big-endian 16-bit PCM, holding back
+/// what doesn't fill a packet until the next block.
+#[derive(Debug)]
+struct RtpPacketizer {
+    channels: usize,
+    ssrc: u32,
+    sequence: u16,
+    /// Frames sent so far, wrapping like the RTP timestamp does.
+    timestamp: u32,
+    pending: Vec<i16>,
+}
+
+impl RtpPacketizer {
+    fn new(channels: u16, ssrc: u32) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            ssrc,
+            sequence: 0,
+            timestamp: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Packets ready to send after `samples`.
+    fn push(&mut self, samples: &[f32]) -> Vec<Vec<u8>> {
+        self.pending.extend(
+            samples
+                .iter()
+                .map(|&sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16),
+        );
+        let frames_per_packet = RTP_PAYLOAD_BYTES / (2 * self.channels);
+        let samples_per_packet = frames_per_packet * self.channels;
+        let mut packets = Vec::new();
+        while self.pending.len() >= samples_per_packet {
+            let mut packet = Vec::with_capacity(12 + 2 * samples_per_packet);
+            // Version 2, no padding, extension or CSRCs; the marker opens the stream
+            packet.push(0x80);
+            packet.push(RTP_PAYLOAD_TYPE | if self.sequence == 0 { 0x80 } else { 0 });
+            packet.extend_from_slice(&self.sequence.to_be_bytes());
+            packet.extend_from_slice(&self.timestamp.to_be_bytes());
+            packet.extend_from_slice(&self.ssrc.to_be_bytes());
+            for sample in self.pending.drain(..samples_per_packet) {
+                packet.extend_from_slice(&sample.to_be_bytes());
+            }
+            packets.push(packet);
+            self.sequence = self.sequence.wrapping_add(1);
+            self.timestamp = self.timestamp.wrapping_add(frames_per_packet as u32);
+        }
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!(
+            "tcp://0.0.0.0:9000".parse(),
+            Ok(StreamTarget::Tcp(([0, 0, 0, 0], 9000).into()))
+        );
+        assert_eq!(
+            "udp://127.0.0.1:5004".parse(),
+            Ok(StreamTarget::Udp(([127, 0, 0, 1], 5004).into()))
+        );
+        assert!("http://127.0.0.1:80".parse::<StreamTarget>().is_err());
+        assert!("127.0.0.1:9000".parse::<StreamTarget>().is_err());
+    }
+
+    #[test]
+    fn rtp_packets_carry_whole_frames_in_order() {
+        let mut packetizer = RtpPacketizer::new(2, 7);
+        assert!(packetizer.push(&[0.5; 400]).is_empty());
+        let packets = packetizer.push(&[0.5; 800]);
+
+        // 300 stereo frames fit in 1200 bytes
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].len(), 12 + 1200);
+        assert_eq!(packets[0][..2], [0x80, 0x80 | 96]);
+        assert_eq!(packets[1][..4], [0x80, 96, 0, 1]);
+        assert_eq!(packets[1][4..8], 300_u32.to_be_bytes());
+        assert_eq!(packets[1][8..12], 7_u32.to_be_bytes());
+        assert_eq!(packets[1][12..14], 16383_i16.to_be_bytes());
+        assert_eq!(packetizer.pending.len(), 0);
+    }
+
+    #[test]
+    fn tcp_clients_get_a_wav_stream() {
+        let target = StreamTarget::Tcp(([127, 0, 0, 1], 0).into());
+        let stream = NetworkStream::start(&target, 48_000, 1).unwrap();
+        let mut client = TcpStream::connect(stream.local_addr()).unwrap();
+        let mut header = [0; 44];
+        client.read_exact(&mut header).unwrap();
+        stream.push(&[0.5, -0.5]);
+        drop(stream);
+        let mut audio = Vec::new();
+        client.read_to_end(&mut audio).unwrap();
+
+        assert_eq!(header.as_slice(), wav_header(48_000, 1));
+        assert_eq!(audio, pipe::to_s16le(&[0.5, -0.5]));
+    }
+}
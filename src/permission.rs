@@ -0,0 +1,152 @@
+//! Microphone access on systems that make the user grant it: the privacy prompt
+//! on macOS and the privacy settings on Windows. Without access, input streams
+//! either fail with an error that doesn't say why or record pure silence.
+
+use std::io;
+use std::process::Command;
+
+/// Whether this process may record from the microphone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Granted,
+    /// Not asked for yet; [`request`] brings up the system's prompt.
+    NotDetermined,
+    /// Turned off by the user or a policy. Only the system settings can change it.
+    Denied,
+}
+
+/// Current access to the microphone, without asking for it.
+#[cfg(target_os = "macos")]
+pub fn status() -> Permission {
+    use objc2_av_foundation::{AVAuthorizationStatus, AVCaptureDevice, AVMediaTypeAudio};
+
+    let Some(audio) = (unsafe { AVMediaTypeAudio }) else {
+        return Permission::Granted;
+    };
+    match unsafe { AVCaptureDevice::authorizationStatusForMediaType(audio) } {
+        AVAuthorizationStatus::Authorized => Permission::Granted,
+        AVAuthorizationStatus::NotDetermined => Permission::NotDetermined,
+        _ => Permission::Denied,
+    }
+}
+
+/// Current access to the microphone: denied if the privacy settings turn it off
+/// for the whole device, for this user, or for desktop apps.
+#[cfg(windows)]
+pub fn status() -> Permission {
+    use windows_sys::Win32::System::Registry::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+
+    const CONSENT_STORE: &str = r"Software\Microsoft\Windows\CurrentVersion\CapabilityAccessManager\ConsentStore\microphone";
+    let desktop_apps = format!(r"{CONSENT_STORE}\NonPackaged");
+    let denied = [
+        (HKEY_LOCAL_MACHINE, CONSENT_STORE),
+        (HKEY_CURRENT_USER, CONSENT_STORE),
+        (HKEY_CURRENT_USER, desktop_apps.as_str()),
+    ]
+    .into_iter()
+    .any(|(root, key)| registry_string(root, key, "Value").as_deref() == Some("Deny"));
+    if denied {
+        Permission::Denied
+    } else {
+        Permission::Granted
+    }
+}
+
+/// Access needs no permission here.
+#[cfg(not(any(target_os = "macos", windows)))]
+pub fn status() -> Permission {
+    Permission::Granted
+}
+
+/// Asks for access if it hasn't been asked for yet, waiting for the user to
+/// answer the prompt, and returns the outcome.
+#[cfg(target_os = "macos")]
+pub fn request() -> Permission {
+    use block2::RcBlock;
+    use objc2::runtime::Bool;
+    use objc2_av_foundation::{AVCaptureDevice, AVMediaTypeAudio};
+
+    let (Permission::NotDetermined, Some(audio)) = (status(), unsafe { AVMediaTypeAudio }) else {
+        return status();
+    };
+    let (answered_tx, answered) = std::sync::mpsc::channel();
+    let handler = RcBlock::new(move |granted: Bool| {
+        answered_tx.send(granted.as_bool()).ok();
+    });
+    unsafe { AVCaptureDevice::requestAccessForMediaType_completionHandler(audio, &handler) };
+    match answered.recv() {
+        Ok(true) => Permission::Granted,
+        Ok(false) | Err(_) => Permission::Denied,
+    }
+}
+
+/// Returns the current access; there is no prompt to bring up here.
+#[cfg(not(target_os = "macos"))]
+pub fn request() -> Permission {
+    status()
+}
+
+/// How to grant access once it was denied.
+pub fn instructions() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "microphone access is off for this terminal; turn it on in System Settings > \
+         Privacy & Security > Microphone, then restart the terminal if recording stays silent"
+    } else if cfg!(windows) {
+        "microphone access is off; turn on \"Microphone access\" and \"Let desktop apps \
+         access your microphone\" in Settings > Privacy & security > Microphone"
+    } else {
+        "microphone access is off in the system settings"
+    }
+}
+
+/// Opens the system settings page that grants microphone access.
+pub fn open_settings() -> io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("x-apple.systempreferences:com.apple.preference.security?Privacy_Microphone");
+        command
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", "ms-settings:privacy-microphone"]);
+        command
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no microphone privacy settings here",
+        ));
+    };
+    command.spawn().map(drop)
+}
+
+/// The string value `name` of the registry key `key` under `root`, if it exists.
+#[cfg(windows)]
+fn registry_string(
+    root: windows_sys::Win32::System::Registry::HKEY,
+    key: &str,
+    name: &str,
+) -> Option<String> {
+    use windows_sys::Win32::Foundation::ERROR_SUCCESS;
+    use windows_sys::Win32::System::Registry::{RegGetValueW, RRF_RT_REG_SZ};
+
+    let wide = |s: &str| s.encode_utf16().chain([0]).collect::<Vec<u16>>();
+    let (key, name) = (wide(key), wide(name));
+    let mut value = [0u16; 64];
+    let mut size = std::mem::size_of_val(&value) as u32;
+    let status = unsafe {
+        RegGetValueW(
+            root,
+            key.as_ptr(),
+            name.as_ptr(),
+            RRF_RT_REG_SZ,
+            std::ptr::null_mut(),
+            value.as_mut_ptr().cast(),
+            &mut size,
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+    // The size includes the terminating null
+    let len = (size as usize / 2).saturating_sub(1);
+    Some(String::from_utf16_lossy(&value[..len]))
+}
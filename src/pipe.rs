@@ -0,0 +1,351 @@
+//! A copy of the take streamed to stdout, a named pipe or another program while
+//! recording: as 16 kHz mono 16-bit PCM, e.g. for whisper.cpp's `stream` to
+//! transcribe live, or raw as recorded, e.g. for ffmpeg to encode on the fly.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::dsp;
+use crate::resample::Resampler;
+
+/// Rate of the streamed audio, what speech recognizers expect.
+pub const PIPE_SAMPLE_RATE: u32 = 16_000;
+/// How long a program gets to finish up after its input is closed, before it is
+/// killed.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Blocks waiting to be written. Any more are dropped, so a reader that stalls,
+/// or a named pipe nobody opens, can't take up memory while recording.
+const QUEUED_BLOCKS: usize = 64;
+
+/// Where the audio goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipeTarget {
+    Stdout,
+    /// A shell command reading the audio on its stdin. What it prints is saved
+    /// next to the recording as `<file>-transcript.txt`.
+    Command(String),
+    /// A file, named pipe or device such as `/dev/fd/3`. A named pipe is opened
+    /// from the pipe's thread, since opening it waits for a reader.
+    File(PathBuf),
+}
+
+/// How the streamed audio is encoded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PipeFormat {
+    /// 16 kHz mono signed 16-bit little-endian, what speech recognizers expect.
+    #[default]
+    Speech,
+    /// Interleaved 32-bit float little-endian, at the take's own rate and
+    /// channels, untouched.
+    Raw,
+}
+
+/// Streams audio to a [`PipeTarget`] from a thread of its own, so a slow reader
+/// never holds up recording.
+pub struct PcmPipe {
+    channels: usize,
+    /// Converts to [`PIPE_SAMPLE_RATE`], for [`PipeFormat::Speech`].
+    resampler: Option<Resampler>,
+    blocks_tx: Option<SyncSender<Vec<u8>>>,
+    /// Blocks dropped because the reader fell behind.
+    dropped: u64,
+    thread: Option<JoinHandle<io::Result<()>>>,
+    /// Whether the output is open, which for a named pipe means it has a reader.
+    opened: Arc<AtomicBool>,
+    child: Option<Child>,
+    transcript: Option<PathBuf>,
+}
+
+impl PcmPipe {
+    /// Starts streaming audio of `channels` at `sample_rate` in `format`. A
+    /// command's output goes next to `recording`.
+    pub fn start(
+        target: &PipeTarget,
+        format: PipeFormat,
+        recording: &Path,
+        sample_rate: u32,
+        channels: usize,
+    ) -> io::Result<Self> {
+        let mut child = None;
+        let mut transcript = None;
+        let output = match target {
+            PipeTarget::Stdout => Output::Ready(Box::new(io::stdout())),
+            PipeTarget::File(path) if is_fifo(path) => Output::Fifo(path.clone()),
+            PipeTarget::File(path) => Output::Ready(Box::new(File::create(path)?)),
+            PipeTarget::Command(command) => {
+                let stem = recording.file_stem().unwrap_or_default().to_string_lossy();
+                let path = recording.with_file_name(format!("{stem}-transcript.txt"));
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)?;
+                let mut spawned = match spawn(command, file) {
+                    Ok(spawned) => spawned,
+                    Err(err) => {
+                        fs::remove_file(&path).ok();
+                        return Err(err);
+                    }
+                };
+                transcript = Some(path);
+                let stdin = spawned.stdin.take().expect("stdin is piped");
+                child = Some(spawned);
+                Output::Ready(Box::new(stdin))
+            }
+        };
+
+        let (blocks_tx, blocks) = sync_channel::<Vec<u8>>(QUEUED_BLOCKS);
+        let opened = Arc::new(AtomicBool::new(matches!(output, Output::Ready(_))));
+        let thread_opened = Arc::clone(&opened);
+        let thread = thread::spawn(move || {
+            let mut output = output.open()?;
+            thread_opened.store(true, Ordering::Relaxed);
+            for block in blocks {
+                output.write_all(&block)?;
+                output.flush()?;
+            }
+            Ok(())
+        });
+        Ok(Self {
+            channels: channels.max(1),
+            resampler: (format == PipeFormat::Speech)
+                .then(|| Resampler::new(sample_rate, PIPE_SAMPLE_RATE)),
+            blocks_tx: Some(blocks_tx),
+            dropped: 0,
+            thread: Some(thread),
+            opened,
+            child,
+            transcript,
+        })
+    }
+
+    /// Queues a block of interleaved samples, or drops it if the reader has fallen
+    /// too far behind. Fails once the reader has gone away, e.g. because the
+    /// program exited.
+    pub fn push(&mut self, samples: &[f32]) -> io::Result<()> {
+        let block = match self.resampler.as_mut() {
+            Some(resampler) => {
+                let mono = dsp::mixdown(samples, self.channels);
+                to_s16le(&resampler.process(&mono))
+            }
+            None => to_f32le(samples),
+        };
+        let sent = match self
+            .blocks_tx
+            .as_ref()
+            .map(|blocks_tx| blocks_tx.try_send(block))
+        {
+            Some(Ok(())) => true,
+            Some(Err(TrySendError::Full(_))) => {
+                self.dropped += 1;
+                true
+            }
+            Some(Err(TrySendError::Disconnected(_))) | None => false,
+        };
+        if sent {
+            return Ok(());
+        }
+        self.blocks_tx = None;
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(Err(err))) => Err(err),
+            _ => Err(io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed")),
+        }
+    }
+
+    /// How many blocks were dropped so far because the reader fell behind.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Closes the stream and waits for the program to finish. Returns its
+    /// transcript, unless it printed nothing.
+    pub fn finish(mut self) -> io::Result<Option<PathBuf>> {
+        self.blocks_tx = None;
+        // Nothing ever came to read from the named pipe, so there's nothing to wait for
+        if !self.opened.load(Ordering::Relaxed) {
+            self.thread.take_if(|thread| !thread.is_finished());
+        }
+        let written = match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("pipe thread panicked")),
+            None => Ok(()),
+        };
+        if let Some(child) = self.child.take() {
+            wait_or_kill(child)?;
+        }
+        // A reader that quit early has already been reported by `push`
+        if let Err(err) = written {
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                return Err(err);
+            }
+        }
+        match self.transcript.take() {
+            Some(path) if fs::metadata(&path)?.len() == 0 => {
+                fs::remove_file(&path)?;
+                Ok(None)
+            }
+            transcript => Ok(transcript),
+        }
+    }
+}
+
+/// Destination of the pipe's thread.
+enum Output {
+    Ready(Box<dyn Write + Send>),
+    /// A named pipe still to be opened, see [`PipeTarget::File`].
+    Fifo(PathBuf),
+}
+
+impl Output {
+    fn open(self) -> io::Result<Box<dyn Write + Send>> {
+        match self {
+            Output::Ready(output) => Ok(output),
+            Output::Fifo(path) => Ok(Box::new(File::create(path)?)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+/// Runs `command` through the shell with its stdin piped and its stdout going to
+/// `output`. It's kept out of our process group, so Ctrl-C stopping the recording
+/// doesn't stop it before it has read everything.
+fn spawn(command: &str, output: File) -> io::Result<Child> {
+    #[cfg(unix)]
+    let mut process = {
+        use std::os::unix::process::CommandExt;
+
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(command).process_group(0);
+        process
+    };
+    #[cfg(not(unix))]
+    let mut process = {
+        let mut process = Command::new("cmd");
+        process.arg("/C").arg(command);
+        process
+    };
+    process
+        .stdin(Stdio::piped())
+        .stdout(output)
+        .stderr(Stdio::null())
+        .spawn()
+}
+
+fn wait_or_kill(mut child: Child) -> io::Result<()> {
+    let started = Instant::now();
+    while child.try_wait()?.is_none() {
+        if started.elapsed() >= EXIT_TIMEOUT {
+            child.kill()?;
+            child.wait()?;
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(())
+}
+
+/// Signed 16-bit little-endian samples, clipped to full scale.
+pub fn to_s16le(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|&sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect()
+}
+
+/// 32-bit float little-endian samples, as they are.
+pub fn to_f32le(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn converts_to_little_endian_16_bit() {
+        assert_eq!(
+            to_s16le(&[0.0, 1.0, -2.0, 0.5]),
+            [0, 0, 0xff, 0x7f, 0x01, 0x80, 0xff, 0x3f]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saves_what_the_command_prints() {
+        let dir = std::env::temp_dir().join(format!("micrec-pipe-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("memo.wav");
+        let target = PipeTarget::Command(String::from("wc -c"));
+
+        let mut pipe = PcmPipe::start(&target, PipeFormat::Speech, &recording, 48_000, 2).unwrap();
+        pipe.push(&vec![0.1; 96_000]).unwrap();
+        let transcript = pipe.finish().unwrap().unwrap();
+        let printed = fs::read_to_string(&transcript).unwrap();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(transcript, dir.join("memo-transcript.txt"));
+        // One second of 16 kHz mono, two bytes a sample
+        assert_eq!(printed.trim(), "32000");
+    }
+
+    #[test]
+    fn raw_audio_is_streamed_as_recorded() {
+        let path = std::env::temp_dir().join(format!("micrec-raw-{}.f32", process::id()));
+        let target = PipeTarget::File(path.clone());
+        let samples = [0.25, -0.5, 1.5, 0.0];
+
+        let mut pipe = PcmPipe::start(&target, PipeFormat::Raw, &path, 44_100, 2).unwrap();
+        pipe.push(&samples).unwrap();
+        assert_eq!(pipe.finish().unwrap(), None);
+        let written = fs::read(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(written, to_f32le(&samples));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn drops_what_nobody_reads() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::env::temp_dir().join(format!("micrec-fifo-{}", process::id()));
+        let name = CString::new(path.as_os_str().as_bytes()).unwrap();
+        // SAFETY: `name` is NUL-terminated
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+        let target = PipeTarget::File(path.clone());
+
+        let mut pipe = PcmPipe::start(&target, PipeFormat::Raw, &path, 44_100, 1).unwrap();
+        for _ in 0..QUEUED_BLOCKS + 3 {
+            pipe.push(&[0.5]).unwrap();
+        }
+        let dropped = pipe.dropped();
+        let finished = pipe.finish();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(dropped, 3);
+        assert_eq!(finished.unwrap(), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::{thread, time::Duration};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Stream, StreamConfig};
+
+/// Handle to a playback running on the default output device.
+#[derive(Debug)]
+pub struct Playback {
+    position: Arc<AtomicUsize>,
+    finished: Arc<AtomicBool>,
+    total_frames: usize,
+    sample_rate: u32,
+    stop_tx: Sender<()>,
+}
+
+impl Playback {
+    /// Starts playing interleaved `samples` captured with `config`.
+    ///
+    /// Returns once the output stream is running, or with the reason it couldn't be
+    /// opened.
+    pub fn start(samples: Arc<[f32]>, config: StreamConfig) -> Result<Self> {
+        let channels = config.channels.max(1) as usize;
+        let position = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicBool::new(false));
+        let (stop_tx, stop_rx) = channel::<()>();
+        let (ready_tx, ready_rx) = channel::<Result<(), String>>();
+
+        let playback = Self {
+            position: Arc::clone(&position),
+            finished: Arc::clone(&finished),
+            total_frames: samples.len() / channels,
+            sample_rate: config.sample_rate.0,
+            stop_tx,
+        };
+
+        thread::spawn(move || {
+            let stream = match open_output(samples, config, position, Arc::clone(&finished)) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    finished.store(true, Ordering::Relaxed);
+                    ready_tx.send(Err(format!("{err:#}"))).ok();
+                    return;
+                }
+            };
+            ready_tx.send(Ok(())).ok();
+
+            while stop_rx.try_recv().is_err() && !finished.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            drop(stream);
+            finished.store(true, Ordering::Relaxed);
+        });
+
+        ready_rx
+            .recv()
+            .wrap_err("playback thread exited")?
+            .map_err(|err| eyre!(err))?;
+        Ok(playback)
+    }
+
+    pub fn stop(&self) {
+        self.stop_tx.send(()).ok();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Fraction of the recording played so far, in `0.0..=1.0`.
+    pub fn progress(&self) -> f32 {
+        if self.total_frames == 0 {
+            return 1.0;
+        }
+        self.position.load(Ordering::Relaxed) as f32 / self.total_frames as f32
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(
+            self.position.load(Ordering::Relaxed) as f64 / self.sample_rate.max(1) as f64,
+        )
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.total_frames as f64 / self.sample_rate.max(1) as f64)
+    }
+}
+
+/// Opens and starts the default output device, playing `samples` from the start.
+fn open_output(
+    samples: Arc<[f32]>,
+    config: StreamConfig,
+    position: Arc<AtomicUsize>,
+    finished: Arc<AtomicBool>,
+) -> Result<Stream> {
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .ok_or_else(|| eyre!("no output device found"))?;
+    let output_config: StreamConfig = device
+        .default_output_config()
+        .wrap_err("failed to query the output device")?
+        .into();
+
+    let channels = config.channels.max(1) as usize;
+    let out_channels = output_config.channels.max(1) as usize;
+    let step = config.sample_rate.0 as f64 / output_config.sample_rate.0 as f64;
+    let total_frames = samples.len() / channels;
+    let mut cursor = 0.0_f64;
+
+    let stream_finished = Arc::clone(&finished);
+    let stream = device
+        .build_output_stream(
+            &output_config,
+            move |data: &mut [f32], _| {
+                for frame in data.chunks_mut(out_channels) {
+                    let src_frame = cursor as usize;
+                    if src_frame >= total_frames {
+                        frame.fill(0.0);
+                        stream_finished.store(true, Ordering::Relaxed);
+                        continue;
+                    }
+
+                    // Map output channels onto the captured ones, repeating the
+                    // last captured channel when the output has more.
+                    for (c, out) in frame.iter_mut().enumerate() {
+                        *out = samples[src_frame * channels + c.min(channels - 1)];
+                    }
+                    cursor += step;
+                }
+                position.store((cursor as usize).min(total_frames), Ordering::Relaxed);
+            },
+            // Printing would corrupt the TUI; a broken output just ends playback
+            move |_| finished.store(true, Ordering::Relaxed),
+            None,
+        )
+        .wrap_err("failed to open the output stream")?;
+
+    stream
+        .play()
+        .wrap_err("failed to start the output stream")?;
+    Ok(stream)
+}
@@ -0,0 +1,298 @@
+//! Checklist shown before a take is armed: is the input there, is there room on
+//! disk, does the input hear something without clipping, are headphones plugged
+//! in. Which checks run comes from the `[preflight]` section of the config.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::HostId;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Row, Table, Widget},
+};
+
+use crate::config::{KeyBindings, PreflightConfig};
+use micrec::engine::{self, CaptureSource, SILENCE_THRESHOLD_DBFS};
+use micrec::library::{self, format_size};
+use micrec::permission::{self, Permission};
+use micrec::probe;
+
+/// How long the input is listened to for the level check.
+const LEVEL_CHECK_TIME: Duration = Duration::from_secs(3);
+/// Peak level from which the input counts as clipping.
+const CLIP_DBFS: f32 = -1.0;
+/// Words in an output device's name that suggest headphones.
+const HEADPHONE_NAMES: [&str; 6] = [
+    "headphone",
+    "headset",
+    "earphone",
+    "earbud",
+    "airpods",
+    "buds",
+];
+
+/// What the app should do after the checklist handled a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightAction {
+    None,
+    /// Start recording.
+    Arm,
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Device,
+    DiskSpace,
+    Levels,
+    Headphones,
+}
+
+impl Item {
+    fn label(self) -> &'static str {
+        match self {
+            Item::Device => "Input device",
+            Item::DiskSpace => "Disk space",
+            Item::Levels => "Input level",
+            Item::Headphones => "Headphones",
+        }
+    }
+}
+
+/// What a check found, or why it failed.
+type Outcome = Result<String, String>;
+
+#[derive(Debug)]
+pub struct Preflight {
+    config: PreflightConfig,
+    host: Option<HostId>,
+    capture: CaptureSource,
+    device: Option<String>,
+    output_dir: PathBuf,
+    keys: KeyBindings,
+    /// Every check to run, with its outcome once known.
+    checks: Vec<(Item, Option<Outcome>)>,
+    /// Outcomes from the thread running the checks.
+    results: Receiver<(Item, Outcome)>,
+    /// Set when Enter was pressed with checks failing.
+    blocked: bool,
+}
+
+impl Preflight {
+    /// Starts checking `device` on `host` and `output_dir` in the background.
+    pub fn start(
+        config: PreflightConfig,
+        host: Option<HostId>,
+        capture: CaptureSource,
+        device: Option<String>,
+        output_dir: PathBuf,
+        keys: KeyBindings,
+    ) -> Self {
+        let (_, results) = mpsc::channel();
+        let mut preflight = Self {
+            config,
+            host,
+            capture,
+            device,
+            output_dir,
+            keys,
+            checks: Vec::new(),
+            results,
+            blocked: false,
+        };
+        preflight.run_checks();
+        preflight
+    }
+
+    fn run_checks(&mut self) {
+        let mut items = vec![Item::Device, Item::DiskSpace];
+        // There may well be nothing playing yet when recording the output
+        if self.config.check_levels && self.capture == CaptureSource::Microphone {
+            items.push(Item::Levels);
+        }
+        if self.config.require_headphones {
+            items.push(Item::Headphones);
+        }
+        self.checks = items.iter().map(|&item| (item, None)).collect();
+        self.blocked = false;
+
+        let (results_tx, results) = mpsc::channel();
+        self.results = results;
+        let (host, capture) = (self.host, self.capture);
+        let device = self.device.clone();
+        let output_dir = self.output_dir.clone();
+        let min_free = self.config.min_free_mb * 1024 * 1024;
+        thread::spawn(move || {
+            for item in items {
+                let outcome = match item {
+                    Item::Device => check_device(host, capture, device.as_deref()),
+                    Item::DiskSpace => check_disk_space(&output_dir, min_free),
+                    Item::Levels => check_levels(host, device.as_deref()),
+                    Item::Headphones => check_headphones(host),
+                };
+                if results_tx.send((item, outcome)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Picks up the outcomes of checks that finished since the last call.
+    pub fn poll(&mut self) {
+        for (item, outcome) in self.results.try_iter() {
+            if let Some((_, slot)) = self.checks.iter_mut().find(|(i, _)| *i == item) {
+                *slot = Some(outcome);
+            }
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.checks.iter().all(|(_, outcome)| outcome.is_some())
+    }
+
+    fn failures(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Some(Err(_))))
+            .count()
+    }
+
+    pub fn handle_key(&mut self, key_event: KeyEvent) -> PreflightAction {
+        match key_event.code {
+            KeyCode::Enter if self.is_done() && self.failures() == 0 => PreflightAction::Arm,
+            KeyCode::Enter => {
+                self.blocked = true;
+                PreflightAction::None
+            }
+            _ if self.keys.skip_checks.matches(&key_event) => PreflightAction::Arm,
+            _ if self.keys.retry.matches(&key_event) => {
+                self.run_checks();
+                PreflightAction::None
+            }
+            _ if self.keys.quit.matches(&key_event) => PreflightAction::Quit,
+            _ => PreflightAction::None,
+        }
+    }
+}
+
+fn check_device(host: Option<HostId>, capture: CaptureSource, name: Option<&str>) -> Outcome {
+    if capture == CaptureSource::Microphone && permission::status() == Permission::Denied {
+        return Err(permission::instructions().to_string());
+    }
+    let device = engine::open_host(host)
+        .and_then(|host| engine::select_capture_device(&host, capture, name))
+        .map_err(|err| format!("{err:#}"))?;
+    Ok(device
+        .name()
+        .unwrap_or_else(|_| String::from("default input")))
+}
+
+fn check_disk_space(dir: &Path, min_free: u64) -> Outcome {
+    let free =
+        library::free_space(dir).map_err(|err| format!("can't check {}: {err}", dir.display()))?;
+    let message = format!("{} free in {}", format_size(free), dir.display());
+    if free >= min_free {
+        Ok(message)
+    } else {
+        Err(format!("only {message}, need {}", format_size(min_free)))
+    }
+}
+
+fn check_levels(host: Option<HostId>, device: Option<&str>) -> Outcome {
+    let peak =
+        probe::input_peak(host, device, LEVEL_CHECK_TIME).map_err(|err| format!("{err:#}"))?;
+    if peak < SILENCE_THRESHOLD_DBFS {
+        Err(String::from(
+            "no signal; check the cable, mute switch and input gain",
+        ))
+    } else if peak >= CLIP_DBFS {
+        Err(format!(
+            "peaks at {peak:.1} dBFS and clips; turn the gain down"
+        ))
+    } else {
+        Ok(format!("peaks at {peak:.1} dBFS"))
+    }
+}
+
+fn check_headphones(host: Option<HostId>) -> Outcome {
+    let name = engine::open_host(host)
+        .map_err(|err| format!("{err:#}"))?
+        .default_output_device()
+        .ok_or_else(|| String::from("no output device"))?
+        .name()
+        .map_err(|err| err.to_string())?;
+    let lower = name.to_lowercase();
+    if HEADPHONE_NAMES.iter().any(|word| lower.contains(word)) {
+        Ok(name)
+    } else {
+        Err(format!(
+            "output is {name}, which doesn't look like headphones"
+        ))
+    }
+}
+
+impl Widget for &Preflight {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let keys = &self.keys;
+        let mut instructions = Line::default();
+        for (action, key) in [
+            (" Record ", String::from("<Enter>")),
+            (" Record anyway ", keys.skip_checks.label()),
+            (" Check again ", keys.retry.label()),
+            (" Quit ", keys.quit.label()),
+        ] {
+            instructions.push_span(action);
+            instructions.push_span(key.blue().bold());
+        }
+        instructions.push_span(" ");
+
+        let failures = self.failures();
+        let status = if !self.is_done() {
+            " Checking...".bold()
+        } else if failures == 0 {
+            " All checks passed".green().bold()
+        } else if self.blocked {
+            format!(
+                " {failures} failed; fix and press {} or record anyway with {}",
+                keys.retry.label(),
+                keys.skip_checks.label()
+            )
+            .red()
+            .bold()
+        } else {
+            format!(" {failures} failed").red().bold()
+        };
+
+        let block = Block::new()
+            .title_top(Line::from(" Pre-flight checklist ".bold()))
+            .title_bottom(Line::from(status).left_aligned())
+            .title_bottom(instructions.right_aligned());
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let rows = self.checks.iter().map(|(item, outcome)| match outcome {
+            None if *item == Item::Levels => {
+                Row::new(vec!["…", item.label(), "Listening, say something"])
+            }
+            None => Row::new(vec!["…", item.label(), "Checking"]),
+            Some(Ok(message)) => Row::new(vec!["✔", item.label(), message]).green(),
+            Some(Err(message)) => Row::new(vec!["✖", item.label(), message]).red(),
+        });
+        Table::new(
+            rows,
+            [
+                Constraint::Length(2),
+                Constraint::Length(14),
+                Constraint::Fill(1),
+            ],
+        )
+        .render(inner, buf);
+    }
+}
@@ -0,0 +1,92 @@
+//! Listens to inputs for a moment: to find one that actually hears something when
+//! the selected one only delivers silence, or to check the level before a take.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::HostId;
+
+use crate::dsp;
+use crate::engine::{self, SILENCE_THRESHOLD_DBFS};
+
+/// How long each device is listened to.
+const LISTEN_TIME: Duration = Duration::from_millis(500);
+
+/// An input device that picked up signal while probing.
+#[derive(Debug, Clone)]
+pub struct ActiveInput {
+    pub name: String,
+    pub peak_dbfs: f32,
+}
+
+/// Listens briefly to every input device of `host` except `current` and returns the loudest
+/// one above the silence threshold, if any.
+///
+/// Devices that are busy or can't capture `f32` are skipped.
+pub fn find_active_input(
+    host: Option<HostId>,
+    current: Option<&str>,
+) -> Result<Option<ActiveInput>> {
+    let host = engine::open_host(host)?;
+    let current = match current {
+        Some(name) => Some(name.to_string()),
+        None => host.default_input_device().and_then(|d| d.name().ok()),
+    };
+
+    let mut loudest: Option<ActiveInput> = None;
+    for device in host
+        .input_devices()
+        .wrap_err("failed to list input devices")?
+    {
+        let Ok(name) = device.name() else { continue };
+        if current.as_deref() == Some(name.as_str()) {
+            continue;
+        }
+        let Some(peak_dbfs) = listen(&device, LISTEN_TIME) else {
+            continue;
+        };
+        if peak_dbfs >= SILENCE_THRESHOLD_DBFS
+            && loudest.as_ref().is_none_or(|l| peak_dbfs > l.peak_dbfs)
+        {
+            loudest = Some(ActiveInput { name, peak_dbfs });
+        }
+    }
+
+    Ok(loudest)
+}
+
+/// Peak level in dBFS heard on the input of `host` called `name`, or the default
+/// input, over `time`.
+pub fn input_peak(host: Option<HostId>, name: Option<&str>, time: Duration) -> Result<f32> {
+    let device = engine::select_input_device(&engine::open_host(host)?, name)?;
+    listen(&device, time).ok_or_else(|| eyre!("couldn't open the input device"))
+}
+
+/// Peak level heard on `device` over `time`, or `None` if it can't be opened.
+fn listen(device: &cpal::Device, time: Duration) -> Option<f32> {
+    let config = engine::input_stream_config(device, None).ok()?;
+    let peak = Arc::new(Mutex::new(0.0_f32));
+
+    let stream_peak = Arc::clone(&peak);
+    let stream = device
+        .build_input_stream(
+            &config,
+            move |data: &[f32], _| {
+                if let Ok(mut peak) = stream_peak.lock() {
+                    *peak = peak.max(dsp::peak(data));
+                }
+            },
+            |_| {},
+            None,
+        )
+        .ok()?;
+    stream.play().ok()?;
+    thread::sleep(time);
+    drop(stream);
+
+    let peak = *peak.lock().ok()?;
+    Some(dsp::to_dbfs(peak))
+}
@@ -0,0 +1,281 @@
+//! Effects applied to the input before it's written: a high-pass filter for rumble
+//! and a noise gate for the gaps between words, plus a low-pass for resampling.
+//! Each is a [`Processor`], and a [`Chain`] runs several in order.
+
+use std::f32::consts::PI;
+
+use crate::dsp;
+
+/// Cutoff of the high-pass filter unless configured otherwise, below the voice
+/// but above traffic, air conditioning and handling noise.
+pub const DEFAULT_HIGH_PASS_HZ: f32 = 80.0;
+/// Time for the gate to open once the level goes above the threshold.
+const GATE_ATTACK_SECS: f32 = 0.001;
+/// Time the gate stays open after the level drops, so word endings aren't cut.
+const GATE_HOLD_SECS: f32 = 0.2;
+/// Time for the gate to close after the hold.
+const GATE_RELEASE_SECS: f32 = 0.05;
+
+/// An effect working on blocks of interleaved samples, in place. Keeps whatever
+/// state it needs between blocks.
+pub trait Processor: Send {
+    fn process(&mut self, samples: &mut [f32]);
+
+    /// Forgets the state built up from earlier blocks, e.g. after being bypassed
+    /// for a while.
+    fn reset(&mut self) {}
+}
+
+/// Processors run one after another, each of which can be bypassed.
+#[derive(Default)]
+pub struct Chain {
+    stages: Vec<(Box<dyn Processor>, bool)>,
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `processor`, and returns its index for [`Chain::set_enabled`].
+    pub fn push(&mut self, processor: impl Processor + 'static, enabled: bool) -> usize {
+        self.stages.push((Box::new(processor), enabled));
+        self.stages.len() - 1
+    }
+
+    /// Bypasses stage `index`, or brings it back with its state reset.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some((processor, on)) = self.stages.get_mut(index) {
+            if enabled && !*on {
+                processor.reset();
+            }
+            *on = enabled;
+        }
+    }
+
+    /// Whether any stage would change the audio.
+    pub fn is_active(&self) -> bool {
+        self.stages.iter().any(|(_, enabled)| *enabled)
+    }
+}
+
+impl Processor for Chain {
+    fn process(&mut self, samples: &mut [f32]) {
+        for (processor, enabled) in &mut self.stages {
+            if *enabled {
+                processor.process(samples);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for (processor, _) in &mut self.stages {
+            processor.reset();
+        }
+    }
+}
+
+/// Second-order filter over interleaved channels.
+struct Biquad {
+    /// `b0, b1, b2, a1, a2`, normalized so that `a0` is 1.
+    coefficients: [f32; 5],
+    /// Last two inputs and outputs of each channel.
+    state: Vec<[f32; 4]>,
+}
+
+impl Biquad {
+    /// Butterworth (Q = 1/sqrt(2)) high- or low-pass, from the Audio EQ Cookbook.
+    fn butterworth(high_pass: bool, cutoff_hz: f32, sample_rate: u32, channels: usize) -> Self {
+        let nyquist = sample_rate as f32 / 2.0;
+        let omega = 2.0 * PI * cutoff_hz.clamp(1.0, nyquist * 0.99) / sample_rate as f32;
+        let alpha = omega.sin() / 2.0_f32.sqrt();
+        let cos = omega.cos();
+        let a0 = 1.0 + alpha;
+        let (b0, b1) = if high_pass {
+            ((1.0 + cos) / 2.0, -(1.0 + cos))
+        } else {
+            ((1.0 - cos) / 2.0, 1.0 - cos)
+        };
+        Self {
+            coefficients: [
+                b0 / a0,
+                b1 / a0,
+                b0 / a0,
+                -2.0 * cos / a0,
+                (1.0 - alpha) / a0,
+            ],
+            state: vec![[0.0; 4]; channels.max(1)],
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        for frame in samples.chunks_mut(self.state.len()) {
+            for (sample, [x1, x2, y1, y2]) in frame.iter_mut().zip(&mut self.state) {
+                let x = *sample;
+                let y = b0 * x + b1 * *x1 + b2 * *x2 - a1 * *y1 - a2 * *y2;
+                (*x2, *x1, *y2, *y1) = (*x1, x, *y1, y);
+                *sample = y;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.fill([0.0; 4]);
+    }
+}
+
+/// Second-order Butterworth high-pass filter, 12 dB per octave below the cutoff.
+pub struct HighPass(Biquad);
+
+impl HighPass {
+    pub fn new(cutoff_hz: f32, sample_rate: u32, channels: usize) -> Self {
+        Self(Biquad::butterworth(true, cutoff_hz, sample_rate, channels))
+    }
+}
+
+impl Processor for HighPass {
+    fn process(&mut self, samples: &mut [f32]) {
+        self.0.process(samples);
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+/// Second-order Butterworth low-pass filter, 12 dB per octave above the cutoff.
+pub struct LowPass(Biquad);
+
+impl LowPass {
+    pub fn new(cutoff_hz: f32, sample_rate: u32, channels: usize) -> Self {
+        Self(Biquad::butterworth(false, cutoff_hz, sample_rate, channels))
+    }
+}
+
+impl Processor for LowPass {
+    fn process(&mut self, samples: &mut [f32]) {
+        self.0.process(samples);
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+/// Mutes the input while its level stays under a threshold, judged on the RMS
+/// of each block across all channels.
+pub struct NoiseGate {
+    threshold_dbfs: f32,
+    channels: usize,
+    sample_rate: f32,
+    /// Current gain, ramping between 0 and 1.
+    gain: f32,
+    /// Samples per channel left before the gate starts closing.
+    hold: usize,
+}
+
+impl NoiseGate {
+    pub fn new(threshold_dbfs: f32, sample_rate: u32, channels: usize) -> Self {
+        Self {
+            threshold_dbfs,
+            channels: channels.max(1),
+            sample_rate: sample_rate as f32,
+            gain: 0.0,
+            hold: 0,
+        }
+    }
+
+    /// Per-sample smoothing factor reaching about 63% of the way in `secs`.
+    fn coefficient(&self, secs: f32) -> f32 {
+        (-1.0 / (secs * self.sample_rate)).exp()
+    }
+}
+
+impl Processor for NoiseGate {
+    fn process(&mut self, samples: &mut [f32]) {
+        let frames = samples.len() / self.channels;
+        if dsp::to_dbfs(dsp::rms(samples)) >= self.threshold_dbfs {
+            self.hold = (GATE_HOLD_SECS * self.sample_rate) as usize + frames;
+        }
+
+        let attack = self.coefficient(GATE_ATTACK_SECS);
+        let release = self.coefficient(GATE_RELEASE_SECS);
+        for frame in samples.chunks_mut(self.channels) {
+            let (target, smoothing) = if self.hold > 0 {
+                self.hold -= 1;
+                (1.0, attack)
+            } else {
+                (0.0, release)
+            };
+            self.gain = target + (self.gain - target) * smoothing;
+            frame.iter_mut().for_each(|sample| *sample *= self.gain);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.gain = 0.0;
+        self.hold = 0;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A tone at half of full scale.
+    pub(crate) fn sine(frequency: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| 0.5 * (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn high_pass_cuts_rumble_and_keeps_the_voice() {
+        let mut rumble = sine(20.0, 48_000, 48_000);
+        let mut voice = sine(1000.0, 48_000, 48_000);
+        HighPass::new(80.0, 48_000, 1).process(&mut rumble);
+        HighPass::new(80.0, 48_000, 1).process(&mut voice);
+
+        // Past the filter's settling time
+        assert!(dsp::to_dbfs(dsp::rms(&rumble[24_000..])) < dsp::to_dbfs(0.5) - 20.0);
+        let voice_change = dsp::rms(&voice[24_000..]) / dsp::rms(&sine(1000.0, 48_000, 24_000));
+        assert!((dsp::to_dbfs(voice_change)).abs() < 0.1);
+    }
+
+    #[test]
+    fn gate_mutes_quiet_blocks_and_passes_loud_ones() {
+        let mut gate = NoiseGate::new(-40.0, 48_000, 2);
+        let mut hiss = vec![0.001; 9600];
+        gate.process(&mut hiss);
+        assert!(dsp::peak(&hiss) < 1e-6);
+
+        let mut speech = sine(440.0, 48_000, 9600);
+        gate.process(&mut speech);
+        assert!(dsp::rms(&speech[960..]) > 0.3);
+    }
+
+    #[test]
+    fn gate_holds_open_after_speech() {
+        let mut gate = NoiseGate::new(-40.0, 48_000, 1);
+        gate.process(&mut sine(440.0, 48_000, 4800));
+
+        // Quiet, but within the hold time
+        let mut tail = vec![0.001; 4800];
+        gate.process(&mut tail);
+        assert!((tail[4799] - 0.001).abs() < 1e-5);
+    }
+
+    #[test]
+    fn chain_skips_bypassed_stages() {
+        let mut chain = Chain::new();
+        let gate = chain.push(NoiseGate::new(-40.0, 48_000, 1), false);
+        let mut samples = vec![0.001; 480];
+        chain.process(&mut samples);
+        assert_eq!(samples, vec![0.001; 480]);
+
+        chain.set_enabled(gate, true);
+        chain.process(&mut samples);
+        assert!(dsp::peak(&samples) < 1e-4);
+    }
+}
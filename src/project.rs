@@ -0,0 +1,193 @@
+//! Editor projects written next to a recording, so a take opens with its regions
+//! already in place.
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::encoder::OutputFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectFormat {
+    /// Audacity label track (`<file>-labels.txt`), for File > Import > Labels
+    Audacity,
+    /// Reaper project (`<file>.rpp`) with the recording on a track
+    Reaper,
+}
+
+/// A named span of the take. Zero-length regions are point markers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub start: Duration,
+    pub end: Duration,
+    pub name: String,
+}
+
+/// What the project refers to.
+#[derive(Debug, Clone)]
+pub struct Take<'a> {
+    pub recording: &'a Path,
+    pub format: OutputFormat,
+    /// Safety track recorded alongside, put on a muted track of its own.
+    pub safety_track: Option<&'a Path>,
+    pub duration: Duration,
+    pub sample_rate: u32,
+    pub regions: &'a [Region],
+}
+
+/// Writes the project for `take` next to its recording and returns its path. An
+/// existing file is never overwritten.
+pub fn export(format: ProjectFormat, take: &Take) -> io::Result<PathBuf> {
+    let stem = take
+        .recording
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let (name, contents) = match format {
+        ProjectFormat::Audacity => (format!("{stem}-labels.txt"), audacity_labels(take.regions)),
+        ProjectFormat::Reaper => (format!("{stem}.rpp"), reaper_project(take)),
+    };
+    let path = take.recording.with_file_name(name);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(path)
+}
+
+/// Audacity's tab-separated label format: start and end in seconds, then the
+/// label text.
+pub fn audacity_labels(regions: &[Region]) -> String {
+    let mut labels = String::new();
+    for region in regions {
+        writeln!(
+            labels,
+            "{:.6}\t{:.6}\t{}",
+            region.start.as_secs_f64(),
+            region.end.as_secs_f64(),
+            single_line(&region.name)
+        )
+        .unwrap();
+    }
+    labels
+}
+
+/// A minimal Reaper project: the recording as one item on a track, the safety
+/// track muted below it, and the regions as Reaper regions and markers. Files are
+/// referenced by name, which Reaper resolves next to the project.
+pub fn reaper_project(take: &Take) -> String {
+    let mut rpp = String::from("<REAPER_PROJECT 0.1 \"6.0\" 0\n");
+    writeln!(rpp, "  SAMPLERATE {} 0 0", take.sample_rate).unwrap();
+    for (index, region) in take.regions.iter().enumerate() {
+        let index = index + 1;
+        let name = quoted(&region.name);
+        let start = region.start.as_secs_f64();
+        if region.end > region.start {
+            writeln!(rpp, "  MARKER {index} {start:.6} {name} 1").unwrap();
+            let end = region.end.as_secs_f64();
+            writeln!(rpp, "  MARKER {index} {end:.6} \"\" 1").unwrap();
+        } else {
+            writeln!(rpp, "  MARKER {index} {start:.6} {name} 0").unwrap();
+        }
+    }
+    reaper_track(&mut rpp, take.recording, take, false);
+    if let Some(safety) = take.safety_track {
+        reaper_track(&mut rpp, safety, take, true);
+    }
+    rpp.push_str(">\n");
+    rpp
+}
+
+fn reaper_track(rpp: &mut String, file: &Path, take: &Take, muted: bool) {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let source = match take.format {
+        OutputFormat::Wav => "WAVE",
+        OutputFormat::Flac => "FLAC",
+        OutputFormat::Opus => "OPUS",
+    };
+    rpp.push_str("  <TRACK\n");
+    writeln!(rpp, "    NAME {}", quoted(&name)).unwrap();
+    writeln!(rpp, "    MUTESOLO {} 0 0", u8::from(muted)).unwrap();
+    rpp.push_str("    <ITEM\n      POSITION 0\n");
+    writeln!(rpp, "      LENGTH {:.6}", take.duration.as_secs_f64()).unwrap();
+    writeln!(rpp, "      NAME {}", quoted(&name)).unwrap();
+    writeln!(rpp, "      <SOURCE {source}").unwrap();
+    writeln!(rpp, "        FILE {}", quoted(&name)).unwrap();
+    rpp.push_str("      >\n    >\n  >\n");
+}
+
+/// Quotes a string for an RPP line. Reaper has no escapes, so a name containing
+/// double quotes is wrapped in single quotes instead, and those are dropped if it
+/// has both.
+fn quoted(text: &str) -> String {
+    let text = single_line(text);
+    if !text.contains('"') {
+        format!("\"{text}\"")
+    } else {
+        format!("'{}'", text.replace('\'', ""))
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\n', '\r', '\t'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions() -> Vec<Region> {
+        vec![
+            Region {
+                start: Duration::ZERO,
+                end: Duration::from_millis(12_500),
+                name: String::from("take"),
+            },
+            Region {
+                start: Duration::from_secs(3),
+                end: Duration::from_secs(3),
+                name: String::from("cough"),
+            },
+        ]
+    }
+
+    #[test]
+    fn audacity_labels_are_tab_separated() {
+        assert_eq!(
+            audacity_labels(&regions()),
+            "0.000000\t12.500000\ttake\n3.000000\t3.000000\tcough\n"
+        );
+    }
+
+    #[test]
+    fn reaper_project_references_the_files() {
+        let regions = regions();
+        let take = Take {
+            recording: Path::new("/recordings/memo.flac"),
+            format: OutputFormat::Flac,
+            safety_track: Some(Path::new("/recordings/memo-safety.flac")),
+            duration: Duration::from_millis(12_500),
+            sample_rate: 48_000,
+            regions: &regions,
+        };
+        let rpp = reaper_project(&take);
+
+        assert!(rpp.contains("  MARKER 1 0.000000 \"take\" 1\n  MARKER 1 12.500000 \"\" 1\n"));
+        assert!(rpp.contains("  MARKER 2 3.000000 \"cough\" 0\n"));
+        assert!(rpp.contains("<SOURCE FLAC\n        FILE \"memo.flac\"\n"));
+        assert!(rpp.contains("NAME \"memo-safety.flac\"\n    MUTESOLO 1 0 0\n"));
+        assert!(rpp.ends_with(">\n"));
+    }
+
+    #[test]
+    fn quotes_names_with_quotes() {
+        assert_eq!(quoted("the \"good\" take"), "'the \"good\" take'");
+    }
+}
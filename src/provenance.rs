@@ -0,0 +1,184 @@
+//! A JSON file next to each recording saying how it was made: the device and the
+//! config it ran at, the processing applied, the markers and a summary of the
+//! levels, so a take can be audited or reproduced later.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::dsp;
+use crate::encoder::OutputFormat;
+use crate::engine::{CaptureSource, OutputChannels};
+use crate::markers::Marker;
+
+/// Everything recorded about a take.
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    pub file: String,
+    pub app: &'static str,
+    pub version: &'static str,
+    /// When capture started, in RFC 3339 local time.
+    pub started_at: String,
+    pub device: DeviceInfo,
+    pub stream: StreamInfo,
+    pub output: OutputInfo,
+    pub processing: ProcessingInfo,
+    pub markers: Vec<Marker>,
+    pub summary: Summary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub capture: CaptureSource,
+    pub name: String,
+    /// Devices capture resumed on after the input went away, in order.
+    pub reconnected_to: Vec<String>,
+    /// Devices recorded at the same time, whose channels follow this one's.
+    pub extra_devices: Vec<String>,
+}
+
+/// The config negotiated with the device.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputInfo {
+    pub format: OutputFormat,
+    pub channels: OutputChannels,
+    pub sample_rate: u32,
+    /// File name of the safety track, if one was recorded.
+    pub safety_track: Option<String>,
+}
+
+/// The processing chain as it stood at the end of the take, in the order it's
+/// applied. Filters that were off are `None`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessingInfo {
+    /// Input channels, counted from 1.
+    pub inverted_channels: Vec<usize>,
+    pub gain_db: f32,
+    pub soft_limit: bool,
+    pub noise_reduction: bool,
+    pub high_pass_hz: Option<f32>,
+    pub gate_threshold_dbfs: Option<f32>,
+}
+
+/// Levels over the whole file, as written.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Summary {
+    pub duration_secs: f64,
+    pub frames: u64,
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+    /// Samples at or beyond full scale.
+    pub clipped_samples: u64,
+}
+
+/// Builds a [`Summary`] from blocks of samples as they're written.
+#[derive(Debug, Clone, Default)]
+pub struct LevelSummary {
+    peak: f32,
+    sum_of_squares: f64,
+    samples: u64,
+    clipped: u64,
+}
+
+impl LevelSummary {
+    pub fn add(&mut self, samples: &[f32]) {
+        self.peak = self.peak.max(dsp::peak(samples));
+        self.sum_of_squares += samples.iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+        self.samples += samples.len() as u64;
+        self.clipped += samples.iter().filter(|s| s.abs() >= 1.0).count() as u64;
+    }
+
+    pub fn summary(&self, frames: u64, sample_rate: u32) -> Summary {
+        let rms = (self.sum_of_squares / self.samples.max(1) as f64).sqrt() as f32;
+        Summary {
+            duration_secs: frames as f64 / sample_rate.max(1) as f64,
+            frames,
+            peak_dbfs: dsp::to_dbfs(self.peak),
+            rms_dbfs: dsp::to_dbfs(rms),
+            clipped_samples: self.clipped,
+        }
+    }
+}
+
+/// Writes `provenance` as `<file>-info.json` next to `recording`, and returns its
+/// path. An existing file is never overwritten.
+pub fn export(recording: &Path, provenance: &Provenance) -> io::Result<PathBuf> {
+    let stem = recording.file_stem().unwrap_or_default().to_string_lossy();
+    let path = recording.with_file_name(format!("{stem}-info.json"));
+    let json = serde_json::to_string_pretty(provenance).map_err(io::Error::other)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    file.write_all(json.as_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_levels_across_blocks() {
+        let mut levels = LevelSummary::default();
+        levels.add(&[0.5, -0.5]);
+        levels.add(&[1.0, -0.5]);
+        let summary = levels.summary(2, 48_000);
+
+        assert_eq!(summary.peak_dbfs, 0.0);
+        assert!((summary.rms_dbfs - dsp::to_dbfs(0.661_437_8)).abs() < 0.01);
+        assert_eq!(summary.clipped_samples, 1);
+    }
+
+    #[test]
+    fn serializes_the_chain_as_applied() {
+        let provenance = Provenance {
+            file: String::from("memo.wav"),
+            app: "micrec",
+            version: "0.1.0",
+            started_at: String::from("2024-05-01T10:00:00+02:00"),
+            device: DeviceInfo {
+                capture: CaptureSource::Microphone,
+                name: String::from("USB Mic"),
+                reconnected_to: Vec::new(),
+                extra_devices: Vec::new(),
+            },
+            stream: StreamInfo {
+                sample_rate: 48_000,
+                channels: 1,
+            },
+            output: OutputInfo {
+                format: OutputFormat::Flac,
+                channels: OutputChannels::Mono,
+                sample_rate: 48_000,
+                safety_track: None,
+            },
+            processing: ProcessingInfo {
+                inverted_channels: vec![2],
+                gain_db: 6.0,
+                soft_limit: true,
+                noise_reduction: false,
+                high_pass_hz: Some(80.0),
+                gate_threshold_dbfs: None,
+            },
+            markers: Vec::new(),
+            summary: LevelSummary::default().summary(0, 48_000),
+        };
+        let json: serde_json::Value = serde_json::to_value(&provenance).unwrap();
+
+        assert_eq!(json["output"]["format"], "flac");
+        assert_eq!(json["output"]["channels"], "mono");
+        assert_eq!(json["processing"]["high_pass_hz"], 80.0);
+        assert!(json["processing"]["gate_threshold_dbfs"].is_null());
+        assert_eq!(json["summary"]["frames"], 0);
+    }
+}
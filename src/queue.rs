@@ -0,0 +1,152 @@
+//! Hands captured audio from the input stream's callback to the capture thread.
+//! The callback runs on the audio system's real-time thread, so it only copies
+//! into a lock-free ring buffer allocated up front: no allocating, no locking, and
+//! no waiting on a capture thread that has fallen behind.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use color_eyre::eyre::{bail, Result};
+use cpal::StreamConfig;
+use ringbuf::traits::{Consumer, Observer, Producer};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+
+/// Audio the queue holds, far more than the capture thread ever leaves in it
+/// unless writing the file stalls.
+const QUEUE_LENGTH: Duration = Duration::from_secs(10);
+
+/// The capture thread's end of the queue.
+///
+/// Once the backlog fills three quarters of the queue, the oldest audio is dropped
+/// down to half, so the callback always has room for the newest. A block that
+/// still doesn't fit, because the capture thread stopped reading altogether, is
+/// dropped instead. Either way counts as an overrun.
+pub struct CaptureQueue {
+    rb: Arc<HeapRb<f32>>,
+    consumer: HeapCons<f32>,
+    channels: usize,
+    overruns: Arc<AtomicU64>,
+}
+
+/// The callback's end of a [`CaptureQueue`].
+pub struct QueueWriter {
+    producer: HeapProd<f32>,
+    overruns: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for CaptureQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureQueue")
+            .field("channels", &self.channels)
+            .field("overruns", &self.overruns())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CaptureQueue {
+    /// A queue for audio in the layout of `config`.
+    pub fn new(config: &StreamConfig) -> Self {
+        let channels = config.channels.max(1) as usize;
+        let frames = (QUEUE_LENGTH.as_secs_f64() * config.sample_rate.0 as f64) as usize;
+        Self::with_capacity(frames.max(1) * channels, channels)
+    }
+
+    fn with_capacity(samples: usize, channels: usize) -> Self {
+        let rb = Arc::new(HeapRb::new(samples));
+        Self {
+            consumer: HeapCons::new(Arc::clone(&rb)),
+            rb,
+            channels,
+            overruns: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The end to give a new stream's callback. There can only be one at a time,
+    /// so this fails while the previous stream's callback is still around.
+    pub fn writer(&self) -> Result<QueueWriter> {
+        if self.rb.write_is_held() {
+            bail!("the previous input stream is still running");
+        }
+        Ok(QueueWriter {
+            producer: HeapProd::new(Arc::clone(&self.rb)),
+            overruns: Arc::clone(&self.overruns),
+        })
+    }
+
+    /// Takes everything queued so far, if anything.
+    pub fn pop(&mut self) -> Option<Arc<[f32]>> {
+        let capacity = self.consumer.capacity().get();
+        let backlog = self.consumer.occupied_len();
+        if backlog > capacity / 4 * 3 {
+            let excess = backlog - capacity / 2;
+            self.consumer.skip(excess - excess % self.channels);
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let available = self.consumer.occupied_len();
+        let len = available - available % self.channels;
+        if len == 0 {
+            return None;
+        }
+        let mut block = vec![0.0; len];
+        self.consumer.pop_slice(&mut block);
+        Some(Arc::from(block))
+    }
+
+    /// How many times audio was dropped so far.
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+}
+
+impl QueueWriter {
+    /// Queues a block of interleaved samples, or drops all of it if there's no
+    /// room.
+    pub fn push(&mut self, samples: &[f32]) {
+        if self.producer.vacant_len() < samples.len() {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.producer.push_slice(samples);
+    }
+
+    /// How many times audio was dropped so far, the same as
+    /// [`CaptureQueue::overruns`].
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_audio_goes_first() {
+        let mut queue = CaptureQueue::with_capacity(16, 2);
+        let mut writer = queue.writer().unwrap();
+        assert!(queue.writer().is_err());
+
+        writer.push(&[1.0, 1.0, 2.0, 2.0]);
+        assert_eq!(queue.pop().as_deref(), Some(&[1.0, 1.0, 2.0, 2.0][..]));
+        assert_eq!(queue.pop(), None);
+
+        // Past three quarters full, the backlog is cut down to the newest half
+        for frame in 1..=7 {
+            writer.push(&[frame as f32; 2]);
+        }
+        let newest: Vec<f32> = (4..=7).flat_map(|frame| [frame as f32; 2]).collect();
+        assert_eq!(queue.pop().as_deref(), Some(&newest[..]));
+        assert_eq!(queue.overruns(), 1);
+
+        // A block with no room left is dropped whole
+        writer.push(&[0.0; 18]);
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.overruns(), 2);
+
+        drop(writer);
+        assert!(queue.writer().is_ok());
+    }
+}
@@ -0,0 +1,232 @@
+//! Streaming sample rate conversion: [`Resampler`] is cheap linear interpolation
+//! for a single channel, good enough for speech, and [`FileResampler`] converts a
+//! take to the rate of its file at full quality.
+
+use color_eyre::eyre::{Result, WrapErr};
+use rubato::{FftFixedIn, Resampler as _};
+
+use crate::processing::{LowPass, Processor};
+
+/// Low-pass cutoff as a fraction of the target rate, a little under its Nyquist
+/// frequency.
+const ANTI_ALIAS_CUTOFF: f32 = 0.45;
+/// Frames handed to rubato at a time.
+const CHUNK_FRAMES: usize = 1024;
+
+/// Converts a mono stream from one rate to another, block by block.
+pub struct Resampler {
+    /// Input samples per output sample.
+    step: f64,
+    /// Where the next output sample falls, in input samples from the start of the
+    /// next block. -1 is the last sample of the previous block.
+    position: f64,
+    previous: f32,
+    /// Two filters in a row for a 24 dB per octave slope.
+    anti_alias: Option<[LowPass; 2]>,
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32) -> Self {
+        let anti_alias = (to < from).then(|| {
+            let cutoff = to as f32 * ANTI_ALIAS_CUTOFF;
+            [LowPass::new(cutoff, from, 1), LowPass::new(cutoff, from, 1)]
+        });
+        Self {
+            step: from.max(1) as f64 / to.max(1) as f64,
+            position: 0.0,
+            previous: 0.0,
+            anti_alias,
+        }
+    }
+
+    /// Takes a block at the input rate and returns the samples it completes at
+    /// the output rate.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        if samples.is_empty() {
+            return Vec::new();
+        }
+        let mut filtered;
+        let samples = match &mut self.anti_alias {
+            Some(filters) => {
+                filtered = samples.to_vec();
+                filters
+                    .iter_mut()
+                    .for_each(|filter| filter.process(&mut filtered));
+                &filtered
+            }
+            None => samples,
+        };
+
+        let previous = self.previous;
+        let sample_at = |index: isize| match index {
+            -1 => previous,
+            index => samples[index as usize],
+        };
+        let last = (samples.len() - 1) as f64;
+        let mut output = Vec::with_capacity((samples.len() as f64 / self.step) as usize + 1);
+        while self.position <= last {
+            let index = self.position.floor();
+            let fraction = (self.position - index) as f32;
+            let before = sample_at(index as isize);
+            let after = if fraction > 0.0 {
+                sample_at(index as isize + 1)
+            } else {
+                before
+            };
+            output.push(before + (after - before) * fraction);
+            self.position += self.step;
+        }
+        self.position -= samples.len() as f64;
+        self.previous = samples[samples.len() - 1];
+        output
+    }
+}
+
+/// Converts interleaved audio from one rate to another with rubato's FFT
+/// resampler, buffering the input into whole chunks. Its delay is trimmed, so the
+/// output lines up with the input and, once flushed, matches it in length.
+pub struct FileResampler {
+    channels: usize,
+    resampler: FftFixedIn<f32>,
+    /// Input waiting for a whole chunk, one buffer per channel.
+    pending: Vec<Vec<f32>>,
+    /// Output frames still to drop from the start.
+    delay: usize,
+    frames_in: u64,
+    frames_out: u64,
+    from: u32,
+    to: u32,
+}
+
+impl FileResampler {
+    pub fn new(from: u32, to: u32, channels: usize) -> Result<Self> {
+        let channels = channels.max(1);
+        let resampler = FftFixedIn::new(from as usize, to as usize, CHUNK_FRAMES, 2, channels)
+            .wrap_err_with(|| format!("can't resample from {from} Hz to {to} Hz"))?;
+        Ok(Self {
+            channels,
+            delay: resampler.output_delay(),
+            resampler,
+            pending: vec![Vec::new(); channels],
+            frames_in: 0,
+            frames_out: 0,
+            from,
+            to,
+        })
+    }
+
+    /// Takes interleaved samples at the input rate and returns what's ready at
+    /// the output rate.
+    pub fn process(&mut self, samples: &[f32]) -> Result<Vec<f32>> {
+        for frame in samples.chunks_exact(self.channels) {
+            for (pending, &sample) in self.pending.iter_mut().zip(frame) {
+                pending.push(sample);
+            }
+        }
+        self.frames_in += (samples.len() / self.channels) as u64;
+
+        let mut output = Vec::new();
+        while self.pending[0].len() >= self.resampler.input_frames_next() {
+            let frames = self.resampler.input_frames_next();
+            let chunk: Vec<Vec<f32>> = self
+                .pending
+                .iter_mut()
+                .map(|pending| pending.drain(..frames).collect())
+                .collect();
+            let resampled = self.resampler.process(&chunk, None)?;
+            self.append(&resampled, u64::MAX, &mut output);
+        }
+        Ok(output)
+    }
+
+    /// Pushes out the rest of the input. The resampler can't be used after.
+    pub fn flush(&mut self) -> Result<Vec<f32>> {
+        let expected = self.frames_in * self.to as u64 / self.from.max(1) as u64;
+        let mut output = Vec::new();
+        let pending = std::mem::take(&mut self.pending);
+        let resampled = self.resampler.process_partial(Some(&pending), None)?;
+        self.append(&resampled, expected, &mut output);
+        while self.frames_out < expected {
+            let resampled = self.resampler.process_partial::<Vec<f32>>(None, None)?;
+            self.append(&resampled, expected, &mut output);
+        }
+        Ok(output)
+    }
+
+    /// Interleaves `resampled` onto `output`, after the delay and up to `limit`
+    /// frames in all.
+    fn append(&mut self, resampled: &[Vec<f32>], limit: u64, output: &mut Vec<f32>) {
+        let frames = resampled[0].len();
+        let skip = self.delay.min(frames);
+        self.delay -= skip;
+        let take = ((frames - skip) as u64).min(limit.saturating_sub(self.frames_out)) as usize;
+        for frame in skip..skip + take {
+            output.extend(resampled.iter().map(|channel| channel[frame]));
+        }
+        self.frames_out += take as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp;
+    use crate::processing::tests::sine;
+
+    #[test]
+    fn output_length_follows_the_ratio_across_blocks() {
+        let mut resampler = Resampler::new(48_000, 16_000);
+        let input = sine(440.0, 48_000, 48_000);
+        let output: usize = input
+            .chunks(441)
+            .map(|block| resampler.process(block).len())
+            .sum();
+        assert_eq!(output, 16_000);
+    }
+
+    #[test]
+    fn keeps_speech_and_drops_what_would_alias() {
+        let mut voice = Resampler::new(48_000, 16_000);
+        let voice = voice.process(&sine(1000.0, 48_000, 48_000));
+        let mut hiss = Resampler::new(48_000, 16_000);
+        let hiss = hiss.process(&sine(15_000.0, 48_000, 48_000));
+
+        assert!(
+            (dsp::to_dbfs(dsp::rms(&voice[8000..])) - dsp::to_dbfs(0.5 / 2_f32.sqrt())).abs() < 0.5
+        );
+        assert!(dsp::to_dbfs(dsp::rms(&hiss[8000..])) < dsp::to_dbfs(0.5) - 30.0);
+    }
+
+    #[test]
+    fn file_resampler_keeps_length_and_alignment() {
+        let mut resampler = FileResampler::new(96_000, 16_000, 2).unwrap();
+        let stereo: Vec<f32> = sine(1000.0, 96_000, 96_000)
+            .into_iter()
+            .flat_map(|sample| [sample, -sample])
+            .collect();
+        let mut output = Vec::new();
+        for block in stereo.chunks(2 * 441) {
+            output.extend(resampler.process(block).unwrap());
+        }
+        output.extend(resampler.flush().unwrap());
+
+        assert_eq!(output.len(), 2 * 16_000);
+        let left: Vec<f32> = output.iter().step_by(2).copied().collect();
+        let expected = sine(1000.0, 16_000, 16_000);
+        let error = left[100..15_900]
+            .iter()
+            .zip(&expected[100..15_900])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(error < 0.01, "off by {error}");
+        assert_eq!(output[1], -output[0]);
+    }
+
+    #[test]
+    fn upsampling_interpolates_between_samples() {
+        let mut resampler = Resampler::new(8_000, 16_000);
+        let mut output = resampler.process(&[0.0, 1.0]);
+        output.extend(resampler.process(&[0.0]));
+        assert_eq!(output, [0.0, 0.5, 1.0, 0.5, 0.0]);
+    }
+}
@@ -0,0 +1,35 @@
+//! Building and appending the chunks of RIFF files, for metadata added to WAV
+//! recordings once they're finished.
+
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Appends a RIFF chunk to `out`, padded to an even length.
+pub fn append_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Writes `chunks` at the end of the WAV `file` and updates its RIFF header to
+/// match. `what` names the chunks when the file would grow too large for them.
+pub fn append_to_file(file: &mut File, chunks: &[u8], what: &str) -> io::Result<()> {
+    let end = file.seek(SeekFrom::End(0))?;
+    if end % 2 == 1 {
+        // Chunks start on even offsets
+        file.write_all(&[0])?;
+    }
+    file.write_all(chunks)?;
+    let riff_size = u32::try_from(file.stream_position()? - 8).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!("WAV file too large for {what}"),
+        )
+    })?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_size.to_le_bytes())?;
+    file.sync_all()
+}
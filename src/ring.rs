@@ -0,0 +1,207 @@
+//! A circular buffer on disk: capture goes on for hours while only the most recent
+//! stretch is kept, and clips of it are saved on request.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, WrapErr};
+
+use crate::encoder::AudioWriter;
+
+const BYTES_PER_SAMPLE: u64 = 4;
+/// Frames read at a time when saving a clip.
+const READ_FRAMES: u64 = 16_384;
+
+/// Interleaved `f32` frames in a file of fixed capacity. Once it is full, new
+/// frames overwrite the oldest.
+pub struct RingFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    channels: usize,
+    sample_rate: u32,
+    /// Frames the file holds when full.
+    capacity: u64,
+    /// Frame the next write goes to.
+    position: u64,
+    /// Frames holding audio, up to `capacity`.
+    filled: u64,
+}
+
+impl RingFile {
+    /// Creates a buffer at `path` holding `length` of audio. An existing file is
+    /// never overwritten.
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        channels: usize,
+        length: Duration,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        let capacity = (length.as_secs_f64() * sample_rate as f64) as u64;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: BufWriter::new(file),
+            channels: channels.max(1),
+            sample_rate,
+            capacity: capacity.max(1),
+            position: 0,
+            filled: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Length of the audio held, at most the buffer's length.
+    pub fn available(&self) -> Duration {
+        Duration::from_secs_f64(self.filled as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// Appends interleaved samples, wrapping around to the start when the end of
+    /// the file is reached.
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for frames in samples.chunks(self.channels) {
+            if self.position == self.capacity {
+                self.position = 0;
+                self.file.seek(SeekFrom::Start(0))?;
+            }
+            for sample in frames {
+                self.file.write_all(&sample.to_le_bytes())?;
+            }
+            self.position += 1;
+            self.filled = self.filled.max(self.position);
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    /// The last `length` of audio, or all of it if there is less. Reading it stays
+    /// valid while writing goes on, as long as the clip is read in less time than
+    /// the rest of the buffer takes to fill.
+    pub fn clip(&mut self, length: Duration) -> io::Result<Clip> {
+        self.flush()?;
+        let frames = ((length.as_secs_f64() * self.sample_rate as f64) as u64).min(self.filled);
+        let start = (self.position + self.capacity - frames) % self.capacity;
+        Ok(Clip {
+            path: self.path.clone(),
+            start,
+            frames,
+            capacity: self.capacity,
+            channels: self.channels,
+        })
+    }
+}
+
+/// A stretch of a [`RingFile`], see [`RingFile::clip`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clip {
+    path: PathBuf,
+    /// First frame, as an offset into the file.
+    start: u64,
+    frames: u64,
+    capacity: u64,
+    channels: usize,
+}
+
+impl Clip {
+    /// Reads the clip from its own handle on the buffer, passing it to `each` in
+    /// blocks of interleaved samples.
+    pub fn read(&self, mut each: impl FnMut(&[f32]) -> Result<()>) -> Result<()> {
+        let mut file = File::open(&self.path)
+            .wrap_err_with(|| format!("failed to open {}", self.path.display()))?;
+        let frame_bytes = BYTES_PER_SAMPLE * self.channels as u64;
+        let mut position = self.start;
+        let mut left = self.frames;
+        let mut bytes = Vec::new();
+        while left > 0 {
+            // Up to the end of the file at most, then from the start
+            let frames = left.min(READ_FRAMES).min(self.capacity - position);
+            bytes.resize((frames * frame_bytes) as usize, 0);
+            file.seek(SeekFrom::Start(position * frame_bytes))?;
+            file.read_exact(&mut bytes)?;
+            let samples: Vec<f32> = bytes
+                .chunks_exact(BYTES_PER_SAMPLE as usize)
+                .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+                .collect();
+            each(&samples)?;
+            position = (position + frames) % self.capacity;
+            left -= frames;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a take into a shared [`RingFile`], which the engine keeps a handle on to
+/// save clips. The buffer is deleted when the take ends.
+pub struct RingWriter(pub Arc<Mutex<RingFile>>);
+
+impl AudioWriter for RingWriter {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        let mut ring = self.0.lock().expect("ring buffer lock poisoned");
+        ring.write(samples).wrap_err("failed to write the buffer")
+    }
+
+    fn publish(&mut self) -> Result<()> {
+        let mut ring = self.0.lock().expect("ring buffer lock poisoned");
+        Ok(ring.flush()?)
+    }
+
+    fn finalize(self: Box<Self>) -> Result<()> {
+        let path = self
+            .0
+            .lock()
+            .expect("ring buffer lock poisoned")
+            .path
+            .clone();
+        fs::remove_file(&path)
+            .wrap_err_with(|| format!("failed to remove the buffer {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn read_all(clip: &Clip) -> Vec<f32> {
+        let mut samples = Vec::new();
+        clip.read(|block| {
+            samples.extend_from_slice(block);
+            Ok(())
+        })
+        .unwrap();
+        samples
+    }
+
+    #[test]
+    fn keeps_only_the_latest_audio() {
+        let path = std::env::temp_dir().join(format!("micrec-ring-{}.raw", process::id()));
+        // Five stereo frames at 10 Hz
+        let mut ring = RingFile::create(&path, 10, 2, Duration::from_millis(500)).unwrap();
+        let frames: Vec<f32> = (0..8).flat_map(|i| [i as f32, -(i as f32)]).collect();
+        ring.write(&frames[..6]).unwrap();
+        ring.write(&frames[6..]).unwrap();
+
+        let clip = read_all(&ring.clip(Duration::from_millis(300)).unwrap());
+        let everything = read_all(&ring.clip(Duration::from_secs(60)).unwrap());
+        let size = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(clip, [5.0, -5.0, 6.0, -6.0, 7.0, -7.0]);
+        assert_eq!(everything, frames[6..]);
+        assert_eq!(size, 5 * 2 * 4);
+        assert_eq!(ring.available(), Duration::from_millis(500));
+    }
+}
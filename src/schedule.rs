@@ -0,0 +1,75 @@
+//! Wall-clock start times for takes armed ahead of time with `--start-at`.
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use color_eyre::eyre::{eyre, Result};
+
+/// Parses a start time given as `14:30`, `14:30:15` or `2024-05-17 14:30`. A time
+/// of day that has already passed today means tomorrow.
+pub fn parse_start_time(s: &str) -> Result<DateTime<Local>> {
+    start_time_after(s.trim(), Local::now())
+}
+
+fn start_time_after(s: &str, now: DateTime<Local>) -> Result<DateTime<Local>> {
+    let invalid = || eyre!("invalid start time \"{s}\", expected e.g. 14:30 or 2024-05-17 14:30");
+    if let Some(time) = ["%H:%M", "%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(s, format).ok())
+    {
+        let today = local(now.date_naive().and_time(time)).ok_or_else(invalid)?;
+        if today > now {
+            return Ok(today);
+        }
+        let tomorrow = now.date_naive() + TimeDelta::days(1);
+        return local(tomorrow.and_time(time)).ok_or_else(invalid);
+    }
+    let at = ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+            Some(date.and_time(NaiveTime::MIN))
+        })
+        .ok_or_else(invalid)?;
+    let at = local(at).ok_or_else(invalid)?;
+    if at <= now {
+        return Err(eyre!("start time {s} has already passed"));
+    }
+    Ok(at)
+}
+
+/// `at` in the local time zone, taking the earlier of two readings when clocks go
+/// back. `None` for a time skipped when clocks go forward.
+fn local(at: NaiveDateTime) -> Option<DateTime<Local>> {
+    at.and_local_timezone(Local).earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn times_of_day_are_today_or_tomorrow() {
+        let now = Local.with_ymd_and_hms(2024, 5, 17, 12, 0, 0).unwrap();
+        assert_eq!(
+            start_time_after("14:30", now).unwrap(),
+            Local.with_ymd_and_hms(2024, 5, 17, 14, 30, 0).unwrap()
+        );
+        assert_eq!(
+            start_time_after("09:15:30", now).unwrap(),
+            Local.with_ymd_and_hms(2024, 5, 18, 9, 15, 30).unwrap()
+        );
+    }
+
+    #[test]
+    fn dates_must_be_in_the_future() {
+        let now = Local.with_ymd_and_hms(2024, 5, 17, 12, 0, 0).unwrap();
+        assert_eq!(
+            start_time_after("2024-06-01 08:00", now).unwrap(),
+            Local.with_ymd_and_hms(2024, 6, 1, 8, 0, 0).unwrap()
+        );
+        assert!(start_time_after("2024-05-01 08:00", now).is_err());
+        assert!(start_time_after("half past two", now).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! Audio made up on the spot, fed to a take in place of an input device: for
+//! tests that can't count on hardware, and for `micrec --demo`.
+
+use std::f32::consts::{PI, TAU};
+
+use clap::ValueEnum;
+use cpal::{BufferSize, SampleRate, StreamConfig};
+
+/// Anything that can stand in for an input device, see
+/// [`Recorder::start_with_source`](crate::engine::Recorder::start_with_source).
+/// It's played in real time, a block at a time, as a device would deliver it.
+pub trait AudioSource: Send {
+    /// Shown and saved where a device name would be.
+    fn name(&self) -> String;
+    /// Rate and channels of what `fill` produces.
+    fn config(&self) -> StreamConfig;
+    /// Fills `block` with the next interleaved samples, in whole frames.
+    fn fill(&mut self, block: &mut [f32]);
+}
+
+/// What a [`Synth`] plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Signal {
+    /// A steady 440 Hz tone at -12 dBFS
+    Sine,
+    /// White noise, peaking at -20 dBFS
+    Noise,
+    /// Syllables of a voiced tone in phrases, with pauses between them
+    Speech,
+}
+
+/// Level of the sine, -12 dBFS.
+const SINE_AMPLITUDE: f32 = 0.25;
+const SINE_HZ: f32 = 440.0;
+const NOISE_AMPLITUDE: f32 = 0.1;
+/// Length of a syllable of [`Signal::Speech`], and their levels through a phrase.
+const SYLLABLE_SECS: f32 = 0.22;
+const SYLLABLE_LEVELS: [f32; 8] = [0.8, 0.5, 1.0, 0.6, 0.9, 0.4, 0.7, 0.55];
+/// Quiet after each phrase.
+const PAUSE_SECS: f32 = 1.0;
+/// Room tone under the speech, so the pauses aren't digital silence.
+const ROOM_TONE: f32 = 0.002;
+
+/// Plays a [`Signal`], the same on every channel.
+#[derive(Debug, Clone)]
+pub struct Synth {
+    signal: Signal,
+    sample_rate: u32,
+    channels: u16,
+    /// Frames played so far.
+    position: u64,
+    /// Of the speech's fundamental, in cycles.
+    phase: f32,
+    /// State of the xorshift generator behind the noise.
+    seed: u32,
+}
+
+impl Synth {
+    pub fn new(signal: Signal, sample_rate: u32, channels: u16) -> Self {
+        Self {
+            signal,
+            sample_rate: sample_rate.max(1),
+            channels: channels.max(1),
+            position: 0,
+            phase: 0.0,
+            seed: 0x9e37_79b9,
+        }
+    }
+
+    fn noise(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let t = self.position as f32 / self.sample_rate as f32;
+        self.position += 1;
+        match self.signal {
+            Signal::Sine => SINE_AMPLITUDE * (TAU * SINE_HZ * t).sin(),
+            Signal::Noise => NOISE_AMPLITUDE * self.noise(),
+            Signal::Speech => {
+                // A fundamental gliding around 120 Hz with its harmonics, shaped
+                // into syllables
+                let f0 = 120.0 + 20.0 * (TAU * 0.4 * t).sin();
+                self.phase = (self.phase + f0 / self.sample_rate as f32).fract();
+                let voice: f32 = (1..=8)
+                    .map(|harmonic| (TAU * harmonic as f32 * self.phase).sin() / harmonic as f32)
+                    .sum();
+                let phrase_secs = SYLLABLE_SECS * SYLLABLE_LEVELS.len() as f32;
+                let into_phrase = t % (phrase_secs + PAUSE_SECS);
+                let envelope = if into_phrase < phrase_secs {
+                    let syllable = (into_phrase / SYLLABLE_SECS) as usize;
+                    let within = into_phrase / SYLLABLE_SECS - syllable as f32;
+                    SYLLABLE_LEVELS[syllable] * (PI * within).sin().powi(2)
+                } else {
+                    0.0
+                };
+                0.3 * envelope * voice + ROOM_TONE * self.noise()
+            }
+        }
+    }
+}
+
+impl AudioSource for Synth {
+    fn name(&self) -> String {
+        let signal = self.signal.to_possible_value().expect("no skipped signals");
+        format!("Demo ({})", signal.get_name())
+    }
+
+    fn config(&self) -> StreamConfig {
+        StreamConfig {
+            channels: self.channels,
+            sample_rate: SampleRate(self.sample_rate),
+            buffer_size: BufferSize::Default,
+        }
+    }
+
+    fn fill(&mut self, block: &mut [f32]) {
+        for frame in block.chunks_mut(self.channels as usize) {
+            frame.fill(self.next_sample());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp;
+
+    fn play(signal: Signal, secs: f32) -> Vec<f32> {
+        let mut synth = Synth::new(signal, 8000, 2);
+        let mut block = vec![0.0; (secs * 8000.0) as usize * 2];
+        synth.fill(&mut block);
+        block
+    }
+
+    #[test]
+    fn sine_is_at_its_level_on_every_channel() {
+        let block = play(Signal::Sine, 1.0);
+
+        assert!(block.chunks(2).all(|frame| frame[0] == frame[1]));
+        assert!((dsp::to_dbfs(dsp::peak(&block)) - -12.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn speech_pauses_between_phrases() {
+        let block = play(Signal::Speech, 2.76);
+        let (phrase, pause) = block.split_at((1.76 * 8000.0) as usize * 2);
+
+        assert!(dsp::to_dbfs(dsp::rms(phrase)) > -30.0);
+        assert!(dsp::to_dbfs(dsp::peak(pause)) < -50.0);
+    }
+}
@@ -0,0 +1,123 @@
+//! Spoken level and time readouts, for recording away from the screen.
+//!
+//! Speech goes through whichever text-to-speech command the system has, so it
+//! plays on the default output and never ends up in the recording.
+
+use std::env;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+
+use micrec::dsp;
+
+/// Text-to-speech commands to look for, in order of preference.
+const PROGRAMS: [&str; 4] = ["spd-say", "espeak-ng", "espeak", "say"];
+/// Peaks below this are announced as no signal.
+const SIGNAL_FLOOR_DBFS: f32 = -60.0;
+
+/// Speaks the loudest peak and the elapsed time every `interval` of recording.
+#[derive(Debug)]
+pub struct Announcer {
+    program: &'static str,
+    interval: Duration,
+    /// Recording time at which the next readout is due.
+    next: Duration,
+    /// Loudest sample since the last readout.
+    peak: f32,
+    speaking: Option<Child>,
+}
+
+impl Announcer {
+    /// Fails if none of the supported text-to-speech commands is installed.
+    pub fn new(interval: Duration) -> Result<Self> {
+        let program = PROGRAMS
+            .into_iter()
+            .find(|program| on_path(program))
+            .ok_or_else(|| {
+                eyre!(
+                    "spoken levels need one of {} installed",
+                    PROGRAMS.join(", ")
+                )
+            })?;
+
+        Ok(Self {
+            program,
+            interval,
+            next: interval,
+            peak: 0.0,
+            speaking: None,
+        })
+    }
+
+    /// Takes in a block of samples, and speaks up if a readout is due now that
+    /// `elapsed` has been recorded.
+    pub fn process(&mut self, samples: &[f32], elapsed: Duration) -> Result<()> {
+        self.peak = self.peak.max(dsp::peak(samples));
+        if elapsed < self.next {
+            return Ok(());
+        }
+        self.next = elapsed + self.interval;
+        let peak = std::mem::take(&mut self.peak);
+
+        // Skip a readout rather than queue it behind one still being spoken
+        if let Some(child) = &mut self.speaking {
+            if child.try_wait()?.is_none() {
+                return Ok(());
+            }
+        }
+        let child = Command::new(self.program)
+            .arg(readout(peak, elapsed))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .wrap_err_with(|| format!("failed to run {}", self.program))?;
+        self.speaking = Some(child);
+        Ok(())
+    }
+}
+
+/// Sentence describing `peak` and `elapsed`, e.g. "Peak minus 12 dB. 2 minutes 30."
+fn readout(peak: f32, elapsed: Duration) -> String {
+    let peak_dbfs = dsp::to_dbfs(peak).round();
+    let level = if peak >= 1.0 {
+        String::from("Clipping")
+    } else if peak_dbfs < SIGNAL_FLOOR_DBFS {
+        String::from("No signal")
+    } else if peak_dbfs < 0.0 {
+        format!("Peak minus {} dB", -peak_dbfs)
+    } else {
+        String::from("Peak 0 dB")
+    };
+
+    let (minutes, seconds) = (elapsed.as_secs() / 60, elapsed.as_secs() % 60);
+    let time = match (minutes, seconds) {
+        (0, seconds) => format!("{seconds} seconds"),
+        (1, 0) => String::from("1 minute"),
+        (minutes, 0) => format!("{minutes} minutes"),
+        (1, seconds) => format!("1 minute {seconds}"),
+        (minutes, seconds) => format!("{minutes} minutes {seconds}"),
+    };
+
+    format!("{level}. {time}.")
+}
+
+/// Whether `program` is an executable file in one of the `PATH` directories.
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| is_executable(&dir.join(program))))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file() || path.with_extension("exe").is_file()
+}
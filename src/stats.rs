@@ -0,0 +1,164 @@
+//! Running figures for the take being written: how long it is, how big the file
+//! has got and how much room is left on disk, kept up to date by the capture
+//! thread as it writes.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::library;
+
+/// Free space below this many times the minimum gets a warning.
+pub const LOW_DISK_SPACE_FACTOR: u64 = 2;
+
+/// A snapshot of the take in progress.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RecordingStats {
+    /// Length of audio written so far.
+    pub elapsed: Duration,
+    /// Bytes on disk, including any safety track, as of the last update.
+    pub file_size: u64,
+    /// Free space on the drive being recorded to, if it can be checked.
+    pub free_space: Option<u64>,
+    /// How fast the files grow, estimated from the format.
+    pub bytes_per_sec: u64,
+    /// Times captured audio was dropped on its way from the input stream to the
+    /// file because the capture thread fell behind.
+    pub overruns: u64,
+}
+
+impl RecordingStats {
+    /// How much longer recording can go on before the disk is full.
+    pub fn time_left(&self) -> Option<Duration> {
+        let free = self.free_space?;
+        (self.bytes_per_sec > 0).then(|| Duration::from_secs(free / self.bytes_per_sec))
+    }
+}
+
+/// What the latest disk check found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DiskSpace {
+    Ok,
+    /// Free space dropped under [`LOW_DISK_SPACE_FACTOR`] times the minimum, for
+    /// the first time this take.
+    Low(u64),
+    /// Free space dropped under the minimum.
+    Full(u64),
+}
+
+/// Keeps the [`RecordingStats`] shared with the front-end up to date.
+pub(crate) struct StatsTracker {
+    /// The files making up the take.
+    files: Vec<PathBuf>,
+    /// Least free space to keep, in bytes.
+    min_free: Option<u64>,
+    sample_rate: u32,
+    warned: bool,
+    stats: RecordingStats,
+    shared: Arc<Mutex<Option<RecordingStats>>>,
+}
+
+impl StatsTracker {
+    pub(crate) fn new(
+        files: Vec<PathBuf>,
+        min_free: Option<u64>,
+        sample_rate: u32,
+        bytes_per_sec: u64,
+        shared: Arc<Mutex<Option<RecordingStats>>>,
+    ) -> Self {
+        let mut tracker = Self {
+            files,
+            min_free,
+            sample_rate,
+            warned: false,
+            stats: RecordingStats {
+                bytes_per_sec,
+                ..RecordingStats::default()
+            },
+            shared,
+        };
+        tracker.check_disk();
+        tracker
+    }
+
+    /// Brings the length up to `frames` written.
+    pub(crate) fn set_frames(&mut self, frames: u64) {
+        self.stats.elapsed =
+            Duration::from_secs_f64(frames as f64 / self.sample_rate.max(1) as f64);
+        self.share();
+    }
+
+    /// Updates the overrun count, shared along with the next length.
+    pub(crate) fn set_overruns(&mut self, overruns: u64) {
+        self.stats.overruns = overruns;
+    }
+
+    /// Measures the files and the free space, which is best done once the
+    /// writer has flushed.
+    pub(crate) fn check_disk(&mut self) -> DiskSpace {
+        self.stats.file_size = self
+            .files
+            .iter()
+            .filter_map(|file| fs::metadata(file).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        self.stats.free_space = self
+            .files
+            .first()
+            .and_then(|file| file.parent())
+            .and_then(|dir| library::free_space(dir).ok());
+        self.share();
+
+        let check = disk_space(self.stats.free_space, self.min_free, self.warned);
+        if matches!(check, DiskSpace::Low(_)) {
+            self.warned = true;
+        }
+        check
+    }
+
+    fn share(&self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            *shared = Some(self.stats);
+        }
+    }
+}
+
+fn disk_space(free: Option<u64>, min_free: Option<u64>, warned: bool) -> DiskSpace {
+    let (Some(free), Some(min_free)) = (free, min_free) else {
+        return DiskSpace::Ok;
+    };
+    if free < min_free {
+        DiskSpace::Full(free)
+    } else if free < min_free.saturating_mul(LOW_DISK_SPACE_FACTOR) && !warned {
+        DiskSpace::Low(free)
+    } else {
+        DiskSpace::Ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_once_then_stops() {
+        let min = Some(100);
+        assert_eq!(disk_space(Some(1000), min, false), DiskSpace::Ok);
+        assert_eq!(disk_space(Some(150), min, false), DiskSpace::Low(150));
+        assert_eq!(disk_space(Some(120), min, true), DiskSpace::Ok);
+        assert_eq!(disk_space(Some(99), min, true), DiskSpace::Full(99));
+        assert_eq!(disk_space(Some(0), None, false), DiskSpace::Ok);
+    }
+
+    #[test]
+    fn time_left_follows_the_data_rate() {
+        let stats = RecordingStats {
+            free_space: Some(192_000 * 3600),
+            bytes_per_sec: 192_000,
+            ..RecordingStats::default()
+        };
+        assert_eq!(stats.time_left(), Some(Duration::from_secs(3600)));
+        assert_eq!(RecordingStats::default().time_left(), None);
+    }
+}
@@ -0,0 +1,361 @@
+//! Title, artist and comment tags embedded in finished recordings, so archived
+//! takes describe themselves: INFO and `bext` chunks in WAV files, and Vorbis
+//! comments in FLAC and Opus files.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::encoder::OutputFormat;
+use crate::riff::{self, append_chunk};
+
+/// Vendor string of the Vorbis comments we write.
+const VENDOR: &str = "micrec";
+/// FLAC metadata block types, see the FLAC format's METADATA_BLOCK_HEADER.
+const FLAC_PADDING: u8 = 1;
+const FLAC_VORBIS_COMMENT: u8 = 4;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Tags {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl Tags {
+    pub fn is_empty(&self) -> bool {
+        self.fields().next().is_none()
+    }
+
+    /// The tags that are set, by their Vorbis comment names.
+    fn fields(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("TITLE", &self.title),
+            ("ARTIST", &self.artist),
+            ("COMMENT", &self.comment),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_deref().filter(|v| !v.is_empty())?)))
+    }
+}
+
+/// Replaces the tags of the recording at `path` with `tags`. Empty tags remove
+/// the ones there were.
+pub fn write(path: &Path, tags: &Tags) -> io::Result<()> {
+    match OutputFormat::from_path(path) {
+        Some(OutputFormat::Wav) => write_wav(path, tags),
+        Some(OutputFormat::Flac) => rewrite(path, |from, to| copy_flac(from, to, tags)),
+        #[cfg(feature = "opus")]
+        Some(OutputFormat::Opus) => rewrite(path, |from, to| copy_opus(from, to, tags)),
+        #[cfg(not(feature = "opus"))]
+        Some(OutputFormat::Opus) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this build of micrec has no Opus support (rebuild with --features opus)",
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("can't tag {}", path.display()),
+        )),
+    }
+}
+
+/// Drops the INFO list and `bext` chunk the WAV file at `path` had, and appends
+/// new ones. Trailing tag chunks are cut off; any before the audio are turned into
+/// `JUNK` chunks that players skip, so the audio never has to move.
+fn write_wav(path: &Path, tags: &Tags) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a WAV file", path.display()),
+        ));
+    }
+
+    let len = file.metadata()?.len();
+    let mut end = 12;
+    let mut stale = Vec::new();
+    let mut at = 12;
+    while at + 8 <= len {
+        let mut chunk = [0; 12];
+        file.seek(SeekFrom::Start(at))?;
+        let read = file.read(&mut chunk)?;
+        let size = u32::from_le_bytes(chunk[4..8].try_into().unwrap()) as u64;
+        let is_tags = &chunk[..4] == b"bext"
+            || (&chunk[..4] == b"LIST" && read == 12 && &chunk[8..] == b"INFO");
+        if is_tags {
+            stale.push(at);
+        } else {
+            end = (at + 8 + size + size % 2).min(len);
+        }
+        at += 8 + size + size % 2;
+    }
+    for at in stale.into_iter().filter(|&at| at < end) {
+        file.seek(SeekFrom::Start(at))?;
+        file.write_all(b"JUNK")?;
+    }
+    file.set_len(end)?;
+
+    let mut chunks = Vec::new();
+    if !tags.is_empty() {
+        let recorded = file
+            .metadata()?
+            .modified()
+            .map(DateTime::<Local>::from)
+            .ok();
+        append_chunk(&mut chunks, b"bext", &bext(tags, recorded));
+        let mut info = Vec::from(*b"INFO");
+        for (id, value) in [
+            (b"INAM", &tags.title),
+            (b"IART", &tags.artist),
+            (b"ICMT", &tags.comment),
+        ] {
+            if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+                let mut text = value.as_bytes().to_vec();
+                text.push(0);
+                append_chunk(&mut info, id, &text);
+            }
+        }
+        append_chunk(&mut info, b"ISFT", format!("{VENDOR}\0").as_bytes());
+        append_chunk(&mut chunks, b"LIST", &info);
+    }
+    riff::append_to_file(&mut file, &chunks, "tags")
+}
+
+/// A version 1 Broadcast Wave `bext` chunk: the comment, or the title, as its
+/// description and the artist as its originator.
+fn bext(tags: &Tags, recorded: Option<DateTime<Local>>) -> Vec<u8> {
+    let mut bext = Vec::with_capacity(602);
+    let mut field = |text: &str, len: usize| {
+        let text = truncated(text, len);
+        bext.extend_from_slice(text.as_bytes());
+        bext.resize(bext.len() + len - text.len(), 0);
+    };
+    let description = tags.comment.as_deref().or(tags.title.as_deref());
+    field(description.unwrap_or_default(), 256);
+    field(tags.artist.as_deref().unwrap_or_default(), 32);
+    field("", 32); // Originator reference
+    let date = recorded.map(|at| at.format("%Y-%m-%d").to_string());
+    field(date.as_deref().unwrap_or_default(), 10);
+    let time = recorded.map(|at| at.format("%H:%M:%S").to_string());
+    field(time.as_deref().unwrap_or_default(), 8);
+    bext.extend_from_slice(&0u64.to_le_bytes()); // Time reference
+    bext.extend_from_slice(&1u16.to_le_bytes()); // Version
+    bext.resize(bext.len() + 64 + 190, 0); // UMID and reserved
+    bext
+}
+
+/// The longest start of `text` that fits in `len` bytes.
+fn truncated(text: &str, len: usize) -> &str {
+    let mut end = text.len().min(len);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// The tags as a Vorbis comment block, as FLAC and Opus both store them.
+fn vorbis_comment(tags: &Tags) -> Vec<u8> {
+    let mut comment = Vec::new();
+    comment.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    comment.extend_from_slice(VENDOR.as_bytes());
+    let fields: Vec<String> = tags
+        .fields()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    comment.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+    for field in fields {
+        comment.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        comment.extend_from_slice(field.as_bytes());
+    }
+    comment
+}
+
+/// Copies the recording at `path` through `copy` into a new file, which then
+/// takes its place. Nothing changes if the copy fails.
+fn rewrite(
+    path: &Path,
+    copy: impl FnOnce(&mut BufReader<File>, &mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".tagging");
+    let temp = path.with_file_name(name);
+    let copied = (|| {
+        let mut from = BufReader::new(File::open(path)?);
+        let mut to = BufWriter::new(File::create(&temp)?);
+        copy(&mut from, &mut to)?;
+        to.into_inner().map_err(|err| err.into_error())?.sync_all()
+    })();
+    match copied {
+        Ok(()) => fs::rename(&temp, path),
+        Err(err) => {
+            fs::remove_file(&temp).ok();
+            Err(err)
+        }
+    }
+}
+
+/// Copies a FLAC file with its Vorbis comment and padding blocks replaced by a
+/// comment block holding `tags`.
+fn copy_flac(from: &mut impl Read, to: &mut impl Write, tags: &Tags) -> io::Result<()> {
+    let mut marker = [0; 4];
+    from.read_exact(&mut marker)?;
+    if &marker != b"fLaC" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a FLAC file",
+        ));
+    }
+    to.write_all(&marker)?;
+
+    let mut blocks = Vec::new();
+    loop {
+        let mut header = [0; 4];
+        from.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let mut data = vec![0; len];
+        from.read_exact(&mut data)?;
+        if kind != FLAC_VORBIS_COMMENT && kind != FLAC_PADDING {
+            blocks.push((kind, data));
+        }
+        if last {
+            break;
+        }
+    }
+    blocks.push((FLAC_VORBIS_COMMENT, vorbis_comment(tags)));
+
+    let count = blocks.len();
+    for (i, (kind, data)) in blocks.into_iter().enumerate() {
+        let last = if i + 1 == count { 0x80 } else { 0 };
+        let len = (data.len() as u32).to_be_bytes();
+        to.write_all(&[last | kind, len[1], len[2], len[3]])?;
+        to.write_all(&data)?;
+    }
+    io::copy(from, to)?;
+    Ok(())
+}
+
+/// Copies an Ogg Opus file page by page, with its comment header replaced by one
+/// holding `tags`.
+#[cfg(feature = "opus")]
+fn copy_opus(from: &mut BufReader<File>, to: &mut BufWriter<File>, tags: &Tags) -> io::Result<()> {
+    use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+    use ogg::PacketReader;
+
+    let mut reader = PacketReader::new(from);
+    let mut writer = PacketWriter::new(to);
+    let mut index = 0;
+    while let Some(packet) = reader.read_packet().map_err(io::Error::other)? {
+        let end = if packet.last_in_stream() {
+            PacketWriteEndInfo::EndStream
+        } else if packet.last_in_page() {
+            PacketWriteEndInfo::EndPage
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+        let (serial, absgp) = (packet.stream_serial(), packet.absgp_page());
+        let data = match index {
+            1 if packet.data.starts_with(b"OpusTags") => {
+                let mut data = Vec::from(*b"OpusTags");
+                data.extend(vorbis_comment(tags));
+                data
+            }
+            1 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not an Opus file",
+                ));
+            }
+            _ => packet.data,
+        };
+        writer.write_packet(data, serial, end, absgp)?;
+        index += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use cpal::{BufferSize, SampleRate, StreamConfig};
+
+    use super::*;
+    use crate::{decoder, encoder};
+
+    fn tags(title: &str) -> Tags {
+        Tags {
+            title: Some(title.to_string()),
+            artist: Some(String::from("Field Team")),
+            comment: None,
+        }
+    }
+
+    fn occurrences(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack
+            .windows(needle.len())
+            .filter(|w| *w == needle)
+            .count()
+    }
+
+    fn record(format: OutputFormat) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "micrec-tags-{}.{}",
+            process::id(),
+            format.extension()
+        ));
+        let config = StreamConfig {
+            channels: 1,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Default,
+        };
+        let mut writer = encoder::create(format, File::create(&path).unwrap(), &config).unwrap();
+        writer.write(&[0.25; 9600]).unwrap();
+        writer.finalize().unwrap();
+        path
+    }
+
+    #[test]
+    fn wav_tags_are_replaced_not_piled_up() {
+        let path = record(OutputFormat::Wav);
+        write(&path, &tags("Dawn chorus")).unwrap();
+        write(&path, &tags("Dusk chorus")).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let samples = decoder::decode_file(&path).unwrap().samples.len();
+        write(&path, &Tags::default()).unwrap();
+        let untagged = fs::read(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(occurrences(&bytes, b"Dusk chorus\0"), 2); // INAM and bext
+        assert_eq!(occurrences(&bytes, b"Dawn chorus"), 0);
+        assert_eq!(occurrences(&bytes, b"bext"), 1);
+        let riff_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        assert_eq!(riff_size as usize, bytes.len() - 8);
+        assert_eq!(samples, 9600);
+        assert_eq!(occurrences(&untagged, b"INFO"), 0);
+    }
+
+    #[test]
+    fn flac_gets_a_vorbis_comment() {
+        let path = record(OutputFormat::Flac);
+        write(&path, &tags("Dawn chorus")).unwrap();
+        write(&path, &tags("Dusk chorus")).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let samples = decoder::decode_file(&path).unwrap().samples.len();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(occurrences(&bytes, b"TITLE=Dusk chorus"), 1);
+        assert_eq!(occurrences(&bytes, b"TITLE=Dawn chorus"), 0);
+        assert_eq!(occurrences(&bytes, b"ARTIST=Field Team"), 1);
+        assert_eq!(samples, 9600);
+    }
+}
@@ -0,0 +1,230 @@
+//! Colors of the bars, meters and sliders on the recorder screen.
+//!
+//! Themes are written in RGB and brought down to what the terminal can show:
+//! without truecolor, each color becomes the nearest of the 256 indexed colors, or
+//! of the 16 basic ones where even those are missing.
+
+use std::env;
+
+use ratatui::style::Color;
+
+use crate::config::ThemeName;
+
+/// Colors the terminal can show, as far as its environment tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+    TrueColor,
+    Indexed,
+    Basic,
+}
+
+impl ColorSupport {
+    /// Guesses from `COLORTERM` and `TERM`, which is all terminals offer. Assumes
+    /// the least when in doubt, since a few colors look fine anywhere while RGB
+    /// turns to garbage where it isn't understood.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        Self::from_env(&colorterm, &term)
+    }
+
+    fn from_env(colorterm: &str, term: &str) -> Self {
+        if matches!(colorterm, "truecolor" | "24bit") || term.ends_with("-direct") {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Indexed
+        } else {
+            ColorSupport::Basic
+        }
+    }
+}
+
+/// Where a cell of a level meter or progress bar sits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Safe,
+    Caution,
+    Danger,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    name: ThemeName,
+    support: ColorSupport,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName, support: ColorSupport) -> Self {
+        Self { name, support }
+    }
+
+    /// A bar at `value`, 0 to 1, highlighted once playback has passed it.
+    pub fn bar(&self, value: f32, played: bool) -> Color {
+        let value = value.clamp(0.0, 1.0);
+        let brightness = ((value + 0.1).min(1.0) * 255.0) as u8;
+        let color = match (self.name, played) {
+            (ThemeName::Default, false) => Color::Rgb(brightness, brightness, brightness),
+            (ThemeName::Default, true) => Color::Rgb(brightness / 3, brightness / 2, brightness),
+            (ThemeName::Mono, false) => Color::Reset,
+            (ThemeName::Mono, true) => Color::DarkGray,
+            // Green through yellow to red as the level rises
+            (ThemeName::Gradient, false) => {
+                let red = (value * 2.0).min(1.0);
+                let green = ((1.0 - value) * 2.0).min(1.0);
+                Color::Rgb((red * 230.0) as u8, (green * 200.0) as u8, 40)
+            }
+            (ThemeName::Gradient, true) => Color::Rgb(60, 120, (brightness / 2).max(160)),
+            (ThemeName::HighContrast, false) => Color::White,
+            (ThemeName::HighContrast, true) => Color::LightCyan,
+        };
+        self.fit(color)
+    }
+
+    /// The line the bars grow from.
+    pub fn baseline(&self) -> Color {
+        match self.name {
+            ThemeName::Default | ThemeName::Gradient => self.fit(Color::Rgb(50, 50, 50)),
+            ThemeName::Mono | ThemeName::HighContrast => Color::Gray,
+        }
+    }
+
+    /// The empty part of meters, sliders and progress bars.
+    pub fn track(&self) -> Color {
+        match self.name {
+            ThemeName::HighContrast => Color::Gray,
+            _ => Color::DarkGray,
+        }
+    }
+
+    /// The filled part of the gain slider, and its knob.
+    pub fn accent(&self) -> Color {
+        match self.name {
+            ThemeName::Mono => Color::Reset,
+            ThemeName::HighContrast => Color::LightCyan,
+            _ => Color::Blue,
+        }
+    }
+
+    /// A level meter or progress bar in `zone`.
+    pub fn zone(&self, zone: Zone) -> Color {
+        match (self.name, zone) {
+            (ThemeName::Mono, Zone::Danger) => Color::White,
+            (ThemeName::Mono, _) => Color::Reset,
+            (ThemeName::HighContrast, Zone::Safe) => Color::LightGreen,
+            (ThemeName::HighContrast, Zone::Caution) => Color::LightYellow,
+            (ThemeName::HighContrast, Zone::Danger) => Color::LightRed,
+            (_, Zone::Safe) => Color::Green,
+            (_, Zone::Caution) => Color::Yellow,
+            (_, Zone::Danger) => Color::Red,
+        }
+    }
+
+    /// `color`, or the nearest the terminal can show.
+    fn fit(&self, color: Color) -> Color {
+        match (color, self.support) {
+            (Color::Rgb(r, g, b), ColorSupport::Indexed) => Color::Indexed(to_indexed(r, g, b)),
+            (Color::Rgb(r, g, b), ColorSupport::Basic) => to_basic(r, g, b),
+            _ => color,
+        }
+    }
+}
+
+/// Nearest of the xterm 256 colors: the 6×6×6 cube, or the gray ramp for grays.
+fn to_indexed(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..8 => 16,
+            249.. => 231,
+            _ => 232 + ((r - 8) / 10).min(23),
+        };
+    }
+    let level = |c: u8| match c {
+        0..48 => 0,
+        48..115 => 1,
+        _ => (c - 35) / 40,
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+/// Nearest of the 16 basic colors, by their usual xterm values. Black is left out,
+/// as it's the background of most terminals.
+fn to_basic(r: u8, g: u8, b: u8) -> Color {
+    const PALETTE: [(Color, [u8; 3]); 15] = [
+        (Color::Red, [205, 0, 0]),
+        (Color::Green, [0, 205, 0]),
+        (Color::Yellow, [205, 205, 0]),
+        (Color::Blue, [0, 0, 238]),
+        (Color::Magenta, [205, 0, 205]),
+        (Color::Cyan, [0, 205, 205]),
+        (Color::Gray, [229, 229, 229]),
+        (Color::DarkGray, [127, 127, 127]),
+        (Color::LightRed, [255, 0, 0]),
+        (Color::LightGreen, [0, 255, 0]),
+        (Color::LightYellow, [255, 255, 0]),
+        (Color::LightBlue, [92, 92, 255]),
+        (Color::LightMagenta, [255, 0, 255]),
+        (Color::LightCyan, [0, 255, 255]),
+        (Color::White, [255, 255, 255]),
+    ];
+    let distance = |[pr, pg, pb]: [u8; 3]| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, pr) + d(g, pg) + d(b, pb)
+    };
+    PALETTE
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grays_use_the_ramp() {
+        assert_eq!(to_indexed(0, 0, 0), 16);
+        assert_eq!(to_indexed(8, 8, 8), 232);
+        assert_eq!(to_indexed(128, 128, 128), 244);
+        assert_eq!(to_indexed(248, 248, 248), 255);
+        assert_eq!(to_indexed(255, 255, 255), 231);
+    }
+
+    #[test]
+    fn colors_use_the_cube() {
+        assert_eq!(to_indexed(255, 0, 0), 196);
+        assert_eq!(to_indexed(0, 255, 0), 46);
+        assert_eq!(to_indexed(0, 0, 255), 21);
+        // xterm's own values for 67
+        assert_eq!(to_indexed(95, 135, 175), 67);
+        assert_eq!(to_indexed(40, 0, 0), 16);
+    }
+
+    #[test]
+    fn basic_colors_are_the_nearest() {
+        assert_eq!(to_basic(255, 0, 0), Color::LightRed);
+        assert_eq!(to_basic(190, 10, 10), Color::Red);
+        assert_eq!(to_basic(128, 128, 128), Color::DarkGray);
+        assert_eq!(to_basic(250, 250, 250), Color::White);
+        assert_eq!(to_basic(10, 10, 200), Color::Blue);
+    }
+
+    #[test]
+    fn support_follows_the_environment() {
+        assert_eq!(
+            ColorSupport::from_env("truecolor", "xterm-256color"),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(ColorSupport::from_env("24bit", ""), ColorSupport::TrueColor);
+        assert_eq!(
+            ColorSupport::from_env("", "xterm-direct"),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env("", "screen-256color"),
+            ColorSupport::Indexed
+        );
+        assert_eq!(ColorSupport::from_env("", "xterm"), ColorSupport::Basic);
+        assert_eq!(ColorSupport::from_env("yes", "linux"), ColorSupport::Basic);
+        assert_eq!(ColorSupport::from_env("", ""), ColorSupport::Basic);
+    }
+}
@@ -0,0 +1,259 @@
+//! Compressed copies of finished takes for sharing. They are made once recording
+//! has stopped, so capture never waits on an encoder, and the take itself stays
+//! next to them as the master.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use color_eyre::eyre::{bail, Result, WrapErr};
+use cpal::{BufferSize, SampleRate, StreamConfig};
+use serde::{Deserialize, Serialize};
+
+use crate::decoder;
+use crate::encoder::{self, AudioWriter, OutputFormat};
+use crate::resample::FileResampler;
+
+/// Frames encoded between progress reports.
+const CHUNK_FRAMES: usize = 48_000;
+/// Rate audio Opus can't encode is resampled to.
+const OPUS_FALLBACK_RATE: u32 = 48_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscodeFormat {
+    /// MP3 at 192 kbps, plays anywhere
+    Mp3,
+    /// Ogg Opus at 32 kbps per channel, the smallest
+    Opus,
+}
+
+impl TranscodeFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TranscodeFormat::Mp3 => "mp3",
+            TranscodeFormat::Opus => "opus",
+        }
+    }
+
+    /// Where the copy of `source` goes: next to it, with the extension swapped.
+    pub fn path_for(self, source: &Path) -> PathBuf {
+        source.with_extension(self.extension())
+    }
+}
+
+/// Writes a copy of the recording at `source` in `format`, see
+/// [`TranscodeFormat::path_for`], and returns its path. `progress` is called with
+/// the fraction done as encoding goes along. A copy that fails halfway is deleted.
+pub fn transcode(
+    source: &Path,
+    format: TranscodeFormat,
+    mut progress: impl FnMut(f32),
+) -> Result<PathBuf> {
+    let target = format.path_for(source);
+    if target == source {
+        bail!("{} is already in that format", source.display());
+    }
+    let audio = decoder::decode_file(source)?;
+    let resampler = match format {
+        TranscodeFormat::Opus if !encoder::OPUS_SAMPLE_RATES.contains(&audio.sample_rate) => {
+            Some(FileResampler::new(
+                audio.sample_rate,
+                OPUS_FALLBACK_RATE,
+                audio.channels as usize,
+            )?)
+        }
+        _ => None,
+    };
+    let config = StreamConfig {
+        channels: audio.channels,
+        sample_rate: SampleRate(match resampler {
+            Some(_) => OPUS_FALLBACK_RATE,
+            None => audio.sample_rate,
+        }),
+        buffer_size: BufferSize::Default,
+    };
+
+    let file =
+        File::create(&target).wrap_err_with(|| format!("failed to create {}", target.display()))?;
+    let written = create(format, file, &config).and_then(|writer| {
+        encode(
+            writer,
+            &audio.samples,
+            audio.channels as usize,
+            resampler,
+            &mut progress,
+        )
+    });
+    if let Err(err) = written {
+        fs::remove_file(&target).ok();
+        return Err(err.wrap_err(format!("failed to write {}", target.display())));
+    }
+    Ok(target)
+}
+
+fn create(
+    format: TranscodeFormat,
+    file: File,
+    config: &StreamConfig,
+) -> Result<Box<dyn AudioWriter>> {
+    match format {
+        #[cfg(feature = "mp3")]
+        TranscodeFormat::Mp3 => Ok(Box::new(mp3_file::Mp3File::new(file, config)?)),
+        #[cfg(not(feature = "mp3"))]
+        TranscodeFormat::Mp3 => {
+            bail!("this build of micrec has no MP3 support (rebuild with --features mp3)")
+        }
+        TranscodeFormat::Opus => encoder::create(OutputFormat::Opus, file, config),
+    }
+}
+
+fn encode(
+    mut writer: Box<dyn AudioWriter>,
+    samples: &[f32],
+    channels: usize,
+    mut resampler: Option<FileResampler>,
+    progress: &mut impl FnMut(f32),
+) -> Result<()> {
+    let chunk = CHUNK_FRAMES * channels.max(1);
+    let chunks = samples.len().div_ceil(chunk).max(1);
+    progress(0.0);
+    for (done, block) in samples.chunks(chunk).enumerate() {
+        match resampler.as_mut() {
+            Some(resampler) => writer.write(&resampler.process(block)?)?,
+            None => writer.write(block)?,
+        }
+        progress((done + 1) as f32 / chunks as f32);
+    }
+    if let Some(resampler) = resampler.as_mut() {
+        writer.write(&resampler.flush()?)?;
+    }
+    writer.finalize()?;
+    progress(1.0);
+    Ok(())
+}
+
+#[cfg(feature = "mp3")]
+mod mp3_file {
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+    use cpal::StreamConfig;
+    use mp3lame_encoder::{
+        Bitrate, Builder, Encoder, FlushNoGap, InterleavedPcm, MonoPcm, Quality,
+    };
+
+    use crate::encoder::AudioWriter;
+
+    /// Raw MPEG audio frames, which is all an MP3 file needs to be.
+    pub struct Mp3File {
+        file: BufWriter<File>,
+        encoder: Encoder,
+        channels: usize,
+        buffer: Vec<u8>,
+    }
+
+    impl Mp3File {
+        pub fn new(file: File, config: &StreamConfig) -> Result<Self> {
+            let channels = config.channels;
+            if !(1..=2).contains(&channels) {
+                bail!("MP3 can't hold {channels} channels, try --output-channels mono");
+            }
+            let mut builder =
+                Builder::new().ok_or_else(|| eyre!("failed to start the MP3 encoder"))?;
+            builder.set_num_channels(channels as u8)?;
+            builder.set_sample_rate(config.sample_rate.0)?;
+            builder.set_brate(Bitrate::Kbps192)?;
+            builder.set_quality(Quality::Best)?;
+            Ok(Self {
+                file: BufWriter::new(file),
+                encoder: builder.build()?,
+                channels: channels as usize,
+                buffer: Vec::new(),
+            })
+        }
+    }
+
+    impl AudioWriter for Mp3File {
+        fn write(&mut self, samples: &[f32]) -> Result<()> {
+            let frames = samples.len() / self.channels;
+            self.buffer.clear();
+            self.buffer
+                .reserve(mp3lame_encoder::max_required_buffer_size(frames));
+            match self.channels {
+                1 => self
+                    .encoder
+                    .encode_to_vec(MonoPcm(samples), &mut self.buffer)?,
+                _ => self
+                    .encoder
+                    .encode_to_vec(InterleavedPcm(samples), &mut self.buffer)?,
+            };
+            self.file
+                .write_all(&self.buffer)
+                .wrap_err("failed to write MP3")
+        }
+
+        fn publish(&mut self) -> Result<()> {
+            self.file.flush().wrap_err("failed to write MP3")
+        }
+
+        fn finalize(mut self: Box<Self>) -> Result<()> {
+            self.buffer.clear();
+            // The last frame takes up to 7200 bytes, see lame_encode_flush
+            self.buffer.reserve(7200);
+            self.encoder.flush_to_vec::<FlushNoGap>(&mut self.buffer)?;
+            self.file.write_all(&self.buffer)?;
+            self.file.flush().wrap_err("failed to write MP3")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_go_next_to_the_take() {
+        let take = Path::new("/takes/memo.wav");
+        assert_eq!(
+            TranscodeFormat::Mp3.path_for(take),
+            Path::new("/takes/memo.mp3")
+        );
+
+        let opus = Path::new("/takes/memo.opus");
+        let err = transcode(opus, TranscodeFormat::Opus, |_| {}).unwrap_err();
+        assert!(err.to_string().contains("already"));
+    }
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn mp3_copy_reports_progress() {
+        let path =
+            std::env::temp_dir().join(format!("micrec-transcode-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut wav = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..100_000 {
+            let sample = (i as f32 * 0.05).sin() * 0.5;
+            wav.write_sample(sample).unwrap();
+            wav.write_sample(-sample).unwrap();
+        }
+        wav.finalize().unwrap();
+
+        let mut reports = Vec::new();
+        let copy = transcode(&path, TranscodeFormat::Mp3, |done| reports.push(done)).unwrap();
+        let size = fs::metadata(&copy).unwrap().len();
+        fs::remove_file(&path).ok();
+        fs::remove_file(&copy).ok();
+
+        assert!(size > 10_000);
+        assert_eq!(reports.first(), Some(&0.0));
+        assert_eq!(reports.last(), Some(&1.0));
+        assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}